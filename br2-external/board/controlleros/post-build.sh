@@ -7,6 +7,10 @@ cp -f "${BR2_EXTERNAL_CONTROLLEROS_PATH}/../configs/bluez/main.conf" \
 cp -f "${BR2_EXTERNAL_CONTROLLEROS_PATH}/../configs/bluez/input.conf" \
 	"${TARGET_DIR}/etc/bluetooth/input.conf"
 
+mkdir -p "${TARGET_DIR}/etc/dbus-1/system.d"
+cp -f "${BR2_EXTERNAL_CONTROLLEROS_PATH}/../configs/dbus/org.controlleros.Hidd.conf" \
+	"${TARGET_DIR}/etc/dbus-1/system.d/org.controlleros.Hidd.conf"
+
 mkdir -p "${TARGET_DIR}/etc/init.d"
 cp -f "${BR2_EXTERNAL_CONTROLLEROS_PATH}/../configs/init/S20dbus-prep" \
 	"${TARGET_DIR}/etc/init.d/S20dbus-prep"
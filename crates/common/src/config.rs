@@ -4,13 +4,25 @@ use std::path::Path;
 use serde::Deserialize;
 use thiserror::Error;
 
-use crate::hid::{
-    HidProfileMode, XBOX_BUTTON_MAX_INDEX, XBOX_COUNTRY_CODE, XBOX_ONE_S_1708_PRODUCT_ID,
-    XBOX_ONE_S_1708_VERSION, XBOX_TRIGGER_MAX, XBOX_VENDOR_ID,
-};
+use crate::hid::composite::CompositeProfileConfig;
+use crate::hid::generic::GenericProfileConfig;
+use crate::hid::{HidProfile, HidProfileMode, XBOX_BUTTON_MAX_INDEX, XBOX_TRIGGER_MAX};
 
 pub const DEFAULT_HID_CONFIG_PATH: &str = "/etc/controlleros/hid.toml";
 
+/// Longest Device Information Service string, the GATT attribute value limit.
+pub const MAX_DEVICE_INFO_LEN: usize = 512;
+
+/// Data bytes in a legacy advertising or scan response PDU.
+pub const MAX_ADVERTISING_DATA_LEN: usize = 31;
+
+/// Room the device name needs at least: BlueZ shortens it to what is left,
+/// down to its AD header and first character.
+const MIN_ADVERTISED_NAME_LEN: usize = 3;
+
+/// LE advertising interval range allowed by the Core spec, in milliseconds.
+pub const ADVERTISING_INTERVAL_MS: std::ops::RangeInclusive<u32> = 20..=10_240;
+
 #[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
 pub struct HidConfig {
     pub device: DeviceConfig,
@@ -18,53 +30,433 @@ pub struct HidConfig {
     pub profile: ProfileConfig,
     pub report: ReportConfig,
     pub pattern: PatternConfig,
+    #[serde(default)]
+    pub bluetooth: BluetoothConfig,
+    #[serde(default)]
+    pub advertising: AdvertisingConfig,
+    #[serde(default)]
+    pub pairing: PairingConfig,
+    #[serde(default)]
+    pub power: PowerConfig,
+    #[serde(default)]
+    pub output: OutputConfig,
+    #[serde(default)]
+    pub network: NetworkConfig,
 }
 
 #[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
 pub struct DeviceConfig {
     pub name: String,
+    /// Device Information Service strings. Unset ones fall back to the
+    /// profile's defaults (see [`HidConfig::device_info`]).
+    pub manufacturer: Option<String>,
+    pub model: Option<String>,
+    pub serial: Option<String>,
+    /// Use the Deck controller's unit serial instead of `serial` when it can
+    /// be read.
+    #[serde(default)]
+    pub serial_from_controller: bool,
+    pub firmware_revision: Option<String>,
+    pub hardware_revision: Option<String>,
+    pub software_revision: Option<String>,
 }
 
+/// Device Information Service strings with defaults applied. `None` fields
+/// are left out of the service.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DeviceInfo {
+    pub manufacturer: String,
+    pub model: String,
+    pub serial: Option<String>,
+    pub firmware_revision: String,
+    pub hardware_revision: Option<String>,
+    pub software_revision: String,
+}
+
+/// HID profile and the identity it presents. Identity fields left out of the
+/// config default to the selected mode's real device.
 #[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(from = "RawProfileConfig")]
 pub struct ProfileConfig {
-    #[serde(default)]
     pub mode: HidProfileMode,
-    #[serde(default = "default_profile_vendor_id")]
     pub vendor_id: u16,
-    #[serde(default = "default_profile_product_id")]
     pub product_id: u16,
-    #[serde(default = "default_profile_version")]
     pub version: u16,
-    #[serde(default = "default_profile_country")]
     pub country: u16,
+    /// Layout for `mode = "generic"`; ignored by the other modes.
+    pub generic: GenericProfileConfig,
+    /// Pointer settings for `mode = "composite"`.
+    pub composite: CompositeProfileConfig,
+}
+
+impl ProfileConfig {
+    pub fn for_mode(mode: HidProfileMode) -> Self {
+        let identity = mode.identity();
+        Self {
+            mode,
+            vendor_id: identity.vendor_id,
+            product_id: identity.product_id,
+            version: identity.version,
+            country: identity.country,
+            generic: GenericProfileConfig::default(),
+            composite: CompositeProfileConfig::default(),
+        }
+    }
+
+    /// Switch to `mode` with its real device identity, keeping the generic
+    /// and composite layouts.
+    pub fn with_mode(&self, mode: HidProfileMode) -> Self {
+        Self {
+            generic: self.generic.clone(),
+            composite: self.composite.clone(),
+            ..Self::for_mode(mode)
+        }
+    }
+
+    /// Build the descriptor and report list this config presents.
+    pub fn hid_profile(&self) -> HidProfile {
+        HidProfile::new(self)
+    }
 }
 
 impl Default for ProfileConfig {
+    fn default() -> Self {
+        Self::for_mode(HidProfileMode::default())
+    }
+}
+
+#[derive(Deserialize)]
+struct RawProfileConfig {
+    #[serde(default)]
+    mode: HidProfileMode,
+    vendor_id: Option<u16>,
+    product_id: Option<u16>,
+    version: Option<u16>,
+    country: Option<u16>,
+    #[serde(default)]
+    generic: GenericProfileConfig,
+    #[serde(default)]
+    composite: CompositeProfileConfig,
+}
+
+impl From<RawProfileConfig> for ProfileConfig {
+    fn from(raw: RawProfileConfig) -> Self {
+        let defaults = Self::for_mode(raw.mode);
+        Self {
+            mode: raw.mode,
+            vendor_id: raw.vendor_id.unwrap_or(defaults.vendor_id),
+            product_id: raw.product_id.unwrap_or(defaults.product_id),
+            version: raw.version.unwrap_or(defaults.version),
+            country: raw.country.unwrap_or(defaults.country),
+            generic: raw.generic,
+            composite: raw.composite,
+        }
+    }
+}
+
+/// How `hidd` presents itself over Bluetooth.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize)]
+pub struct BluetoothConfig {
+    #[serde(default)]
+    pub transport: BluetoothTransport,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum BluetoothTransport {
+    /// HID over GATT on LE.
+    #[default]
+    Hogp,
+    /// Classic (BR/EDR) HID over L2CAP.
+    Bredr,
+}
+
+impl BluetoothTransport {
+    pub const fn as_str(self) -> &'static str {
+        match self {
+            Self::Hogp => "hogp",
+            Self::Bredr => "bredr",
+        }
+    }
+}
+
+/// Where live mode sends reports. Pattern mode always uses both.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize)]
+pub struct OutputConfig {
+    #[serde(default)]
+    pub sink: OutputSink,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum OutputSink {
+    /// The `[bluetooth]` transport.
+    #[default]
+    Bluetooth,
+    /// A local `/dev/uhid` device, for testing without a radio or a host.
+    Uhid,
+    Both,
+}
+
+impl OutputSink {
+    pub const ALL: [Self; 3] = [Self::Bluetooth, Self::Uhid, Self::Both];
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|s| s.as_str() == name)
+    }
+
+    pub const fn as_str(self) -> &'static str {
+        match self {
+            Self::Bluetooth => "bluetooth",
+            Self::Uhid => "uhid",
+            Self::Both => "both",
+        }
+    }
+
+    pub const fn uses_bluetooth(self) -> bool {
+        matches!(self, Self::Bluetooth | Self::Both)
+    }
+
+    pub const fn uses_uhid(self) -> bool {
+        matches!(self, Self::Uhid | Self::Both)
+    }
+}
+
+/// Copy of the live input stream sent to a receiver on the network, next to
+/// the `[output] sink` (see [`crate::net`]).
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize)]
+pub struct NetworkConfig {
+    /// `host:port` of the receiver; unset sends nothing.
+    pub receiver: Option<String>,
+    #[serde(default)]
+    pub transport: NetTransport,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum NetTransport {
+    /// One datagram per report; late reports are lost rather than queued.
+    #[default]
+    Udp,
+    Tcp,
+}
+
+impl NetTransport {
+    pub const fn as_str(self) -> &'static str {
+        match self {
+            Self::Udp => "udp",
+            Self::Tcp => "tcp",
+        }
+    }
+}
+
+/// LE advertisement contents and reconnection behaviour (HOGP only).
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+pub struct AdvertisingConfig {
+    /// GAP appearance; defaults to the profile's (see
+    /// [`HidConfig::appearance`]).
+    pub appearance: Option<u16>,
+    /// Company identifier for `manufacturer_data`.
+    pub manufacturer_id: Option<u16>,
+    #[serde(default)]
+    pub manufacturer_data: Vec<u8>,
+    #[serde(default)]
+    pub include_tx_power: bool,
+    /// Put manufacturer data and the Battery / Device Information UUIDs in
+    /// the scan response, leaving room for the name in the advertisement.
+    #[serde(default)]
+    pub scan_response: bool,
+    pub min_interval_ms: Option<u32>,
+    pub max_interval_ms: Option<u32>,
+    /// Stop advertising after this many seconds; unset advertises until a
+    /// host connects.
+    pub timeout_s: Option<u16>,
+    #[serde(default)]
+    pub reconnect: ReconnectMode,
+    /// How long `reconnect = "bonded"` stays non-discoverable after a host
+    /// drops; 0 keeps it until the next start.
+    #[serde(default = "default_reconnect_timeout_s")]
+    pub reconnect_timeout_s: u32,
+}
+
+impl Default for AdvertisingConfig {
+    fn default() -> Self {
+        Self {
+            appearance: None,
+            manufacturer_id: None,
+            manufacturer_data: Vec::new(),
+            include_tx_power: false,
+            scan_response: false,
+            min_interval_ms: None,
+            max_interval_ms: None,
+            timeout_s: None,
+            reconnect: ReconnectMode::default(),
+            reconnect_timeout_s: default_reconnect_timeout_s(),
+        }
+    }
+}
+
+impl AdvertisingConfig {
+    /// Bytes `hidd` puts in the advertisement and in the scan response,
+    /// device name excluded: flags, appearance, 16-bit service UUIDs, TX
+    /// power and manufacturer data, each with its AD header.
+    pub fn data_lens(&self) -> (usize, usize) {
+        const FLAGS: usize = 3;
+        const APPEARANCE: usize = 4;
+        const TX_POWER: usize = 3;
+        let uuid_list = |count: usize| 2 + 2 * count;
+        let manufacturer = if self.manufacturer_id.is_some() {
+            4 + self.manufacturer_data.len()
+        } else {
+            0
+        };
+
+        let mut advertisement = FLAGS + APPEARANCE;
+        let mut scan_response = 0;
+        if self.include_tx_power {
+            advertisement += TX_POWER;
+        }
+        // HID in the advertisement; Battery and Device Information beside it
+        // or in the scan response.
+        if self.scan_response {
+            advertisement += uuid_list(1);
+            scan_response += uuid_list(2) + manufacturer;
+        } else {
+            advertisement += uuid_list(3) + manufacturer;
+        }
+        (advertisement, scan_response)
+    }
+}
+
+fn default_reconnect_timeout_s() -> u32 {
+    60
+}
+
+/// Who may find the controller after a bonded host disconnects.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ReconnectMode {
+    /// Advertise as discoverable and pairable to everyone.
+    #[default]
+    Any,
+    /// Advertise without the discoverable flag and refuse new pairings, so
+    /// only bonded hosts reconnect.
+    Bonded,
+}
+
+impl ReconnectMode {
+    pub const fn as_str(self) -> &'static str {
+        match self {
+            Self::Any => "any",
+            Self::Bonded => "bonded",
+        }
+    }
+}
+
+/// Who may pair with and connect to the controller.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+pub struct PairingConfig {
+    /// Refuse new hosts unless pairing mode was requested or they are on
+    /// `allowlist`. Bonded hosts can always reconnect.
+    #[serde(default)]
+    pub locked: bool,
+    /// Host addresses (`XX:XX:XX:XX:XX:XX`) that may pair while locked.
+    #[serde(default)]
+    pub allowlist: Vec<String>,
+    /// How long pairing mode stays open when requested without a duration.
+    #[serde(default = "default_pairing_timeout_s")]
+    pub pairing_timeout_s: u32,
+    /// IO capability the pairing agent registers with BlueZ.
+    #[serde(default)]
+    pub agent_capability: AgentCapability,
+    /// How long a passkey stays on screen, and how long a numeric comparison
+    /// waits for an answer before it is rejected.
+    #[serde(default = "default_prompt_timeout_s")]
+    pub prompt_timeout_s: u32,
+}
+
+impl Default for PairingConfig {
     fn default() -> Self {
         Self {
-            mode: HidProfileMode::default(),
-            vendor_id: default_profile_vendor_id(),
-            product_id: default_profile_product_id(),
-            version: default_profile_version(),
-            country: default_profile_country(),
+            locked: false,
+            allowlist: Vec::new(),
+            pairing_timeout_s: default_pairing_timeout_s(),
+            agent_capability: AgentCapability::default(),
+            prompt_timeout_s: default_prompt_timeout_s(),
         }
     }
 }
 
-fn default_profile_vendor_id() -> u16 {
-    XBOX_VENDOR_ID
+fn default_pairing_timeout_s() -> u32 {
+    120
 }
 
-fn default_profile_product_id() -> u16 {
-    XBOX_ONE_S_1708_PRODUCT_ID
+fn default_prompt_timeout_s() -> u32 {
+    30
 }
 
-fn default_profile_version() -> u16 {
-    XBOX_ONE_S_1708_VERSION
+/// Power saving while the host does not need input.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize)]
+pub struct PowerConfig {
+    /// Read the Deck controller ten times a second instead of at its full
+    /// rate while the host has HID input suspended.
+    #[serde(default)]
+    pub slow_input_on_suspend: bool,
+    /// Minutes without controller input before `idle_action` runs; 0 never
+    /// goes idle.
+    #[serde(default)]
+    pub idle_timeout_min: u32,
+    #[serde(default)]
+    pub idle_action: IdleAction,
+    /// Suspend the Deck to RAM after `idle_action`.
+    #[serde(default)]
+    pub idle_suspend: bool,
 }
 
-fn default_profile_country() -> u16 {
-    XBOX_COUNTRY_CODE
+/// What `hidd` does with Bluetooth once the controller is idle. The Steam
+/// button undoes it.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum IdleAction {
+    /// Disconnect the host and stop advertising.
+    #[default]
+    Disconnect,
+    /// Also power the adapter down.
+    AdapterOff,
+}
+
+impl IdleAction {
+    pub const fn as_str(self) -> &'static str {
+        match self {
+            Self::Disconnect => "disconnect",
+            Self::AdapterOff => "adapter_off",
+        }
+    }
+}
+
+/// BlueZ agent IO capability. Anything but `NoInputNoOutput` gives bonds
+/// MITM protection; the passkey is shown on the Deck screen.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AgentCapability {
+    /// Just Works pairing, no prompt.
+    #[default]
+    NoInputNoOutput,
+    /// Show a passkey for the host to type.
+    DisplayOnly,
+    /// Show a passkey, or ask to confirm a number shown on both devices.
+    DisplayYesNo,
+}
+
+impl AgentCapability {
+    /// Capability string for `AgentManager1.RegisterAgent`.
+    pub const fn as_bluez(self) -> &'static str {
+        match self {
+            Self::NoInputNoOutput => "NoInputNoOutput",
+            Self::DisplayOnly => "DisplayOnly",
+            Self::DisplayYesNo => "DisplayYesNo",
+        }
+    }
 }
 
 #[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
@@ -141,12 +533,69 @@ impl HidConfig {
         Ok(parsed)
     }
 
+    /// Device Information Service strings. Manufacturer and model default to
+    /// the profile mode's device, the firmware revision to `profile.version`
+    /// as `major.minor`, and the software revision to the ControllerOS
+    /// version.
+    pub fn device_info(&self) -> DeviceInfo {
+        let identity = self.profile.mode.identity();
+        let device = &self.device;
+        DeviceInfo {
+            manufacturer: device
+                .manufacturer
+                .clone()
+                .unwrap_or_else(|| identity.manufacturer.to_string()),
+            model: device
+                .model
+                .clone()
+                .unwrap_or_else(|| identity.model.to_string()),
+            serial: device.serial.clone(),
+            firmware_revision: device.firmware_revision.clone().unwrap_or_else(|| {
+                format!(
+                    "{}.{}",
+                    self.profile.version >> 8,
+                    self.profile.version & 0xff
+                )
+            }),
+            hardware_revision: device.hardware_revision.clone(),
+            software_revision: device
+                .software_revision
+                .clone()
+                .unwrap_or_else(|| env!("CARGO_PKG_VERSION").to_string()),
+        }
+    }
+
+    /// GAP appearance to advertise: the configured one or the profile's.
+    pub fn appearance(&self) -> u16 {
+        self.advertising
+            .appearance
+            .unwrap_or(self.profile.mode.identity().appearance)
+    }
+
     fn validate(&self) -> Result<(), HidConfigError> {
         if self.device.name.trim().is_empty() {
             return Err(HidConfigError::Validation(
                 "device.name must not be empty".to_string(),
             ));
         }
+        let device_strings = [
+            ("manufacturer", &self.device.manufacturer),
+            ("model", &self.device.model),
+            ("serial", &self.device.serial),
+            ("firmware_revision", &self.device.firmware_revision),
+            ("hardware_revision", &self.device.hardware_revision),
+            ("software_revision", &self.device.software_revision),
+        ];
+        for (field, value) in device_strings {
+            if value
+                .as_ref()
+                .is_some_and(|v| v.len() > MAX_DEVICE_INFO_LEN)
+            {
+                return Err(HidConfigError::Validation(format!(
+                    "device.{field} must be at most {MAX_DEVICE_INFO_LEN} bytes"
+                )));
+            }
+        }
         if self.profile.vendor_id == 0 {
             return Err(HidConfigError::Validation(
                 "profile.vendor_id must be non-zero".to_string(),
@@ -157,6 +606,47 @@ impl HidConfig {
                 "profile.product_id must be non-zero".to_string(),
             ));
         }
+        if self.profile.mode == HidProfileMode::Generic {
+            self.profile
+                .generic
+                .validate()
+                .map_err(HidConfigError::Validation)?;
+        }
+        if self.profile.mode == HidProfileMode::Composite {
+            self.profile
+                .composite
+                .validate()
+                .map_err(HidConfigError::Validation)?;
+        }
+        self.validate_advertising()?;
+        if let Some(address) = self.pairing.allowlist.iter().find(|a| !is_bd_address(a)) {
+            return Err(HidConfigError::Validation(format!(
+                "pairing.allowlist entry {address:?} is not a Bluetooth address"
+            )));
+        }
+        if self.pairing.pairing_timeout_s == 0 {
+            return Err(HidConfigError::Validation(
+                "pairing.pairing_timeout_s must be greater than zero".to_string(),
+            ));
+        }
+        if self.power.idle_suspend && self.power.idle_timeout_min == 0 {
+            return Err(HidConfigError::Validation(
+                "power.idle_suspend needs power.idle_timeout_min".to_string(),
+            ));
+        }
+        if let Some(receiver) = &self.network.receiver {
+            if !is_host_port(receiver) {
+                return Err(HidConfigError::Validation(format!(
+                    "network.receiver {receiver:?} must be host:port"
+                )));
+            }
+        }
+        // BlueZ gives up on agent requests after 60 seconds.
+        if !(1..=60).contains(&self.pairing.prompt_timeout_s) {
+            return Err(HidConfigError::Validation(
+                "pairing.prompt_timeout_s must be in 1..=60".to_string(),
+            ));
+        }
         if self.report.rate_hz == 0 || self.report.rate_hz > 1000 {
             return Err(HidConfigError::Validation(
                 "report.rate_hz must be in 1..=1000".to_string(),
@@ -211,11 +701,82 @@ impl HidConfig {
 
         Ok(())
     }
+
+    fn validate_advertising(&self) -> Result<(), HidConfigError> {
+        let adv = &self.advertising;
+        if !adv.manufacturer_data.is_empty() && adv.manufacturer_id.is_none() {
+            return Err(HidConfigError::Validation(
+                "advertising.manufacturer_data needs advertising.manufacturer_id".to_string(),
+            ));
+        }
+        let (advertisement, scan_response) = adv.data_lens();
+        if advertisement + MIN_ADVERTISED_NAME_LEN > MAX_ADVERTISING_DATA_LEN {
+            return Err(HidConfigError::Validation(format!(
+                "advertising data needs {advertisement} bytes plus {MIN_ADVERTISED_NAME_LEN} for \
+                 the name, more than {MAX_ADVERTISING_DATA_LEN}; shorten \
+                 advertising.manufacturer_data or set advertising.scan_response"
+            )));
+        }
+        if scan_response > MAX_ADVERTISING_DATA_LEN {
+            return Err(HidConfigError::Validation(format!(
+                "advertising scan response needs {scan_response} bytes, more than \
+                 {MAX_ADVERTISING_DATA_LEN}; shorten advertising.manufacturer_data"
+            )));
+        }
+        for (field, value) in [
+            ("min_interval_ms", adv.min_interval_ms),
+            ("max_interval_ms", adv.max_interval_ms),
+        ] {
+            if value.is_some_and(|v| !ADVERTISING_INTERVAL_MS.contains(&v)) {
+                return Err(HidConfigError::Validation(format!(
+                    "advertising.{field} must be in {}..={}",
+                    ADVERTISING_INTERVAL_MS.start(),
+                    ADVERTISING_INTERVAL_MS.end()
+                )));
+            }
+        }
+        if let (Some(min), Some(max)) = (adv.min_interval_ms, adv.max_interval_ms) {
+            if min > max {
+                return Err(HidConfigError::Validation(
+                    "advertising.min_interval_ms must not exceed max_interval_ms".to_string(),
+                ));
+            }
+        }
+        if adv.timeout_s == Some(0) {
+            return Err(HidConfigError::Validation(
+                "advertising.timeout_s must be greater than zero".to_string(),
+            ));
+        }
+        Ok(())
+    }
+}
+
+/// Whether `address` is a `XX:XX:XX:XX:XX:XX` Bluetooth address.
+pub fn is_bd_address(address: &str) -> bool {
+    let parts = address.split(':').collect::<Vec<_>>();
+    parts.len() == 6
+        && parts
+            .iter()
+            .all(|p| p.len() == 2 && p.chars().all(|c| c.is_ascii_hexdigit()))
+}
+
+/// Whether `address` is `host:port` with a non-zero port. IPv6 hosts need
+/// brackets, as in `[::1]:47800`.
+fn is_host_port(address: &str) -> bool {
+    let Some((host, port)) = address.rsplit_once(':') else {
+        return false;
+    };
+    let bare_ipv6 = host.contains(':') && !(host.starts_with('[') && host.ends_with(']'));
+    !host.is_empty() && !bare_ipv6 && port.parse::<u16>().is_ok_and(|p| p != 0)
 }
 
 #[cfg(test)]
 mod tests {
-    use super::{AxisName, HidConfig, HidProfileMode, PatternConfig};
+    use super::{
+        is_bd_address, AdvertisingConfig, AgentCapability, AxisName, BluetoothTransport, HidConfig,
+        HidProfileMode, IdleAction, NetTransport, OutputSink, PatternConfig, ProfileConfig,
+        ReconnectMode,
+    };
 
     #[test]
     fn parses_valid_button_toggle_config() {
@@ -279,6 +840,415 @@ mod tests {
         assert_eq!(cfg.profile.product_id, 0x02fd);
         assert_eq!(cfg.profile.version, 0x0408);
         assert_eq!(cfg.profile.country, 0);
+
+        let info = cfg.device_info();
+        assert_eq!(info.manufacturer, "Microsoft");
+        assert_eq!(info.model, "Xbox Wireless Controller");
+        assert_eq!(info.firmware_revision, "4.8");
+        assert_eq!(info.serial, None);
+        assert_eq!(info.hardware_revision, None);
+        assert!(!cfg.device.serial_from_controller);
+        assert_eq!(cfg.bluetooth.transport, BluetoothTransport::Hogp);
+        assert_eq!(cfg.appearance(), 0x03c4);
+        assert_eq!(cfg.advertising.reconnect, ReconnectMode::Any);
+        assert_eq!(cfg.advertising.reconnect_timeout_s, 60);
+    }
+
+    #[test]
+    fn profile_switch_takes_identity_of_new_mode() {
+        let mut profile = ProfileConfig::for_mode(HidProfileMode::Generic);
+        profile.vendor_id = 0x1234;
+        profile.generic.buttons = 4;
+        let switched = profile.with_mode(HidProfileMode::DualShock4);
+        assert_eq!(switched.mode, HidProfileMode::DualShock4);
+        assert_eq!(switched.vendor_id, 0x054c);
+        assert_eq!(switched.generic.buttons, 4);
+    }
+
+    #[test]
+    fn profile_identity_defaults_follow_mode() {
+        let cfg = HidConfig::from_toml_str(
+            r#"
+            [device]
+            name = "ControllerOS DS4"
+
+            [profile]
+            mode = "dualshock4"
+            version = 0x0200
+
+            [report]
+            rate_hz = 250
+
+            [pattern]
+            kind = "button_toggle"
+            button_index = 0
+            period_reports = 30
+            "#,
+        )
+        .expect("config should parse");
+
+        assert_eq!(cfg.profile.mode, HidProfileMode::DualShock4);
+        assert_eq!(cfg.profile.vendor_id, 0x054c);
+        assert_eq!(cfg.profile.product_id, 0x09cc);
+        assert_eq!(cfg.profile.version, 0x0200);
+        assert_eq!(cfg.profile.country, 0);
+    }
+
+    #[test]
+    fn parses_bredr_transport() {
+        let cfg = HidConfig::from_toml_str(
+            r#"
+            [device]
+            name = "ControllerOS Xbox Controller"
+
+            [report]
+            rate_hz = 125
+
+            [pattern]
+            kind = "button_toggle"
+            button_index = 0
+            period_reports = 30
+
+            [bluetooth]
+            transport = "bredr"
+            "#,
+        )
+        .expect("config should parse");
+        assert_eq!(cfg.bluetooth.transport, BluetoothTransport::Bredr);
+        assert_eq!(cfg.output.sink, OutputSink::Bluetooth);
+    }
+
+    #[test]
+    fn parses_output_sink() {
+        let cfg = HidConfig::from_toml_str(
+            r#"
+            [device]
+            name = "ControllerOS Xbox Controller"
+
+            [report]
+            rate_hz = 125
+
+            [pattern]
+            kind = "button_toggle"
+            button_index = 0
+            period_reports = 30
+
+            [output]
+            sink = "both"
+            "#,
+        )
+        .expect("config should parse");
+        assert_eq!(cfg.output.sink, OutputSink::Both);
+        assert!(cfg.output.sink.uses_bluetooth() && cfg.output.sink.uses_uhid());
+        assert_eq!(OutputSink::from_name("uhid"), Some(OutputSink::Uhid));
+        assert_eq!(OutputSink::from_name("ble"), None);
+    }
+
+    #[test]
+    fn parses_network_receiver() {
+        let toml = |network: &str| {
+            format!(
+                r#"
+                [device]
+                name = "ControllerOS Xbox Controller"
+
+                [report]
+                rate_hz = 125
+
+                [pattern]
+                kind = "button_toggle"
+                button_index = 0
+                period_reports = 30
+
+                [network]
+                {network}
+                "#
+            )
+        };
+
+        let cfg = HidConfig::from_toml_str(&toml("")).expect("config should parse");
+        assert_eq!(cfg.network.receiver, None);
+        assert_eq!(cfg.network.transport, NetTransport::Udp);
+
+        let cfg = HidConfig::from_toml_str(&toml(
+            "receiver = \"lab-pc.local:47800\"\ntransport = \"tcp\"",
+        ))
+        .expect("config should parse");
+        assert_eq!(cfg.network.receiver.as_deref(), Some("lab-pc.local:47800"));
+        assert_eq!(cfg.network.transport, NetTransport::Tcp);
+
+        assert!(HidConfig::from_toml_str(&toml("receiver = \"[::1]:47800\"")).is_ok());
+        for bad in ["lab-pc.local", "lab-pc.local:0", ":47800", "::1:47800"] {
+            let err = HidConfig::from_toml_str(&toml(&format!("receiver = \"{bad}\"")))
+                .expect_err("receiver without a usable port must be rejected");
+            assert!(err.to_string().contains("network.receiver"), "{err}");
+        }
+    }
+
+    #[test]
+    fn parses_advertising_section() {
+        let toml = |advertising: &str| {
+            format!(
+                r#"
+                [device]
+                name = "ControllerOS Xbox Controller"
+
+                [report]
+                rate_hz = 125
+
+                [pattern]
+                kind = "button_toggle"
+                button_index = 0
+                period_reports = 30
+
+                [advertising]
+                {advertising}
+                "#
+            )
+        };
+        let cfg = HidConfig::from_toml_str(&toml(
+            r#"
+            appearance = 0x03c0
+            manufacturer_id = 0x045e
+            manufacturer_data = [1, 2, 3]
+            include_tx_power = true
+            scan_response = true
+            min_interval_ms = 30
+            max_interval_ms = 50
+            reconnect = "bonded"
+            reconnect_timeout_s = 0
+            "#,
+        ))
+        .expect("config should parse");
+        assert_eq!(cfg.appearance(), 0x03c0);
+        assert_eq!(cfg.advertising.manufacturer_data, [1, 2, 3]);
+        // Flags, appearance, TX power, HID UUID; Battery/DIS UUIDs, data.
+        assert_eq!(cfg.advertising.data_lens(), (3 + 4 + 3 + 4, 6 + 4 + 3));
+        assert_eq!(AdvertisingConfig::default().data_lens(), (3 + 4 + 8, 0));
+        assert_eq!(cfg.advertising.reconnect, ReconnectMode::Bonded);
+        assert_eq!(cfg.advertising.reconnect_timeout_s, 0);
+
+        for (advertising, field) in [
+            ("manufacturer_data = [1]", "manufacturer_id"),
+            ("min_interval_ms = 10", "min_interval_ms"),
+            (
+                "min_interval_ms = 60\nmax_interval_ms = 50",
+                "min_interval_ms",
+            ),
+            ("timeout_s = 0", "timeout_s"),
+            (
+                "manufacturer_id = 1\nmanufacturer_data = [0, 0, 0, 0, 0, 0, 0, 0, 0, 0]",
+                "manufacturer_data",
+            ),
+            (
+                "manufacturer_id = 1\nscan_response = true\nmanufacturer_data = [\n\
+                 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0]",
+                "scan response",
+            ),
+        ] {
+            let err = HidConfig::from_toml_str(&toml(advertising))
+                .expect_err("invalid advertising config should fail");
+            assert!(err.to_string().contains(field), "{err}");
+        }
+    }
+
+    #[test]
+    fn parses_pairing_lock() {
+        let toml = |pairing: &str| {
+            format!(
+                r#"
+                [device]
+                name = "ControllerOS Xbox Controller"
+
+                [report]
+                rate_hz = 125
+
+                [pattern]
+                kind = "button_toggle"
+                button_index = 0
+                period_reports = 30
+
+                [pairing]
+                {pairing}
+                "#
+            )
+        };
+        let cfg = HidConfig::from_toml_str(&toml(
+            r#"
+            locked = true
+            allowlist = ["98:B6:E9:01:02:03"]
+            "#,
+        ))
+        .expect("config should parse");
+        assert!(cfg.pairing.locked);
+        assert_eq!(cfg.pairing.allowlist, ["98:B6:E9:01:02:03"]);
+        assert_eq!(cfg.pairing.pairing_timeout_s, 120);
+        assert_eq!(
+            cfg.pairing.agent_capability,
+            AgentCapability::NoInputNoOutput
+        );
+
+        let err = HidConfig::from_toml_str(&toml(r#"allowlist = ["my laptop"]"#))
+            .expect_err("invalid allowlist entry should fail");
+        assert!(err.to_string().contains("pairing.allowlist"));
+
+        let cfg = HidConfig::from_toml_str(&toml(
+            r#"
+            agent_capability = "display_yes_no"
+            prompt_timeout_s = 45
+            "#,
+        ))
+        .expect("config should parse");
+        assert_eq!(cfg.pairing.agent_capability, AgentCapability::DisplayYesNo);
+        assert_eq!(cfg.pairing.agent_capability.as_bluez(), "DisplayYesNo");
+        assert_eq!(cfg.pairing.prompt_timeout_s, 45);
+
+        let err = HidConfig::from_toml_str(&toml("prompt_timeout_s = 90"))
+            .expect_err("prompt timeout beyond BlueZ's should fail");
+        assert!(err.to_string().contains("prompt_timeout_s"));
+    }
+
+    #[test]
+    fn parses_idle_policy() {
+        let toml = |power: &str| {
+            format!(
+                r#"
+                [device]
+                name = "ControllerOS Xbox Controller"
+
+                [report]
+                rate_hz = 125
+
+                [pattern]
+                kind = "button_toggle"
+                button_index = 0
+                period_reports = 30
+
+                [power]
+                {power}
+                "#
+            )
+        };
+        let cfg = HidConfig::from_toml_str(&toml("")).expect("config should parse");
+        assert_eq!(cfg.power.idle_timeout_min, 0);
+        assert_eq!(cfg.power.idle_action, IdleAction::Disconnect);
+
+        let cfg = HidConfig::from_toml_str(&toml(
+            r#"
+            idle_timeout_min = 20
+            idle_action = "adapter_off"
+            idle_suspend = true
+            "#,
+        ))
+        .expect("config should parse");
+        assert_eq!(cfg.power.idle_timeout_min, 20);
+        assert_eq!(cfg.power.idle_action, IdleAction::AdapterOff);
+        assert!(cfg.power.idle_suspend);
+
+        let err = HidConfig::from_toml_str(&toml("idle_suspend = true"))
+            .expect_err("suspend without a timeout should fail");
+        assert!(err.to_string().contains("idle_timeout_min"));
+    }
+
+    #[test]
+    fn validates_bd_addresses() {
+        assert!(is_bd_address("98:B6:E9:01:02:03"));
+        assert!(!is_bd_address("98:B6:E9:01:02"));
+        assert!(!is_bd_address("98:B6:E9:01:02:0G"));
+        assert!(!is_bd_address(""));
+    }
+
+    #[test]
+    fn device_info_overrides_profile_defaults() {
+        let cfg = HidConfig::from_toml_str(
+            r#"
+            [device]
+            name = "ControllerOS Pro"
+            model = "Deck Pro"
+            serial = "CO-0001"
+            serial_from_controller = true
+            hardware_revision = "LCD"
+
+            [profile]
+            mode = "switch_pro"
+
+            [report]
+            rate_hz = 125
+
+            [pattern]
+            kind = "button_toggle"
+            button_index = 0
+            period_reports = 30
+            "#,
+        )
+        .expect("config should parse");
+
+        let info = cfg.device_info();
+        assert_eq!(info.manufacturer, "Nintendo");
+        assert_eq!(info.model, "Deck Pro");
+        assert_eq!(info.serial.as_deref(), Some("CO-0001"));
+        assert_eq!(info.hardware_revision.as_deref(), Some("LCD"));
+        assert_eq!(info.software_revision, env!("CARGO_PKG_VERSION"));
+        assert!(cfg.device.serial_from_controller);
+    }
+
+    #[test]
+    fn parses_generic_profile_layout() {
+        let cfg = HidConfig::from_toml_str(
+            r#"
+            [device]
+            name = "ControllerOS Gamepad"
+
+            [profile]
+            mode = "generic"
+
+            [profile.generic]
+            buttons = 32
+            hat = false
+            axes = [
+                { usage = "x", source = "lx" },
+                { usage = "slider", source = "rt", bits = 8 },
+            ]
+
+            [report]
+            rate_hz = 250
+
+            [pattern]
+            kind = "button_toggle"
+            button_index = 0
+            period_reports = 30
+            "#,
+        )
+        .expect("config should parse");
+
+        assert_eq!(cfg.profile.vendor_id, 0x1209);
+        assert_eq!(cfg.profile.generic.buttons, 32);
+        assert!(!cfg.profile.generic.hat);
+        assert_eq!(cfg.profile.generic.axes[0].bits, 16);
+        assert_eq!(cfg.profile.generic.axes[1].bits, 8);
+        // 4 button bytes, 16-bit X, 8-bit slider.
+        assert_eq!(cfg.profile.hid_profile().input_report_len(), 1 + 4 + 3);
+
+        let err = HidConfig::from_toml_str(
+            r#"
+            [device]
+            name = "ControllerOS Gamepad"
+
+            [profile]
+            mode = "generic"
+            generic = { buttons = 40 }
+
+            [report]
+            rate_hz = 250
+
+            [pattern]
+            kind = "button_toggle"
+            button_index = 0
+            period_reports = 30
+            "#,
+        )
+        .expect_err("40 buttons should fail");
+        assert!(err.to_string().contains("profile.generic.buttons"));
     }
 
     #[test]
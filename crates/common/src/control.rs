@@ -0,0 +1,49 @@
+//! Names of the control interface `hidd` exports on the system bus.
+
+/// Well-known bus name owned by `hidd`.
+pub const CONTROL_BUS_NAME: &str = "org.controlleros.Hidd";
+/// Object path of the control object.
+pub const CONTROL_OBJECT_PATH: &str = "/org/controlleros/hidd";
+/// Control interface. Methods:
+///
+/// - `ListHosts() -> a(ssbb)`: bonded hosts as (address, name, connected,
+///   current), sorted by address.
+/// - `ListConnections() -> a(ssbbuayb)`: devices hidd knows on the adapter
+///   as (address, name, connected, bonded, seconds connected, subscribed
+///   input report IDs, subscribed to battery level), sorted by object path.
+/// - `NextHost()`: switch to the bonded host after the current one.
+/// - `SelectHost(s address)`: switch to the bonded host with `address`.
+/// - `StartPairing(u timeout_s)`: accept new hosts for `timeout_s` seconds
+///   (0 uses `[pairing] pairing_timeout_s`), even while pairing is locked.
+/// - `StopPairing()`: close the pairing window early.
+/// - `PairingPrompt() -> (s address, u passkey, b confirm)`: the passkey to
+///   show while a host pairs, or an empty address when there is none.
+///   `confirm` means the host shows the same number and waits for
+///   `ConfirmPairing`.
+/// - `ConfirmPairing(b accept)`: answer the prompt. Unanswered comparisons
+///   are rejected after `[pairing] prompt_timeout_s`.
+/// - `SelectMapping(s name)`: switch to the `--mapping-config` with this path
+///   or file stem.
+/// - `SelectProfile(s profile)`: re-register with another profile mode.
+///   Hosts cache the report map, so they must pair again.
+/// - `Disconnect()`: disconnect the connected host.
+///
+/// The host methods fail with `NotSupported` on the BR/EDR transport.
+///
+/// Read-only properties, announced with `PropertiesChanged`:
+///
+/// - `Transport` (s): `hogp` or `bredr`.
+/// - `Profile` (s): active profile mode.
+/// - `Mapping` (s), `Mappings` (as): active and available mapping paths;
+///   empty in pattern mode.
+/// - `ConnectedHost` (s): address of the connected host, or empty.
+/// - `Notifying` (b): the host receives input reports.
+/// - `Suspended` (b): the host suspended HID input (HID Control Point on
+///   HOGP, `HID_CONTROL` on BR/EDR), so no input reports are sent.
+/// - `ReportRate` (q): input reports per second.
+/// - `InputHealth` (s): `waiting`, `ok`, `stale` or `error` for the
+///   controller, `pattern` without one.
+/// - `Pairing` (b): the pairing window is open.
+/// - `Idle` (b): no controller input for `[power] idle_timeout_min`, so
+///   Bluetooth sleeps until the Steam button is pressed.
+pub const CONTROL_INTERFACE: &str = "org.controlleros.Hidd1";
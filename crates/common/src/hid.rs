@@ -1,12 +1,45 @@
-//! Shared HID profile constants and report packing for checkpoint 03.
+//! Shared HID profile constants and report packing.
+
+use std::borrow::Cow;
 
 use serde::Deserialize;
 
+use crate::config::ProfileConfig;
+
+pub mod builder;
+pub mod composite;
+pub mod descriptor;
+pub mod ds4;
+pub mod generic;
+pub mod switch_pro;
+
+use composite::{CompositeProfileConfig, CompositeSession, COMPOSITE_PRODUCT_ID};
+use ds4::{
+    Ds4Session, DS4_CALIBRATION_PAYLOAD_LEN, DS4_CALIBRATION_REPORT_ID, DS4_FEATURE_REPORT_IDS,
+    DS4_FIRMWARE_PAYLOAD_LEN, DS4_FIRMWARE_REPORT_ID, DS4_HID_REPORT_DESCRIPTOR,
+    DS4_INPUT_PAYLOAD_LEN, DS4_INPUT_REPORT_ID, DS4_MAC_PAYLOAD_LEN, DS4_MAC_REPORT_ID,
+    DS4_OUTPUT_PAYLOAD_LEN, DS4_OUTPUT_REPORT_ID, DS4_PAIRING_PAYLOAD_LEN, DS4_PAIRING_REPORT_ID,
+    DS4_V2_PRODUCT_ID, DS4_VERSION, SONY_VENDOR_ID,
+};
+use generic::{
+    GenericProfile, GENERIC_INPUT_REPORT_ID, GENERIC_PRODUCT_ID, GENERIC_VENDOR_ID, GENERIC_VERSION,
+};
+use switch_pro::{
+    SwitchProSession, NINTENDO_VENDOR_ID, SWITCH_DEFAULT_BATTERY_PERCENT, SWITCH_FULL_PAYLOAD_LEN,
+    SWITCH_FULL_REPORT_ID, SWITCH_OUTPUT_PAYLOAD_LEN, SWITCH_PRO_HID_REPORT_DESCRIPTOR,
+    SWITCH_PRO_PRODUCT_ID, SWITCH_PRO_VERSION, SWITCH_REPLY_REPORT_ID, SWITCH_RUMBLE_REPORT_ID,
+    SWITCH_SIMPLE_PAYLOAD_LEN, SWITCH_SIMPLE_REPORT_ID, SWITCH_SUBCOMMAND_REPORT_ID,
+};
+
 pub const XBOX_VENDOR_ID: u16 = 0x045e;
 pub const XBOX_ONE_S_1708_PRODUCT_ID: u16 = 0x02fd;
 pub const XBOX_ONE_S_1708_VERSION: u16 = 0x0408;
 pub const XBOX_COUNTRY_CODE: u16 = 0x0000;
 
+/// GAP appearance values (Bluetooth Assigned Numbers, HID category).
+pub const APPEARANCE_GENERIC_HID: u16 = 0x03c0;
+pub const APPEARANCE_GAMEPAD: u16 = 0x03c4;
+
 pub const XBOX_INPUT_REPORT_ID: u8 = 0x01;
 pub const XBOX_EXTRA_INPUT_REPORT_ID: u8 = 0x02;
 pub const XBOX_OUTPUT_REPORT_ID: u8 = 0x03;
@@ -26,6 +59,13 @@ pub const XBOX_BUTTON_RS: u16 = 0x4000;
 pub const XBOX_BUTTON_MASK: u16 = 0x7fff;
 pub const XBOX_BUTTON_MAX_INDEX: u8 = 14;
 
+// Deck controls without an Xbox button, in `InputReport::extra_buttons`.
+pub const EXTRA_BUTTON_L4: u16 = 0x0001;
+pub const EXTRA_BUTTON_R4: u16 = 0x0002;
+pub const EXTRA_BUTTON_L5: u16 = 0x0004;
+pub const EXTRA_BUTTON_R5: u16 = 0x0008;
+pub const EXTRA_BUTTON_QAM: u16 = 0x0010;
+
 pub const XBOX_STICK_MIN: i16 = -32768;
 pub const XBOX_STICK_MAX: i16 = 32767;
 pub const XBOX_TRIGGER_MIN: u16 = 0;
@@ -69,30 +109,211 @@ pub const XBOX_ONE_S_1708_HID_REPORT_DESCRIPTOR: [u8; 334] = [
 pub enum HidProfileMode {
     #[serde(rename = "xbox_one_s_1708")]
     XboxOneS1708,
+    #[serde(rename = "dualshock4")]
+    DualShock4,
+    SwitchPro,
+    Generic,
+    Composite,
 }
 
-impl HidProfileMode {
+/// Main item a report belongs to in a profile's descriptor.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ReportType {
+    Input,
+    Output,
+    /// Read and written by the host on request, never notified.
+    Feature,
+}
+
+impl ReportType {
     pub const fn as_str(self) -> &'static str {
         match self {
-            Self::XboxOneS1708 => "xbox_one_s_1708",
+            Self::Input => "input",
+            Self::Output => "output",
+            Self::Feature => "feature",
         }
     }
+}
 
-    pub const fn report_descriptor(self) -> &'static [u8] {
-        match self {
-            Self::XboxOneS1708 => &XBOX_ONE_S_1708_HID_REPORT_DESCRIPTOR,
-        }
+/// One report declared by a profile's descriptor.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ReportInfo {
+    pub id: u8,
+    pub report_type: ReportType,
+    /// Report length without the report ID byte.
+    pub payload_len: usize,
+}
+
+/// USB-style identity a profile presents when the config does not override it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ProfileIdentity {
+    pub vendor_id: u16,
+    pub product_id: u16,
+    pub version: u16,
+    pub country: u16,
+    /// Device Information Service Manufacturer Name.
+    pub manufacturer: &'static str,
+    /// Device Information Service Model Number.
+    pub model: &'static str,
+    /// GAP appearance advertised over LE.
+    pub appearance: u16,
+}
+
+const XBOX_ONE_S_1708_REPORTS: [ReportInfo; 4] = [
+    ReportInfo {
+        id: XBOX_INPUT_REPORT_ID,
+        report_type: ReportType::Input,
+        payload_len: XBOX_INPUT_PAYLOAD_LEN,
+    },
+    ReportInfo {
+        id: XBOX_OUTPUT_REPORT_ID,
+        report_type: ReportType::Output,
+        payload_len: XBOX_OUTPUT_PAYLOAD_LEN,
+    },
+    ReportInfo {
+        id: XBOX_EXTRA_INPUT_REPORT_ID,
+        report_type: ReportType::Input,
+        payload_len: XBOX_EXTRA_INPUT_PAYLOAD_LEN,
+    },
+    ReportInfo {
+        id: XBOX_STATUS_INPUT_REPORT_ID,
+        report_type: ReportType::Input,
+        payload_len: XBOX_STATUS_INPUT_PAYLOAD_LEN,
+    },
+];
+
+const DS4_REPORTS: [ReportInfo; 6] = [
+    ReportInfo {
+        id: DS4_INPUT_REPORT_ID,
+        report_type: ReportType::Input,
+        payload_len: DS4_INPUT_PAYLOAD_LEN,
+    },
+    ReportInfo {
+        id: DS4_OUTPUT_REPORT_ID,
+        report_type: ReportType::Output,
+        payload_len: DS4_OUTPUT_PAYLOAD_LEN,
+    },
+    ReportInfo {
+        id: DS4_CALIBRATION_REPORT_ID,
+        report_type: ReportType::Feature,
+        payload_len: DS4_CALIBRATION_PAYLOAD_LEN,
+    },
+    ReportInfo {
+        id: DS4_PAIRING_REPORT_ID,
+        report_type: ReportType::Feature,
+        payload_len: DS4_PAIRING_PAYLOAD_LEN,
+    },
+    ReportInfo {
+        id: DS4_MAC_REPORT_ID,
+        report_type: ReportType::Feature,
+        payload_len: DS4_MAC_PAYLOAD_LEN,
+    },
+    ReportInfo {
+        id: DS4_FIRMWARE_REPORT_ID,
+        report_type: ReportType::Feature,
+        payload_len: DS4_FIRMWARE_PAYLOAD_LEN,
+    },
+];
+
+const SWITCH_PRO_REPORTS: [ReportInfo; 5] = [
+    ReportInfo {
+        id: SWITCH_FULL_REPORT_ID,
+        report_type: ReportType::Input,
+        payload_len: SWITCH_FULL_PAYLOAD_LEN,
+    },
+    ReportInfo {
+        id: SWITCH_REPLY_REPORT_ID,
+        report_type: ReportType::Input,
+        payload_len: SWITCH_FULL_PAYLOAD_LEN,
+    },
+    ReportInfo {
+        id: SWITCH_SIMPLE_REPORT_ID,
+        report_type: ReportType::Input,
+        payload_len: SWITCH_SIMPLE_PAYLOAD_LEN,
+    },
+    ReportInfo {
+        id: SWITCH_SUBCOMMAND_REPORT_ID,
+        report_type: ReportType::Output,
+        payload_len: SWITCH_OUTPUT_PAYLOAD_LEN,
+    },
+    ReportInfo {
+        id: SWITCH_RUMBLE_REPORT_ID,
+        report_type: ReportType::Output,
+        payload_len: SWITCH_OUTPUT_PAYLOAD_LEN,
+    },
+];
+
+impl HidProfileMode {
+    pub const ALL: [HidProfileMode; 5] = [
+        Self::XboxOneS1708,
+        Self::DualShock4,
+        Self::SwitchPro,
+        Self::Generic,
+        Self::Composite,
+    ];
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|m| m.as_str() == name)
     }
 
-    pub const fn input_report_len(self) -> usize {
+    pub const fn as_str(self) -> &'static str {
         match self {
-            Self::XboxOneS1708 => XBOX_INPUT_REPORT_LEN,
+            Self::XboxOneS1708 => "xbox_one_s_1708",
+            Self::DualShock4 => "dualshock4",
+            Self::SwitchPro => "switch_pro",
+            Self::Generic => "generic",
+            Self::Composite => "composite",
         }
     }
 
-    pub const fn input_report_id(self) -> u8 {
+    pub const fn identity(self) -> ProfileIdentity {
         match self {
-            Self::XboxOneS1708 => XBOX_INPUT_REPORT_ID,
+            Self::XboxOneS1708 => ProfileIdentity {
+                vendor_id: XBOX_VENDOR_ID,
+                product_id: XBOX_ONE_S_1708_PRODUCT_ID,
+                version: XBOX_ONE_S_1708_VERSION,
+                country: XBOX_COUNTRY_CODE,
+                manufacturer: "Microsoft",
+                model: "Xbox Wireless Controller",
+                appearance: APPEARANCE_GAMEPAD,
+            },
+            Self::DualShock4 => ProfileIdentity {
+                vendor_id: SONY_VENDOR_ID,
+                product_id: DS4_V2_PRODUCT_ID,
+                version: DS4_VERSION,
+                country: 0,
+                manufacturer: "Sony Interactive Entertainment",
+                model: "Wireless Controller",
+                appearance: APPEARANCE_GAMEPAD,
+            },
+            Self::SwitchPro => ProfileIdentity {
+                vendor_id: NINTENDO_VENDOR_ID,
+                product_id: SWITCH_PRO_PRODUCT_ID,
+                version: SWITCH_PRO_VERSION,
+                country: 0,
+                manufacturer: "Nintendo",
+                model: "Pro Controller",
+                appearance: APPEARANCE_GAMEPAD,
+            },
+            Self::Generic => ProfileIdentity {
+                vendor_id: GENERIC_VENDOR_ID,
+                product_id: GENERIC_PRODUCT_ID,
+                version: GENERIC_VERSION,
+                country: 0,
+                manufacturer: "ControllerOS",
+                model: "ControllerOS Gamepad",
+                appearance: APPEARANCE_GAMEPAD,
+            },
+            Self::Composite => ProfileIdentity {
+                vendor_id: GENERIC_VENDOR_ID,
+                product_id: COMPOSITE_PRODUCT_ID,
+                version: GENERIC_VERSION,
+                country: 0,
+                manufacturer: "ControllerOS",
+                model: "ControllerOS Composite Controller",
+                // Gamepad plus mouse; no single HID appearance covers both.
+                appearance: APPEARANCE_GENERIC_HID,
+            },
         }
     }
 }
@@ -103,12 +324,266 @@ impl Default for HidProfileMode {
     }
 }
 
+/// A profile ready to present: the mode plus the descriptor and report list,
+/// which for `generic` and `composite` are generated from config.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct HidProfile {
+    mode: HidProfileMode,
+    descriptor: Cow<'static, [u8]>,
+    reports: Vec<ReportInfo>,
+    /// Gamepad layout for `generic` and `composite`.
+    generic: Option<GenericProfile>,
+    composite: CompositeProfileConfig,
+}
+
+impl HidProfile {
+    pub fn new(config: &ProfileConfig) -> Self {
+        let mode = config.mode;
+        let fixed = |descriptor: &'static [u8], reports: &[ReportInfo]| Self {
+            mode,
+            descriptor: Cow::Borrowed(descriptor),
+            reports: reports.to_vec(),
+            generic: None,
+            composite: config.composite.clone(),
+        };
+        match mode {
+            HidProfileMode::XboxOneS1708 => fixed(
+                &XBOX_ONE_S_1708_HID_REPORT_DESCRIPTOR,
+                &XBOX_ONE_S_1708_REPORTS,
+            ),
+            HidProfileMode::DualShock4 => fixed(&DS4_HID_REPORT_DESCRIPTOR, &DS4_REPORTS),
+            HidProfileMode::SwitchPro => {
+                fixed(&SWITCH_PRO_HID_REPORT_DESCRIPTOR, &SWITCH_PRO_REPORTS)
+            }
+            HidProfileMode::Generic => {
+                let generic = GenericProfile::new(&config.generic);
+                Self {
+                    mode,
+                    descriptor: Cow::Owned(generic.report_descriptor().to_vec()),
+                    reports: vec![ReportInfo {
+                        id: GENERIC_INPUT_REPORT_ID,
+                        report_type: ReportType::Input,
+                        payload_len: generic.payload_len(),
+                    }],
+                    generic: Some(generic),
+                    composite: config.composite.clone(),
+                }
+            }
+            HidProfileMode::Composite => {
+                let built = composite::build_descriptor();
+                Self {
+                    mode,
+                    reports: composite::reports(&built.fields),
+                    descriptor: Cow::Owned(built.descriptor),
+                    generic: Some(composite::gamepad()),
+                    composite: config.composite.clone(),
+                }
+            }
+        }
+    }
+
+    /// Present `descriptor` under `mode`'s identity and packing, with the
+    /// report list taken from the descriptor itself.
+    pub fn with_descriptor(
+        mode: HidProfileMode,
+        descriptor: Vec<u8>,
+    ) -> Result<Self, descriptor::DescriptorError> {
+        let reports = descriptor::ReportDescriptor::parse(&descriptor)?.report_infos();
+        Ok(Self {
+            mode,
+            descriptor: Cow::Owned(descriptor),
+            reports,
+            generic: None,
+            composite: CompositeProfileConfig::default(),
+        })
+    }
+
+    pub fn mode(&self) -> HidProfileMode {
+        self.mode
+    }
+
+    pub fn report_descriptor(&self) -> &[u8] {
+        &self.descriptor
+    }
+
+    /// Reports declared by the descriptor, in GATT characteristic order.
+    pub fn reports(&self) -> &[ReportInfo] {
+        &self.reports
+    }
+
+    pub fn report(&self, report_type: ReportType, id: u8) -> Option<ReportInfo> {
+        self.reports
+            .iter()
+            .copied()
+            .find(|r| r.report_type == report_type && r.id == id)
+    }
+
+    /// ID of the main input report.
+    pub fn input_report_id(&self) -> u8 {
+        match self.mode {
+            HidProfileMode::XboxOneS1708 => XBOX_INPUT_REPORT_ID,
+            HidProfileMode::DualShock4 => DS4_INPUT_REPORT_ID,
+            HidProfileMode::SwitchPro => SWITCH_FULL_REPORT_ID,
+            HidProfileMode::Generic | HidProfileMode::Composite => GENERIC_INPUT_REPORT_ID,
+        }
+    }
+
+    /// Main input report length, including the report ID byte.
+    pub fn input_report_len(&self) -> usize {
+        self.report(ReportType::Input, self.input_report_id())
+            .map_or(0, |r| r.payload_len + 1)
+    }
+
+    /// Pack `report` as this profile's main input report, including the
+    /// report ID byte.
+    pub fn input_report_bytes(&self, report: &InputReport) -> Vec<u8> {
+        match (self.mode, &self.generic) {
+            (HidProfileMode::DualShock4, _) => {
+                Ds4Session::default().input_report_bytes(report).to_vec()
+            }
+            (HidProfileMode::SwitchPro, _) => {
+                switch_pro::full_report_bytes(report, true, SWITCH_DEFAULT_BATTERY_PERCENT).to_vec()
+            }
+            (_, Some(gamepad)) => gamepad.input_report_bytes(report),
+            _ => report.to_bytes().to_vec(),
+        }
+    }
+
+    /// Status input report carrying `level_percent` (0..=100), for profiles
+    /// that declare one. The Xbox report scales it to Battery Strength
+    /// 0..=255. The DS4 carries the level in its input report instead; see
+    /// [`ProfileSession::battery_report`].
+    pub fn battery_report(&self, level_percent: u8) -> Option<Vec<u8>> {
+        match self.mode {
+            HidProfileMode::XboxOneS1708 => {
+                let strength = u16::from(level_percent.min(100)) * 255 / 100;
+                Some(vec![XBOX_STATUS_INPUT_REPORT_ID, strength as u8])
+            }
+            _ => None,
+        }
+    }
+
+    /// Parse an output report (including its report ID byte) into the shared
+    /// rumble fields.
+    pub fn parse_output_report(&self, data: &[u8]) -> Option<OutputReport> {
+        match self.mode {
+            HidProfileMode::XboxOneS1708 => OutputReport::parse(data),
+            HidProfileMode::DualShock4 => ds4::parse_output_report(data),
+            // HD rumble is not decoded into the shared rumble fields.
+            HidProfileMode::SwitchPro => None,
+            // Generic has no output reports; composite only has keyboard LEDs.
+            HidProfileMode::Generic | HidProfileMode::Composite => None,
+        }
+    }
+
+    /// Start the per-connection protocol state for this profile. `mac` is
+    /// the adapter address, for profiles that report it to the host.
+    pub fn new_session(&self, mac: [u8; 6]) -> ProfileSession {
+        match self.mode {
+            HidProfileMode::DualShock4 => ProfileSession::DualShock4(Ds4Session::new(mac)),
+            HidProfileMode::SwitchPro => ProfileSession::SwitchPro(SwitchProSession::new(mac)),
+            HidProfileMode::Composite => {
+                ProfileSession::Composite(CompositeSession::new(&self.composite))
+            }
+            _ => ProfileSession::Stateless(self.clone()),
+        }
+    }
+}
+
+impl From<HidProfileMode> for HidProfile {
+    /// Profile with the default config for `mode`.
+    fn from(mode: HidProfileMode) -> Self {
+        Self::new(&ProfileConfig::for_mode(mode))
+    }
+}
+
+/// Protocol state for one host connection. Most profiles are stateless; the
+/// DS4 keeps the battery level for its input reports and the address for its
+/// feature reports, the Switch Pro Controller answers subcommands and
+/// switches input report format on request, and the composite profile tracks
+/// pointer motion.
+#[derive(Debug, Clone)]
+pub enum ProfileSession {
+    Stateless(HidProfile),
+    DualShock4(Ds4Session),
+    SwitchPro(SwitchProSession),
+    Composite(CompositeSession),
+}
+
+impl ProfileSession {
+    pub fn mode(&self) -> HidProfileMode {
+        match self {
+            Self::Stateless(profile) => profile.mode(),
+            Self::DualShock4(_) => HidProfileMode::DualShock4,
+            Self::SwitchPro(_) => HidProfileMode::SwitchPro,
+            Self::Composite(_) => HidProfileMode::Composite,
+        }
+    }
+
+    /// Input reports to send for `report` this tick, in the format the host
+    /// currently expects, each including its report ID byte.
+    pub fn input_reports(&mut self, report: &InputReport) -> Vec<Vec<u8>> {
+        match self {
+            Self::Stateless(profile) => vec![profile.input_report_bytes(report)],
+            Self::DualShock4(session) => vec![session.input_report_bytes(report).to_vec()],
+            Self::SwitchPro(session) => vec![session.input_report_bytes(report)],
+            Self::Composite(session) => session.input_reports(report),
+        }
+    }
+
+    /// Status input report announcing a battery level change, if the
+    /// profile has one. The Switch Pro carries the level in its next full
+    /// report instead.
+    pub fn battery_report(&mut self, level_percent: u8) -> Option<Vec<u8>> {
+        match self {
+            Self::Stateless(profile) => profile.battery_report(level_percent),
+            Self::DualShock4(session) => Some(session.battery_report(level_percent).to_vec()),
+            Self::SwitchPro(session) => {
+                session.set_battery_level(level_percent);
+                None
+            }
+            Self::Composite(_) => None,
+        }
+    }
+
+    /// Initial value of each feature report the profile declares, including
+    /// the report ID byte. Hosts read them with GET_REPORT.
+    pub fn feature_reports(&self) -> Vec<Vec<u8>> {
+        match self {
+            Self::DualShock4(session) => DS4_FEATURE_REPORT_IDS
+                .iter()
+                .filter_map(|&id| session.feature_report(id))
+                .collect(),
+            Self::Stateless(_) | Self::SwitchPro(_) | Self::Composite(_) => Vec::new(),
+        }
+    }
+
+    /// Handle an output report (including its report ID byte). Returns an
+    /// input report to send back to the host, if the protocol calls for one.
+    pub fn handle_output_report(&mut self, data: &[u8], current: &InputReport) -> Option<Vec<u8>> {
+        match self {
+            Self::Stateless(_) | Self::DualShock4(_) | Self::Composite(_) => None,
+            Self::SwitchPro(session) => session.handle_output_report(data, current),
+        }
+    }
+}
+
 /// Default profile descriptor exported for compatibility with checkpoint-03 callers.
 pub const HID_REPORT_DESCRIPTOR: [u8; 334] = XBOX_ONE_S_1708_HID_REPORT_DESCRIPTOR;
 
 /// Input report length, including the report ID byte.
 pub const INPUT_REPORT_LEN: usize = XBOX_INPUT_REPORT_LEN;
 
+/// One trackpad contact. Coordinates span the full i16 range with X
+/// positive right and Y positive down.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct TouchPoint {
+    pub active: bool,
+    pub clicked: bool,
+    pub x: i16,
+    pub y: i16,
+}
+
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub struct InputReport {
     pub buttons: u16,
@@ -120,6 +595,19 @@ pub struct InputReport {
     pub lt: u16,
     pub rt: u16,
     pub share: u8,
+    /// `EXTRA_BUTTON_*` bits. Only profiles with spare buttons report them.
+    pub extra_buttons: u16,
+    /// Keyboard usages held by bindings (0 = none), for profiles with a
+    /// keyboard.
+    pub keys: [u8; 6],
+    /// Left and right trackpads.
+    pub touch: [TouchPoint; 2],
+    /// Angular rate (pitch, yaw, roll) at ~16 LSB per deg/s (±2000 deg/s).
+    pub gyro: [i16; 3],
+    /// Acceleration (x right, y up, z toward the player) at 16384 LSB per g.
+    pub accel: [i16; 3],
+    /// Sample time in microseconds on a wrapping monotonic clock.
+    pub timestamp_us: u32,
 }
 
 impl Default for InputReport {
@@ -134,6 +622,12 @@ impl Default for InputReport {
             lt: XBOX_TRIGGER_MIN,
             rt: XBOX_TRIGGER_MIN,
             share: 0,
+            extra_buttons: 0,
+            keys: [0; 6],
+            touch: [TouchPoint::default(); 2],
+            gyro: [0; 3],
+            accel: [0; 3],
+            timestamp_us: 0,
         }
     }
 }
@@ -213,9 +707,12 @@ fn trigger_to_wire(value: u16) -> u16 {
 
 #[cfg(test)]
 mod tests {
+    use super::descriptor::ReportDescriptor;
     use super::{
-        InputReport, OutputReport, HID_REPORT_DESCRIPTOR, INPUT_REPORT_LEN, XBOX_BUTTON_MASK,
-        XBOX_INPUT_REPORT_ID, XBOX_OUTPUT_REPORT_ID, XBOX_TRIGGER_MAX,
+        HidProfile, HidProfileMode, InputReport, OutputReport, ReportType, HID_REPORT_DESCRIPTOR,
+        INPUT_REPORT_LEN, XBOX_BUTTON_A, XBOX_BUTTON_MASK, XBOX_BUTTON_RS, XBOX_INPUT_REPORT_ID,
+        XBOX_OUTPUT_REPORT_ID, XBOX_OUTPUT_REPORT_LEN, XBOX_STATUS_INPUT_REPORT_ID,
+        XBOX_TRIGGER_MAX,
     };
 
     #[test]
@@ -248,6 +745,7 @@ mod tests {
             lt: u16::MAX,
             rt: u16::MAX,
             share: 0xff,
+            ..InputReport::default()
         };
 
         let bytes = report.to_bytes();
@@ -279,4 +777,162 @@ mod tests {
         assert_eq!(parsed.strong_motor_magnitude, 5);
         assert_eq!(parsed.loop_count, 8);
     }
+
+    #[test]
+    fn profile_report_lists_match_input_lengths() {
+        for mode in HidProfileMode::ALL {
+            let profile = HidProfile::from(mode);
+            let input = profile
+                .report(ReportType::Input, profile.input_report_id())
+                .expect("main input report listed");
+            assert_eq!(input.payload_len + 1, profile.input_report_len());
+            assert_eq!(
+                profile.input_report_bytes(&InputReport::default()).len(),
+                profile.input_report_len()
+            );
+            assert!(profile
+                .report_descriptor()
+                .windows(2)
+                .any(|w| w == [0x85, profile.input_report_id()]));
+        }
+    }
+
+    #[test]
+    fn profile_report_lists_match_parsed_descriptors() {
+        for mode in HidProfileMode::ALL {
+            let profile = HidProfile::from(mode);
+            let parsed = ReportDescriptor::parse(profile.report_descriptor())
+                .unwrap_or_else(|e| panic!("{}: {e}", mode.as_str()));
+            let mut declared = parsed.report_infos();
+            let mut listed = profile.reports().to_vec();
+            let key = |r: &super::ReportInfo| (r.id, r.report_type.as_str());
+            declared.sort_by_key(key);
+            listed.sort_by_key(key);
+            assert_eq!(declared, listed, "{}", mode.as_str());
+
+            for field in parsed.reports.iter().flat_map(|r| &r.fields) {
+                // Vendor fields are copied from real devices as-is; the DS4's
+                // 6-bit report counter declares a 0..127 range.
+                let vendor = field.usages.iter().all(|u| u >> 16 >= 0xff00);
+                if field.is_constant() || vendor {
+                    continue;
+                }
+                let span = i64::from(field.logical_max) - i64::from(field.logical_min);
+                assert!(
+                    span < 1 << field.bit_size,
+                    "{}: logical range {}..{} does not fit {} bits",
+                    mode.as_str(),
+                    field.logical_min,
+                    field.logical_max,
+                    field.bit_size
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn xbox_input_packing_matches_descriptor() {
+        let parsed = ReportDescriptor::parse(&HID_REPORT_DESCRIPTOR).unwrap();
+        let layout = parsed
+            .report(ReportType::Input, XBOX_INPUT_REPORT_ID)
+            .unwrap();
+        let report = InputReport {
+            buttons: XBOX_BUTTON_A | XBOX_BUTTON_RS,
+            hat: 3,
+            lx: -32768,
+            ly: 1234,
+            rx: 32767,
+            ry: -1,
+            lt: 1023,
+            rt: 17,
+            share: 1,
+            ..InputReport::default()
+        };
+        let bytes = report.to_bytes();
+        let payload = &bytes[1..];
+        assert_eq!(payload.len(), layout.payload_len());
+
+        let value = |usage: u32, index: u32| {
+            let field = layout.field(usage).expect("usage declared");
+            let element = field.usages.iter().position(|u| *u == usage).unwrap() as u32;
+            let v = field.value(payload, element + index).unwrap();
+            assert!(
+                (field.logical_min..=field.logical_max).contains(&v),
+                "usage {usage:08x} value {v} outside logical range"
+            );
+            v
+        };
+        assert_eq!(value(0x0001_0030, 0), 0);
+        assert_eq!(value(0x0001_0031, 0), 0x8000 + 1234);
+        assert_eq!(value(0x0001_0032, 0), 0xffff);
+        assert_eq!(value(0x0001_0035, 0), 0x7fff);
+        assert_eq!(value(0x0002_00c5, 0), 1023);
+        assert_eq!(value(0x0002_00c4, 0), 17);
+        assert_eq!(value(0x0001_0039, 0), 3);
+        assert_eq!(value(0x0009_0001, 0), 1);
+        assert_eq!(value(0x0009_0002, 0), 0);
+        assert_eq!(value(0x0009_000f, 0), 1);
+        assert_eq!(value(0x000c_0224, 0), 1);
+    }
+
+    #[test]
+    fn xbox_output_parsing_matches_descriptor() {
+        let parsed = ReportDescriptor::parse(&HID_REPORT_DESCRIPTOR).unwrap();
+        let layout = parsed
+            .report(ReportType::Output, XBOX_OUTPUT_REPORT_ID)
+            .unwrap();
+        assert_eq!(layout.payload_len() + 1, XBOX_OUTPUT_REPORT_LEN);
+
+        let raw = [XBOX_OUTPUT_REPORT_ID, 0x0f, 10, 20, 30, 40, 50, 60, 70];
+        let parsed_report = OutputReport::parse(&raw).unwrap();
+        let payload = &raw[1..];
+        let field = |usage: u32| layout.field(usage).expect("usage declared");
+
+        let enable = field(0x000f_0097);
+        assert_eq!(enable.value(payload, 0), Some(0x0f));
+        assert_eq!(parsed_report.dc_enable_actuators & 0x0f, 0x0f);
+
+        let magnitude = field(0x000f_0070);
+        assert_eq!(magnitude.count, 4);
+        let magnitudes = [
+            parsed_report.left_trigger_magnitude,
+            parsed_report.right_trigger_magnitude,
+            parsed_report.weak_motor_magnitude,
+            parsed_report.strong_motor_magnitude,
+        ];
+        for (i, m) in magnitudes.iter().enumerate() {
+            assert_eq!(magnitude.value(payload, i as u32), Some(i32::from(*m)));
+        }
+        assert_eq!(
+            field(0x000f_0050).value(payload, 0),
+            Some(i32::from(parsed_report.duration))
+        );
+        assert_eq!(
+            field(0x000f_00a7).value(payload, 0),
+            Some(i32::from(parsed_report.start_delay))
+        );
+        assert_eq!(
+            field(0x000f_007c).value(payload, 0),
+            Some(i32::from(parsed_report.loop_count))
+        );
+    }
+
+    #[test]
+    fn xbox_status_report_scales_battery_level() {
+        let profile = HidProfile::from(HidProfileMode::XboxOneS1708);
+        let info = profile
+            .report(ReportType::Input, XBOX_STATUS_INPUT_REPORT_ID)
+            .expect("status report");
+        let report = profile
+            .battery_report(100)
+            .expect("xbox has a status report");
+        assert_eq!(report.len(), info.payload_len + 1);
+        assert_eq!(report, [XBOX_STATUS_INPUT_REPORT_ID, 0xff]);
+        assert_eq!(profile.battery_report(50).unwrap()[1], 127);
+        assert_eq!(profile.battery_report(0).unwrap()[1], 0);
+        assert_eq!(profile.battery_report(200).unwrap()[1], 0xff);
+        assert!(HidProfile::from(HidProfileMode::DualShock4)
+            .battery_report(50)
+            .is_none());
+    }
 }
@@ -0,0 +1,484 @@
+//! Typed HID report descriptor builder.
+//!
+//! A profile declares its usage pages, collections, report IDs and fields;
+//! the builder emits the descriptor bytes and a [`FieldMap`] with every named
+//! field's report, bit offset and width, so packing code writes fields by
+//! name instead of by hand-counted byte index.
+
+use super::ReportType;
+
+pub const PAGE_GENERIC_DESKTOP: u16 = 0x01;
+pub const PAGE_SIMULATION: u16 = 0x02;
+pub const PAGE_KEYBOARD: u16 = 0x07;
+pub const PAGE_LED: u16 = 0x08;
+pub const PAGE_BUTTON: u16 = 0x09;
+pub const PAGE_CONSUMER: u16 = 0x0c;
+pub const PAGE_PID: u16 = 0x0f;
+
+pub const USAGE_POINTER: u16 = 0x01;
+pub const USAGE_MOUSE: u16 = 0x02;
+pub const USAGE_GAMEPAD: u16 = 0x05;
+pub const USAGE_KEYBOARD: u16 = 0x06;
+pub const USAGE_X: u16 = 0x30;
+pub const USAGE_Y: u16 = 0x31;
+pub const USAGE_WHEEL: u16 = 0x38;
+pub const USAGE_HAT_SWITCH: u16 = 0x39;
+
+/// Main item data bits.
+pub const MAIN_CONSTANT: u8 = 0x01;
+pub const MAIN_VARIABLE: u8 = 0x02;
+pub const MAIN_RELATIVE: u8 = 0x04;
+pub const MAIN_NULL_STATE: u8 = 0x40;
+
+/// Unit (English Rotation: degrees), as used by hat switches.
+pub const UNIT_DEGREES: u32 = 0x14;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Collection {
+    Physical = 0x00,
+    Application = 0x01,
+    Logical = 0x02,
+}
+
+/// Size, count, logical range and flags of one main item.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FieldSpec {
+    pub bits: u32,
+    pub count: u32,
+    pub logical_min: i32,
+    pub logical_max: i32,
+    pub flags: u8,
+}
+
+impl FieldSpec {
+    /// One absolute variable element.
+    pub const fn variable(bits: u32, logical_min: i32, logical_max: i32) -> Self {
+        Self {
+            bits,
+            count: 1,
+            logical_min,
+            logical_max,
+            flags: MAIN_VARIABLE,
+        }
+    }
+
+    /// `count` one-bit on/off elements.
+    pub const fn buttons(count: u32) -> Self {
+        Self::variable(1, 0, 1).count(count)
+    }
+
+    /// `count` array slots, each holding one usage index.
+    pub const fn array(bits: u32, count: u32, logical_min: i32, logical_max: i32) -> Self {
+        Self {
+            bits,
+            count,
+            logical_min,
+            logical_max,
+            flags: 0,
+        }
+    }
+
+    pub const fn count(mut self, count: u32) -> Self {
+        self.count = count;
+        self
+    }
+
+    pub const fn relative(mut self) -> Self {
+        self.flags |= MAIN_RELATIVE;
+        self
+    }
+
+    pub const fn null_state(mut self) -> Self {
+        self.flags |= MAIN_NULL_STATE;
+        self
+    }
+}
+
+/// Where a named field lives.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FieldLayout {
+    pub name: String,
+    pub kind: ReportType,
+    /// Report ID, or 0 when the descriptor declares none.
+    pub report_id: u8,
+    /// Bit offset into the payload (after the report ID byte).
+    pub bit_offset: u32,
+    pub bit_size: u32,
+    pub count: u32,
+    pub logical_min: i32,
+    pub logical_max: i32,
+}
+
+impl FieldLayout {
+    /// Write element `index` into `report`, which starts with the report ID
+    /// byte when the field has one. `value` is truncated to the field width,
+    /// so values outside the logical range (null states) pass through.
+    pub fn write(&self, report: &mut [u8], index: u32, value: i32) {
+        if index >= self.count {
+            return;
+        }
+        let header = if self.report_id == 0 { 0 } else { 8 };
+        let start = header + self.bit_offset + index * self.bit_size;
+        let value = value as u32;
+        for bit in 0..self.bit_size.min(32) {
+            let pos = start + bit;
+            let Some(byte) = report.get_mut((pos / 8) as usize) else {
+                return;
+            };
+            let mask = 1 << (pos % 8);
+            if value >> bit & 1 != 0 {
+                *byte |= mask;
+            } else {
+                *byte &= !mask;
+            }
+        }
+    }
+}
+
+/// Named fields and report sizes produced by [`DescriptorBuilder::build`].
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct FieldMap {
+    fields: Vec<FieldLayout>,
+    /// (kind, report ID, bit length) of every report, padding included.
+    reports: Vec<(ReportType, u8, u32)>,
+}
+
+impl FieldMap {
+    pub fn get(&self, name: &str) -> Option<&FieldLayout> {
+        self.fields.iter().find(|f| f.name == name)
+    }
+
+    pub fn fields(&self) -> &[FieldLayout] {
+        &self.fields
+    }
+
+    /// Report length without the report ID byte.
+    pub fn payload_len(&self, report_type: ReportType, id: u8) -> usize {
+        self.reports
+            .iter()
+            .find(|(k, i, _)| *k == report_type && *i == id)
+            .map_or(0, |(_, _, bits)| bits.div_ceil(8) as usize)
+    }
+
+    /// Zeroed report buffer, starting with the report ID byte if `id != 0`.
+    pub fn new_report(&self, report_type: ReportType, id: u8) -> Vec<u8> {
+        let mut out = vec![0; usize::from(id != 0) + self.payload_len(report_type, id)];
+        if id != 0 {
+            out[0] = id;
+        }
+        out
+    }
+
+    /// Write element `index` of field `name`. Fields that were not declared
+    /// are skipped, so config-driven layouts can leave controls out.
+    pub fn set(&self, report: &mut [u8], name: &str, index: u32, value: i32) {
+        if let Some(field) = self.get(name) {
+            field.write(report, index, value);
+        }
+    }
+}
+
+/// Descriptor bytes with their field map.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BuiltDescriptor {
+    pub descriptor: Vec<u8>,
+    pub fields: FieldMap,
+}
+
+/// Global items last emitted. `None` means not emitted yet, so a builder's
+/// output is correct even when appended to another descriptor.
+#[derive(Debug, Clone, Copy, Default)]
+struct Globals {
+    usage_page: Option<u16>,
+    logical: Option<(i32, i32)>,
+    physical: Option<(i32, i32)>,
+    unit: Option<u32>,
+    report_size: Option<u32>,
+    report_count: Option<u32>,
+}
+
+#[derive(Debug, Default)]
+pub struct DescriptorBuilder {
+    bytes: Vec<u8>,
+    globals: Globals,
+    report_id: u8,
+    depth: usize,
+    fields: FieldMap,
+}
+
+impl DescriptorBuilder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn usage_page(&mut self, page: u16) -> &mut Self {
+        if self.globals.usage_page != Some(page) {
+            self.globals.usage_page = Some(page);
+            self.unsigned_item(0x04, u32::from(page));
+        }
+        self
+    }
+
+    /// Usage on the current usage page.
+    pub fn usage(&mut self, id: u16) -> &mut Self {
+        self.unsigned_item(0x08, u32::from(id))
+    }
+
+    pub fn usage_range(&mut self, min: u16, max: u16) -> &mut Self {
+        self.unsigned_item(0x18, u32::from(min));
+        self.unsigned_item(0x28, u32::from(max))
+    }
+
+    pub fn begin_collection(&mut self, kind: Collection) -> &mut Self {
+        self.depth += 1;
+        self.bytes.extend_from_slice(&[0xa1, kind as u8]);
+        self
+    }
+
+    pub fn end_collection(&mut self) -> &mut Self {
+        assert!(self.depth > 0, "end_collection without begin_collection");
+        self.depth -= 1;
+        self.bytes.push(0xc0);
+        self
+    }
+
+    /// Report ID for the following main items. IDs are always emitted, since
+    /// each top-level collection usually starts a new one.
+    pub fn report_id(&mut self, id: u8) -> &mut Self {
+        assert!(id != 0, "report ID 0 is reserved");
+        self.report_id = id;
+        self.unsigned_item(0x84, u32::from(id))
+    }
+
+    /// Physical range and unit for the following main items. Call with
+    /// `(0, 0)` and unit 0 to clear them again.
+    pub fn physical(&mut self, min: i32, max: i32, unit: u32) -> &mut Self {
+        if self.globals.physical != Some((min, max)) {
+            self.globals.physical = Some((min, max));
+            self.signed_item(0x34, min);
+            self.signed_item(0x44, max);
+        }
+        if self.globals.unit != Some(unit) {
+            self.globals.unit = Some(unit);
+            self.unsigned_item(0x64, unit);
+        }
+        self
+    }
+
+    pub fn input(&mut self, name: &str, spec: FieldSpec) -> &mut Self {
+        self.main(ReportType::Input, Some(name), spec)
+    }
+
+    pub fn output(&mut self, name: &str, spec: FieldSpec) -> &mut Self {
+        self.main(ReportType::Output, Some(name), spec)
+    }
+
+    pub fn feature(&mut self, name: &str, spec: FieldSpec) -> &mut Self {
+        self.main(ReportType::Feature, Some(name), spec)
+    }
+
+    /// Constant bits in the current report.
+    pub fn padding(&mut self, kind: ReportType, bits: u32) -> &mut Self {
+        if bits == 0 {
+            return self;
+        }
+        let spec = FieldSpec {
+            bits: 1,
+            count: bits,
+            logical_min: 0,
+            logical_max: 1,
+            flags: MAIN_CONSTANT,
+        };
+        self.main(kind, None, spec)
+    }
+
+    /// Constant bits up to the next byte boundary of the current report.
+    pub fn pad_to_byte(&mut self, kind: ReportType) -> &mut Self {
+        let bits = self.report_bits(kind, self.report_id);
+        self.padding(kind, (8 - bits % 8) % 8)
+    }
+
+    pub fn build(&self) -> BuiltDescriptor {
+        assert_eq!(self.depth, 0, "unclosed collection");
+        BuiltDescriptor {
+            descriptor: self.bytes.clone(),
+            fields: self.fields.clone(),
+        }
+    }
+
+    fn main(&mut self, kind: ReportType, name: Option<&str>, spec: FieldSpec) -> &mut Self {
+        assert!(spec.bits > 0 && spec.count > 0, "empty field");
+        if self.globals.logical != Some((spec.logical_min, spec.logical_max)) {
+            self.globals.logical = Some((spec.logical_min, spec.logical_max));
+            self.signed_item(0x14, spec.logical_min);
+            self.signed_item(0x24, spec.logical_max);
+        }
+        if self.globals.report_size != Some(spec.bits) {
+            self.globals.report_size = Some(spec.bits);
+            self.unsigned_item(0x74, spec.bits);
+        }
+        if self.globals.report_count != Some(spec.count) {
+            self.globals.report_count = Some(spec.count);
+            self.unsigned_item(0x94, spec.count);
+        }
+        let tag = match kind {
+            ReportType::Input => 0x80,
+            ReportType::Output => 0x90,
+            ReportType::Feature => 0xb0,
+        };
+        self.unsigned_item(tag, u32::from(spec.flags));
+
+        let id = self.report_id;
+        let bit_offset = self.report_bits(kind, id);
+        let len = bit_offset + spec.bits * spec.count;
+        match self
+            .fields
+            .reports
+            .iter_mut()
+            .find(|(k, i, _)| *k == kind && *i == id)
+        {
+            Some(report) => report.2 = len,
+            None => self.fields.reports.push((kind, id, len)),
+        }
+        if let Some(name) = name {
+            assert!(self.fields.get(name).is_none(), "duplicate field {name}");
+            self.fields.fields.push(FieldLayout {
+                name: name.to_string(),
+                kind,
+                report_id: id,
+                bit_offset,
+                bit_size: spec.bits,
+                count: spec.count,
+                logical_min: spec.logical_min,
+                logical_max: spec.logical_max,
+            });
+        }
+        self
+    }
+
+    fn report_bits(&self, kind: ReportType, id: u8) -> u32 {
+        self.fields
+            .reports
+            .iter()
+            .find(|(k, i, _)| *k == kind && *i == id)
+            .map_or(0, |(_, _, bits)| *bits)
+    }
+
+    /// Short item with the smallest unsigned encoding.
+    fn unsigned_item(&mut self, tag: u8, value: u32) -> &mut Self {
+        let bytes = value.to_le_bytes();
+        let size = if value <= 0xff {
+            1
+        } else if value <= 0xffff {
+            2
+        } else {
+            4
+        };
+        self.push_item(tag, &bytes[..size])
+    }
+
+    /// Short item with the smallest encoding that keeps the sign.
+    fn signed_item(&mut self, tag: u8, value: i32) -> &mut Self {
+        let bytes = value.to_le_bytes();
+        let size = if i8::try_from(value).is_ok() {
+            1
+        } else if i16::try_from(value).is_ok() {
+            2
+        } else {
+            4
+        };
+        self.push_item(tag, &bytes[..size])
+    }
+
+    fn push_item(&mut self, tag: u8, data: &[u8]) -> &mut Self {
+        let size_bits = match data.len() {
+            1 => 1,
+            2 => 2,
+            _ => 3,
+        };
+        self.bytes.push(tag | size_bits);
+        self.bytes.extend_from_slice(data);
+        self
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::hid::descriptor::ReportDescriptor;
+
+    fn sample() -> BuiltDescriptor {
+        let mut b = DescriptorBuilder::new();
+        b.usage_page(PAGE_GENERIC_DESKTOP)
+            .usage(USAGE_GAMEPAD)
+            .begin_collection(Collection::Application)
+            .report_id(3)
+            .usage_page(PAGE_BUTTON)
+            .usage_range(1, 5)
+            .input("buttons", FieldSpec::buttons(5))
+            .pad_to_byte(ReportType::Input)
+            .usage_page(PAGE_GENERIC_DESKTOP)
+            .usage(USAGE_X)
+            .usage(USAGE_Y)
+            .input("stick", FieldSpec::variable(16, 0, 0xffff).count(2))
+            .usage(USAGE_WHEEL)
+            .input("wheel", FieldSpec::variable(8, -127, 127).relative())
+            .report_id(4)
+            .usage_page(PAGE_LED)
+            .usage_range(1, 3)
+            .output("leds", FieldSpec::buttons(3))
+            .end_collection();
+        b.build()
+    }
+
+    #[test]
+    fn field_map_matches_parsed_descriptor() {
+        let built = sample();
+        let parsed = ReportDescriptor::parse(&built.descriptor).unwrap();
+        for layout in built.fields.fields() {
+            let report = parsed.report(layout.kind, layout.report_id).unwrap();
+            let field = report
+                .fields
+                .iter()
+                .find(|f| f.bit_offset == layout.bit_offset && !f.is_constant())
+                .unwrap_or_else(|| panic!("{} not found", layout.name));
+            assert_eq!(field.bit_size, layout.bit_size);
+            assert_eq!(field.count, layout.count);
+            assert_eq!(field.logical_min, layout.logical_min);
+            assert_eq!(field.logical_max, layout.logical_max);
+        }
+        assert_eq!(built.fields.payload_len(ReportType::Input, 3), 6);
+        assert_eq!(built.fields.payload_len(ReportType::Output, 4), 1);
+    }
+
+    #[test]
+    fn writes_fields_by_name() {
+        let built = sample();
+        let fields = &built.fields;
+        let mut report = fields.new_report(ReportType::Input, 3);
+        fields.set(&mut report, "buttons", 4, 1);
+        fields.set(&mut report, "stick", 1, 0xabcd);
+        fields.set(&mut report, "wheel", 0, -2);
+        fields.set(&mut report, "missing", 0, 1);
+        assert_eq!(report, [3, 0x10, 0x00, 0x00, 0xcd, 0xab, 0xfe]);
+    }
+
+    #[test]
+    fn items_use_shortest_encoding_and_skip_repeated_globals() {
+        let mut b = DescriptorBuilder::new();
+        b.report_id(1)
+            .input("a", FieldSpec::variable(16, 0, 0xffff))
+            .input("b", FieldSpec::variable(16, 0, 0xffff))
+            .input("c", FieldSpec::variable(8, -1, 1000));
+        assert_eq!(
+            b.build().descriptor,
+            [
+                0x85, 0x01, // Report ID
+                0x15, 0x00, 0x27, 0xff, 0xff, 0x00, 0x00, // Logical 0..65535
+                0x75, 0x10, 0x95, 0x01, 0x81, 0x02, // a
+                0x81, 0x02, // b reuses every global
+                0x15, 0xff, 0x26, 0xe8, 0x03, 0x75, 0x08, 0x81, 0x02, // c
+            ]
+        );
+    }
+}
@@ -0,0 +1,445 @@
+//! Composite gamepad + mouse + keyboard profile.
+//!
+//! The gamepad is the default generic layout (report `0x01`). The right
+//! trackpad drives a relative mouse (report `0x02`): pad motion moves the
+//! pointer, the left pad scrolls and pad clicks are mouse buttons. Keyboard
+//! keys held by bindings (`InputReport::keys`) go out as a boot-style
+//! keyboard report (`0x03`).
+
+use serde::Deserialize;
+
+use super::builder::{
+    BuiltDescriptor, Collection, FieldMap, FieldSpec, PAGE_BUTTON, PAGE_GENERIC_DESKTOP,
+    PAGE_KEYBOARD, PAGE_LED, USAGE_KEYBOARD, USAGE_MOUSE, USAGE_POINTER, USAGE_WHEEL, USAGE_X,
+    USAGE_Y,
+};
+use super::generic::{self, GenericProfile, GenericProfileConfig};
+use super::{InputReport, ReportInfo, ReportType};
+
+pub const COMPOSITE_PRODUCT_ID: u16 = 0x0002;
+
+pub const COMPOSITE_GAMEPAD_REPORT_ID: u8 = 0x01;
+pub const COMPOSITE_MOUSE_REPORT_ID: u8 = 0x02;
+pub const COMPOSITE_KEYBOARD_REPORT_ID: u8 = 0x03;
+
+pub const COMPOSITE_MOUSE_PAYLOAD_LEN: usize = 6;
+pub const COMPOSITE_KEYBOARD_PAYLOAD_LEN: usize = 8;
+pub const COMPOSITE_KEYBOARD_OUTPUT_PAYLOAD_LEN: usize = 1;
+
+/// Gamepad buttons up to QAM. Pad clicks are mouse buttons in this profile.
+const COMPOSITE_GAMEPAD_BUTTONS: u8 = 17;
+
+/// Pad units per mouse count at 100% pointer speed. A full pad swipe moves
+/// about 1365 counts.
+const PAD_UNITS_PER_COUNT: i32 = 48;
+/// Pad units per wheel detent at 100% scroll speed.
+const PAD_UNITS_PER_DETENT: i32 = 2048;
+
+const MOUSE_BUTTON_LEFT: u8 = 0x01;
+const MOUSE_BUTTON_RIGHT: u8 = 0x02;
+
+const KEY_USAGE_LEFT_CTRL: u8 = 0xe0;
+const KEY_USAGE_RIGHT_META: u8 = 0xe7;
+/// Highest key usage in the keyboard's key array.
+const KEY_USAGE_MAX: u8 = 0x65;
+
+/// `[profile.composite]` section of `hid.toml`.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(default)]
+pub struct CompositeProfileConfig {
+    /// Pointer speed in percent.
+    pub pointer_speed: u16,
+    /// Scroll speed in percent.
+    pub scroll_speed: u16,
+}
+
+impl Default for CompositeProfileConfig {
+    fn default() -> Self {
+        Self {
+            pointer_speed: 100,
+            scroll_speed: 100,
+        }
+    }
+}
+
+impl CompositeProfileConfig {
+    pub fn validate(&self) -> Result<(), String> {
+        if self.pointer_speed == 0 || self.scroll_speed == 0 {
+            return Err("profile.composite pointer_speed and scroll_speed must be > 0".to_string());
+        }
+        Ok(())
+    }
+}
+
+fn gamepad_config() -> GenericProfileConfig {
+    GenericProfileConfig {
+        buttons: COMPOSITE_GAMEPAD_BUTTONS,
+        ..GenericProfileConfig::default()
+    }
+}
+
+pub(super) fn gamepad() -> GenericProfile {
+    GenericProfile::new(&gamepad_config())
+}
+
+/// Gamepad, mouse and keyboard collections with their field map.
+pub(super) fn build_descriptor() -> BuiltDescriptor {
+    let mut b = generic::build_descriptor(&gamepad_config());
+
+    b.usage_page(PAGE_GENERIC_DESKTOP)
+        .usage(USAGE_MOUSE)
+        .begin_collection(Collection::Application)
+        .report_id(COMPOSITE_MOUSE_REPORT_ID)
+        .usage(USAGE_POINTER)
+        .begin_collection(Collection::Physical)
+        .usage_page(PAGE_BUTTON)
+        .usage_range(1, 3)
+        .input("mouse_buttons", FieldSpec::buttons(3))
+        .pad_to_byte(ReportType::Input)
+        .usage_page(PAGE_GENERIC_DESKTOP)
+        .usage(USAGE_X)
+        .usage(USAGE_Y)
+        .input(
+            "pointer",
+            FieldSpec::variable(16, -32767, 32767).count(2).relative(),
+        )
+        .usage(USAGE_WHEEL)
+        .input("wheel", FieldSpec::variable(8, -127, 127).relative())
+        .end_collection()
+        .end_collection();
+
+    b.usage_page(PAGE_GENERIC_DESKTOP)
+        .usage(USAGE_KEYBOARD)
+        .begin_collection(Collection::Application)
+        .report_id(COMPOSITE_KEYBOARD_REPORT_ID)
+        .usage_page(PAGE_KEYBOARD)
+        .usage_range(
+            u16::from(KEY_USAGE_LEFT_CTRL),
+            u16::from(KEY_USAGE_RIGHT_META),
+        )
+        .input("modifiers", FieldSpec::buttons(8))
+        .padding(ReportType::Input, 8)
+        .usage_range(0, u16::from(KEY_USAGE_MAX))
+        .input("keys", FieldSpec::array(8, 6, 0, i32::from(KEY_USAGE_MAX)))
+        .usage_page(PAGE_LED)
+        .usage_range(1, 5)
+        .output("leds", FieldSpec::buttons(5))
+        .pad_to_byte(ReportType::Output)
+        .end_collection();
+
+    b.build()
+}
+
+pub(super) fn reports(fields: &FieldMap) -> Vec<ReportInfo> {
+    [
+        (COMPOSITE_GAMEPAD_REPORT_ID, ReportType::Input),
+        (COMPOSITE_MOUSE_REPORT_ID, ReportType::Input),
+        (COMPOSITE_KEYBOARD_REPORT_ID, ReportType::Input),
+        (COMPOSITE_KEYBOARD_REPORT_ID, ReportType::Output),
+    ]
+    .into_iter()
+    .map(|(id, report_type)| ReportInfo {
+        id,
+        report_type,
+        payload_len: fields.payload_len(report_type, id),
+    })
+    .collect()
+}
+
+/// Pointer tracking for one host connection.
+#[derive(Debug, Clone)]
+pub struct CompositeSession {
+    gamepad: GenericProfile,
+    fields: FieldMap,
+    config: CompositeProfileConfig,
+    /// Last right/left pad positions while touched.
+    last_pointer: Option<(i16, i16)>,
+    last_scroll: Option<i16>,
+    /// Sub-count motion carried to the next report, scaled by 100.
+    residual: (i32, i32, i32),
+    last_mouse_buttons: u8,
+    last_keyboard: Vec<u8>,
+}
+
+impl CompositeSession {
+    pub fn new(config: &CompositeProfileConfig) -> Self {
+        let fields = build_descriptor().fields;
+        let last_keyboard = fields.new_report(ReportType::Input, COMPOSITE_KEYBOARD_REPORT_ID);
+        Self {
+            gamepad: gamepad(),
+            fields,
+            config: config.clone(),
+            last_pointer: None,
+            last_scroll: None,
+            residual: (0, 0, 0),
+            last_mouse_buttons: 0,
+            last_keyboard,
+        }
+    }
+
+    /// The gamepad report every tick, then mouse and keyboard reports when
+    /// they carry motion or a state change. Each includes its report ID.
+    pub fn input_reports(&mut self, report: &InputReport) -> Vec<Vec<u8>> {
+        let mut out = vec![self.gamepad.input_report_bytes(report)];
+
+        let (dx, dy) = self.pointer_motion(report);
+        let wheel = self.scroll_motion(report);
+        let mut buttons = 0;
+        if report.touch[1].clicked {
+            buttons |= MOUSE_BUTTON_LEFT;
+        }
+        if report.touch[0].clicked {
+            buttons |= MOUSE_BUTTON_RIGHT;
+        }
+        if dx != 0 || dy != 0 || wheel != 0 || buttons != self.last_mouse_buttons {
+            self.last_mouse_buttons = buttons;
+            let fields = &self.fields;
+            let mut mouse = fields.new_report(ReportType::Input, COMPOSITE_MOUSE_REPORT_ID);
+            for i in 0..3 {
+                fields.set(&mut mouse, "mouse_buttons", i, i32::from(buttons >> i & 1));
+            }
+            fields.set(&mut mouse, "pointer", 0, i32::from(dx));
+            fields.set(&mut mouse, "pointer", 1, i32::from(dy));
+            fields.set(&mut mouse, "wheel", 0, i32::from(wheel));
+            out.push(mouse);
+        }
+
+        let keyboard = self.keyboard_report(&report.keys);
+        if keyboard != self.last_keyboard {
+            self.last_keyboard = keyboard.clone();
+            out.push(keyboard);
+        }
+        out
+    }
+
+    fn pointer_motion(&mut self, report: &InputReport) -> (i16, i16) {
+        let pad = report.touch[1];
+        let current = pad.active.then_some((pad.x, pad.y));
+        let (dx, dy) = match (self.last_pointer, current) {
+            (Some((lx, ly)), Some((x, y))) => {
+                (i32::from(x) - i32::from(lx), i32::from(y) - i32::from(ly))
+            }
+            _ => {
+                self.residual.0 = 0;
+                self.residual.1 = 0;
+                (0, 0)
+            }
+        };
+        self.last_pointer = current;
+        let speed = i32::from(self.config.pointer_speed);
+        let x = scale(dx * speed, PAD_UNITS_PER_COUNT * 100, &mut self.residual.0);
+        let y = scale(dy * speed, PAD_UNITS_PER_COUNT * 100, &mut self.residual.1);
+        (clamp_i16(x), clamp_i16(y))
+    }
+
+    fn scroll_motion(&mut self, report: &InputReport) -> i8 {
+        let pad = report.touch[0];
+        let current = pad.active.then_some(pad.y);
+        let dy = match (self.last_scroll, current) {
+            (Some(last), Some(y)) => i32::from(y) - i32::from(last),
+            _ => {
+                self.residual.2 = 0;
+                0
+            }
+        };
+        self.last_scroll = current;
+        // Swiping up (negative y) scrolls up (positive wheel).
+        let speed = i32::from(self.config.scroll_speed);
+        let wheel = scale(
+            -dy * speed,
+            PAD_UNITS_PER_DETENT * 100,
+            &mut self.residual.2,
+        );
+        wheel.clamp(-127, 127) as i8
+    }
+
+    /// Modifier bits, then up to six key usages.
+    fn keyboard_report(&self, keys: &[u8; 6]) -> Vec<u8> {
+        let fields = &self.fields;
+        let mut out = fields.new_report(ReportType::Input, COMPOSITE_KEYBOARD_REPORT_ID);
+        let mut slot = 0;
+        for &usage in keys.iter().filter(|&&k| k != 0) {
+            if (KEY_USAGE_LEFT_CTRL..=KEY_USAGE_RIGHT_META).contains(&usage) {
+                let bit = u32::from(usage - KEY_USAGE_LEFT_CTRL);
+                fields.set(&mut out, "modifiers", bit, 1);
+            } else if usage <= KEY_USAGE_MAX {
+                fields.set(&mut out, "keys", slot, i32::from(usage));
+                slot += 1;
+            }
+        }
+        out
+    }
+}
+
+/// Divide `value` by `divisor`, carrying the remainder in `residual`.
+fn scale(value: i32, divisor: i32, residual: &mut i32) -> i32 {
+    let total = value + *residual;
+    let out = total / divisor;
+    *residual = total - out * divisor;
+    out
+}
+
+fn clamp_i16(value: i32) -> i16 {
+    value.clamp(-i32::from(i16::MAX), i32::from(i16::MAX)) as i16
+}
+
+/// HID keyboard usage for a key name as used in mapping configs (after the
+/// `key_` prefix), e.g. `"a"`, `"enter"`, `"f5"`, `"left_ctrl"`.
+pub fn keyboard_usage(name: &str) -> Option<u8> {
+    if let [c] = name.as_bytes() {
+        return match c {
+            b'a'..=b'z' => Some(0x04 + (c - b'a')),
+            b'1'..=b'9' => Some(0x1e + (c - b'1')),
+            b'0' => Some(0x27),
+            _ => None,
+        };
+    }
+    if let Some(n) = name.strip_prefix('f').and_then(|n| n.parse::<u8>().ok()) {
+        return (1..=12).contains(&n).then(|| 0x3a + n - 1);
+    }
+    let usage = match name {
+        "enter" => 0x28,
+        "esc" => 0x29,
+        "backspace" => 0x2a,
+        "tab" => 0x2b,
+        "space" => 0x2c,
+        "minus" => 0x2d,
+        "equal" => 0x2e,
+        "print_screen" => 0x46,
+        "pause" => 0x48,
+        "insert" => 0x49,
+        "home" => 0x4a,
+        "page_up" => 0x4b,
+        "delete" => 0x4c,
+        "end" => 0x4d,
+        "page_down" => 0x4e,
+        "right" => 0x4f,
+        "left" => 0x50,
+        "down" => 0x51,
+        "up" => 0x52,
+        "menu" => 0x65,
+        "left_ctrl" => 0xe0,
+        "left_shift" => 0xe1,
+        "left_alt" => 0xe2,
+        "left_meta" => 0xe3,
+        "right_ctrl" => 0xe4,
+        "right_shift" => 0xe5,
+        "right_alt" => 0xe6,
+        "right_meta" => 0xe7,
+        _ => return None,
+    };
+    Some(usage)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::hid::descriptor::ReportDescriptor;
+    use crate::hid::TouchPoint;
+
+    fn touching(x: i16, y: i16) -> InputReport {
+        let mut report = InputReport::default();
+        report.touch[1] = TouchPoint {
+            active: true,
+            clicked: false,
+            x,
+            y,
+        };
+        report
+    }
+
+    #[test]
+    fn right_pad_motion_becomes_relative_mouse_reports() {
+        let mut session = CompositeSession::new(&CompositeProfileConfig::default());
+        // First touch only anchors the pointer.
+        let reports = session.input_reports(&touching(0, 0));
+        assert_eq!(reports.len(), 1);
+        assert_eq!(reports[0][0], COMPOSITE_GAMEPAD_REPORT_ID);
+
+        let reports = session.input_reports(&touching(480, -96));
+        let mouse = &reports[1];
+        assert_eq!(mouse.len(), 1 + COMPOSITE_MOUSE_PAYLOAD_LEN);
+        assert_eq!(mouse[0], COMPOSITE_MOUSE_REPORT_ID);
+        assert_eq!(i16::from_le_bytes([mouse[2], mouse[3]]), 10);
+        assert_eq!(i16::from_le_bytes([mouse[4], mouse[5]]), -2);
+
+        // Slow motion accumulates instead of being lost.
+        assert_eq!(session.input_reports(&touching(504, -96)).len(), 1);
+        let reports = session.input_reports(&touching(528, -96));
+        assert_eq!(i16::from_le_bytes([reports[1][2], reports[1][3]]), 1);
+    }
+
+    #[test]
+    fn pad_clicks_and_keys_are_sent_on_change() {
+        let mut session = CompositeSession::new(&CompositeProfileConfig::default());
+        let mut report = InputReport::default();
+        report.touch[1].clicked = true;
+        report.keys[0] = keyboard_usage("left_ctrl").unwrap();
+        report.keys[1] = keyboard_usage("c").unwrap();
+
+        let reports = session.input_reports(&report);
+        assert_eq!(reports.len(), 3);
+        assert_eq!(reports[1][1], MOUSE_BUTTON_LEFT);
+        assert_eq!(
+            reports[2],
+            vec![COMPOSITE_KEYBOARD_REPORT_ID, 0x01, 0, 0x06, 0, 0, 0, 0, 0]
+        );
+
+        // Nothing changed: gamepad only.
+        assert_eq!(session.input_reports(&report).len(), 1);
+
+        // Release sends a final mouse and keyboard report.
+        assert_eq!(session.input_reports(&InputReport::default()).len(), 3);
+    }
+
+    #[test]
+    fn keyboard_usages_follow_hid_tables() {
+        assert_eq!(keyboard_usage("a"), Some(0x04));
+        assert_eq!(keyboard_usage("z"), Some(0x1d));
+        assert_eq!(keyboard_usage("1"), Some(0x1e));
+        assert_eq!(keyboard_usage("0"), Some(0x27));
+        assert_eq!(keyboard_usage("f1"), Some(0x3a));
+        assert_eq!(keyboard_usage("f12"), Some(0x45));
+        assert_eq!(keyboard_usage("f13"), None);
+        assert_eq!(keyboard_usage("esc"), Some(0x29));
+        assert_eq!(keyboard_usage("turbo"), None);
+    }
+
+    #[test]
+    fn keyboard_leds_parse_as_one_bit_flags() {
+        let descriptor = ReportDescriptor::parse(&build_descriptor().descriptor)
+            .expect("composite descriptor should parse");
+        let output = descriptor
+            .report(ReportType::Output, COMPOSITE_KEYBOARD_REPORT_ID)
+            .expect("keyboard output report");
+        let leds = output
+            .field(u32::from(PAGE_LED) << 16 | 0x01)
+            .expect("Num Lock LED");
+        assert_eq!((leds.bit_size, leds.count), (1, 5));
+        assert_eq!((leds.logical_min, leds.logical_max), (0, 1));
+        assert!(leds.is_variable());
+        assert_eq!(leds.usage(4), Some(u32::from(PAGE_LED) << 16 | 0x05));
+    }
+
+    #[test]
+    fn descriptor_declares_each_report_id() {
+        let built = build_descriptor();
+        let descriptor = &built.descriptor;
+        for report in reports(&built.fields) {
+            assert!(descriptor.windows(2).any(|w| w == [0x85, report.id]));
+        }
+        let fields = &built.fields;
+        assert_eq!(
+            fields.payload_len(ReportType::Input, COMPOSITE_MOUSE_REPORT_ID),
+            COMPOSITE_MOUSE_PAYLOAD_LEN
+        );
+        assert_eq!(
+            fields.payload_len(ReportType::Input, COMPOSITE_KEYBOARD_REPORT_ID),
+            COMPOSITE_KEYBOARD_PAYLOAD_LEN
+        );
+        assert_eq!(
+            fields.payload_len(ReportType::Output, COMPOSITE_KEYBOARD_REPORT_ID),
+            COMPOSITE_KEYBOARD_OUTPUT_PAYLOAD_LEN
+        );
+    }
+}
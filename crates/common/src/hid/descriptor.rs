@@ -0,0 +1,504 @@
+//! HID report descriptor parser.
+//!
+//! Turns a raw descriptor into the reports it declares, with each field's bit
+//! offset, size, logical range and usages. Profiles are checked against it in
+//! tests, and `controllerosctl hid descriptor` prints it.
+
+use thiserror::Error;
+
+use super::{ReportInfo, ReportType};
+
+/// Upper bound on a usage range expanded into a field's usage list.
+const MAX_USAGE_RANGE: u32 = 0x1_0000;
+/// Widest element a field can read into an `i32`.
+const MAX_REPORT_SIZE: u32 = 32;
+
+#[derive(Debug, Clone, PartialEq, Eq, Error)]
+pub enum DescriptorError {
+    #[error("item at offset {offset} runs past the end of the descriptor")]
+    Truncated { offset: usize },
+    #[error("End Collection at offset {offset} without an open collection")]
+    UnbalancedEndCollection { offset: usize },
+    #[error("Pop at offset {offset} without a matching Push")]
+    UnbalancedPop { offset: usize },
+    #[error("{open} collection(s) left open at end of descriptor")]
+    UnclosedCollection { open: usize },
+    #[error("main item at offset {offset} has zero Report Size or Report Count")]
+    EmptyField { offset: usize },
+    #[error("report ID 0 at offset {offset} is reserved")]
+    ReservedReportId { offset: usize },
+    #[error("report ID {id} at offset {offset} does not fit in a byte")]
+    ReportIdTooLarge { id: u32, offset: usize },
+    #[error("Report Size {size} at offset {offset} is over {MAX_REPORT_SIZE} bits")]
+    ReportSizeTooLarge { size: u32, offset: usize },
+    #[error("main item at offset {offset} makes its report too long")]
+    ReportTooLong { offset: usize },
+}
+
+/// One main item: `count` elements of `size` bits each.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ReportField {
+    /// Bit offset into the report payload (after the report ID byte).
+    pub bit_offset: u32,
+    pub bit_size: u32,
+    pub count: u32,
+    /// Main item data bits (constant, variable, relative, null state, ...).
+    pub flags: u32,
+    pub logical_min: i32,
+    pub logical_max: i32,
+    /// Extended usages (`page << 16 | id`). A variable field whose list is
+    /// shorter than `count` repeats the last usage.
+    pub usages: Vec<u32>,
+    /// Usage of the innermost enclosing collection, if any.
+    pub collection_usage: Option<u32>,
+}
+
+impl ReportField {
+    pub fn is_constant(&self) -> bool {
+        self.flags & 0x01 != 0
+    }
+
+    pub fn is_variable(&self) -> bool {
+        self.flags & 0x02 != 0
+    }
+
+    pub fn is_relative(&self) -> bool {
+        self.flags & 0x04 != 0
+    }
+
+    pub fn has_null_state(&self) -> bool {
+        self.flags & 0x40 != 0
+    }
+
+    /// Parsed fields never saturate; the parser rejects reports whose
+    /// length does not fit.
+    pub fn bit_len(&self) -> u32 {
+        self.bit_size.saturating_mul(self.count)
+    }
+
+    /// Usage of element `index`, for variable fields.
+    pub fn usage(&self, index: u32) -> Option<u32> {
+        if index >= self.count {
+            return None;
+        }
+        self.usages
+            .get(index as usize)
+            .or_else(|| self.usages.last())
+            .copied()
+    }
+
+    /// Whether any element of this field carries `usage`.
+    pub fn has_usage(&self, usage: u32) -> bool {
+        self.usages.contains(&usage)
+    }
+
+    /// Element `index` read from `payload`, sign-extended when the logical
+    /// range is signed.
+    pub fn value(&self, payload: &[u8], index: u32) -> Option<i32> {
+        if index >= self.count || self.bit_size == 0 || self.bit_size > MAX_REPORT_SIZE {
+            return None;
+        }
+        let start = index
+            .checked_mul(self.bit_size)?
+            .checked_add(self.bit_offset)?;
+        let end = start.checked_add(self.bit_size)?;
+        let mut raw: u32 = 0;
+        for pos in start..end {
+            let bit = pos - start;
+            let byte = *payload.get((pos / 8) as usize)?;
+            if byte >> (pos % 8) & 1 != 0 {
+                raw |= 1 << bit;
+            }
+        }
+        if self.logical_min < 0 && self.bit_size < 32 && raw >> (self.bit_size - 1) & 1 != 0 {
+            raw |= u32::MAX << self.bit_size;
+        }
+        Some(raw as i32)
+    }
+}
+
+/// Every field of one report, in descriptor order.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParsedReport {
+    /// Report ID, or 0 when the descriptor declares no report IDs.
+    pub id: u8,
+    pub kind: ReportType,
+    pub fields: Vec<ReportField>,
+}
+
+impl ParsedReport {
+    pub fn bit_len(&self) -> u32 {
+        self.fields
+            .iter()
+            .fold(0, |len, f| len.saturating_add(f.bit_len()))
+    }
+
+    /// Report length without the report ID byte.
+    pub fn payload_len(&self) -> usize {
+        self.bit_len().div_ceil(8) as usize
+    }
+
+    /// First non-constant field carrying `usage`.
+    pub fn field(&self, usage: u32) -> Option<&ReportField> {
+        self.fields
+            .iter()
+            .find(|f| !f.is_constant() && f.has_usage(usage))
+    }
+}
+
+/// All reports declared by a descriptor.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ReportDescriptor {
+    pub reports: Vec<ParsedReport>,
+}
+
+impl ReportDescriptor {
+    pub fn parse(data: &[u8]) -> Result<Self, DescriptorError> {
+        Parser::default().run(data)
+    }
+
+    pub fn report(&self, kind: ReportType, id: u8) -> Option<&ParsedReport> {
+        self.reports.iter().find(|r| r.kind == kind && r.id == id)
+    }
+
+    /// Every report as [`ReportInfo`], for comparing with a profile's
+    /// report list.
+    pub fn report_infos(&self) -> Vec<ReportInfo> {
+        self.reports
+            .iter()
+            .map(|r| ReportInfo {
+                id: r.id,
+                report_type: r.kind,
+                payload_len: r.payload_len(),
+            })
+            .collect()
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+struct GlobalState {
+    usage_page: u16,
+    logical_min: i32,
+    logical_max: i32,
+    logical_max_unsigned: u32,
+    report_size: u32,
+    report_count: u32,
+    report_id: u8,
+}
+
+#[derive(Debug, Default)]
+struct Parser {
+    global: GlobalState,
+    stack: Vec<GlobalState>,
+    usages: Vec<u32>,
+    usage_min: Option<u32>,
+    collections: Vec<Option<u32>>,
+    reports: Vec<ParsedReport>,
+}
+
+impl Parser {
+    fn run(mut self, data: &[u8]) -> Result<ReportDescriptor, DescriptorError> {
+        let mut offset = 0;
+        while offset < data.len() {
+            let prefix = data[offset];
+            if prefix == 0xfe {
+                // Long item: size, tag, data. No long item tags are defined.
+                let size = *data
+                    .get(offset + 1)
+                    .ok_or(DescriptorError::Truncated { offset })?;
+                offset += 3 + usize::from(size);
+                if offset > data.len() {
+                    return Err(DescriptorError::Truncated { offset });
+                }
+                continue;
+            }
+            let size = match prefix & 0x03 {
+                3 => 4,
+                n => usize::from(n),
+            };
+            let bytes = data
+                .get(offset + 1..offset + 1 + size)
+                .ok_or(DescriptorError::Truncated { offset })?;
+            let unsigned = bytes
+                .iter()
+                .rev()
+                .fold(0u32, |acc, b| acc << 8 | u32::from(*b));
+            let signed = match size {
+                1 => i32::from(bytes[0] as i8),
+                2 => i32::from(i16::from_le_bytes([bytes[0], bytes[1]])),
+                _ => unsigned as i32,
+            };
+            self.item(prefix & 0xfc, size, unsigned, signed, offset)?;
+            offset += 1 + size;
+        }
+        if !self.collections.is_empty() {
+            return Err(DescriptorError::UnclosedCollection {
+                open: self.collections.len(),
+            });
+        }
+        Ok(ReportDescriptor {
+            reports: self.reports,
+        })
+    }
+
+    fn item(
+        &mut self,
+        tag: u8,
+        size: usize,
+        unsigned: u32,
+        signed: i32,
+        offset: usize,
+    ) -> Result<(), DescriptorError> {
+        match tag {
+            // Main items.
+            0x80 => self.main(ReportType::Input, unsigned, offset)?,
+            0x90 => self.main(ReportType::Output, unsigned, offset)?,
+            0xb0 => self.main(ReportType::Feature, unsigned, offset)?,
+            0xa0 => {
+                self.collections.push(self.usages.first().copied());
+                self.clear_locals();
+            }
+            0xc0 => {
+                self.collections
+                    .pop()
+                    .ok_or(DescriptorError::UnbalancedEndCollection { offset })?;
+                self.clear_locals();
+            }
+            // Global items.
+            0x04 => self.global.usage_page = unsigned as u16,
+            0x14 => self.global.logical_min = signed,
+            0x24 => {
+                self.global.logical_max = signed;
+                self.global.logical_max_unsigned = unsigned;
+            }
+            0x74 => self.global.report_size = unsigned,
+            0x84 => {
+                if unsigned == 0 {
+                    return Err(DescriptorError::ReservedReportId { offset });
+                }
+                self.global.report_id =
+                    u8::try_from(unsigned).map_err(|_| DescriptorError::ReportIdTooLarge {
+                        id: unsigned,
+                        offset,
+                    })?;
+            }
+            0x94 => self.global.report_count = unsigned,
+            0xa4 => self.stack.push(self.global),
+            0xb4 => {
+                self.global = self
+                    .stack
+                    .pop()
+                    .ok_or(DescriptorError::UnbalancedPop { offset })?;
+            }
+            // Local items.
+            0x08 => {
+                let usage = self.extend_usage(size, unsigned);
+                self.usages.push(usage);
+            }
+            0x18 => self.usage_min = Some(self.extend_usage(size, unsigned)),
+            0x28 => {
+                let max = self.extend_usage(size, unsigned);
+                if let Some(min) = self.usage_min.take() {
+                    let max = max.min(min.saturating_add(MAX_USAGE_RANGE - 1));
+                    self.usages.extend(min..=max);
+                }
+            }
+            // Physical range, units, designators and strings don't affect
+            // the report layout.
+            _ => {}
+        }
+        Ok(())
+    }
+
+    /// Usages shorter than 4 bytes take the current Usage Page.
+    fn extend_usage(&self, size: usize, value: u32) -> u32 {
+        if size == 4 {
+            value
+        } else {
+            u32::from(self.global.usage_page) << 16 | (value & 0xffff)
+        }
+    }
+
+    fn clear_locals(&mut self) {
+        self.usages.clear();
+        self.usage_min = None;
+    }
+
+    fn main(&mut self, kind: ReportType, flags: u32, offset: usize) -> Result<(), DescriptorError> {
+        let global = self.global;
+        if global.report_size == 0 || global.report_count == 0 {
+            return Err(DescriptorError::EmptyField { offset });
+        }
+        if global.report_size > MAX_REPORT_SIZE {
+            return Err(DescriptorError::ReportSizeTooLarge {
+                size: global.report_size,
+                offset,
+            });
+        }
+        // Logical Maximum is signed on the wire, but descriptors commonly
+        // encode an unsigned maximum (0xff, 0xffff) in the minimum width.
+        let logical_max = if global.logical_min >= 0 && global.logical_max < global.logical_min {
+            i32::try_from(global.logical_max_unsigned).unwrap_or(i32::MAX)
+        } else {
+            global.logical_max
+        };
+
+        let index = match self
+            .reports
+            .iter()
+            .position(|r| r.kind == kind && r.id == global.report_id)
+        {
+            Some(index) => index,
+            None => {
+                self.reports.push(ParsedReport {
+                    id: global.report_id,
+                    kind,
+                    fields: Vec::new(),
+                });
+                self.reports.len() - 1
+            }
+        };
+        let report = &mut self.reports[index];
+        let bit_offset = report.bit_len();
+        global
+            .report_size
+            .checked_mul(global.report_count)
+            .and_then(|len| len.checked_add(bit_offset))
+            .ok_or(DescriptorError::ReportTooLong { offset })?;
+        report.fields.push(ReportField {
+            bit_offset,
+            bit_size: global.report_size,
+            count: global.report_count,
+            flags,
+            logical_min: global.logical_min,
+            logical_max,
+            usages: std::mem::take(&mut self.usages),
+            collection_usage: self.collections.last().copied().flatten(),
+        });
+        self.clear_locals();
+        Ok(())
+    }
+}
+
+/// Short name for common usages, `page:id` in hex otherwise.
+pub fn usage_name(usage: u32) -> String {
+    let page = (usage >> 16) as u16;
+    let id = (usage & 0xffff) as u16;
+    let name = match (page, id) {
+        (0x01, 0x01) => "Pointer",
+        (0x01, 0x02) => "Mouse",
+        (0x01, 0x04) => "Joystick",
+        (0x01, 0x05) => "Gamepad",
+        (0x01, 0x06) => "Keyboard",
+        (0x01, 0x30) => "X",
+        (0x01, 0x31) => "Y",
+        (0x01, 0x32) => "Z",
+        (0x01, 0x33) => "Rx",
+        (0x01, 0x34) => "Ry",
+        (0x01, 0x35) => "Rz",
+        (0x01, 0x36) => "Slider",
+        (0x01, 0x37) => "Dial",
+        (0x01, 0x38) => "Wheel",
+        (0x01, 0x39) => "Hat Switch",
+        (0x02, 0xc4) => "Accelerator",
+        (0x02, 0xc5) => "Brake",
+        (0x06, 0x20) => "Battery Strength",
+        (0x09, n) => return format!("Button {n}"),
+        (0x07, n) => return format!("Key 0x{n:02x}"),
+        (0x08, n) => return format!("LED {n}"),
+        (0x0c, 0x01) => "Consumer Control",
+        (0x0c, 0x223) => "AC Home",
+        (0x0c, 0x224) => "AC Back",
+        (0x0f, 0x21) => "Set Effect Report",
+        (0x0f, 0x50) => "Duration",
+        (0x0f, 0x70) => "Magnitude",
+        (0x0f, 0x7c) => "Loop Count",
+        (0x0f, 0x97) => "DC Enable Actuators",
+        (0x0f, 0xa7) => "Start Delay",
+        _ => return format!("{page:04x}:{id:04x}"),
+    };
+    name.to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_ids_offsets_and_ranges() {
+        let descriptor = [
+            0x05, 0x01, 0x09, 0x05, 0xa1, 0x01, // Gamepad collection
+            0x85, 0x07, // Report ID 7
+            0x05, 0x09, 0x19, 0x01, 0x29, 0x03, // Buttons 1..3
+            0x15, 0x00, 0x25, 0x01, 0x75, 0x01, 0x95, 0x03, 0x81, 0x02, //
+            0x75, 0x05, 0x95, 0x01, 0x81, 0x03, // Padding
+            0x05, 0x01, 0x09, 0x30, // X
+            0x16, 0x00, 0x80, 0x26, 0xff, 0x7f, 0x75, 0x10, 0x95, 0x01, 0x81, 0x02, //
+            0x09, 0x32, // Z, 0..255 written as a one-byte maximum
+            0x15, 0x00, 0x25, 0xff, 0x75, 0x08, 0x81, 0x02, //
+            0xc0,
+        ];
+        let parsed = ReportDescriptor::parse(&descriptor).unwrap();
+        let report = parsed.report(ReportType::Input, 7).unwrap();
+        assert_eq!(report.payload_len(), 4);
+        assert_eq!(report.fields.len(), 4);
+
+        let buttons = &report.fields[0];
+        assert_eq!(buttons.usage(2), Some(0x0009_0003));
+        assert_eq!(buttons.collection_usage, Some(0x0001_0005));
+        assert!(report.fields[1].is_constant());
+
+        let x = report.field(0x0001_0030).unwrap();
+        assert_eq!((x.bit_offset, x.bit_size), (8, 16));
+        assert_eq!((x.logical_min, x.logical_max), (-32768, 32767));
+        assert_eq!(x.value(&[0, 0x00, 0x80, 0], 0), Some(-32768));
+
+        let z = report.field(0x0001_0032).unwrap();
+        assert_eq!((z.logical_min, z.logical_max), (0, 255));
+        assert_eq!(z.value(&[0, 0, 0, 0xff], 0), Some(255));
+    }
+
+    #[test]
+    fn rejects_malformed_descriptors() {
+        assert_eq!(
+            ReportDescriptor::parse(&[0x05]),
+            Err(DescriptorError::Truncated { offset: 0 })
+        );
+        assert_eq!(
+            ReportDescriptor::parse(&[0xa1, 0x01]),
+            Err(DescriptorError::UnclosedCollection { open: 1 })
+        );
+        assert_eq!(
+            ReportDescriptor::parse(&[0xc0]),
+            Err(DescriptorError::UnbalancedEndCollection { offset: 0 })
+        );
+        assert_eq!(
+            ReportDescriptor::parse(&[0x81, 0x02]),
+            Err(DescriptorError::EmptyField { offset: 0 })
+        );
+        assert_eq!(
+            ReportDescriptor::parse(&[0x86, 0x00, 0x01]),
+            Err(DescriptorError::ReportIdTooLarge { id: 256, offset: 0 })
+        );
+        // Report Size 0xffffffff, Report Count 2.
+        assert_eq!(
+            ReportDescriptor::parse(&[0x77, 0xff, 0xff, 0xff, 0xff, 0x95, 0x02, 0x81, 0x02]),
+            Err(DescriptorError::ReportSizeTooLarge {
+                size: u32::MAX,
+                offset: 7
+            })
+        );
+        // Report Size 32 with a count whose bit length overflows, then a
+        // second field past an almost full report.
+        assert_eq!(
+            ReportDescriptor::parse(&[0x75, 0x20, 0x97, 0xff, 0xff, 0xff, 0xff, 0x81, 0x02]),
+            Err(DescriptorError::ReportTooLong { offset: 7 })
+        );
+        assert_eq!(
+            ReportDescriptor::parse(&[
+                0x75, 0x20, 0x97, 0xff, 0xff, 0xff, 0x07, 0x81, 0x02, 0x95, 0x02, 0x81, 0x02
+            ]),
+            Err(DescriptorError::ReportTooLong { offset: 11 })
+        );
+    }
+}
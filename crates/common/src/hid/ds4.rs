@@ -0,0 +1,522 @@
+//! Sony DualShock 4 (CUH-ZCT2) profile.
+//!
+//! Uses the DS4's USB input report layout (report 0x01, 64 bytes), which the
+//! SDL, Steam and PS Remote Play HID paths accept over any transport, and the
+//! USB feature reports Linux's `hid-playstation` reads at probe time.
+
+use super::{InputReport, OutputReport, TouchPoint, XBOX_TRIGGER_MAX};
+use super::{
+    XBOX_BUTTON_A, XBOX_BUTTON_B, XBOX_BUTTON_HOME, XBOX_BUTTON_LB, XBOX_BUTTON_LS, XBOX_BUTTON_RB,
+    XBOX_BUTTON_RS, XBOX_BUTTON_SELECT, XBOX_BUTTON_START, XBOX_BUTTON_X, XBOX_BUTTON_Y,
+};
+
+pub const SONY_VENDOR_ID: u16 = 0x054c;
+pub const DS4_V2_PRODUCT_ID: u16 = 0x09cc;
+pub const DS4_VERSION: u16 = 0x0100;
+
+pub const DS4_INPUT_REPORT_ID: u8 = 0x01;
+pub const DS4_OUTPUT_REPORT_ID: u8 = 0x05;
+pub const DS4_CALIBRATION_REPORT_ID: u8 = 0x02;
+pub const DS4_PAIRING_REPORT_ID: u8 = 0x12;
+pub const DS4_MAC_REPORT_ID: u8 = 0x81;
+pub const DS4_FIRMWARE_REPORT_ID: u8 = 0xa3;
+
+/// Feature reports the profile declares, in descriptor order.
+pub const DS4_FEATURE_REPORT_IDS: [u8; 4] = [
+    DS4_CALIBRATION_REPORT_ID,
+    DS4_PAIRING_REPORT_ID,
+    DS4_MAC_REPORT_ID,
+    DS4_FIRMWARE_REPORT_ID,
+];
+
+pub const DS4_INPUT_REPORT_LEN: usize = 64;
+pub const DS4_INPUT_PAYLOAD_LEN: usize = DS4_INPUT_REPORT_LEN - 1;
+pub const DS4_OUTPUT_REPORT_LEN: usize = 32;
+pub const DS4_OUTPUT_PAYLOAD_LEN: usize = DS4_OUTPUT_REPORT_LEN - 1;
+pub const DS4_CALIBRATION_PAYLOAD_LEN: usize = 36;
+pub const DS4_PAIRING_PAYLOAD_LEN: usize = 15;
+pub const DS4_MAC_PAYLOAD_LEN: usize = 6;
+pub const DS4_FIRMWARE_PAYLOAD_LEN: usize = 48;
+
+pub const DS4_TOUCHPAD_WIDTH: u16 = 1920;
+pub const DS4_TOUCHPAD_HEIGHT: u16 = 942;
+
+/// Hat value reported when no d-pad direction is held.
+pub const DS4_HAT_NEUTRAL: u8 = 0x08;
+
+/// Analog level (0..255) above which the L2/R2 digital bits are set.
+const DS4_TRIGGER_BUTTON_THRESHOLD: u8 = 16;
+
+/// Battery level reported until the first reading arrives.
+const DS4_DEFAULT_BATTERY_PERCENT: u8 = 100;
+
+/// Firmware and hardware versions in feature report 0xa3.
+const DS4_FIRMWARE_VERSION: u16 = 0x0100;
+const DS4_HARDWARE_VERSION: u16 = 0x00b4;
+
+// Calibration limits for the scales the input report uses: gyro at 16 LSB
+// per deg/s (speed 2 x 540 over a +-8640 range) and accel at 8192 LSB per g.
+const DS4_CAL_GYRO_LIMIT: i16 = 8640;
+const DS4_CAL_GYRO_SPEED: i16 = 540;
+const DS4_CAL_ACCEL_LIMIT: i16 = 8192;
+
+// Byte offsets in the input report, including the report ID byte.
+const OFF_LX: usize = 1;
+const OFF_HAT_BUTTONS: usize = 5;
+const OFF_BUTTONS: usize = 6;
+const OFF_PS_COUNTER: usize = 7;
+const OFF_L2: usize = 8;
+const OFF_R2: usize = 9;
+const OFF_TIMESTAMP: usize = 10;
+const OFF_GYRO: usize = 13;
+const OFF_ACCEL: usize = 19;
+const OFF_BATTERY: usize = 30;
+const OFF_TOUCH_COUNT: usize = 33;
+const OFF_TOUCH_PACKET: usize = 34;
+const OFF_TOUCH_FINGERS: usize = 35;
+
+// Button bits.
+const DS4_SQUARE: u8 = 0x10;
+const DS4_CROSS: u8 = 0x20;
+const DS4_CIRCLE: u8 = 0x40;
+const DS4_TRIANGLE: u8 = 0x80;
+const DS4_L1: u8 = 0x01;
+const DS4_R1: u8 = 0x02;
+const DS4_L2: u8 = 0x04;
+const DS4_R2: u8 = 0x08;
+const DS4_SHARE: u8 = 0x10;
+const DS4_OPTIONS: u8 = 0x20;
+const DS4_L3: u8 = 0x40;
+const DS4_R3: u8 = 0x80;
+const DS4_PS: u8 = 0x01;
+const DS4_TOUCHPAD_CLICK: u8 = 0x02;
+
+// Output report 0x05 offsets, including the report ID byte.
+const OUT_OFF_WEAK_MOTOR: usize = 4;
+const OUT_OFF_STRONG_MOTOR: usize = 5;
+
+// Feature report offsets, including the report ID byte.
+const FIRMWARE_OFF_HW_VERSION: usize = 35;
+const FIRMWARE_OFF_FW_VERSION: usize = 41;
+
+pub const DS4_HID_REPORT_DESCRIPTOR: [u8; 146] = [
+    0x05, 0x01, // Usage Page (Generic Desktop)
+    0x09, 0x05, // Usage (Game Pad)
+    0xa1, 0x01, // Collection (Application)
+    0x85, 0x01, //   Report ID (1)
+    0x09, 0x30, 0x09, 0x31, 0x09, 0x32, 0x09, 0x35, // Usage (X, Y, Z, Rz)
+    0x15, 0x00, 0x26, 0xff, 0x00, // Logical 0..255
+    0x75, 0x08, 0x95, 0x04, 0x81, 0x02, // 4 x 8 bits, Input (Data,Var,Abs)
+    0x09, 0x39, // Usage (Hat switch)
+    0x15, 0x00, 0x25, 0x07, // Logical 0..7
+    0x35, 0x00, 0x46, 0x3b, 0x01, // Physical 0..315
+    0x65, 0x14, // Unit (Degrees)
+    0x75, 0x04, 0x95, 0x01, 0x81, 0x42, // 1 x 4 bits, Input (Data,Var,Abs,Null)
+    0x65, 0x00, // Unit (None)
+    0x05, 0x09, 0x19, 0x01, 0x29, 0x0e, // Usage Page (Button), Usage 1..14
+    0x15, 0x00, 0x25, 0x01, 0x75, 0x01, 0x95, 0x0e, 0x81, 0x02, // 14 x 1 bit
+    0x06, 0x00, 0xff, 0x09, 0x20, // Vendor usage 0x20 (report counter)
+    0x75, 0x06, 0x95, 0x01, 0x15, 0x00, 0x25, 0x7f, 0x81, 0x02, // 1 x 6 bits
+    0x05, 0x01, 0x09, 0x33, 0x09, 0x34, // Usage (Rx, Ry)
+    0x15, 0x00, 0x26, 0xff, 0x00, 0x75, 0x08, 0x95, 0x02, 0x81, 0x02, // 2 x 8 bits
+    0x06, 0x00, 0xff, 0x09, 0x21, // Vendor usage 0x21 (motion, touchpad, status)
+    0x95, 0x36, 0x81, 0x02, // 54 x 8 bits
+    0x85, 0x05, 0x09, 0x22, // Report ID (5), vendor usage 0x22
+    0x95, 0x1f, 0x91, 0x02, // 31 x 8 bits, Output (Data,Var,Abs)
+    0x85, 0x02, 0x09, 0x24, // Report ID (2), vendor usage 0x24 (calibration)
+    0x95, 0x24, 0xb1, 0x02, // 36 x 8 bits, Feature (Data,Var,Abs)
+    0x85, 0x12, 0x09, 0x25, // Report ID (0x12), vendor usage 0x25 (pairing)
+    0x95, 0x0f, 0xb1, 0x02, // 15 x 8 bits, Feature (Data,Var,Abs)
+    0x85, 0x81, 0x09, 0x26, // Report ID (0x81), vendor usage 0x26 (MAC)
+    0x95, 0x06, 0xb1, 0x02, // 6 x 8 bits, Feature (Data,Var,Abs)
+    0x85, 0xa3, 0x09, 0x27, // Report ID (0xa3), vendor usage 0x27 (firmware)
+    0x95, 0x30, 0xb1, 0x02, // 48 x 8 bits, Feature (Data,Var,Abs)
+    0xc0, // End Collection
+];
+
+/// Battery level and address for one host connection. The DS4 has no status
+/// report; the level rides in every input report.
+#[derive(Debug, Clone)]
+pub struct Ds4Session {
+    mac: [u8; 6],
+    battery_percent: u8,
+    last: InputReport,
+}
+
+impl Default for Ds4Session {
+    fn default() -> Self {
+        Self::new([0; 6])
+    }
+}
+
+impl Ds4Session {
+    /// `mac` is reported in the pairing and MAC feature reports, most
+    /// significant byte first.
+    pub fn new(mac: [u8; 6]) -> Self {
+        Self {
+            mac,
+            battery_percent: DS4_DEFAULT_BATTERY_PERCENT,
+            last: InputReport::default(),
+        }
+    }
+
+    pub fn input_report_bytes(&mut self, report: &InputReport) -> [u8; DS4_INPUT_REPORT_LEN] {
+        self.last = *report;
+        input_report_bytes(report, self.battery_percent)
+    }
+
+    /// Take `level_percent` (0..=100) and return the last input report
+    /// carrying it, so the host sees the change without waiting a tick.
+    pub fn battery_report(&mut self, level_percent: u8) -> [u8; DS4_INPUT_REPORT_LEN] {
+        self.battery_percent = level_percent.min(100);
+        input_report_bytes(&self.last, self.battery_percent)
+    }
+
+    /// Feature report `report_id`, including the report ID byte, or `None`
+    /// if the profile does not declare it.
+    pub fn feature_report(&self, report_id: u8) -> Option<Vec<u8>> {
+        // The DS4 sends its address least significant byte first.
+        let mut mac = self.mac;
+        mac.reverse();
+        let mut report = match report_id {
+            DS4_CALIBRATION_REPORT_ID => calibration_report(),
+            // The pairing report's host address stays zero, as on an
+            // unpaired DS4.
+            DS4_PAIRING_REPORT_ID | DS4_MAC_REPORT_ID => {
+                let payload_len = if report_id == DS4_PAIRING_REPORT_ID {
+                    DS4_PAIRING_PAYLOAD_LEN
+                } else {
+                    DS4_MAC_PAYLOAD_LEN
+                };
+                let mut report = vec![0; payload_len + 1];
+                report[1..7].copy_from_slice(&mac);
+                report
+            }
+            DS4_FIRMWARE_REPORT_ID => {
+                let mut report = vec![0; DS4_FIRMWARE_PAYLOAD_LEN + 1];
+                report[FIRMWARE_OFF_HW_VERSION..FIRMWARE_OFF_HW_VERSION + 2]
+                    .copy_from_slice(&DS4_HARDWARE_VERSION.to_le_bytes());
+                report[FIRMWARE_OFF_FW_VERSION..FIRMWARE_OFF_FW_VERSION + 2]
+                    .copy_from_slice(&DS4_FIRMWARE_VERSION.to_le_bytes());
+                report
+            }
+            _ => return None,
+        };
+        report[0] = report_id;
+        Some(report)
+    }
+}
+
+/// USB calibration report 0x02: zero gyro bias, then the gyro limits as
+/// plus/minus pairs per axis, the gyro speed and the accel limits per axis.
+fn calibration_report() -> Vec<u8> {
+    let gyro = DS4_CAL_GYRO_LIMIT;
+    let speed = DS4_CAL_GYRO_SPEED;
+    let accel = DS4_CAL_ACCEL_LIMIT;
+    let values: [i16; 17] = [
+        0, 0, 0, // pitch, yaw, roll bias
+        gyro, -gyro, gyro, -gyro, gyro, -gyro, // pitch, yaw, roll plus/minus
+        speed, speed, // speed plus, minus
+        accel, -accel, accel, -accel, accel, -accel, // X, Y, Z plus/minus
+    ];
+    let mut report = vec![0; DS4_CALIBRATION_PAYLOAD_LEN + 1];
+    for (i, value) in values.into_iter().enumerate() {
+        report[1 + 2 * i..3 + 2 * i].copy_from_slice(&value.to_le_bytes());
+    }
+    report
+}
+
+/// Pack `report` as a DS4 input report 0x01, including the report ID byte.
+/// `battery_percent` is reported in tenths, 0..=10.
+pub fn input_report_bytes(report: &InputReport, battery_percent: u8) -> [u8; DS4_INPUT_REPORT_LEN] {
+    let mut out = [0u8; DS4_INPUT_REPORT_LEN];
+    out[0] = DS4_INPUT_REPORT_ID;
+
+    for (i, value) in [report.lx, report.ly, report.rx, report.ry]
+        .into_iter()
+        .enumerate()
+    {
+        out[OFF_LX + i] = stick_to_u8(value);
+    }
+
+    let hat = match report.hat {
+        1..=8 => report.hat - 1,
+        _ => DS4_HAT_NEUTRAL,
+    };
+    let buttons = report.buttons;
+    let l2 = trigger_to_u8(report.lt);
+    let r2 = trigger_to_u8(report.rt);
+
+    let mut face = hat;
+    for (xbox, ds4) in [
+        (XBOX_BUTTON_X, DS4_SQUARE),
+        (XBOX_BUTTON_A, DS4_CROSS),
+        (XBOX_BUTTON_B, DS4_CIRCLE),
+        (XBOX_BUTTON_Y, DS4_TRIANGLE),
+    ] {
+        if buttons & xbox != 0 {
+            face |= ds4;
+        }
+    }
+    out[OFF_HAT_BUTTONS] = face;
+
+    let mut shoulder = 0u8;
+    for (xbox, ds4) in [
+        (XBOX_BUTTON_LB, DS4_L1),
+        (XBOX_BUTTON_RB, DS4_R1),
+        (XBOX_BUTTON_SELECT, DS4_SHARE),
+        (XBOX_BUTTON_START, DS4_OPTIONS),
+        (XBOX_BUTTON_LS, DS4_L3),
+        (XBOX_BUTTON_RS, DS4_R3),
+    ] {
+        if buttons & xbox != 0 {
+            shoulder |= ds4;
+        }
+    }
+    if l2 > DS4_TRIGGER_BUTTON_THRESHOLD {
+        shoulder |= DS4_L2;
+    }
+    if r2 > DS4_TRIGGER_BUTTON_THRESHOLD {
+        shoulder |= DS4_R2;
+    }
+    out[OFF_BUTTONS] = shoulder;
+
+    // The 6-bit counter ticks once per millisecond of sample time.
+    let counter = ((report.timestamp_us / 1000) & 0x3f) as u8;
+    let mut system = counter << 2;
+    if buttons & XBOX_BUTTON_HOME != 0 {
+        system |= DS4_PS;
+    }
+    if report.touch.iter().any(|t| t.clicked) {
+        system |= DS4_TOUCHPAD_CLICK;
+    }
+    out[OFF_PS_COUNTER] = system;
+    out[OFF_L2] = l2;
+    out[OFF_R2] = r2;
+
+    // Sensor timestamp in units of 16/3 us.
+    let ticks = (u64::from(report.timestamp_us) * 3 / 16) as u16;
+    out[OFF_TIMESTAMP..OFF_TIMESTAMP + 2].copy_from_slice(&ticks.to_le_bytes());
+
+    // Both devices report gyro at ~16 LSB per deg/s; the DS4 reports
+    // acceleration at 8192 LSB per g, half the Deck's resolution.
+    for i in 0..3 {
+        let gyro = report.gyro[i].to_le_bytes();
+        let accel = (report.accel[i] / 2).to_le_bytes();
+        out[OFF_GYRO + 2 * i..OFF_GYRO + 2 * i + 2].copy_from_slice(&gyro);
+        out[OFF_ACCEL + 2 * i..OFF_ACCEL + 2 * i + 2].copy_from_slice(&accel);
+    }
+
+    out[OFF_BATTERY] = battery_percent.min(100) / 10;
+
+    // One touch packet. The left pad covers the left half of the DS4
+    // touchpad and the right pad the right half.
+    out[OFF_TOUCH_COUNT] = 1;
+    out[OFF_TOUCH_PACKET] = counter;
+    for (i, point) in report.touch.iter().enumerate() {
+        let off = OFF_TOUCH_FINGERS + 4 * i;
+        out[off..off + 4].copy_from_slice(&encode_finger(i as u8, i as u16, point));
+    }
+
+    out
+}
+
+/// Parse a DS4 output report 0x05 into the shared rumble fields.
+pub fn parse_output_report(data: &[u8]) -> Option<OutputReport> {
+    if data.len() != DS4_OUTPUT_REPORT_LEN || data[0] != DS4_OUTPUT_REPORT_ID {
+        return None;
+    }
+    Some(OutputReport {
+        dc_enable_actuators: data[1],
+        weak_motor_magnitude: data[OUT_OFF_WEAK_MOTOR],
+        strong_motor_magnitude: data[OUT_OFF_STRONG_MOTOR],
+        ..OutputReport::default()
+    })
+}
+
+fn stick_to_u8(value: i16) -> u8 {
+    ((i32::from(value) + 0x8000) >> 8) as u8
+}
+
+fn trigger_to_u8(value: u16) -> u8 {
+    (u32::from(value.min(XBOX_TRIGGER_MAX)) * 255 / u32::from(XBOX_TRIGGER_MAX)) as u8
+}
+
+/// Encode one finger slot: active flag and ID, then packed 12-bit X and Y.
+fn encode_finger(id: u8, half: u16, point: &TouchPoint) -> [u8; 4] {
+    let half_width = u32::from(DS4_TOUCHPAD_WIDTH / 2);
+    let x = u32::from(half) * half_width + axis_to_range(point.x, half_width);
+    let y = axis_to_range(point.y, u32::from(DS4_TOUCHPAD_HEIGHT));
+    let inactive = if point.active { 0x00 } else { 0x80 };
+    [
+        inactive | (id & 0x7f),
+        (x & 0xff) as u8,
+        (((x >> 8) & 0x0f) | ((y & 0x0f) << 4)) as u8,
+        (y >> 4) as u8,
+    ]
+}
+
+/// Map a signed i16 axis onto `0..size`.
+fn axis_to_range(value: i16, size: u32) -> u32 {
+    let offset = (i32::from(value) + 0x8000) as u32;
+    (offset * size / 0x1_0000).min(size - 1)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn neutral_report_centers_sticks_and_hat() {
+        let bytes = input_report_bytes(&InputReport::default(), 100);
+        assert_eq!(bytes.len(), DS4_INPUT_REPORT_LEN);
+        assert_eq!(bytes[0], DS4_INPUT_REPORT_ID);
+        assert_eq!(&bytes[1..5], &[0x80; 4]);
+        assert_eq!(bytes[OFF_HAT_BUTTONS], DS4_HAT_NEUTRAL);
+        assert_eq!(bytes[OFF_BUTTONS], 0);
+        // Both fingers reported as not touching.
+        assert_eq!(bytes[OFF_TOUCH_FINGERS] & 0x80, 0x80);
+        assert_eq!(bytes[OFF_TOUCH_FINGERS + 4] & 0x80, 0x80);
+    }
+
+    #[test]
+    fn packs_buttons_hat_and_triggers() {
+        let report = InputReport {
+            buttons: XBOX_BUTTON_A | XBOX_BUTTON_Y | XBOX_BUTTON_LB | XBOX_BUTTON_HOME,
+            hat: 3,
+            lt: XBOX_TRIGGER_MAX,
+            ..InputReport::default()
+        };
+        let bytes = input_report_bytes(&report, 100);
+        assert_eq!(bytes[OFF_HAT_BUTTONS], 0x02 | DS4_CROSS | DS4_TRIANGLE);
+        assert_eq!(bytes[OFF_BUTTONS], DS4_L1 | DS4_L2);
+        assert_eq!(bytes[OFF_PS_COUNTER] & 0x03, DS4_PS);
+        assert_eq!(bytes[OFF_L2], 0xff);
+        assert_eq!(bytes[OFF_R2], 0);
+    }
+
+    #[test]
+    fn packs_touch_points_into_pad_halves() {
+        let report = InputReport {
+            touch: [
+                TouchPoint {
+                    active: true,
+                    clicked: false,
+                    x: i16::MIN,
+                    y: i16::MIN,
+                },
+                TouchPoint {
+                    active: true,
+                    clicked: true,
+                    x: i16::MAX,
+                    y: i16::MAX,
+                },
+            ],
+            ..InputReport::default()
+        };
+        let bytes = input_report_bytes(&report, 100);
+        let decode = |off: usize| {
+            let f = &bytes[off..off + 4];
+            let x = u16::from(f[1]) | (u16::from(f[2] & 0x0f) << 8);
+            let y = u16::from(f[2] >> 4) | (u16::from(f[3]) << 4);
+            (f[0], x, y)
+        };
+        assert_eq!(decode(OFF_TOUCH_FINGERS), (0, 0, 0));
+        assert_eq!(
+            decode(OFF_TOUCH_FINGERS + 4),
+            (1, DS4_TOUCHPAD_WIDTH - 1, DS4_TOUCHPAD_HEIGHT - 1)
+        );
+        assert_eq!(
+            bytes[OFF_PS_COUNTER] & DS4_TOUCHPAD_CLICK,
+            DS4_TOUCHPAD_CLICK
+        );
+    }
+
+    #[test]
+    fn packs_imu_with_ds4_accel_scale() {
+        let report = InputReport {
+            gyro: [100, -200, 300],
+            accel: [16384, 0, -16384],
+            ..InputReport::default()
+        };
+        let bytes = input_report_bytes(&report, 100);
+        let read = |off: usize| i16::from_le_bytes([bytes[off], bytes[off + 1]]);
+        assert_eq!(
+            [read(OFF_GYRO), read(OFF_GYRO + 2), read(OFF_GYRO + 4)],
+            [100, -200, 300]
+        );
+        assert_eq!(
+            [read(OFF_ACCEL), read(OFF_ACCEL + 2), read(OFF_ACCEL + 4)],
+            [8192, 0, -8192]
+        );
+    }
+
+    #[test]
+    fn session_reports_battery_level_in_tenths() {
+        let mut session = Ds4Session::default();
+        assert_eq!(
+            session.input_report_bytes(&InputReport::default())[OFF_BATTERY],
+            10
+        );
+        let report = InputReport {
+            buttons: XBOX_BUTTON_A,
+            ..InputReport::default()
+        };
+        session.input_report_bytes(&report);
+
+        let status = session.battery_report(47);
+        assert_eq!(status[OFF_BATTERY], 4);
+        assert_eq!(status[OFF_HAT_BUTTONS] & DS4_CROSS, DS4_CROSS);
+        assert_eq!(session.input_report_bytes(&report)[OFF_BATTERY], 4);
+        assert_eq!(session.battery_report(200)[OFF_BATTERY], 10);
+    }
+
+    #[test]
+    fn feature_reports_carry_calibration_address_and_firmware() {
+        let mac = [0x98, 0xb6, 0xe9, 0x01, 0x02, 0x03];
+        let session = Ds4Session::new(mac);
+        let read = |report: &[u8], off: usize| i16::from_le_bytes([report[off], report[off + 1]]);
+
+        let calibration = session.feature_report(DS4_CALIBRATION_REPORT_ID).unwrap();
+        assert_eq!(calibration.len(), DS4_CALIBRATION_PAYLOAD_LEN + 1);
+        assert_eq!(calibration[0], DS4_CALIBRATION_REPORT_ID);
+        // Pitch plus/minus, gyro speed plus/minus and accel X plus/minus.
+        assert_eq!(
+            [read(&calibration, 7), read(&calibration, 9)],
+            [8640, -8640]
+        );
+        assert_eq!([read(&calibration, 19), read(&calibration, 21)], [540, 540]);
+        assert_eq!(
+            [read(&calibration, 23), read(&calibration, 25)],
+            [8192, -8192]
+        );
+
+        for (report_id, payload_len) in [
+            (DS4_PAIRING_REPORT_ID, DS4_PAIRING_PAYLOAD_LEN),
+            (DS4_MAC_REPORT_ID, DS4_MAC_PAYLOAD_LEN),
+        ] {
+            let report = session.feature_report(report_id).unwrap();
+            assert_eq!(report.len(), payload_len + 1);
+            assert_eq!(report[0], report_id);
+            assert_eq!(report[1..7], [0x03, 0x02, 0x01, 0xe9, 0xb6, 0x98]);
+        }
+
+        let firmware = session.feature_report(DS4_FIRMWARE_REPORT_ID).unwrap();
+        assert_eq!(firmware.len(), DS4_FIRMWARE_PAYLOAD_LEN + 1);
+        assert_eq!(read(&firmware, FIRMWARE_OFF_FW_VERSION), 0x0100);
+        assert!(session.feature_report(DS4_OUTPUT_REPORT_ID).is_none());
+    }
+
+    #[test]
+    fn parses_rumble_from_output_report() {
+        let mut raw = [0u8; DS4_OUTPUT_REPORT_LEN];
+        raw[0] = DS4_OUTPUT_REPORT_ID;
+        raw[1] = 0x07;
+        raw[OUT_OFF_WEAK_MOTOR] = 0x40;
+        raw[OUT_OFF_STRONG_MOTOR] = 0x80;
+        let parsed = parse_output_report(&raw).expect("output report should parse");
+        assert_eq!(parsed.weak_motor_magnitude, 0x40);
+        assert_eq!(parsed.strong_motor_magnitude, 0x80);
+        assert!(parse_output_report(&raw[..8]).is_none());
+    }
+}
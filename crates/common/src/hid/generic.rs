@@ -0,0 +1,477 @@
+//! Generic HID gamepad whose descriptor is generated from config.
+//!
+//! The report has one ID (`0x01`) and packs, LSB first: the buttons, an
+//! optional 4-bit hat, then each axis at its configured width. Hosts that
+//! read raw HID (SDL, DirectInput) see every control as its own input.
+
+use serde::Deserialize;
+
+use super::builder::{
+    Collection, DescriptorBuilder, FieldLayout, FieldSpec, PAGE_BUTTON, PAGE_GENERIC_DESKTOP,
+    UNIT_DEGREES, USAGE_GAMEPAD, USAGE_HAT_SWITCH,
+};
+use super::{
+    InputReport, ReportType, EXTRA_BUTTON_L4, EXTRA_BUTTON_L5, EXTRA_BUTTON_QAM, EXTRA_BUTTON_R4,
+    EXTRA_BUTTON_R5, XBOX_BUTTON_A, XBOX_BUTTON_B, XBOX_BUTTON_HOME, XBOX_BUTTON_LB,
+    XBOX_BUTTON_LS, XBOX_BUTTON_RB, XBOX_BUTTON_RS, XBOX_BUTTON_SELECT, XBOX_BUTTON_START,
+    XBOX_BUTTON_X, XBOX_BUTTON_Y, XBOX_TRIGGER_MAX,
+};
+
+/// pid.codes test VID/PID. Set `profile.vendor_id`/`product_id` for a
+/// registered pair.
+pub const GENERIC_VENDOR_ID: u16 = 0x1209;
+pub const GENERIC_PRODUCT_ID: u16 = 0x0001;
+pub const GENERIC_VERSION: u16 = 0x0100;
+
+pub const GENERIC_INPUT_REPORT_ID: u8 = 0x01;
+pub const GENERIC_MAX_BUTTONS: u8 = 32;
+pub const GENERIC_MAX_AXIS_BITS: u8 = 16;
+
+/// Buttons in the default layout: the order up to RT. The default hat
+/// already carries the d-pad, so its four buttons are left out.
+pub const GENERIC_DEFAULT_BUTTONS: u8 = 21;
+
+/// Analog level (0..1023) above which the LT/RT buttons are pressed.
+const TRIGGER_PRESS_THRESHOLD: u16 = XBOX_TRIGGER_MAX / 4;
+
+/// Button sources in report order. Button N of the descriptor is entry N-1;
+/// buttons past the end of this list are always released.
+pub const GENERIC_BUTTON_ORDER: [GenericButton; 25] = [
+    GenericButton::A,
+    GenericButton::B,
+    GenericButton::X,
+    GenericButton::Y,
+    GenericButton::Lb,
+    GenericButton::Rb,
+    GenericButton::Back,
+    GenericButton::Start,
+    GenericButton::Home,
+    GenericButton::Ls,
+    GenericButton::Rs,
+    GenericButton::Share,
+    GenericButton::L4,
+    GenericButton::R4,
+    GenericButton::L5,
+    GenericButton::R5,
+    GenericButton::Qam,
+    GenericButton::LeftPadClick,
+    GenericButton::RightPadClick,
+    GenericButton::Lt,
+    GenericButton::Rt,
+    GenericButton::DpadUp,
+    GenericButton::DpadDown,
+    GenericButton::DpadLeft,
+    GenericButton::DpadRight,
+];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum GenericButton {
+    A,
+    B,
+    X,
+    Y,
+    Lb,
+    Rb,
+    Back,
+    Start,
+    Home,
+    Ls,
+    Rs,
+    Share,
+    L4,
+    R4,
+    L5,
+    R5,
+    Qam,
+    LeftPadClick,
+    RightPadClick,
+    Lt,
+    Rt,
+    DpadUp,
+    DpadDown,
+    DpadLeft,
+    DpadRight,
+}
+
+impl GenericButton {
+    fn pressed(self, report: &InputReport) -> bool {
+        let button = |mask: u16| report.buttons & mask != 0;
+        let extra = |mask: u16| report.extra_buttons & mask != 0;
+        match self {
+            Self::A => button(XBOX_BUTTON_A),
+            Self::B => button(XBOX_BUTTON_B),
+            Self::X => button(XBOX_BUTTON_X),
+            Self::Y => button(XBOX_BUTTON_Y),
+            Self::Lb => button(XBOX_BUTTON_LB),
+            Self::Rb => button(XBOX_BUTTON_RB),
+            Self::Back => button(XBOX_BUTTON_SELECT),
+            Self::Start => button(XBOX_BUTTON_START),
+            Self::Home => button(XBOX_BUTTON_HOME),
+            Self::Ls => button(XBOX_BUTTON_LS),
+            Self::Rs => button(XBOX_BUTTON_RS),
+            Self::Share => report.share != 0,
+            Self::L4 => extra(EXTRA_BUTTON_L4),
+            Self::R4 => extra(EXTRA_BUTTON_R4),
+            Self::L5 => extra(EXTRA_BUTTON_L5),
+            Self::R5 => extra(EXTRA_BUTTON_R5),
+            Self::Qam => extra(EXTRA_BUTTON_QAM),
+            Self::LeftPadClick => report.touch[0].clicked,
+            Self::RightPadClick => report.touch[1].clicked,
+            Self::Lt => report.lt > TRIGGER_PRESS_THRESHOLD,
+            Self::Rt => report.rt > TRIGGER_PRESS_THRESHOLD,
+            Self::DpadUp => matches!(report.hat, 1 | 2 | 8),
+            Self::DpadDown => matches!(report.hat, 4..=6),
+            Self::DpadLeft => matches!(report.hat, 6..=8),
+            Self::DpadRight => matches!(report.hat, 2..=4),
+        }
+    }
+}
+
+/// Generic Desktop usage reported for an axis.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AxisUsage {
+    X,
+    Y,
+    Z,
+    Rx,
+    Ry,
+    Rz,
+    Slider,
+    Dial,
+    Wheel,
+}
+
+impl AxisUsage {
+    pub const fn usage_id(self) -> u8 {
+        match self {
+            Self::X => 0x30,
+            Self::Y => 0x31,
+            Self::Z => 0x32,
+            Self::Rx => 0x33,
+            Self::Ry => 0x34,
+            Self::Rz => 0x35,
+            Self::Slider => 0x36,
+            Self::Dial => 0x37,
+            Self::Wheel => 0x38,
+        }
+    }
+}
+
+/// `InputReport` field that drives an axis.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AxisSource {
+    Lx,
+    Ly,
+    Rx,
+    Ry,
+    Lt,
+    Rt,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+pub struct GenericAxisConfig {
+    pub usage: AxisUsage,
+    pub source: AxisSource,
+    #[serde(default = "default_axis_bits")]
+    pub bits: u8,
+}
+
+fn default_axis_bits() -> u8 {
+    GENERIC_MAX_AXIS_BITS
+}
+
+/// `[profile.generic]` section of `hid.toml`.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(default)]
+pub struct GenericProfileConfig {
+    pub buttons: u8,
+    pub hat: bool,
+    pub axes: Vec<GenericAxisConfig>,
+}
+
+impl Default for GenericProfileConfig {
+    fn default() -> Self {
+        let axis = |usage, source| GenericAxisConfig {
+            usage,
+            source,
+            bits: GENERIC_MAX_AXIS_BITS,
+        };
+        Self {
+            buttons: GENERIC_DEFAULT_BUTTONS,
+            hat: true,
+            axes: vec![
+                axis(AxisUsage::X, AxisSource::Lx),
+                axis(AxisUsage::Y, AxisSource::Ly),
+                axis(AxisUsage::Rx, AxisSource::Rx),
+                axis(AxisUsage::Ry, AxisSource::Ry),
+                axis(AxisUsage::Z, AxisSource::Lt),
+                axis(AxisUsage::Rz, AxisSource::Rt),
+            ],
+        }
+    }
+}
+
+impl GenericProfileConfig {
+    pub fn validate(&self) -> Result<(), String> {
+        if self.buttons > GENERIC_MAX_BUTTONS {
+            return Err(format!(
+                "profile.generic.buttons must be in 0..={GENERIC_MAX_BUTTONS}"
+            ));
+        }
+        if self.buttons == 0 && !self.hat && self.axes.is_empty() {
+            return Err("profile.generic must declare at least one control".to_string());
+        }
+        for axis in &self.axes {
+            if axis.bits == 0 || axis.bits > GENERIC_MAX_AXIS_BITS {
+                return Err(format!(
+                    "profile.generic axis {:?}: bits must be in 1..={GENERIC_MAX_AXIS_BITS}",
+                    axis.usage
+                ));
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Descriptor and packing for one validated [`GenericProfileConfig`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct GenericProfile {
+    config: GenericProfileConfig,
+    descriptor: Vec<u8>,
+    payload_len: usize,
+    buttons: Option<FieldLayout>,
+    hat: Option<FieldLayout>,
+    /// One layout per configured axis, in config order.
+    axes: Vec<FieldLayout>,
+}
+
+impl GenericProfile {
+    pub fn new(config: &GenericProfileConfig) -> Self {
+        let built = build_descriptor(config).build();
+        let fields = &built.fields;
+        Self {
+            config: config.clone(),
+            payload_len: fields.payload_len(ReportType::Input, GENERIC_INPUT_REPORT_ID),
+            buttons: fields.get("buttons").cloned(),
+            hat: fields.get("hat").cloned(),
+            axes: (0..config.axes.len())
+                .filter_map(|i| fields.get(&axis_field(i)).cloned())
+                .collect(),
+            descriptor: built.descriptor,
+        }
+    }
+
+    pub fn report_descriptor(&self) -> &[u8] {
+        &self.descriptor
+    }
+
+    /// Input report length without the report ID byte.
+    pub fn payload_len(&self) -> usize {
+        self.payload_len
+    }
+
+    /// Pack `report`, including the report ID byte.
+    pub fn input_report_bytes(&self, report: &InputReport) -> Vec<u8> {
+        let mut out = vec![0; 1 + self.payload_len];
+        out[0] = GENERIC_INPUT_REPORT_ID;
+
+        if let Some(buttons) = &self.buttons {
+            for (i, button) in GENERIC_BUTTON_ORDER.iter().enumerate() {
+                buttons.write(&mut out, i as u32, i32::from(button.pressed(report)));
+            }
+        }
+
+        if let Some(hat) = &self.hat {
+            // Descriptor hat is 0..7 clockwise from north; 8 is the null state.
+            let value = match report.hat {
+                1..=8 => report.hat - 1,
+                _ => 8,
+            };
+            hat.write(&mut out, 0, i32::from(value));
+        }
+
+        for (layout, axis) in self.axes.iter().zip(&self.config.axes) {
+            layout.write(&mut out, 0, axis_value(report, axis) as i32);
+        }
+        out
+    }
+}
+
+fn axis_field(index: usize) -> String {
+    format!("axis{index}")
+}
+
+/// Scale an axis source to `0..2^bits`. Sticks are centered at the midpoint.
+fn axis_value(report: &InputReport, axis: &GenericAxisConfig) -> u32 {
+    let shift = 16 - u32::from(axis.bits);
+    let stick = |v: i16| (i32::from(v) + 0x8000) as u32 >> shift;
+    let trigger = |v: u16| {
+        let max = (1u32 << axis.bits) - 1;
+        u32::from(v.min(XBOX_TRIGGER_MAX)) * max / u32::from(XBOX_TRIGGER_MAX)
+    };
+    match axis.source {
+        AxisSource::Lx => stick(report.lx),
+        AxisSource::Ly => stick(report.ly),
+        AxisSource::Rx => stick(report.rx),
+        AxisSource::Ry => stick(report.ry),
+        AxisSource::Lt => trigger(report.lt),
+        AxisSource::Rt => trigger(report.rt),
+    }
+}
+
+/// Builder with the gamepad collection for `config` declared. Composite
+/// profiles append their own collections before building.
+pub(super) fn build_descriptor(config: &GenericProfileConfig) -> DescriptorBuilder {
+    let mut b = DescriptorBuilder::new();
+    b.usage_page(PAGE_GENERIC_DESKTOP)
+        .usage(USAGE_GAMEPAD)
+        .begin_collection(Collection::Application)
+        .report_id(GENERIC_INPUT_REPORT_ID);
+
+    if config.buttons > 0 {
+        b.usage_page(PAGE_BUTTON)
+            .usage_range(1, u16::from(config.buttons))
+            .input("buttons", FieldSpec::buttons(u32::from(config.buttons)))
+            .pad_to_byte(ReportType::Input);
+    }
+
+    if config.hat {
+        b.usage_page(PAGE_GENERIC_DESKTOP)
+            .usage(USAGE_HAT_SWITCH)
+            .physical(0, 315, UNIT_DEGREES)
+            .input("hat", FieldSpec::variable(4, 0, 7).null_state())
+            .physical(0, 0, 0)
+            .padding(ReportType::Input, 4);
+    }
+
+    if !config.axes.is_empty() {
+        b.usage_page(PAGE_GENERIC_DESKTOP);
+        for (i, axis) in config.axes.iter().enumerate() {
+            let max = (1i32 << axis.bits) - 1;
+            b.usage(u16::from(axis.usage.usage_id())).input(
+                &axis_field(i),
+                FieldSpec::variable(u32::from(axis.bits), 0, max),
+            );
+        }
+        b.pad_to_byte(ReportType::Input);
+    }
+
+    b.end_collection();
+    b
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn default_layout_packs_buttons_hat_and_axes() {
+        let profile = GenericProfile::new(&GenericProfileConfig::default());
+        // 21 buttons -> 3 bytes, hat byte, six 16-bit axes.
+        assert_eq!(profile.payload_len(), 3 + 1 + 12);
+
+        let report = InputReport {
+            buttons: XBOX_BUTTON_A,
+            extra_buttons: EXTRA_BUTTON_R5,
+            hat: 3,
+            lx: i16::MIN,
+            rt: XBOX_TRIGGER_MAX,
+            ..InputReport::default()
+        };
+        let bytes = profile.input_report_bytes(&report);
+        assert_eq!(bytes.len(), 1 + profile.payload_len());
+        assert_eq!(bytes[0], GENERIC_INPUT_REPORT_ID);
+        assert_eq!(bytes[1], 0x01); // A
+        assert_eq!(bytes[2], 0x80); // R5 is button 16
+        assert_eq!(bytes[3], 0x10); // RT is button 21; no d-pad buttons
+        assert_eq!(bytes[4], 2); // east
+        assert_eq!(&bytes[5..7], &[0x00, 0x00]); // LX fully left
+        assert_eq!(&bytes[7..9], &[0x00, 0x80]); // LY centered
+        assert_eq!(&bytes[15..17], &[0xff, 0xff]); // RT fully pressed
+    }
+
+    #[test]
+    fn dpad_buttons_cover_every_direction_without_a_hat() {
+        let profile = GenericProfile::new(&GenericProfileConfig {
+            buttons: GENERIC_BUTTON_ORDER.len() as u8,
+            hat: false,
+            axes: Vec::new(),
+        });
+        // D-pad up, down, left, right are buttons 22..=25: bits 5..=7 of the
+        // third button byte and bit 0 of the fourth.
+        let dpad = |hat: u8| {
+            let bytes = profile.input_report_bytes(&InputReport {
+                hat,
+                ..InputReport::default()
+            });
+            (bytes[3] >> 5) | (bytes[4] << 3)
+        };
+        assert_eq!(dpad(0), 0b0000);
+        assert_eq!(dpad(1), 0b0001); // north
+        assert_eq!(dpad(3), 0b1000); // east
+        assert_eq!(dpad(4), 0b1010); // south-east
+        assert_eq!(dpad(7), 0b0100); // west
+        assert_eq!(dpad(8), 0b0101); // north-west
+    }
+
+    #[test]
+    fn narrow_axes_are_bit_packed_and_padded() {
+        let config = GenericProfileConfig {
+            buttons: 32,
+            hat: false,
+            axes: vec![
+                GenericAxisConfig {
+                    usage: AxisUsage::X,
+                    source: AxisSource::Lx,
+                    bits: 10,
+                },
+                GenericAxisConfig {
+                    usage: AxisUsage::Slider,
+                    source: AxisSource::Lt,
+                    bits: 4,
+                },
+            ],
+        };
+        let profile = GenericProfile::new(&config);
+        assert_eq!(profile.payload_len(), 4 + 2);
+
+        let report = InputReport {
+            lx: i16::MAX,
+            lt: XBOX_TRIGGER_MAX,
+            ..InputReport::default()
+        };
+        let bytes = profile.input_report_bytes(&report);
+        // LX = 0x3ff in bits 0..10, LT = 0xf in bits 10..14.
+        assert_eq!(&bytes[5..7], &[0xff, 0x3f]);
+
+        let descriptor = profile.report_descriptor();
+        assert!(descriptor.windows(2).any(|w| w == [0x95, 32]));
+        assert!(descriptor.windows(3).any(|w| w == [0x26, 0xff, 0x03]));
+        assert!(descriptor.windows(2).any(|w| w == [0x95, 2])); // axis padding
+    }
+
+    #[test]
+    fn full_width_axis_uses_four_byte_logical_maximum() {
+        let profile = GenericProfile::new(&GenericProfileConfig::default());
+        assert!(profile
+            .report_descriptor()
+            .windows(5)
+            .any(|w| w == [0x27, 0xff, 0xff, 0x00, 0x00]));
+    }
+
+    #[test]
+    fn validate_rejects_out_of_range_sizes() {
+        let mut config = GenericProfileConfig {
+            buttons: 33,
+            ..GenericProfileConfig::default()
+        };
+        assert!(config.validate().is_err());
+        config.buttons = 32;
+        config.axes[0].bits = 17;
+        assert!(config.validate().is_err());
+        config.axes[0].bits = 8;
+        assert!(config.validate().is_ok());
+    }
+}
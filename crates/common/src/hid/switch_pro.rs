@@ -0,0 +1,603 @@
+//! Nintendo Switch Pro Controller profile.
+//!
+//! Besides the input reports, the Pro Controller answers a subcommand
+//! protocol on output report 0x01. Hosts (SDL, Steam, emulators) use it to
+//! read device info and factory calibration from SPI flash, set the input
+//! report mode and the player lights. [`SwitchProSession`] keeps that state
+//! and builds the 0x21 replies.
+
+use super::{
+    InputReport, XBOX_BUTTON_A, XBOX_BUTTON_B, XBOX_BUTTON_HOME, XBOX_BUTTON_LB, XBOX_BUTTON_LS,
+    XBOX_BUTTON_RB, XBOX_BUTTON_RS, XBOX_BUTTON_SELECT, XBOX_BUTTON_START, XBOX_BUTTON_X,
+    XBOX_BUTTON_Y, XBOX_TRIGGER_MAX,
+};
+
+pub const NINTENDO_VENDOR_ID: u16 = 0x057e;
+pub const SWITCH_PRO_PRODUCT_ID: u16 = 0x2009;
+pub const SWITCH_PRO_VERSION: u16 = 0x0200;
+
+pub const SWITCH_REPLY_REPORT_ID: u8 = 0x21;
+pub const SWITCH_FULL_REPORT_ID: u8 = 0x30;
+pub const SWITCH_SIMPLE_REPORT_ID: u8 = 0x3f;
+pub const SWITCH_SUBCOMMAND_REPORT_ID: u8 = 0x01;
+pub const SWITCH_RUMBLE_REPORT_ID: u8 = 0x10;
+
+pub const SWITCH_FULL_REPORT_LEN: usize = 49;
+pub const SWITCH_FULL_PAYLOAD_LEN: usize = SWITCH_FULL_REPORT_LEN - 1;
+pub const SWITCH_SIMPLE_PAYLOAD_LEN: usize = 11;
+pub const SWITCH_OUTPUT_PAYLOAD_LEN: usize = 48;
+
+// Subcommands (output report 0x01, byte 10).
+const SUBCMD_MANUAL_PAIRING: u8 = 0x01;
+const SUBCMD_DEVICE_INFO: u8 = 0x02;
+const SUBCMD_SET_INPUT_MODE: u8 = 0x03;
+const SUBCMD_TRIGGER_ELAPSED: u8 = 0x04;
+const SUBCMD_SPI_READ: u8 = 0x10;
+const SUBCMD_SET_PLAYER_LIGHTS: u8 = 0x30;
+const SUBCMD_ENABLE_IMU: u8 = 0x40;
+const SUBCMD_ENABLE_VIBRATION: u8 = 0x48;
+
+const OUT_OFF_SUBCMD: usize = 10;
+const OUT_OFF_SUBCMD_DATA: usize = 11;
+
+// Input report offsets, including the report ID byte.
+const OFF_TIMER: usize = 1;
+const OFF_BATTERY: usize = 2;
+const OFF_BUTTONS: usize = 3;
+const OFF_LEFT_STICK: usize = 6;
+const OFF_RIGHT_STICK: usize = 9;
+const OFF_VIBRATOR: usize = 12;
+const OFF_ACK: usize = 13;
+const OFF_REPLY_SUBCMD: usize = 14;
+const OFF_REPLY_DATA: usize = 15;
+const OFF_IMU: usize = 13;
+
+/// Battery level reported until the first reading arrives.
+pub const SWITCH_DEFAULT_BATTERY_PERCENT: u8 = 100;
+/// Pro Controller connection type, not USB powered. The battery level goes
+/// in the high nibble.
+const BATTERY_CONNECTION_PRO: u8 = 0x00;
+const FIRMWARE_VERSION: [u8; 2] = [0x03, 0x48];
+const CONTROLLER_TYPE_PRO: u8 = 0x03;
+const MAX_SPI_READ: usize = 0x1d;
+
+/// 12-bit stick range either side of center, matching the factory
+/// calibration served from SPI flash.
+const STICK_CENTER: i32 = 0x800;
+const STICK_RANGE: i32 = 0x700;
+
+/// Analog level (0..1023) above which ZL/ZR are reported pressed.
+const TRIGGER_PRESS_THRESHOLD: u16 = XBOX_TRIGGER_MAX / 4;
+
+// Full report button bits: right, shared, left bytes.
+const BTN_Y: u32 = 0x00_0001;
+const BTN_X: u32 = 0x00_0002;
+const BTN_B: u32 = 0x00_0004;
+const BTN_A: u32 = 0x00_0008;
+const BTN_R: u32 = 0x00_0040;
+const BTN_ZR: u32 = 0x00_0080;
+const BTN_MINUS: u32 = 0x00_0100;
+const BTN_PLUS: u32 = 0x00_0200;
+const BTN_RSTICK: u32 = 0x00_0400;
+const BTN_LSTICK: u32 = 0x00_0800;
+const BTN_HOME: u32 = 0x00_1000;
+const BTN_CAPTURE: u32 = 0x00_2000;
+const BTN_DOWN: u32 = 0x01_0000;
+const BTN_UP: u32 = 0x02_0000;
+const BTN_RIGHT: u32 = 0x04_0000;
+const BTN_LEFT: u32 = 0x08_0000;
+const BTN_L: u32 = 0x40_0000;
+const BTN_ZL: u32 = 0x80_0000;
+
+pub const SWITCH_PRO_HID_REPORT_DESCRIPTOR: [u8; 123] = [
+    0x05, 0x01, // Usage Page (Generic Desktop)
+    0x09, 0x05, // Usage (Game Pad)
+    0xa1, 0x01, // Collection (Application)
+    0x06, 0x01, 0xff, //   Usage Page (Vendor 0xFF01)
+    0x15, 0x00, 0x26, 0xff, 0x00, // Logical 0..255
+    0x85, 0x21, 0x09, 0x21, 0x75, 0x08, 0x95, 0x30, 0x81, 0x02, // 0x21: 48 bytes in
+    0x85, 0x30, 0x09, 0x30, 0x75, 0x08, 0x95, 0x30, 0x81, 0x02, // 0x30: 48 bytes in
+    0x85, 0x3f, // Report ID (0x3F), simple HID report
+    0x05, 0x09, 0x19, 0x01, 0x29, 0x10, // Usage Page (Button), Usage 1..16
+    0x15, 0x00, 0x25, 0x01, 0x75, 0x01, 0x95, 0x10, 0x81, 0x02, // 16 x 1 bit
+    0x05, 0x01, 0x09, 0x39, // Usage (Hat switch)
+    0x15, 0x00, 0x25, 0x07, 0x75, 0x04, 0x95, 0x01, 0x81, 0x42, // 1 x 4 bits, Null
+    0x75, 0x04, 0x95, 0x01, 0x81, 0x01, // 4 bits padding
+    0x09, 0x30, 0x09, 0x31, 0x09, 0x33, 0x09, 0x34, // Usage (X, Y, Rx, Ry)
+    0x16, 0x00, 0x00, 0x27, 0xff, 0xff, 0x00, 0x00, // Logical 0..65535
+    0x75, 0x10, 0x95, 0x04, 0x81, 0x02, // 4 x 16 bits
+    0x06, 0x01, 0xff, // Usage Page (Vendor 0xFF01)
+    0x15, 0x00, 0x26, 0xff, 0x00, // Logical 0..255
+    0x85, 0x01, 0x09, 0x01, 0x75, 0x08, 0x95, 0x30, 0x91, 0x02, // 0x01: 48 bytes out
+    0x85, 0x10, 0x09, 0x10, 0x75, 0x08, 0x95, 0x30, 0x91, 0x02, // 0x10: 48 bytes out
+    0xc0, // End Collection
+];
+
+/// Protocol state for one host connection.
+#[derive(Debug, Clone)]
+pub struct SwitchProSession {
+    mac: [u8; 6],
+    input_mode: u8,
+    player_lights: u8,
+    imu_enabled: bool,
+    vibration_enabled: bool,
+    battery_percent: u8,
+}
+
+impl SwitchProSession {
+    /// `mac` is reported in the device info reply, most significant byte first.
+    pub fn new(mac: [u8; 6]) -> Self {
+        Self {
+            mac,
+            input_mode: SWITCH_SIMPLE_REPORT_ID,
+            player_lights: 0,
+            imu_enabled: false,
+            vibration_enabled: false,
+            battery_percent: SWITCH_DEFAULT_BATTERY_PERCENT,
+        }
+    }
+
+    /// Take `level_percent` (0..=100) for the reports that follow. Only full
+    /// 0x30 and 0x21 reports carry it.
+    pub fn set_battery_level(&mut self, level_percent: u8) {
+        self.battery_percent = level_percent.min(100);
+    }
+
+    pub fn player_lights(&self) -> u8 {
+        self.player_lights
+    }
+
+    /// Pack `report` in the input mode the host selected: simple 0x3F reports
+    /// until it asks for full 0x30 reports.
+    pub fn input_report_bytes(&self, report: &InputReport) -> Vec<u8> {
+        if self.input_mode == SWITCH_FULL_REPORT_ID {
+            full_report_bytes(report, self.imu_enabled, self.battery_percent).to_vec()
+        } else {
+            simple_report_bytes(report).to_vec()
+        }
+    }
+
+    /// Handle an output report (including its report ID byte). Returns the
+    /// 0x21 reply for subcommands.
+    pub fn handle_output_report(&mut self, data: &[u8], current: &InputReport) -> Option<Vec<u8>> {
+        if data.first() != Some(&SWITCH_SUBCOMMAND_REPORT_ID) || data.len() <= OUT_OFF_SUBCMD {
+            return None;
+        }
+        let subcmd = data[OUT_OFF_SUBCMD];
+        let args = &data[OUT_OFF_SUBCMD_DATA..];
+        let arg = |i: usize| args.get(i).copied().unwrap_or(0);
+
+        let (ack, reply): (u8, Vec<u8>) = match subcmd {
+            SUBCMD_MANUAL_PAIRING => (0x81, vec![0x03]),
+            SUBCMD_DEVICE_INFO => {
+                let mut info = Vec::with_capacity(12);
+                info.extend_from_slice(&FIRMWARE_VERSION);
+                info.push(CONTROLLER_TYPE_PRO);
+                info.push(0x02);
+                info.extend_from_slice(&self.mac);
+                info.push(0x01);
+                info.push(0x01);
+                (0x82, info)
+            }
+            SUBCMD_SET_INPUT_MODE => {
+                self.input_mode = arg(0);
+                (0x80, Vec::new())
+            }
+            SUBCMD_TRIGGER_ELAPSED => (0x83, vec![0; 14]),
+            SUBCMD_SPI_READ => {
+                let addr = u32::from_le_bytes([arg(0), arg(1), arg(2), arg(3)]);
+                let len = usize::from(arg(4)).min(MAX_SPI_READ);
+                let mut reply = Vec::with_capacity(5 + len);
+                reply.extend_from_slice(&addr.to_le_bytes());
+                reply.push(len as u8);
+                reply.extend(spi_read(addr, len));
+                (0x90, reply)
+            }
+            SUBCMD_SET_PLAYER_LIGHTS => {
+                self.player_lights = arg(0);
+                (0x80, Vec::new())
+            }
+            SUBCMD_ENABLE_IMU => {
+                self.imu_enabled = arg(0) != 0;
+                (0x80, Vec::new())
+            }
+            SUBCMD_ENABLE_VIBRATION => {
+                self.vibration_enabled = arg(0) != 0;
+                (0x80, Vec::new())
+            }
+            // Shipment mode, HOME light, IMU sensitivity, MCU config and the
+            // rest only need an acknowledgement.
+            _ => (0x80, Vec::new()),
+        };
+
+        let mut out = [0u8; SWITCH_FULL_REPORT_LEN];
+        out[0] = SWITCH_REPLY_REPORT_ID;
+        write_controller_state(&mut out, current, self.battery_percent);
+        out[OFF_ACK] = ack;
+        out[OFF_REPLY_SUBCMD] = subcmd;
+        let len = reply.len().min(SWITCH_FULL_REPORT_LEN - OFF_REPLY_DATA);
+        out[OFF_REPLY_DATA..OFF_REPLY_DATA + len].copy_from_slice(&reply[..len]);
+        Some(out.to_vec())
+    }
+}
+
+/// Pack `report` as a full 0x30 report. With `imu` set, the three IMU
+/// frames all carry the current sample.
+pub fn full_report_bytes(
+    report: &InputReport,
+    imu: bool,
+    battery_percent: u8,
+) -> [u8; SWITCH_FULL_REPORT_LEN] {
+    let mut out = [0u8; SWITCH_FULL_REPORT_LEN];
+    out[0] = SWITCH_FULL_REPORT_ID;
+    write_controller_state(&mut out, report, battery_percent);
+    if imu {
+        let frame = imu_frame(report);
+        for i in 0..3 {
+            let off = OFF_IMU + 12 * i;
+            out[off..off + 12].copy_from_slice(&frame);
+        }
+    }
+    out
+}
+
+/// Pack `report` as a simple 0x3F report, including the report ID byte.
+pub fn simple_report_bytes(report: &InputReport) -> [u8; SWITCH_SIMPLE_PAYLOAD_LEN + 1] {
+    let mut out = [0u8; SWITCH_SIMPLE_PAYLOAD_LEN + 1];
+    out[0] = SWITCH_SIMPLE_REPORT_ID;
+
+    let buttons = report.buttons;
+    let pressed = |mask: u16| buttons & mask != 0;
+    let mut b0 = 0u8;
+    for (i, on) in [
+        pressed(XBOX_BUTTON_A),
+        pressed(XBOX_BUTTON_B),
+        pressed(XBOX_BUTTON_X),
+        pressed(XBOX_BUTTON_Y),
+        pressed(XBOX_BUTTON_LB),
+        pressed(XBOX_BUTTON_RB),
+        report.lt > TRIGGER_PRESS_THRESHOLD,
+        report.rt > TRIGGER_PRESS_THRESHOLD,
+    ]
+    .into_iter()
+    .enumerate()
+    {
+        b0 |= u8::from(on) << i;
+    }
+    let mut b1 = 0u8;
+    for (i, on) in [
+        pressed(XBOX_BUTTON_SELECT),
+        pressed(XBOX_BUTTON_START),
+        pressed(XBOX_BUTTON_LS),
+        pressed(XBOX_BUTTON_RS),
+        pressed(XBOX_BUTTON_HOME),
+        report.share != 0,
+    ]
+    .into_iter()
+    .enumerate()
+    {
+        b1 |= u8::from(on) << i;
+    }
+    out[1] = b0;
+    out[2] = b1;
+    out[3] = match report.hat {
+        1..=8 => report.hat - 1,
+        _ => 0x08,
+    };
+    for (i, value) in [report.lx, report.ly, report.rx, report.ry]
+        .into_iter()
+        .enumerate()
+    {
+        let wire = (i32::from(value) + 0x8000) as u16;
+        out[4 + 2 * i..6 + 2 * i].copy_from_slice(&wire.to_le_bytes());
+    }
+    out
+}
+
+/// Fill the timer, battery, button and stick bytes shared by 0x21 and 0x30.
+fn write_controller_state(
+    out: &mut [u8; SWITCH_FULL_REPORT_LEN],
+    report: &InputReport,
+    battery_percent: u8,
+) {
+    // The timer ticks every 5 ms of sample time.
+    out[OFF_TIMER] = (report.timestamp_us / 5000) as u8;
+    out[OFF_BATTERY] = battery_level(battery_percent) << 4 | BATTERY_CONNECTION_PRO;
+    out[OFF_BUTTONS..OFF_BUTTONS + 3].copy_from_slice(&button_bytes(report));
+    out[OFF_LEFT_STICK..OFF_LEFT_STICK + 3].copy_from_slice(&pack_stick(report.lx, report.ly));
+    out[OFF_RIGHT_STICK..OFF_RIGHT_STICK + 3].copy_from_slice(&pack_stick(report.rx, report.ry));
+    out[OFF_VIBRATOR] = 0x0c;
+}
+
+/// Pro Controller battery level: 8 full, 6 medium, 4 low, 2 critical, 0 empty.
+fn battery_level(percent: u8) -> u8 {
+    match percent {
+        76.. => 8,
+        51..=75 => 6,
+        26..=50 => 4,
+        11..=25 => 2,
+        _ => 0,
+    }
+}
+
+/// Buttons map by position, so Xbox A (bottom) is Switch B (bottom).
+fn button_bytes(report: &InputReport) -> [u8; 3] {
+    let mut bits = 0u32;
+    for (xbox, switch) in [
+        (XBOX_BUTTON_A, BTN_B),
+        (XBOX_BUTTON_B, BTN_A),
+        (XBOX_BUTTON_X, BTN_Y),
+        (XBOX_BUTTON_Y, BTN_X),
+        (XBOX_BUTTON_LB, BTN_L),
+        (XBOX_BUTTON_RB, BTN_R),
+        (XBOX_BUTTON_SELECT, BTN_MINUS),
+        (XBOX_BUTTON_START, BTN_PLUS),
+        (XBOX_BUTTON_LS, BTN_LSTICK),
+        (XBOX_BUTTON_RS, BTN_RSTICK),
+        (XBOX_BUTTON_HOME, BTN_HOME),
+    ] {
+        if report.buttons & xbox != 0 {
+            bits |= switch;
+        }
+    }
+    if report.share != 0 {
+        bits |= BTN_CAPTURE;
+    }
+    if report.lt > TRIGGER_PRESS_THRESHOLD {
+        bits |= BTN_ZL;
+    }
+    if report.rt > TRIGGER_PRESS_THRESHOLD {
+        bits |= BTN_ZR;
+    }
+    let (up, right, down, left) = match report.hat {
+        1 => (true, false, false, false),
+        2 => (true, true, false, false),
+        3 => (false, true, false, false),
+        4 => (false, true, true, false),
+        5 => (false, false, true, false),
+        6 => (false, false, true, true),
+        7 => (false, false, false, true),
+        8 => (true, false, false, true),
+        _ => (false, false, false, false),
+    };
+    for (on, bit) in [
+        (up, BTN_UP),
+        (right, BTN_RIGHT),
+        (down, BTN_DOWN),
+        (left, BTN_LEFT),
+    ] {
+        if on {
+            bits |= bit;
+        }
+    }
+    let bytes = bits.to_le_bytes();
+    [bytes[0], bytes[1], bytes[2]]
+}
+
+/// Pack a stick as two 12-bit values. Switch sticks are Y-up.
+fn pack_stick(x: i16, y: i16) -> [u8; 3] {
+    let scale = |v: i32| (STICK_CENTER + v * STICK_RANGE / 0x8000) as u16;
+    pack_12bit_pair(scale(i32::from(x)), scale(-i32::from(y)))
+}
+
+fn pack_12bit_pair(a: u16, b: u16) -> [u8; 3] {
+    [
+        (a & 0xff) as u8,
+        (((a >> 8) & 0x0f) | ((b & 0x0f) << 4)) as u8,
+        (b >> 4) as u8,
+    ]
+}
+
+/// One IMU frame: accel X/Y/Z then gyro X/Y/Z, in the Pro Controller's own
+/// axes and at the factory calibration served from SPI flash.
+fn imu_frame(report: &InputReport) -> [u8; 12] {
+    // (pitch, yaw, roll) -> Switch (x, y, z) = (-roll, -pitch, yaw)
+    let remap = |v: [i16; 3]| [-i32::from(v[2]), -i32::from(v[0]), i32::from(v[1])];
+    // Deck: 16384 LSB/g and 16.4 LSB/(deg/s). Switch: 4096 LSB/g and
+    // 14.3 LSB/(deg/s).
+    let accel = remap(report.accel).map(|v| v / 4);
+    let gyro = remap(report.gyro).map(|v| v * 7 / 8);
+    let mut frame = [0u8; 12];
+    for (i, v) in accel.into_iter().chain(gyro).enumerate() {
+        let v = v.clamp(i32::from(i16::MIN), i32::from(i16::MAX)) as i16;
+        frame[2 * i..2 * i + 2].copy_from_slice(&v.to_le_bytes());
+    }
+    frame
+}
+
+/// Regions of the emulated SPI flash; everything else reads as erased (0xFF).
+fn spi_regions() -> [(u32, Vec<u8>); 7] {
+    let stick_center = pack_12bit_pair(STICK_CENTER as u16, STICK_CENTER as u16);
+    let stick_range = pack_12bit_pair(STICK_RANGE as u16, STICK_RANGE as u16);
+    // Left stick: max-above, center, min-below. Right: center, min, max.
+    let left_cal = [stick_range, stick_center, stick_range].concat();
+    let right_cal = [stick_center, stick_range, stick_range].concat();
+
+    let mut imu_cal = Vec::with_capacity(24);
+    for word in [
+        0, 0, 0, 0x4000, 0x4000, 0x4000, 0, 0, 0, 0x343b, 0x343b, 0x343b,
+    ] {
+        imu_cal.extend_from_slice(&(word as u16).to_le_bytes());
+    }
+
+    let stick_params = vec![
+        0x0f, 0x30, 0x61, 0x96, 0x30, 0xf3, 0xd4, 0x14, 0x54, 0x41, 0x15, 0x54, 0xc7, 0x79, 0x9c,
+        0x33, 0x36, 0x63,
+    ];
+
+    [
+        // Device type, then color info present.
+        (0x6012, vec![CONTROLLER_TYPE_PRO]),
+        (0x601b, vec![0x01]),
+        (0x6020, imu_cal),
+        (0x603d, [left_cal, right_cal].concat()),
+        // Body, buttons, left grip, right grip.
+        (
+            0x6050,
+            vec![
+                0x32, 0x32, 0x32, 0xff, 0xff, 0xff, 0x32, 0x32, 0x32, 0x32, 0x32, 0x32,
+            ],
+        ),
+        (
+            0x6080,
+            [
+                vec![0x50, 0xfd, 0x00, 0x00, 0xc6, 0x0f],
+                stick_params.clone(),
+            ]
+            .concat(),
+        ),
+        (0x6098, stick_params),
+    ]
+}
+
+/// Read `len` bytes of the emulated SPI flash. User calibration (0x8010..)
+/// reads as erased, so hosts fall back to the factory values.
+fn spi_read(addr: u32, len: usize) -> Vec<u8> {
+    let regions = spi_regions();
+    (0..len as u32)
+        .map(|i| {
+            let a = addr + i;
+            regions
+                .iter()
+                .find_map(|(start, data)| {
+                    a.checked_sub(*start)
+                        .and_then(|off| data.get(off as usize).copied())
+                })
+                .unwrap_or(0xff)
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn subcommand(id: u8, args: &[u8]) -> Vec<u8> {
+        let mut out = vec![0u8; SWITCH_OUTPUT_PAYLOAD_LEN + 1];
+        out[0] = SWITCH_SUBCOMMAND_REPORT_ID;
+        out[OUT_OFF_SUBCMD] = id;
+        out[OUT_OFF_SUBCMD_DATA..OUT_OFF_SUBCMD_DATA + args.len()].copy_from_slice(args);
+        out
+    }
+
+    #[test]
+    fn device_info_reply_carries_type_and_mac() {
+        let mac = [0x98, 0xb6, 0xe9, 0x01, 0x02, 0x03];
+        let mut session = SwitchProSession::new(mac);
+        let reply = session
+            .handle_output_report(
+                &subcommand(SUBCMD_DEVICE_INFO, &[]),
+                &InputReport::default(),
+            )
+            .expect("device info reply");
+        assert_eq!(reply.len(), SWITCH_FULL_REPORT_LEN);
+        assert_eq!(reply[0], SWITCH_REPLY_REPORT_ID);
+        assert_eq!(reply[OFF_ACK], 0x82);
+        assert_eq!(reply[OFF_REPLY_SUBCMD], SUBCMD_DEVICE_INFO);
+        assert_eq!(reply[OFF_REPLY_DATA + 2], CONTROLLER_TYPE_PRO);
+        assert_eq!(&reply[OFF_REPLY_DATA + 4..OFF_REPLY_DATA + 10], &mac);
+    }
+
+    #[test]
+    fn spi_read_returns_factory_calibration() {
+        let mut session = SwitchProSession::new([0; 6]);
+        let reply = session
+            .handle_output_report(
+                &subcommand(SUBCMD_SPI_READ, &[0x20, 0x60, 0x00, 0x00, 0x18]),
+                &InputReport::default(),
+            )
+            .expect("spi reply");
+        assert_eq!(reply[OFF_ACK], 0x90);
+        assert_eq!(
+            &reply[OFF_REPLY_DATA..OFF_REPLY_DATA + 5],
+            &[0x20, 0x60, 0, 0, 0x18]
+        );
+        let data = &reply[OFF_REPLY_DATA + 5..OFF_REPLY_DATA + 5 + 0x18];
+        // Accel sensitivity 0x4000, gyro sensitivity 0x343b.
+        assert_eq!(&data[6..8], &[0x00, 0x40]);
+        assert_eq!(&data[18..20], &[0x3b, 0x34]);
+
+        // User calibration area is erased.
+        assert_eq!(spi_read(0x8010, 2), vec![0xff, 0xff]);
+    }
+
+    #[test]
+    fn input_mode_and_player_lights_follow_subcommands() {
+        let mut session = SwitchProSession::new([0; 6]);
+        let report = InputReport::default();
+        assert_eq!(
+            session.input_report_bytes(&report)[0],
+            SWITCH_SIMPLE_REPORT_ID
+        );
+
+        session.handle_output_report(&subcommand(SUBCMD_SET_INPUT_MODE, &[0x30]), &report);
+        session.handle_output_report(&subcommand(SUBCMD_SET_PLAYER_LIGHTS, &[0x01]), &report);
+        let bytes = session.input_report_bytes(&report);
+        assert_eq!(bytes[0], SWITCH_FULL_REPORT_ID);
+        assert_eq!(bytes.len(), SWITCH_FULL_REPORT_LEN);
+        assert_eq!(session.player_lights(), 0x01);
+
+        // Rumble-only reports need no reply.
+        let mut rumble = vec![0u8; SWITCH_OUTPUT_PAYLOAD_LEN + 1];
+        rumble[0] = SWITCH_RUMBLE_REPORT_ID;
+        assert!(session.handle_output_report(&rumble, &report).is_none());
+    }
+
+    #[test]
+    fn full_report_packs_buttons_by_position_and_centered_sticks() {
+        let report = InputReport {
+            buttons: XBOX_BUTTON_A | XBOX_BUTTON_HOME,
+            hat: 1,
+            rt: XBOX_TRIGGER_MAX,
+            ly: i16::MIN,
+            ..InputReport::default()
+        };
+        let bytes = full_report_bytes(&report, false, SWITCH_DEFAULT_BATTERY_PERCENT);
+        assert_eq!(bytes[OFF_BUTTONS], (BTN_B | BTN_ZR) as u8);
+        assert_eq!(bytes[OFF_BUTTONS + 1], (BTN_HOME >> 8) as u8);
+        assert_eq!(bytes[OFF_BUTTONS + 2], (BTN_UP >> 16) as u8);
+        // Right stick centered; left stick pushed fully up.
+        assert_eq!(
+            &bytes[OFF_RIGHT_STICK..OFF_RIGHT_STICK + 3],
+            &pack_12bit_pair(0x800, 0x800)
+        );
+        assert_eq!(
+            &bytes[OFF_LEFT_STICK..OFF_LEFT_STICK + 3],
+            &pack_12bit_pair(0x800, (STICK_CENTER + STICK_RANGE) as u16)
+        );
+        // IMU disabled: frames are zero.
+        assert!(bytes[OFF_IMU..].iter().all(|b| *b == 0));
+    }
+
+    #[test]
+    fn session_reports_battery_level_in_full_reports() {
+        let mut session = SwitchProSession::new([0; 6]);
+        let report = InputReport::default();
+        session.handle_output_report(&subcommand(SUBCMD_SET_INPUT_MODE, &[0x30]), &report);
+        assert_eq!(session.input_report_bytes(&report)[OFF_BATTERY], 0x80);
+
+        session.set_battery_level(40);
+        assert_eq!(session.input_report_bytes(&report)[OFF_BATTERY], 0x40);
+        let reply = session
+            .handle_output_report(&subcommand(SUBCMD_DEVICE_INFO, &[]), &report)
+            .unwrap();
+        assert_eq!(reply[OFF_BATTERY], 0x40);
+        session.set_battery_level(5);
+        assert_eq!(session.input_report_bytes(&report)[OFF_BATTERY], 0x00);
+    }
+
+    #[test]
+    fn descriptor_declares_all_reports() {
+        for id in [
+            SWITCH_REPLY_REPORT_ID,
+            SWITCH_FULL_REPORT_ID,
+            SWITCH_SIMPLE_REPORT_ID,
+            SWITCH_SUBCOMMAND_REPORT_ID,
+            SWITCH_RUMBLE_REPORT_ID,
+        ] {
+            assert!(SWITCH_PRO_HID_REPORT_DESCRIPTOR
+                .windows(2)
+                .any(|w| w == [0x85, id]));
+        }
+    }
+}
@@ -1,7 +1,9 @@
 #![forbid(unsafe_code)]
 
 pub mod config;
+pub mod control;
 pub mod hid;
+pub mod net;
 
 #[cfg(test)]
 mod tests {
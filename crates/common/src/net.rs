@@ -0,0 +1,300 @@
+//! Wire format of the `[network]` report stream between `hidd` and a
+//! receiver such as `netrecv`.
+//!
+//! Every packet starts with an 8-byte header: the magic `CN`, the protocol
+//! version, the message kind and a little-endian sequence number that the
+//! sender increments per packet. Each kind has a fixed length, so TCP needs no
+//! extra framing and UDP carries one packet per datagram. All integers are
+//! little-endian.
+//!
+//! | Kind | Direction | Body |
+//! |------|-----------|------|
+//! | `0x01` input | hidd to receiver | send time (u64 µs), then the [`InputReport`] fields in declaration order; trackpads as a flags byte (bit 0 active, bit 1 clicked), x and y |
+//! | `0x02` rumble | receiver to hidd | the [`OutputReport`] fields in declaration order, one byte each |
+
+use thiserror::Error;
+
+use crate::hid::{InputReport, OutputReport, TouchPoint};
+
+pub const NET_MAGIC: [u8; 2] = *b"CN";
+pub const NET_PROTOCOL_VERSION: u8 = 1;
+/// Port `netrecv` listens on unless told otherwise.
+pub const DEFAULT_NET_PORT: u16 = 47_800;
+
+pub const NET_HEADER_LEN: usize = 8;
+const KIND_INPUT: u8 = 0x01;
+const KIND_RUMBLE: u8 = 0x02;
+const INPUT_BODY_LEN: usize = 58;
+const RUMBLE_BODY_LEN: usize = 8;
+
+/// Longest packet of any kind.
+pub const NET_MAX_PACKET_LEN: usize = NET_HEADER_LEN + INPUT_BODY_LEN;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum NetMessage {
+    Input {
+        /// Sender clock when the packet was sent, in microseconds since the
+        /// stream started. `report.timestamp_us` is the controller's own
+        /// sample time.
+        sent_us: u64,
+        report: InputReport,
+    },
+    Rumble(OutputReport),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct NetPacket {
+    pub sequence: u32,
+    pub message: NetMessage,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Error)]
+pub enum NetError {
+    /// More bytes are needed; on TCP, read more and try again.
+    #[error("incomplete packet")]
+    Incomplete,
+    #[error("not a ControllerOS report stream packet")]
+    BadMagic,
+    #[error("unsupported protocol version {0}")]
+    Version(u8),
+    #[error("unknown message kind 0x{0:02x}")]
+    Kind(u8),
+}
+
+impl NetPacket {
+    pub fn encode(&self) -> Vec<u8> {
+        let mut out = Vec::with_capacity(NET_MAX_PACKET_LEN);
+        out.extend_from_slice(&NET_MAGIC);
+        out.push(NET_PROTOCOL_VERSION);
+        match self.message {
+            NetMessage::Input { sent_us, report } => {
+                out.push(KIND_INPUT);
+                out.extend_from_slice(&self.sequence.to_le_bytes());
+                encode_input(&mut out, sent_us, &report);
+            }
+            NetMessage::Rumble(rumble) => {
+                out.push(KIND_RUMBLE);
+                out.extend_from_slice(&self.sequence.to_le_bytes());
+                out.extend_from_slice(&[
+                    rumble.dc_enable_actuators,
+                    rumble.left_trigger_magnitude,
+                    rumble.right_trigger_magnitude,
+                    rumble.weak_motor_magnitude,
+                    rumble.strong_motor_magnitude,
+                    rumble.duration,
+                    rumble.start_delay,
+                    rumble.loop_count,
+                ]);
+            }
+        }
+        out
+    }
+
+    /// Decode the packet at the start of `buf`, returning it and its length.
+    pub fn decode(buf: &[u8]) -> Result<(Self, usize), NetError> {
+        if buf.len() < NET_HEADER_LEN {
+            // Reject garbage early instead of waiting for a full header.
+            if !NET_MAGIC.starts_with(&buf[..buf.len().min(NET_MAGIC.len())]) {
+                return Err(NetError::BadMagic);
+            }
+            return Err(NetError::Incomplete);
+        }
+        if buf[..2] != NET_MAGIC {
+            return Err(NetError::BadMagic);
+        }
+        if buf[2] != NET_PROTOCOL_VERSION {
+            return Err(NetError::Version(buf[2]));
+        }
+        let kind = buf[3];
+        let body_len = match kind {
+            KIND_INPUT => INPUT_BODY_LEN,
+            KIND_RUMBLE => RUMBLE_BODY_LEN,
+            other => return Err(NetError::Kind(other)),
+        };
+        let len = NET_HEADER_LEN + body_len;
+        let body = buf.get(NET_HEADER_LEN..len).ok_or(NetError::Incomplete)?;
+        let sequence = u32::from_le_bytes([buf[4], buf[5], buf[6], buf[7]]);
+        let message = if kind == KIND_INPUT {
+            decode_input(body)
+        } else {
+            NetMessage::Rumble(OutputReport {
+                dc_enable_actuators: body[0],
+                left_trigger_magnitude: body[1],
+                right_trigger_magnitude: body[2],
+                weak_motor_magnitude: body[3],
+                strong_motor_magnitude: body[4],
+                duration: body[5],
+                start_delay: body[6],
+                loop_count: body[7],
+            })
+        };
+        Ok((Self { sequence, message }, len))
+    }
+}
+
+fn encode_input(out: &mut Vec<u8>, sent_us: u64, report: &InputReport) {
+    out.extend_from_slice(&sent_us.to_le_bytes());
+    out.extend_from_slice(&report.buttons.to_le_bytes());
+    out.push(report.hat);
+    out.push(report.share);
+    for axis in [report.lx, report.ly, report.rx, report.ry] {
+        out.extend_from_slice(&axis.to_le_bytes());
+    }
+    out.extend_from_slice(&report.lt.to_le_bytes());
+    out.extend_from_slice(&report.rt.to_le_bytes());
+    out.extend_from_slice(&report.extra_buttons.to_le_bytes());
+    out.extend_from_slice(&report.keys);
+    for touch in report.touch {
+        out.push(u8::from(touch.active) | u8::from(touch.clicked) << 1);
+        out.extend_from_slice(&touch.x.to_le_bytes());
+        out.extend_from_slice(&touch.y.to_le_bytes());
+    }
+    for value in report.gyro.into_iter().chain(report.accel) {
+        out.extend_from_slice(&value.to_le_bytes());
+    }
+    out.extend_from_slice(&report.timestamp_us.to_le_bytes());
+}
+
+fn decode_input(body: &[u8]) -> NetMessage {
+    let mut body = Body(body);
+    let sent_us = u64::from_le_bytes(body.take());
+    let buttons = u16::from_le_bytes(body.take());
+    let [hat, share] = body.take();
+    let mut axis = || i16::from_le_bytes(body.take());
+    let (lx, ly, rx, ry) = (axis(), axis(), axis(), axis());
+    let lt = u16::from_le_bytes(body.take());
+    let rt = u16::from_le_bytes(body.take());
+    let extra_buttons = u16::from_le_bytes(body.take());
+    let keys = body.take();
+    let mut touch = [TouchPoint::default(); 2];
+    for point in &mut touch {
+        let [flags] = body.take();
+        *point = TouchPoint {
+            active: flags & 0x01 != 0,
+            clicked: flags & 0x02 != 0,
+            x: i16::from_le_bytes(body.take()),
+            y: i16::from_le_bytes(body.take()),
+        };
+    }
+    let mut motion = [0i16; 6];
+    for value in &mut motion {
+        *value = i16::from_le_bytes(body.take());
+    }
+    let timestamp_us = u32::from_le_bytes(body.take());
+    NetMessage::Input {
+        sent_us,
+        report: InputReport {
+            buttons,
+            hat,
+            lx,
+            ly,
+            rx,
+            ry,
+            lt,
+            rt,
+            share,
+            extra_buttons,
+            keys,
+            touch,
+            gyro: [motion[0], motion[1], motion[2]],
+            accel: [motion[3], motion[4], motion[5]],
+            timestamp_us,
+        },
+    }
+}
+
+/// Reads fixed-size fields off the front of a body whose length was checked.
+struct Body<'a>(&'a [u8]);
+
+impl Body<'_> {
+    fn take<const N: usize>(&mut self) -> [u8; N] {
+        let (field, rest) = self.0.split_at(N);
+        self.0 = rest;
+        field.try_into().expect("split_at returned N bytes")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{NetError, NetMessage, NetPacket, NET_MAX_PACKET_LEN};
+    use crate::hid::{InputReport, OutputReport, TouchPoint};
+
+    #[test]
+    fn packets_round_trip() {
+        let report = InputReport {
+            buttons: 0x1234,
+            hat: 3,
+            lx: -32768,
+            ry: 32767,
+            lt: 1023,
+            share: 1,
+            extra_buttons: 0x0005,
+            keys: [4, 5, 0, 0, 0, 0],
+            touch: [
+                TouchPoint {
+                    active: true,
+                    clicked: false,
+                    x: -100,
+                    y: 200,
+                },
+                TouchPoint {
+                    active: true,
+                    clicked: true,
+                    x: 300,
+                    y: -400,
+                },
+            ],
+            gyro: [1, -2, 3],
+            accel: [-16384, 0, 16384],
+            timestamp_us: 0xdead_beef,
+            ..InputReport::default()
+        };
+        let input = NetPacket {
+            sequence: 7,
+            message: NetMessage::Input {
+                sent_us: 123_456_789,
+                report,
+            },
+        };
+        let bytes = input.encode();
+        assert_eq!(bytes.len(), NET_MAX_PACKET_LEN);
+        assert_eq!(NetPacket::decode(&bytes), Ok((input, bytes.len())));
+
+        let rumble = NetPacket {
+            sequence: u32::MAX,
+            message: NetMessage::Rumble(OutputReport {
+                dc_enable_actuators: 0x0f,
+                weak_motor_magnitude: 40,
+                strong_motor_magnitude: 80,
+                duration: 25,
+                ..OutputReport::default()
+            }),
+        };
+        let bytes = rumble.encode();
+        assert_eq!(NetPacket::decode(&bytes), Ok((rumble, bytes.len())));
+    }
+
+    #[test]
+    fn decode_reports_partial_and_foreign_packets() {
+        let mut bytes = NetPacket {
+            sequence: 1,
+            message: NetMessage::Rumble(OutputReport::default()),
+        }
+        .encode();
+        let len = bytes.len();
+        bytes.extend_from_slice(b"CN");
+        assert_eq!(NetPacket::decode(&bytes).map(|(_, used)| used), Ok(len));
+        assert_eq!(NetPacket::decode(&bytes[len..]), Err(NetError::Incomplete));
+        assert_eq!(
+            NetPacket::decode(&bytes[..len - 1]),
+            Err(NetError::Incomplete)
+        );
+        assert_eq!(NetPacket::decode(b"GET /"), Err(NetError::BadMagic));
+
+        bytes[2] = 2;
+        assert_eq!(NetPacket::decode(&bytes), Err(NetError::Version(2)));
+        bytes[2] = 1;
+        bytes[3] = 0x7f;
+        assert_eq!(NetPacket::decode(&bytes), Err(NetError::Kind(0x7f)));
+    }
+}
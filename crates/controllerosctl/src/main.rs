@@ -8,10 +8,16 @@ use std::time::Duration;
 
 use anyhow::{anyhow, Result};
 use common::config::{HidConfig, DEFAULT_HID_CONFIG_PATH};
+use common::control::{CONTROL_BUS_NAME, CONTROL_INTERFACE, CONTROL_OBJECT_PATH};
+use common::hid::descriptor::{usage_name, ReportDescriptor, ReportField};
 use common::hid::{
-    XBOX_BUTTON_A, XBOX_BUTTON_B, XBOX_BUTTON_HOME, XBOX_BUTTON_LB, XBOX_BUTTON_LS, XBOX_BUTTON_RB,
-    XBOX_BUTTON_RS, XBOX_BUTTON_SELECT, XBOX_BUTTON_START, XBOX_BUTTON_X, XBOX_BUTTON_Y,
+    HidProfile, HidProfileMode, XBOX_BUTTON_A, XBOX_BUTTON_B, XBOX_BUTTON_HOME, XBOX_BUTTON_LB,
+    XBOX_BUTTON_LS, XBOX_BUTTON_RB, XBOX_BUTTON_RS, XBOX_BUTTON_SELECT, XBOX_BUTTON_START,
+    XBOX_BUTTON_X, XBOX_BUTTON_Y,
 };
+use dbus::arg::{prop_cast, PropMap};
+use dbus::blocking::stdintf::org_freedesktop_dbus::Properties;
+use dbus::blocking::Connection;
 use input::{discover_devices, InputReader, MappingConfig};
 
 fn main() -> ExitCode {
@@ -28,8 +34,25 @@ fn run() -> Result<()> {
     let args = Args::parse(env::args().skip(1))?;
     match args.cmd {
         CommandKind::HidSelfTest => run_hid_self_test(&args),
+        CommandKind::HidDescriptor => run_hid_descriptor(&args),
         CommandKind::InputList => run_input_list(),
         CommandKind::InputMonitor => run_input_monitor(&args),
+        CommandKind::HostList => run_host_list(),
+        CommandKind::HostConnections => run_host_connections(),
+        CommandKind::HostNext => call_hidd("NextHost", ()),
+        CommandKind::HostSelect => {
+            call_hidd("SelectHost", (args.host.clone().unwrap_or_default(),))
+        }
+        CommandKind::PairingStart => call_hidd("StartPairing", (args.pairing_timeout_s,)),
+        CommandKind::PairingStop => call_hidd("StopPairing", ()),
+        CommandKind::Status => run_status(),
+        CommandKind::ProfileSelect => {
+            call_hidd("SelectProfile", (args.select.clone().unwrap_or_default(),))
+        }
+        CommandKind::MappingSelect => {
+            call_hidd("SelectMapping", (args.select.clone().unwrap_or_default(),))
+        }
+        CommandKind::Disconnect => call_hidd("Disconnect", ()),
         CommandKind::Help => {
             print_help();
             Ok(())
@@ -44,6 +67,14 @@ struct Args {
     mapping_config_path: String,
     hidd_path: PathBuf,
     pattern_seconds: u64,
+    /// Profile to print instead of the one in `--config`.
+    mode: Option<HidProfileMode>,
+    /// Address for `host select`.
+    host: Option<String>,
+    /// `pairing start --timeout`; 0 uses hidd's configured timeout.
+    pairing_timeout_s: u32,
+    /// Profile or mapping for `profile select` and `mapping select`.
+    select: Option<String>,
 }
 
 const DEFAULT_MAPPING_CONFIG_PATH: &str = "/etc/controlleros/mapping/xbox.toml";
@@ -51,8 +82,19 @@ const DEFAULT_MAPPING_CONFIG_PATH: &str = "/etc/controlleros/mapping/xbox.toml";
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 enum CommandKind {
     HidSelfTest,
+    HidDescriptor,
     InputList,
     InputMonitor,
+    HostList,
+    HostConnections,
+    HostNext,
+    HostSelect,
+    PairingStart,
+    PairingStop,
+    Status,
+    ProfileSelect,
+    MappingSelect,
+    Disconnect,
     Help,
 }
 
@@ -65,14 +107,23 @@ impl Args {
         let mut mapping_config_path = DEFAULT_MAPPING_CONFIG_PATH.to_string();
         let mut hidd_path = infer_hidd_path();
         let mut pattern_seconds = 2u64;
+        let mut mode = None;
+        let mut host = None;
+        let mut pairing_timeout_s = 0u32;
+        let mut select = None;
 
         let first = args.next();
         let mut cmd = match first.as_deref() {
             None | Some("--help") | Some("-h") => CommandKind::Help,
             Some("hid") => match args.next().as_deref() {
                 Some("self-test") => CommandKind::HidSelfTest,
+                Some("descriptor") => CommandKind::HidDescriptor,
                 Some(other) => return Err(anyhow!("unknown hid subcommand: {other}")),
-                None => return Err(anyhow!("missing hid subcommand (expected: self-test)")),
+                None => {
+                    return Err(anyhow!(
+                        "missing hid subcommand (expected: self-test, descriptor)"
+                    ))
+                }
             },
             Some("input") => match args.next().as_deref() {
                 Some("list") => CommandKind::InputList,
@@ -84,6 +135,61 @@ impl Args {
                     ))
                 }
             },
+            Some("host") => match args.next().as_deref() {
+                Some("list") => CommandKind::HostList,
+                Some("connections") => CommandKind::HostConnections,
+                Some("next") => CommandKind::HostNext,
+                Some("select") => {
+                    host = Some(
+                        args.next()
+                            .ok_or_else(|| anyhow!("missing address for host select"))?,
+                    );
+                    CommandKind::HostSelect
+                }
+                Some(other) => return Err(anyhow!("unknown host subcommand: {other}")),
+                None => {
+                    return Err(anyhow!(
+                        "missing host subcommand (expected: list, connections, next, select)"
+                    ))
+                }
+            },
+            Some("pairing") => match args.next().as_deref() {
+                Some("start") => CommandKind::PairingStart,
+                Some("stop") => CommandKind::PairingStop,
+                Some(other) => return Err(anyhow!("unknown pairing subcommand: {other}")),
+                None => {
+                    return Err(anyhow!(
+                        "missing pairing subcommand (expected: start, stop)"
+                    ))
+                }
+            },
+            Some("status") => CommandKind::Status,
+            Some("profile") => match args.next().as_deref() {
+                Some("select") => {
+                    let raw = args
+                        .next()
+                        .ok_or_else(|| anyhow!("missing mode for profile select"))?;
+                    if HidProfileMode::from_name(&raw).is_none() {
+                        return Err(anyhow!("unknown profile mode: {raw}"));
+                    }
+                    select = Some(raw);
+                    CommandKind::ProfileSelect
+                }
+                Some(other) => return Err(anyhow!("unknown profile subcommand: {other}")),
+                None => return Err(anyhow!("missing profile subcommand (expected: select)")),
+            },
+            Some("mapping") => match args.next().as_deref() {
+                Some("select") => {
+                    select = Some(
+                        args.next()
+                            .ok_or_else(|| anyhow!("missing name for mapping select"))?,
+                    );
+                    CommandKind::MappingSelect
+                }
+                Some(other) => return Err(anyhow!("unknown mapping subcommand: {other}")),
+                None => return Err(anyhow!("missing mapping subcommand (expected: select)")),
+            },
+            Some("disconnect") => CommandKind::Disconnect,
             Some(other) => return Err(anyhow!("unknown command: {other}")),
         };
 
@@ -116,6 +222,25 @@ impl Args {
                         return Err(anyhow!("--pattern-seconds must be in 1..=30"));
                     }
                 }
+                "--mode" => {
+                    let raw = args
+                        .next()
+                        .ok_or_else(|| anyhow!("missing value for --mode"))?;
+                    mode = Some(
+                        HidProfileMode::from_name(&raw)
+                            .ok_or_else(|| anyhow!("unknown profile mode: {raw}"))?,
+                    );
+                }
+                "--timeout" => {
+                    let raw = args
+                        .next()
+                        .ok_or_else(|| anyhow!("missing value for --timeout"))?;
+                    pairing_timeout_s = raw
+                        .parse::<u32>()
+                        .ok()
+                        .filter(|&s| s > 0)
+                        .ok_or_else(|| anyhow!("invalid --timeout value: {raw}"))?;
+                }
                 "--help" | "-h" => {
                     cmd = CommandKind::Help;
                 }
@@ -129,6 +254,10 @@ impl Args {
             mapping_config_path,
             hidd_path,
             pattern_seconds,
+            mode,
+            host,
+            pairing_timeout_s,
+            select,
         })
     }
 }
@@ -152,11 +281,9 @@ fn run_hid_self_test(args: &Args) -> Result<()> {
         "profile_identity=vid=0x{:04x} pid=0x{:04x} version=0x{:04x} country={}",
         cfg.profile.vendor_id, cfg.profile.product_id, cfg.profile.version, cfg.profile.country
     );
-    println!(
-        "descriptor_len={}",
-        cfg.profile.mode.report_descriptor().len()
-    );
-    println!("report_len={}", cfg.profile.mode.input_report_len());
+    let profile = cfg.profile.hid_profile();
+    println!("descriptor_len={}", profile.report_descriptor().len());
+    println!("report_len={}", profile.input_report_len());
 
     run_hidd(args, &["--self-test", "--config", &args.config_path])?;
     println!("UHID OK");
@@ -197,6 +324,70 @@ fn run_hid_self_test(args: &Args) -> Result<()> {
     Ok(())
 }
 
+fn run_hid_descriptor(args: &Args) -> Result<()> {
+    let profile = match args.mode {
+        Some(mode) => HidProfile::from(mode),
+        None => HidConfig::load_from_path(&args.config_path)?
+            .profile
+            .hid_profile(),
+    };
+    let descriptor = profile.report_descriptor();
+    println!(
+        "profile={} descriptor_len={}",
+        profile.mode().as_str(),
+        descriptor.len()
+    );
+    let parsed = ReportDescriptor::parse(descriptor)
+        .map_err(|e| anyhow!("invalid report descriptor: {e}"))?;
+    for report in &parsed.reports {
+        println!();
+        println!(
+            "{} report 0x{:02x}: {} bytes",
+            report.kind.as_str(),
+            report.id,
+            report.payload_len()
+        );
+        for field in &report.fields {
+            println!("  {}", format_field(field));
+        }
+    }
+    Ok(())
+}
+
+fn format_field(field: &ReportField) -> String {
+    let position = format!(
+        "bit {:>3} {:>2}x{:<3}",
+        field.bit_offset, field.bit_size, field.count
+    );
+    if field.is_constant() {
+        return format!("{position} padding");
+    }
+    let usages = match field.usages.as_slice() {
+        [] => "-".to_string(),
+        [first, .., last] if field.usages.len() > 4 => {
+            format!("{}..{}", usage_name(*first), usage_name(*last))
+        }
+        usages => usages
+            .iter()
+            .map(|u| usage_name(*u))
+            .collect::<Vec<_>>()
+            .join(", "),
+    };
+    let mut flags = vec![if field.is_variable() { "var" } else { "array" }];
+    if field.is_relative() {
+        flags.push("rel");
+    }
+    if field.has_null_state() {
+        flags.push("null");
+    }
+    format!(
+        "{position} {usages} [{}..{}] {}",
+        field.logical_min,
+        field.logical_max,
+        flags.join(" ")
+    )
+}
+
 fn run_hidd(args: &Args, hidd_args: &[&str]) -> Result<()> {
     let status = Command::new(&args.hidd_path)
         .args(hidd_args)
@@ -237,6 +428,122 @@ fn run_input_list() -> Result<()> {
     Ok(())
 }
 
+fn run_host_list() -> Result<()> {
+    let conn = Connection::new_system().map_err(|e| anyhow!("system bus: {e}"))?;
+    let proxy = conn.with_proxy(
+        CONTROL_BUS_NAME,
+        CONTROL_OBJECT_PATH,
+        Duration::from_secs(5),
+    );
+    let (hosts,): (Vec<(String, String, bool, bool)>,) = proxy
+        .method_call(CONTROL_INTERFACE, "ListHosts", ())
+        .map_err(|e| anyhow!("ListHosts on {CONTROL_BUS_NAME} failed: {e}"))?;
+    if hosts.is_empty() {
+        println!("no bonded hosts");
+        return Ok(());
+    }
+    for (address, name, connected, current) in &hosts {
+        let marker = if *current { " *" } else { "" };
+        let state = if *connected { "connected" } else { "bonded" };
+        println!("{address} \"{name}\" {state}{marker}");
+    }
+    println!();
+    println!("* = current host");
+    Ok(())
+}
+
+fn run_status() -> Result<()> {
+    let conn = Connection::new_system().map_err(|e| anyhow!("system bus: {e}"))?;
+    let proxy = conn.with_proxy(
+        CONTROL_BUS_NAME,
+        CONTROL_OBJECT_PATH,
+        Duration::from_secs(5),
+    );
+    let props: PropMap = proxy
+        .get_all(CONTROL_INTERFACE)
+        .map_err(|e| anyhow!("reading {CONTROL_BUS_NAME} properties failed: {e}"))?;
+    let text = |name: &str| {
+        prop_cast::<String>(&props, name)
+            .filter(|v| !v.is_empty())
+            .map_or("-", String::as_str)
+    };
+    let flag = |name: &str| prop_cast::<bool>(&props, name).copied().unwrap_or(false);
+    println!("transport:  {}", text("Transport"));
+    println!("profile:    {}", text("Profile"));
+    println!("mapping:    {}", text("Mapping"));
+    println!("host:       {}", text("ConnectedHost"));
+    println!(
+        "notifying:  {}",
+        if flag("Notifying") { "yes" } else { "no" }
+    );
+    println!(
+        "suspended:  {}",
+        if flag("Suspended") { "yes" } else { "no" }
+    );
+    match prop_cast::<u16>(&props, "ReportRate") {
+        Some(rate) => println!("rate:       {rate} Hz"),
+        None => println!("rate:       -"),
+    }
+    println!("input:      {}", text("InputHealth"));
+    println!(
+        "pairing:    {}",
+        if flag("Pairing") { "open" } else { "closed" }
+    );
+    println!("idle:       {}", if flag("Idle") { "yes" } else { "no" });
+    Ok(())
+}
+
+fn run_host_connections() -> Result<()> {
+    let conn = Connection::new_system().map_err(|e| anyhow!("system bus: {e}"))?;
+    let proxy = conn.with_proxy(
+        CONTROL_BUS_NAME,
+        CONTROL_OBJECT_PATH,
+        Duration::from_secs(5),
+    );
+    type Entry = (String, String, bool, bool, u32, Vec<u8>, bool);
+    let (connections,): (Vec<Entry>,) = proxy
+        .method_call(CONTROL_INTERFACE, "ListConnections", ())
+        .map_err(|e| anyhow!("ListConnections on {CONTROL_BUS_NAME} failed: {e}"))?;
+    if connections.is_empty() {
+        println!("no known devices");
+        return Ok(());
+    }
+    for (address, name, connected, bonded, seconds, reports, battery) in &connections {
+        let bond = if *bonded { "bonded" } else { "not bonded" };
+        if !connected {
+            println!("{address} \"{name}\" {bond}, disconnected");
+            continue;
+        }
+        let mut notify = reports
+            .iter()
+            .map(|id| format!("0x{id:02x}"))
+            .collect::<Vec<_>>();
+        if *battery {
+            notify.push("battery".to_string());
+        }
+        let notify = if notify.is_empty() {
+            "none".to_string()
+        } else {
+            notify.join(" ")
+        };
+        println!("{address} \"{name}\" {bond}, connected {seconds}s, notify: {notify}");
+    }
+    Ok(())
+}
+
+/// Call a control method on the running hidd.
+fn call_hidd<A: dbus::arg::AppendAll>(method: &str, args: A) -> Result<()> {
+    let conn = Connection::new_system().map_err(|e| anyhow!("system bus: {e}"))?;
+    let proxy = conn.with_proxy(
+        CONTROL_BUS_NAME,
+        CONTROL_OBJECT_PATH,
+        Duration::from_secs(5),
+    );
+    proxy
+        .method_call::<(), _, _, _>(CONTROL_INTERFACE, method, args)
+        .map_err(|e| anyhow!("{method} on {CONTROL_BUS_NAME} failed: {e}"))
+}
+
 fn run_input_monitor(args: &Args) -> Result<()> {
     let config = MappingConfig::from_file(&args.mapping_config_path)
         .map_err(|e| anyhow!("mapping config: {e}"))?;
@@ -329,8 +636,17 @@ fn format_hat(hat: u8) -> &'static str {
 fn print_help() {
     println!("Usage:");
     println!("  controllerosctl hid self-test [--config <path>] [--hidd <path>] [--pattern-seconds <1..30>]");
+    println!("  controllerosctl hid descriptor [--config <path>] [--mode <profile mode>]");
     println!("  controllerosctl input list");
     println!("  controllerosctl input monitor [--mapping-config <path>]");
+    println!("  controllerosctl host list|connections|next");
+    println!("  controllerosctl host select <address>");
+    println!("  controllerosctl pairing start [--timeout <seconds>]");
+    println!("  controllerosctl pairing stop");
+    println!("  controllerosctl status");
+    println!("  controllerosctl profile select <profile mode>");
+    println!("  controllerosctl mapping select <path or name>");
+    println!("  controllerosctl disconnect");
     println!("Defaults:");
     println!("  --config {}", DEFAULT_HID_CONFIG_PATH);
     println!("  --mapping-config {}", DEFAULT_MAPPING_CONFIG_PATH);
@@ -340,7 +656,7 @@ fn print_help() {
 
 #[cfg(test)]
 mod tests {
-    use super::{Args, CommandKind};
+    use super::{Args, CommandKind, HidProfileMode};
 
     #[test]
     fn parses_hid_self_test_defaults() {
@@ -367,6 +683,34 @@ mod tests {
         assert!(err.to_string().contains("1..=30"));
     }
 
+    #[test]
+    fn parses_hid_descriptor_with_mode() {
+        let args = Args::parse(
+            vec![
+                "hid".into(),
+                "descriptor".into(),
+                "--mode".into(),
+                "dualshock4".into(),
+            ]
+            .into_iter(),
+        )
+        .expect("parse should succeed");
+        assert_eq!(args.cmd, CommandKind::HidDescriptor);
+        assert_eq!(args.mode, Some(HidProfileMode::DualShock4));
+
+        let err = Args::parse(
+            vec![
+                "hid".into(),
+                "descriptor".into(),
+                "--mode".into(),
+                "n64".into(),
+            ]
+            .into_iter(),
+        )
+        .expect_err("unknown mode should fail");
+        assert!(err.to_string().contains("unknown profile mode"));
+    }
+
     #[test]
     fn parses_input_list() {
         let args = Args::parse(vec!["input".into(), "list".into()].into_iter())
@@ -396,4 +740,87 @@ mod tests {
         assert_eq!(args.cmd, CommandKind::InputMonitor);
         assert_eq!(args.mapping_config_path, "/tmp/test.toml");
     }
+
+    #[test]
+    fn parses_host_commands() {
+        let args = Args::parse(vec!["host".into(), "next".into()].into_iter())
+            .expect("parse should succeed");
+        assert_eq!(args.cmd, CommandKind::HostNext);
+
+        let args = Args::parse(vec!["host".into(), "connections".into()].into_iter())
+            .expect("parse should succeed");
+        assert_eq!(args.cmd, CommandKind::HostConnections);
+
+        let args = Args::parse(
+            vec!["host".into(), "select".into(), "98:B6:E9:01:02:03".into()].into_iter(),
+        )
+        .expect("parse should succeed");
+        assert_eq!(args.cmd, CommandKind::HostSelect);
+        assert_eq!(args.host.as_deref(), Some("98:B6:E9:01:02:03"));
+
+        let err = Args::parse(vec!["host".into(), "select".into()].into_iter())
+            .expect_err("missing address should fail");
+        assert!(err.to_string().contains("missing address"));
+    }
+
+    #[test]
+    fn parses_pairing_commands() {
+        let args = Args::parse(vec!["pairing".into(), "start".into()].into_iter())
+            .expect("parse should succeed");
+        assert_eq!(args.cmd, CommandKind::PairingStart);
+        assert_eq!(args.pairing_timeout_s, 0);
+
+        let args = Args::parse(
+            vec![
+                "pairing".into(),
+                "start".into(),
+                "--timeout".into(),
+                "60".into(),
+            ]
+            .into_iter(),
+        )
+        .expect("parse should succeed");
+        assert_eq!(args.pairing_timeout_s, 60);
+
+        let args = Args::parse(vec!["pairing".into(), "stop".into()].into_iter())
+            .expect("parse should succeed");
+        assert_eq!(args.cmd, CommandKind::PairingStop);
+
+        let err = Args::parse(
+            vec![
+                "pairing".into(),
+                "start".into(),
+                "--timeout".into(),
+                "0".into(),
+            ]
+            .into_iter(),
+        )
+        .expect_err("zero timeout should fail");
+        assert!(err.to_string().contains("invalid --timeout"));
+    }
+
+    #[test]
+    fn parses_status_and_select_commands() {
+        let args = Args::parse(vec!["status".into()].into_iter()).expect("parse should succeed");
+        assert_eq!(args.cmd, CommandKind::Status);
+
+        let args =
+            Args::parse(vec!["profile".into(), "select".into(), "dualshock4".into()].into_iter())
+                .expect("parse should succeed");
+        assert_eq!(args.cmd, CommandKind::ProfileSelect);
+        assert_eq!(args.select.as_deref(), Some("dualshock4"));
+
+        let err = Args::parse(vec!["profile".into(), "select".into(), "n64".into()].into_iter())
+            .expect_err("unknown mode should fail");
+        assert!(err.to_string().contains("unknown profile mode"));
+
+        let args = Args::parse(vec!["mapping".into(), "select".into(), "fps".into()].into_iter())
+            .expect("parse should succeed");
+        assert_eq!(args.cmd, CommandKind::MappingSelect);
+        assert_eq!(args.select.as_deref(), Some("fps"));
+
+        let args =
+            Args::parse(vec!["disconnect".into()].into_iter()).expect("parse should succeed");
+        assert_eq!(args.cmd, CommandKind::Disconnect);
+    }
 }
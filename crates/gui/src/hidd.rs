@@ -0,0 +1,61 @@
+use anyhow::{Context, Result};
+
+const HIDD_SERVICE: &str = "org.controlleros.Hidd";
+const HIDD_PATH: &str = "/org/controlleros/hidd";
+const HIDD_IFACE: &str = "org.controlleros.Hidd1";
+
+/// Passkey hidd wants shown while a host pairs.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PairingPrompt {
+    pub address: String,
+    pub passkey: u32,
+    /// The host shows the same number; the user must confirm or deny.
+    pub confirm: bool,
+}
+
+async fn proxy(connection: &zbus::Connection) -> Result<zbus::Proxy<'_>> {
+    zbus::proxy::Builder::new(connection)
+        .destination(HIDD_SERVICE)?
+        .path(HIDD_PATH)?
+        .interface(HIDD_IFACE)?
+        .build()
+        .await
+        .context("Failed to create hidd proxy")
+}
+
+/// Whether hidd owns its bus name, i.e. has registered with BlueZ.
+pub async fn is_running(connection: &zbus::Connection) -> Result<bool> {
+    let dbus = zbus::fdo::DBusProxy::new(connection)
+        .await
+        .context("Failed to create D-Bus proxy")?;
+    dbus.name_has_owner(HIDD_SERVICE.try_into()?)
+        .await
+        .context("Failed to call NameHasOwner")
+}
+
+/// The current pairing prompt, if any.
+pub async fn pairing_prompt(connection: &zbus::Connection) -> Result<Option<PairingPrompt>> {
+    let (address, passkey, confirm): (String, u32, bool) = proxy(connection)
+        .await?
+        .call("PairingPrompt", &())
+        .await
+        .context("Failed to call PairingPrompt")?;
+    if address.is_empty() {
+        return Ok(None);
+    }
+    Ok(Some(PairingPrompt {
+        address,
+        passkey,
+        confirm,
+    }))
+}
+
+/// Accept or reject the passkey comparison (or dismiss a displayed passkey).
+pub async fn confirm_pairing(connection: &zbus::Connection, accept: bool) -> Result<()> {
+    proxy(connection)
+        .await?
+        .call_method("ConfirmPairing", &(accept,))
+        .await
+        .context("Failed to call ConfirmPairing")?;
+    Ok(())
+}
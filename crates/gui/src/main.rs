@@ -6,6 +6,8 @@ slint::include_modules!();
 #[cfg(feature = "deck")]
 mod bluez;
 #[cfg(feature = "deck")]
+mod hidd;
+#[cfg(feature = "deck")]
 mod system;
 
 fn main() -> anyhow::Result<()> {
@@ -71,6 +73,7 @@ fn main() -> anyhow::Result<()> {
                 set_status(&w, "Some services failed to start");
             }
             dismiss_splash(&w);
+            h.spawn(poll_pairing_prompt(w.clone()));
             // Start polling loop
             poll_devices(w, h).await;
         });
@@ -139,6 +142,40 @@ fn main() -> anyhow::Result<()> {
         }
     });
 
+    // Pairing prompt answer (confirm/deny a passkey comparison)
+    #[cfg(feature = "deck")]
+    window.on_pairing_answer({
+        let w = window.as_weak();
+        let h = rt_handle.clone();
+        move |accept| {
+            tracing::info!("Pairing prompt answered: accept={accept}");
+            if let Some(win) = w.upgrade() {
+                win.set_pairing_visible(false);
+            }
+            h.spawn(async move {
+                let result = async {
+                    let conn = zbus::Connection::system().await?;
+                    hidd::confirm_pairing(&conn, accept).await
+                }
+                .await;
+                if let Err(e) = result {
+                    tracing::warn!("ConfirmPairing failed: {e}");
+                }
+            });
+        }
+    });
+
+    #[cfg(not(feature = "deck"))]
+    window.on_pairing_answer({
+        let w = window.as_weak();
+        move |accept| {
+            tracing::info!("Pairing prompt answered: accept={accept}");
+            if let Some(w) = w.upgrade() {
+                w.set_pairing_visible(false);
+            }
+        }
+    });
+
     // Confirm action callback (forget, reload, power-off)
     #[cfg(feature = "deck")]
     window.on_confirm_action({
@@ -395,15 +432,13 @@ async fn wait_for_subsystems(w: &slint::Weak<MainWindow>) -> bool {
             tokio::time::sleep(Duration::from_millis(500)).await;
         }
 
-        // Wait for hidd
+        // Wait for hidd to claim its control interface
         loop {
-            if tokio::process::Command::new("pidof")
-                .arg("hidd")
-                .output()
-                .await
-                .map(|o| o.status.success())
-                .unwrap_or(false)
-            {
+            let running = match zbus::Connection::system().await {
+                Ok(conn) => hidd::is_running(&conn).await.unwrap_or(false),
+                Err(_) => false,
+            };
+            if running {
                 tracing::info!("hidd is running");
                 break;
             }
@@ -457,6 +492,47 @@ async fn poll_devices(w: slint::Weak<MainWindow>, _h: tokio::runtime::Handle) {
     }
 }
 
+/// Show hidd's pairing prompt (passkey display or numeric comparison) while
+/// it is pending. hidd rejects comparisons nobody answers in time.
+#[cfg(feature = "deck")]
+async fn poll_pairing_prompt(w: slint::Weak<MainWindow>) {
+    let mut interval = tokio::time::interval(std::time::Duration::from_secs(1));
+    let mut shown = None;
+    loop {
+        interval.tick().await;
+        let prompt = match async {
+            let conn = zbus::Connection::system().await?;
+            hidd::pairing_prompt(&conn).await
+        }
+        .await
+        {
+            Ok(prompt) => prompt,
+            Err(e) => {
+                tracing::debug!("PairingPrompt failed: {e}");
+                None
+            }
+        };
+        if prompt == shown {
+            continue;
+        }
+        shown = prompt.clone();
+        let w = w.clone();
+        slint::invoke_from_event_loop(move || {
+            let Some(w) = w.upgrade() else { return };
+            match prompt {
+                Some(p) => {
+                    w.set_pairing_address(p.address.into());
+                    w.set_pairing_passkey(format!("{:06}", p.passkey).into());
+                    w.set_pairing_confirm(p.confirm);
+                    w.set_pairing_visible(true);
+                }
+                None => w.set_pairing_visible(false),
+            }
+        })
+        .ok();
+    }
+}
+
 struct BatteryInfo {
     level: f32,
     charging: bool,
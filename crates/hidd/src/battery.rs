@@ -0,0 +1,96 @@
+//! Deck battery level read from sysfs power supplies.
+
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+
+pub const POWER_SUPPLY_ROOT: &str = "/sys/class/power_supply";
+
+/// How often sysfs is polled. The kernel updates capacity far less often.
+pub const BATTERY_POLL_INTERVAL: Duration = Duration::from_secs(30);
+
+/// Polls the system battery and reports level changes.
+pub struct BatteryMonitor {
+    root: PathBuf,
+    next_poll: Instant,
+    level: Option<u8>,
+}
+
+impl BatteryMonitor {
+    pub fn new(root: impl Into<PathBuf>) -> Self {
+        Self {
+            root: root.into(),
+            next_poll: Instant::now(),
+            level: None,
+        }
+    }
+
+    /// New battery level in percent, if a poll was due and the level changed
+    /// since the last one. Returns `None` while no battery is found.
+    pub fn poll(&mut self, now: Instant) -> Option<u8> {
+        if now < self.next_poll {
+            return None;
+        }
+        self.next_poll = now + BATTERY_POLL_INTERVAL;
+        let level = read_battery_level(&self.root)?;
+        if self.level == Some(level) {
+            return None;
+        }
+        self.level = Some(level);
+        Some(level)
+    }
+
+    /// Level from the last successful poll.
+    pub fn level(&self) -> Option<u8> {
+        self.level
+    }
+}
+
+/// Capacity of the first system battery under `root`, in percent. Batteries
+/// of attached devices (scope `Device`, e.g. HID peripherals) are skipped.
+pub fn read_battery_level(root: &Path) -> Option<u8> {
+    let mut supplies = fs::read_dir(root)
+        .ok()?
+        .filter_map(|entry| entry.ok().map(|e| e.path()))
+        .collect::<Vec<_>>();
+    supplies.sort();
+    supplies.into_iter().find_map(|path| {
+        let read = |name: &str| fs::read_to_string(path.join(name)).ok();
+        if !is_system_battery(read("type").as_deref()?, read("scope").as_deref()) {
+            return None;
+        }
+        parse_capacity(&read("capacity")?)
+    })
+}
+
+fn is_system_battery(supply_type: &str, scope: Option<&str>) -> bool {
+    supply_type.trim() == "Battery" && scope.map(str::trim) != Some("Device")
+}
+
+fn parse_capacity(raw: &str) -> Option<u8> {
+    let value: u32 = raw.trim().parse().ok()?;
+    Some(value.min(100) as u8)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{is_system_battery, parse_capacity};
+
+    #[test]
+    fn parses_and_clamps_capacity() {
+        assert_eq!(parse_capacity("87\n"), Some(87));
+        assert_eq!(parse_capacity("0"), Some(0));
+        assert_eq!(parse_capacity("104\n"), Some(100));
+        assert_eq!(parse_capacity("-1"), None);
+        assert_eq!(parse_capacity(""), None);
+    }
+
+    #[test]
+    fn only_system_batteries_are_used() {
+        assert!(is_system_battery("Battery\n", None));
+        assert!(is_system_battery("Battery\n", Some("System\n")));
+        assert!(!is_system_battery("Battery\n", Some("Device\n")));
+        assert!(!is_system_battery("Mains\n", None));
+        assert!(!is_system_battery("USB\n", None));
+    }
+}
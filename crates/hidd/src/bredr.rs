@@ -0,0 +1,1030 @@
+//! Classic Bluetooth (BR/EDR) HID device transport.
+//!
+//! BlueZ hands us the L2CAP channels through two `Profile1` objects: the HID
+//! profile carries our SDP record and listens on the control PSM, a second
+//! record-less profile listens on the interrupt PSM. Input reports go out on
+//! the interrupt channel as HIDP DATA messages with the same bytes the HOGP
+//! transport sends; the host's GET_REPORT / SET_REPORT / SET_PROTOCOL
+//! requests arrive on the control channel.
+//!
+//! bluetoothd's own `input` plugin binds both PSMs, so it must be disabled
+//! (`bluetoothd -P input`) and the controller must run in `dual` or `bredr`
+//! mode. See docs/pairing.md.
+
+use std::cell::Cell;
+use std::collections::{HashMap, VecDeque};
+use std::io::{self, Read, Write};
+use std::os::fd::OwnedFd;
+use std::os::unix::net::UnixStream;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use anyhow::{anyhow, Result};
+use common::config::{HidConfig, IdleAction};
+use common::control::CONTROL_OBJECT_PATH;
+use common::hid::{HidProfile, ReportType};
+use dbus::arg::{PropMap, RefArg, Variant};
+use dbus::blocking::stdintf::org_freedesktop_dbus::ObjectManager;
+use dbus::blocking::SyncConnection;
+use dbus::channel::MatchingReceiver;
+use dbus::message::MatchRule;
+use dbus::Path;
+use dbus_crossroads::{Crossroads, IfaceToken, MethodErr};
+
+use crate::connections::device_address;
+use crate::control::{publish_status, ControlRequest, DaemonStatus, SharedControl};
+use crate::hog::{
+    call_method_with_dispatch, configure_adapter, dbus_path, expire_pairing_prompt,
+    read_adapter_address, register_agent, register_agent_iface, register_control_iface,
+    request_control_name, set_adapter_mode, set_adapter_powered, system_bus, unregister_agent,
+    watch_bluez, ControlData, AGENT_PATH, BLUEZ_ADAPTER_IFACE, BLUEZ_DEVICE_IFACE, BLUEZ_ROOT_PATH,
+    BLUEZ_SERVICE, MAX_PENDING_OUTPUT_REPORTS,
+};
+use crate::pairing::{AdapterMode, PairingPolicy, SharedPolicy};
+
+const BLUEZ_PROFILE_MANAGER_IFACE: &str = "org.bluez.ProfileManager1";
+const BLUEZ_PROFILE_IFACE: &str = "org.bluez.Profile1";
+
+const CONTROL_PROFILE_PATH: &str = "/org/controlleros/bredr/control";
+const INTERRUPT_PROFILE_PATH: &str = "/org/controlleros/bredr/interrupt";
+
+const HID_UUID: &str = "00001124-0000-1000-8000-00805f9b34fb";
+/// Private UUID for the interrupt channel profile. Only the HID UUID is
+/// advertised over SDP; hosts find the interrupt PSM in its record.
+const HID_INTERRUPT_UUID: &str = "a8d7c2a6-3f51-4b8e-9d2c-0c6f1e4b7a19";
+
+const PSM_HID_CONTROL: u16 = 0x0011;
+const PSM_HID_INTERRUPT: u16 = 0x0013;
+
+/// Largest HIDP message we read; BlueZ's default L2CAP MTU.
+const HIDP_MAX_MESSAGE_LEN: usize = 672;
+
+const HIDP_HANDSHAKE: u8 = 0x00;
+const HIDP_HID_CONTROL: u8 = 0x10;
+const HIDP_GET_REPORT: u8 = 0x40;
+const HIDP_SET_REPORT: u8 = 0x50;
+const HIDP_GET_PROTOCOL: u8 = 0x60;
+const HIDP_SET_PROTOCOL: u8 = 0x70;
+const HIDP_DATA: u8 = 0xa0;
+
+const HIDP_REPORT_TYPE_INPUT: u8 = 0x01;
+const HIDP_REPORT_TYPE_OUTPUT: u8 = 0x02;
+const HIDP_REPORT_TYPE_FEATURE: u8 = 0x03;
+/// GET_REPORT flag: a little-endian buffer size follows the report ID.
+const HIDP_GET_REPORT_SIZE_FLAG: u8 = 0x08;
+
+const HANDSHAKE_SUCCESSFUL: u8 = 0x00;
+const HANDSHAKE_ERR_INVALID_REPORT_ID: u8 = 0x02;
+const HANDSHAKE_ERR_UNSUPPORTED_REQUEST: u8 = 0x03;
+const HANDSHAKE_ERR_INVALID_PARAMETER: u8 = 0x04;
+
+const HID_CONTROL_SUSPEND: u8 = 0x03;
+const HID_CONTROL_EXIT_SUSPEND: u8 = 0x04;
+const HID_CONTROL_VIRTUAL_CABLE_UNPLUG: u8 = 0x05;
+
+const PROTOCOL_REPORT: u8 = 0x01;
+
+/// SDP HIDDeviceSubclass: gamepad, no keyboard or pointing device.
+const HID_DEVICE_SUBCLASS_GAMEPAD: u8 = 0x08;
+
+type SharedState = Arc<Mutex<BredrState>>;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Channel {
+    Control,
+    Interrupt,
+}
+
+impl Channel {
+    const fn as_str(self) -> &'static str {
+        match self {
+            Self::Control => "control",
+            Self::Interrupt => "interrupt",
+        }
+    }
+}
+
+struct ProfileData {
+    channel: Channel,
+    state: SharedState,
+}
+
+/// What the control channel should do after a host request.
+#[derive(Debug, PartialEq, Eq)]
+enum ControlAction {
+    Reply(Vec<u8>),
+    None,
+    /// The host removed its bond; drop the channels and ours too.
+    Unplug,
+}
+
+#[derive(Debug)]
+struct BredrState {
+    protocol: u8,
+    /// Last value of each report, without the report ID, zeroed until set.
+    input_reports: HashMap<u8, Vec<u8>>,
+    output_reports: HashMap<u8, Vec<u8>>,
+    feature_reports: HashMap<u8, Vec<u8>>,
+    /// Output reports (with report ID) not yet taken by the main loop.
+    pending_outputs: VecDeque<Vec<u8>>,
+    /// The host sent HID_CONTROL SUSPEND; input reports are stored but not
+    /// sent until EXIT_SUSPEND or the host disconnects.
+    suspended: bool,
+    /// The controller went idle: new connections are refused and the
+    /// adapter mode is left alone until it wakes.
+    idle: Option<IdleAction>,
+    host: Option<Path<'static>>,
+    control: Option<UnixStream>,
+    interrupt: Option<UnixStream>,
+}
+
+impl BredrState {
+    fn new(profile: HidProfile) -> Self {
+        let mut input_reports = HashMap::new();
+        let mut output_reports = HashMap::new();
+        let mut feature_reports = HashMap::new();
+        for report in profile.reports() {
+            let slots = match report.report_type {
+                ReportType::Input => &mut input_reports,
+                ReportType::Output => &mut output_reports,
+                ReportType::Feature => &mut feature_reports,
+            };
+            slots.insert(report.id, vec![0; report.payload_len]);
+        }
+
+        Self {
+            protocol: PROTOCOL_REPORT,
+            input_reports,
+            output_reports,
+            feature_reports,
+            pending_outputs: VecDeque::new(),
+            suspended: false,
+            idle: None,
+            host: None,
+            control: None,
+            interrupt: None,
+        }
+    }
+
+    fn reports_mut(&mut self, report_type: u8) -> Option<&mut HashMap<u8, Vec<u8>>> {
+        match report_type {
+            HIDP_REPORT_TYPE_INPUT => Some(&mut self.input_reports),
+            HIDP_REPORT_TYPE_OUTPUT => Some(&mut self.output_reports),
+            HIDP_REPORT_TYPE_FEATURE => Some(&mut self.feature_reports),
+            _ => None,
+        }
+    }
+
+    /// Remember an input report (with report ID) and build its interrupt
+    /// channel DATA message.
+    fn input_message(&mut self, report: &[u8]) -> Result<Vec<u8>> {
+        let (&report_id, payload) = report
+            .split_first()
+            .ok_or_else(|| anyhow!("empty input report"))?;
+        let slot = self
+            .input_reports
+            .get_mut(&report_id)
+            .ok_or_else(|| anyhow!("unsupported input report id=0x{report_id:02x}"))?;
+        slot.clear();
+        slot.extend_from_slice(payload);
+        Ok(data_message(HIDP_REPORT_TYPE_INPUT, report))
+    }
+
+    fn handle_control_message(&mut self, message: &[u8]) -> ControlAction {
+        let Some((&header, body)) = message.split_first() else {
+            return ControlAction::None;
+        };
+        let param = header & 0x0f;
+        match header & 0xf0 {
+            HIDP_HID_CONTROL => match param {
+                HID_CONTROL_VIRTUAL_CABLE_UNPLUG => ControlAction::Unplug,
+                HID_CONTROL_SUSPEND | HID_CONTROL_EXIT_SUSPEND => {
+                    let suspended = param == HID_CONTROL_SUSPEND;
+                    if suspended != self.suspended {
+                        eprintln!(
+                            "hidd: BR/EDR host {} suspend",
+                            if suspended { "entered" } else { "exited" }
+                        );
+                    }
+                    self.suspended = suspended;
+                    ControlAction::None
+                }
+                _ => handshake(HANDSHAKE_ERR_UNSUPPORTED_REQUEST),
+            },
+            HIDP_GET_REPORT => self.get_report(param, body),
+            HIDP_SET_REPORT => self.set_report(param & 0x03, body),
+            HIDP_GET_PROTOCOL => ControlAction::Reply(vec![HIDP_DATA, self.protocol]),
+            HIDP_SET_PROTOCOL => match param & 0x01 {
+                PROTOCOL_REPORT => {
+                    self.protocol = PROTOCOL_REPORT;
+                    handshake(HANDSHAKE_SUCCESSFUL)
+                }
+                // The descriptors are not boot-compatible.
+                _ => handshake(HANDSHAKE_ERR_INVALID_PARAMETER),
+            },
+            _ => handshake(HANDSHAKE_ERR_UNSUPPORTED_REQUEST),
+        }
+    }
+
+    fn get_report(&mut self, param: u8, body: &[u8]) -> ControlAction {
+        let report_type = param & 0x03;
+        let Some(reports) = self.reports_mut(report_type) else {
+            return handshake(HANDSHAKE_ERR_INVALID_PARAMETER);
+        };
+        let Some((&report_id, rest)) = body.split_first() else {
+            return handshake(HANDSHAKE_ERR_INVALID_PARAMETER);
+        };
+        let Some(payload) = reports.get(&report_id) else {
+            return handshake(HANDSHAKE_ERR_INVALID_REPORT_ID);
+        };
+
+        let mut report = Vec::with_capacity(payload.len() + 1);
+        report.push(report_id);
+        report.extend_from_slice(payload);
+        if param & HIDP_GET_REPORT_SIZE_FLAG != 0 {
+            let Some(size) = rest.get(..2) else {
+                return handshake(HANDSHAKE_ERR_INVALID_PARAMETER);
+            };
+            report.truncate(usize::from(u16::from_le_bytes([size[0], size[1]])));
+        }
+        ControlAction::Reply(data_message(report_type, &report))
+    }
+
+    fn set_report(&mut self, report_type: u8, body: &[u8]) -> ControlAction {
+        if report_type == HIDP_REPORT_TYPE_INPUT {
+            return handshake(HANDSHAKE_ERR_INVALID_PARAMETER);
+        }
+        match self.store_report(report_type, body) {
+            Ok(()) => handshake(HANDSHAKE_SUCCESSFUL),
+            Err(code) => handshake(code),
+        }
+    }
+
+    /// Store an output or feature report (with report ID) written by the
+    /// host, queueing output reports for the main loop.
+    fn store_report(&mut self, report_type: u8, report: &[u8]) -> Result<(), u8> {
+        let (&report_id, payload) = report
+            .split_first()
+            .ok_or(HANDSHAKE_ERR_INVALID_PARAMETER)?;
+        let slot = self
+            .reports_mut(report_type)
+            .ok_or(HANDSHAKE_ERR_INVALID_PARAMETER)?
+            .get_mut(&report_id)
+            .ok_or(HANDSHAKE_ERR_INVALID_REPORT_ID)?;
+        let len = slot.len();
+        slot.clear();
+        slot.extend_from_slice(payload);
+        slot.resize(len, 0);
+
+        if report_type == HIDP_REPORT_TYPE_OUTPUT {
+            if self.pending_outputs.len() == MAX_PENDING_OUTPUT_REPORTS {
+                self.pending_outputs.pop_front();
+            }
+            self.pending_outputs.push_back(report.to_vec());
+        }
+        Ok(())
+    }
+
+    /// Interrupt channel messages from the host: only output DATA is valid.
+    fn handle_interrupt_message(&mut self, message: &[u8]) {
+        match message.split_first() {
+            Some((&header, report)) if header == HIDP_DATA | HIDP_REPORT_TYPE_OUTPUT => {
+                if let Err(code) = self.store_report(HIDP_REPORT_TYPE_OUTPUT, report) {
+                    eprintln!("hidd: dropping interrupt output report (handshake 0x{code:02x})");
+                }
+            }
+            Some((&header, _)) => {
+                eprintln!("hidd: ignoring interrupt message with header 0x{header:02x}")
+            }
+            None => {}
+        }
+    }
+
+    fn disconnect(&mut self) {
+        if let Some(host) = self.host.take() {
+            eprintln!("hidd: BR/EDR host {host} disconnected");
+        }
+        self.control = None;
+        self.interrupt = None;
+        self.suspended = false;
+    }
+}
+
+fn handshake(result: u8) -> ControlAction {
+    ControlAction::Reply(vec![HIDP_HANDSHAKE | result])
+}
+
+/// HIDP DATA message for a report that starts with its report ID.
+fn data_message(report_type: u8, report: &[u8]) -> Vec<u8> {
+    let mut message = Vec::with_capacity(report.len() + 1);
+    message.push(HIDP_DATA | report_type);
+    message.extend_from_slice(report);
+    message
+}
+
+pub struct BredrRuntime {
+    conn: SyncConnection,
+    state: SharedState,
+    policy: SharedPolicy,
+    control: SharedControl,
+    /// Discoverable/Pairable last applied to the adapter.
+    adapter_mode: Cell<AdapterMode>,
+    adapter_path: Path<'static>,
+    adapter_address: [u8; 6],
+    /// Set by `watch_bluez`; every registration is stale.
+    bluez_lost: Arc<AtomicBool>,
+}
+
+impl BredrRuntime {
+    pub fn register(cfg: &HidConfig, control: SharedControl) -> Result<Self> {
+        let conn = system_bus().map_err(|e| anyhow!("system bus: {e}"))?;
+        eprintln!(
+            "hidd: opened system D-Bus connection (unique name: {:?})",
+            conn.unique_name()
+        );
+        let adapter_path = find_adapter_path(&conn)?;
+        eprintln!("hidd: using BlueZ adapter {adapter_path}");
+        let adapter_address = read_adapter_address(&conn, &adapter_path).unwrap_or_else(|e| {
+            eprintln!("hidd: {e}; reporting 00:00:00:00:00:00 as device address");
+            [0; 6]
+        });
+        configure_adapter(&conn, &adapter_path, cfg)?;
+        let policy = PairingPolicy::shared(&cfg.pairing);
+        let adapter_mode = policy
+            .lock()
+            .map_err(|_| anyhow!("failed to lock pairing policy"))?
+            .adapter_mode(Instant::now(), false);
+        if adapter_mode
+            != (AdapterMode {
+                discoverable: true,
+                pairable: true,
+            })
+        {
+            set_adapter_mode(&conn, &adapter_path, adapter_mode)?;
+        }
+
+        let profile = cfg.profile.hid_profile();
+        let country_code = u8::try_from(cfg.profile.country)
+            .map_err(|_| anyhow!("profile.country must be in 0..=255 for the SDP record"))?;
+        let record = sdp_record(&cfg.device.name, profile.report_descriptor(), country_code);
+        let state = Arc::new(Mutex::new(BredrState::new(profile)));
+
+        let agent_path = dbus_path(AGENT_PATH)?;
+        let mut crossroads = Crossroads::new();
+        let profile_iface = register_profile_iface(&mut crossroads);
+        let agent_iface = register_agent_iface(&mut crossroads);
+        let control_iface = register_control_iface(&mut crossroads);
+        crossroads.insert(
+            CONTROL_PROFILE_PATH,
+            &[profile_iface],
+            ProfileData {
+                channel: Channel::Control,
+                state: Arc::clone(&state),
+            },
+        );
+        crossroads.insert(
+            INTERRUPT_PROFILE_PATH,
+            &[profile_iface],
+            ProfileData {
+                channel: Channel::Interrupt,
+                state: Arc::clone(&state),
+            },
+        );
+        crossroads.insert(AGENT_PATH, &[agent_iface], Arc::clone(&policy));
+        crossroads.insert(
+            CONTROL_OBJECT_PATH,
+            &[control_iface],
+            ControlData::without_hosts(Arc::clone(&policy), Arc::clone(&control)),
+        );
+
+        let crossroads = Arc::new(Mutex::new(crossroads));
+        conn.start_receive(
+            MatchRule::new_method_call(),
+            Box::new(move |msg, conn| {
+                let iface = msg.interface().map(|i| i.to_string()).unwrap_or_default();
+                let member = msg.member().map(|m| m.to_string()).unwrap_or_default();
+                let path = msg.path().map(|p| p.to_string()).unwrap_or_default();
+                eprintln!("hidd: D-Bus method call {iface}.{member} on {path}");
+                if crossroads
+                    .lock()
+                    .unwrap()
+                    .handle_message(msg, conn)
+                    .is_err()
+                {
+                    eprintln!(
+                        "hidd: failed to handle D-Bus method call {iface}.{member} on {path}"
+                    );
+                }
+                true
+            }),
+        );
+
+        let bluez_lost = watch_bluez(&conn, &adapter_path)?;
+        register_profile(
+            &conn,
+            CONTROL_PROFILE_PATH,
+            HID_UUID,
+            PSM_HID_CONTROL,
+            Some(record),
+        )?;
+        register_profile(
+            &conn,
+            INTERRUPT_PROFILE_PATH,
+            HID_INTERRUPT_UUID,
+            PSM_HID_INTERRUPT,
+            None,
+        )?;
+        register_agent(&conn, &agent_path, cfg.pairing.agent_capability)?;
+        request_control_name(&conn);
+
+        Ok(Self {
+            conn,
+            state,
+            policy,
+            control,
+            adapter_mode: Cell::new(adapter_mode),
+            adapter_path,
+            adapter_address,
+            bluez_lost,
+        })
+    }
+
+    pub fn adapter_path(&self) -> &Path<'static> {
+        &self.adapter_path
+    }
+
+    /// Adapter Bluetooth address, most significant byte first.
+    pub fn adapter_address(&self) -> [u8; 6] {
+        self.adapter_address
+    }
+
+    /// bluetoothd restarted or the adapter went away; the runtime must be
+    /// registered again.
+    pub fn bluez_lost(&self) -> bool {
+        self.bluez_lost.load(Ordering::Acquire)
+    }
+
+    /// The host sent HID_CONTROL SUSPEND.
+    pub fn host_suspended(&self) -> Result<bool> {
+        Ok(self.lock_state()?.suspended)
+    }
+
+    /// Address of the connected host.
+    pub fn current_host(&self) -> Result<Option<String>> {
+        Ok(self
+            .lock_state()?
+            .host
+            .as_ref()
+            .and_then(|path| device_address(path)))
+    }
+
+    /// Put Bluetooth to sleep for an idle controller: disconnect the host,
+    /// refuse new connections and, for `AdapterOff`, power the adapter down.
+    pub fn enter_idle(&self, action: IdleAction) -> Result<()> {
+        self.lock_state()?.idle = Some(action);
+        self.disconnect_host()?;
+        if action == IdleAction::AdapterOff {
+            set_adapter_powered(&self.conn, &self.adapter_path, false)?;
+        }
+        Ok(())
+    }
+
+    /// Undo `enter_idle`. Hosts reconnect on their own.
+    pub fn leave_idle(&self) -> Result<()> {
+        if self.lock_state()?.idle.take() == Some(IdleAction::AdapterOff) {
+            set_adapter_powered(&self.conn, &self.adapter_path, true)?;
+        }
+        Ok(())
+    }
+
+    /// Output reports written by the host since the last call, oldest first,
+    /// each including its report ID byte.
+    pub fn take_output_reports(&self) -> Result<Vec<Vec<u8>>> {
+        let mut state = self.lock_state()?;
+        Ok(state.pending_outputs.drain(..).collect())
+    }
+
+    /// Set the value a host reads from a feature report (with report ID).
+    pub fn set_feature_report(&self, report: &[u8]) -> Result<()> {
+        self.lock_state()?
+            .store_report(HIDP_REPORT_TYPE_FEATURE, report)
+            .map_err(|code| anyhow!("feature report refused (handshake 0x{code:02x})"))
+    }
+
+    /// Send an input report (with report ID) on the interrupt channel. The
+    /// report is dropped when no host is connected, the host suspended input
+    /// or the channel is full.
+    pub fn publish_input_report(&self, report: &[u8]) -> Result<()> {
+        self.process_pending_messages()?;
+        let mut state = self.lock_state()?;
+        let message = state.input_message(report)?;
+        if state.suspended {
+            return Ok(());
+        }
+        if let Some(interrupt) = state.interrupt.as_mut() {
+            match interrupt.write(&message) {
+                Ok(_) => {}
+                Err(e) if e.kind() == io::ErrorKind::WouldBlock => {}
+                Err(e) => {
+                    eprintln!("hidd: BR/EDR interrupt channel write failed: {e}");
+                    state.disconnect();
+                }
+            }
+        }
+        Ok(())
+    }
+
+    pub fn start_pairing(&self, timeout: Option<Duration>) -> Result<()> {
+        self.policy
+            .lock()
+            .map_err(|_| anyhow!("failed to lock pairing policy"))?
+            .start_pairing(Instant::now(), timeout);
+        Ok(())
+    }
+
+    /// Apply `update` to the control properties, fill in the connection
+    /// state, and announce what changed.
+    pub fn publish_status(&self, update: impl FnOnce(&mut DaemonStatus)) -> Result<()> {
+        let (connected_host, notifying, suspended) = {
+            let state = self.lock_state()?;
+            let host = state.host.as_ref().and_then(|host| device_address(host));
+            (host, state.interrupt.is_some(), state.suspended)
+        };
+        let pairing = self
+            .policy
+            .lock()
+            .map_err(|_| anyhow!("failed to lock pairing policy"))?
+            .pairing_open(Instant::now());
+        publish_status(&self.conn, &self.control, |status| {
+            update(status);
+            status.transport = "bredr";
+            status.connected_host = connected_host.unwrap_or_default();
+            status.notifying = notifying;
+            status.suspended = suspended;
+            status.pairing = pairing;
+        })
+    }
+
+    pub fn take_control_requests(&self) -> Result<Vec<ControlRequest>> {
+        Ok(self
+            .control
+            .lock()
+            .map_err(|_| anyhow!("failed to lock control state"))?
+            .take_requests())
+    }
+
+    /// Close the channels and disconnect the connected host.
+    pub fn disconnect_host(&self) -> Result<()> {
+        let host = {
+            let mut state = self.lock_state()?;
+            let host = state.host.clone();
+            state.disconnect();
+            host
+        };
+        let Some(host) = host else {
+            return Ok(());
+        };
+        eprintln!("hidd: disconnecting BR/EDR host {host}");
+        call_method_with_dispatch(
+            &self.conn,
+            BLUEZ_SERVICE,
+            &host,
+            BLUEZ_DEVICE_IFACE,
+            "Disconnect",
+            (),
+            Duration::from_secs(5),
+        )
+        .map_err(|e| anyhow!("failed to disconnect {host}: {e}"))
+    }
+
+    fn lock_state(&self) -> Result<std::sync::MutexGuard<'_, BredrState>> {
+        self.state
+            .lock()
+            .map_err(|_| anyhow!("failed to lock BR/EDR state"))
+    }
+
+    fn process_pending_messages(&self) -> Result<()> {
+        for _ in 0..8 {
+            let had_message = self
+                .conn
+                .process(Duration::from_millis(0))
+                .map_err(|e| anyhow!("failed to process D-Bus traffic: {e}"))?;
+            if !had_message {
+                break;
+            }
+        }
+        if self.bluez_lost() {
+            return Ok(());
+        }
+        if self.lock_state()?.idle.is_none() {
+            self.sync_adapter_mode()?;
+        }
+        self.service_channels()
+    }
+
+    /// Close an expired pairing window and apply the resulting adapter mode.
+    fn sync_adapter_mode(&self) -> Result<()> {
+        let now = Instant::now();
+        expire_pairing_prompt(&self.conn, &self.policy, now)?;
+        let mode = {
+            let mut policy = self
+                .policy
+                .lock()
+                .map_err(|_| anyhow!("failed to lock pairing policy"))?;
+            policy.expire(now);
+            policy.adapter_mode(now, false)
+        };
+        if mode != self.adapter_mode.get() {
+            set_adapter_mode(&self.conn, &self.adapter_path, mode)?;
+            self.adapter_mode.set(mode);
+        }
+        Ok(())
+    }
+
+    /// Answer pending control requests and collect interrupt output reports.
+    fn service_channels(&self) -> Result<()> {
+        let mut unplugged = None;
+        {
+            let mut state = self.lock_state()?;
+            let mut buf = [0u8; HIDP_MAX_MESSAGE_LEN];
+            while let Some(len) = read_message(&mut state.control, Channel::Control, &mut buf) {
+                match state.handle_control_message(&buf[..len]) {
+                    ControlAction::Reply(reply) => {
+                        let written = state.control.as_mut().map(|control| control.write(&reply));
+                        if let Some(Err(e)) = written {
+                            eprintln!("hidd: BR/EDR control channel write failed: {e}");
+                            state.control = None;
+                        }
+                    }
+                    ControlAction::None => {}
+                    ControlAction::Unplug => {
+                        eprintln!("hidd: BR/EDR host sent virtual cable unplug");
+                        unplugged = state.host.clone();
+                        state.disconnect();
+                    }
+                }
+            }
+            while let Some(len) = read_message(&mut state.interrupt, Channel::Interrupt, &mut buf) {
+                state.handle_interrupt_message(&buf[..len]);
+            }
+            if state.host.is_some() && state.control.is_none() && state.interrupt.is_none() {
+                state.disconnect();
+            }
+        }
+
+        if let Some(host) = unplugged {
+            call_method_with_dispatch(
+                &self.conn,
+                BLUEZ_SERVICE,
+                &self.adapter_path,
+                BLUEZ_ADAPTER_IFACE,
+                "RemoveDevice",
+                (host.clone(),),
+                Duration::from_secs(5),
+            )
+            .map_err(|e| anyhow!("failed to remove unplugged host {host}: {e}"))?;
+        }
+        Ok(())
+    }
+}
+
+impl Drop for BredrRuntime {
+    fn drop(&mut self) {
+        if self.bluez_lost() {
+            eprintln!("hidd: dropping BredrRuntime registered with a lost adapter");
+            return;
+        }
+        eprintln!("hidd: shutting down BredrRuntime, unregistering from BlueZ");
+        if let Ok(agent_path) = dbus_path(AGENT_PATH) {
+            let _ = unregister_agent(&self.conn, &agent_path);
+        }
+        for path in [CONTROL_PROFILE_PATH, INTERRUPT_PROFILE_PATH] {
+            if let Ok(profile_path) = dbus_path(path) {
+                eprintln!("hidd: unregistering profile {profile_path}");
+                let _ = unregister_profile(&self.conn, &profile_path);
+            }
+        }
+        eprintln!("hidd: BredrRuntime shutdown complete");
+    }
+}
+
+/// Read one message from a non-blocking channel, closing it on EOF or error.
+fn read_message(
+    stream: &mut Option<UnixStream>,
+    channel: Channel,
+    buf: &mut [u8],
+) -> Option<usize> {
+    let result = stream.as_mut()?.read(buf);
+    match result {
+        Ok(0) => {
+            eprintln!("hidd: BR/EDR {} channel closed by host", channel.as_str());
+            *stream = None;
+            None
+        }
+        Ok(len) => Some(len),
+        Err(e) if e.kind() == io::ErrorKind::WouldBlock => None,
+        Err(e) => {
+            eprintln!("hidd: BR/EDR {} channel read failed: {e}", channel.as_str());
+            *stream = None;
+            None
+        }
+    }
+}
+
+/// The adapter at hci0, or the first adapter BlueZ exports.
+fn find_adapter_path(conn: &SyncConnection) -> Result<Path<'static>> {
+    let proxy = conn.with_proxy(BLUEZ_SERVICE, BLUEZ_ROOT_PATH, Duration::from_secs(10));
+    let objects: HashMap<Path<'static>, HashMap<String, PropMap>> = proxy
+        .get_managed_objects()
+        .map_err(|e| anyhow!("GetManagedObjects on org.bluez failed: {e}"))?;
+
+    let mut adapters = objects
+        .into_iter()
+        .filter(|(_, ifaces)| ifaces.contains_key(BLUEZ_ADAPTER_IFACE))
+        .map(|(path, _)| path)
+        .collect::<Vec<_>>();
+    adapters.sort();
+    adapters
+        .into_iter()
+        .next()
+        .ok_or_else(|| anyhow!("no BlueZ adapter exposes {BLUEZ_ADAPTER_IFACE}"))
+}
+
+fn register_profile(
+    conn: &SyncConnection,
+    profile_path: &str,
+    uuid: &str,
+    psm: u16,
+    service_record: Option<String>,
+) -> Result<()> {
+    let profile_path = dbus_path(profile_path)?;
+    let _ = unregister_profile(conn, &profile_path);
+
+    let mut options: PropMap = HashMap::new();
+    let mut option = |name: &str, value: Box<dyn RefArg>| {
+        options.insert(name.to_string(), Variant(value));
+    };
+    option("Role", Box::new("server".to_string()));
+    option("PSM", Box::new(psm));
+    option("RequireAuthentication", Box::new(true));
+    option("RequireAuthorization", Box::new(false));
+    option("AutoConnect", Box::new(false));
+    if let Some(record) = service_record {
+        option("ServiceRecord", Box::new(record));
+    }
+
+    eprintln!("hidd: registering BR/EDR profile {profile_path} (uuid={uuid}, psm=0x{psm:04x})");
+    call_method_with_dispatch(
+        conn,
+        BLUEZ_SERVICE,
+        &dbus_path("/org/bluez")?,
+        BLUEZ_PROFILE_MANAGER_IFACE,
+        "RegisterProfile",
+        (profile_path, uuid.to_string(), options),
+        Duration::from_secs(5),
+    )
+    .map_err(|e| anyhow!("RegisterProfile for {uuid} failed: {e}"))
+}
+
+fn unregister_profile(conn: &SyncConnection, profile_path: &Path<'static>) -> Result<()> {
+    call_method_with_dispatch(
+        conn,
+        BLUEZ_SERVICE,
+        &dbus_path("/org/bluez")?,
+        BLUEZ_PROFILE_MANAGER_IFACE,
+        "UnregisterProfile",
+        (profile_path.clone(),),
+        Duration::from_secs(2),
+    )
+    .map_err(|e| anyhow!("UnregisterProfile failed: {e}"))
+}
+
+fn register_profile_iface(cr: &mut Crossroads) -> IfaceToken<ProfileData> {
+    cr.register(BLUEZ_PROFILE_IFACE, |b| {
+        b.method("Release", (), (), |_, data: &mut ProfileData, ()| {
+            eprintln!("hidd: BlueZ released {} profile", data.channel.as_str());
+            Ok(())
+        });
+        b.method(
+            "NewConnection",
+            ("device", "fd", "fd_properties"),
+            (),
+            |_, data: &mut ProfileData, (device, fd, _props): (Path<'static>, OwnedFd, PropMap)| {
+                let stream = UnixStream::from(fd);
+                stream
+                    .set_nonblocking(true)
+                    .map_err(|e| MethodErr::failed(&format!("set_nonblocking: {e}")))?;
+                let mut state = data
+                    .state
+                    .lock()
+                    .map_err(|_| MethodErr::failed(&"failed to lock state"))?;
+                if state.idle.is_some() {
+                    eprintln!("hidd: refusing BR/EDR connection from {device} while idle");
+                    return Err(("org.bluez.Error.Rejected", "controller is idle").into());
+                }
+                eprintln!(
+                    "hidd: BR/EDR {} channel connected from {device}",
+                    data.channel.as_str()
+                );
+                if state.host.as_ref().is_some_and(|host| *host != device) {
+                    eprintln!("hidd: replacing BR/EDR channels of previous host");
+                    state.disconnect();
+                }
+                state.host = Some(device);
+                match data.channel {
+                    Channel::Control => state.control = Some(stream),
+                    Channel::Interrupt => state.interrupt = Some(stream),
+                }
+                Ok(())
+            },
+        );
+        b.method(
+            "RequestDisconnection",
+            ("device",),
+            (),
+            |_, data: &mut ProfileData, (device,): (Path<'static>,)| {
+                eprintln!(
+                    "hidd: BlueZ requested disconnection of {} channel for {device}",
+                    data.channel.as_str()
+                );
+                let mut state = data
+                    .state
+                    .lock()
+                    .map_err(|_| MethodErr::failed(&"failed to lock state"))?;
+                match data.channel {
+                    Channel::Control => state.control = None,
+                    Channel::Interrupt => state.interrupt = None,
+                }
+                Ok(())
+            },
+        );
+    })
+}
+
+/// BlueZ XML for the HID SDP record (HID Profile 1.1, section 5.3). The
+/// report descriptor is the same one HOGP serves as its Report Map.
+fn sdp_record(name: &str, descriptor: &[u8], country_code: u8) -> String {
+    let descriptor_hex = descriptor
+        .iter()
+        .map(|b| format!("{b:02x}"))
+        .collect::<String>();
+    let name = xml_escape(name);
+    format!(
+        r#"<?xml version="1.0" encoding="UTF-8" ?>
+<record>
+  <attribute id="0x0001"><sequence><uuid value="0x1124" /></sequence></attribute>
+  <attribute id="0x0004"><sequence>
+    <sequence><uuid value="0x0100" /><uint16 value="0x{PSM_HID_CONTROL:04x}" /></sequence>
+    <sequence><uuid value="0x0011" /></sequence>
+  </sequence></attribute>
+  <attribute id="0x0005"><sequence><uuid value="0x1002" /></sequence></attribute>
+  <attribute id="0x0006"><sequence>
+    <uint16 value="0x656e" /><uint16 value="0x006a" /><uint16 value="0x0100" />
+  </sequence></attribute>
+  <attribute id="0x0009"><sequence>
+    <sequence><uuid value="0x1124" /><uint16 value="0x0101" /></sequence>
+  </sequence></attribute>
+  <attribute id="0x000d"><sequence><sequence>
+    <sequence><uuid value="0x0100" /><uint16 value="0x{PSM_HID_INTERRUPT:04x}" /></sequence>
+    <sequence><uuid value="0x0011" /></sequence>
+  </sequence></sequence></attribute>
+  <attribute id="0x0100"><text value="{name}" /></attribute>
+  <attribute id="0x0201"><uint16 value="0x0111" /></attribute>
+  <attribute id="0x0202"><uint8 value="0x{HID_DEVICE_SUBCLASS_GAMEPAD:02x}" /></attribute>
+  <attribute id="0x0203"><uint8 value="0x{country_code:02x}" /></attribute>
+  <attribute id="0x0204"><boolean value="true" /></attribute>
+  <attribute id="0x0205"><boolean value="false" /></attribute>
+  <attribute id="0x0206"><sequence><sequence>
+    <uint8 value="0x22" /><text encoding="hex" value="{descriptor_hex}" />
+  </sequence></sequence></attribute>
+  <attribute id="0x0207"><sequence>
+    <sequence><uint16 value="0x0409" /><uint16 value="0x0100" /></sequence>
+  </sequence></attribute>
+  <attribute id="0x0209"><boolean value="true" /></attribute>
+  <attribute id="0x020a"><boolean value="true" /></attribute>
+  <attribute id="0x020b"><uint16 value="0x0100" /></attribute>
+  <attribute id="0x020c"><uint16 value="0x0c80" /></attribute>
+  <attribute id="0x020d"><boolean value="true" /></attribute>
+  <attribute id="0x020e"><boolean value="false" /></attribute>
+</record>
+"#
+    )
+}
+
+fn xml_escape(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => out.push_str("&amp;"),
+            '<' => out.push_str("&lt;"),
+            '>' => out.push_str("&gt;"),
+            '"' => out.push_str("&quot;"),
+            '\'' => out.push_str("&apos;"),
+            _ => out.push(c),
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{sdp_record, BredrState, ControlAction};
+    use common::hid::{
+        HidProfile, HidProfileMode, XBOX_INPUT_REPORT_ID, XBOX_OUTPUT_PAYLOAD_LEN,
+        XBOX_OUTPUT_REPORT_ID,
+    };
+
+    fn xbox() -> BredrState {
+        BredrState::new(HidProfile::from(HidProfileMode::XboxOneS1708))
+    }
+
+    #[test]
+    fn input_reports_become_interrupt_data_messages() {
+        let mut state = xbox();
+        let message = state
+            .input_message(&[XBOX_INPUT_REPORT_ID, 1, 2, 3])
+            .expect("known input report");
+        assert_eq!(message, [0xa1, XBOX_INPUT_REPORT_ID, 1, 2, 3]);
+        assert!(state.input_message(&[0x7f, 0]).is_err());
+    }
+
+    #[test]
+    fn get_report_returns_last_input_report() {
+        let mut state = xbox();
+        state
+            .input_message(&[XBOX_INPUT_REPORT_ID, 9, 8])
+            .expect("known input report");
+        assert_eq!(
+            state.handle_control_message(&[0x41, XBOX_INPUT_REPORT_ID]),
+            ControlAction::Reply(vec![0xa1, XBOX_INPUT_REPORT_ID, 9, 8])
+        );
+        // Buffer size limits the reply, report ID included.
+        assert_eq!(
+            state.handle_control_message(&[0x49, XBOX_INPUT_REPORT_ID, 2, 0]),
+            ControlAction::Reply(vec![0xa1, XBOX_INPUT_REPORT_ID, 9])
+        );
+        assert_eq!(
+            state.handle_control_message(&[0x41, 0x7f]),
+            ControlAction::Reply(vec![0x02])
+        );
+    }
+
+    #[test]
+    fn set_report_queues_output_reports() {
+        let mut state = xbox();
+        let mut report = vec![XBOX_OUTPUT_REPORT_ID];
+        report.resize(XBOX_OUTPUT_PAYLOAD_LEN + 1, 0x11);
+        let mut message = vec![0x52];
+        message.extend_from_slice(&report);
+
+        assert_eq!(
+            state.handle_control_message(&message),
+            ControlAction::Reply(vec![0x00])
+        );
+        state.handle_interrupt_message(&[0xa2, XBOX_OUTPUT_REPORT_ID, 0x22]);
+        assert_eq!(state.pending_outputs.len(), 2);
+        assert_eq!(state.pending_outputs[0], report);
+        assert_eq!(state.pending_outputs[1], [XBOX_OUTPUT_REPORT_ID, 0x22]);
+        // Input reports are not writable.
+        assert_eq!(
+            state.handle_control_message(&[0x51, XBOX_INPUT_REPORT_ID, 0]),
+            ControlAction::Reply(vec![0x04])
+        );
+    }
+
+    #[test]
+    fn protocol_and_control_requests() {
+        let mut state = xbox();
+        assert_eq!(
+            state.handle_control_message(&[0x60]),
+            ControlAction::Reply(vec![0xa0, 0x01])
+        );
+        assert_eq!(
+            state.handle_control_message(&[0x70]),
+            ControlAction::Reply(vec![0x04])
+        );
+        assert_eq!(
+            state.handle_control_message(&[0x71]),
+            ControlAction::Reply(vec![0x00])
+        );
+        assert_eq!(state.handle_control_message(&[0x13]), ControlAction::None);
+        assert!(state.suspended);
+        assert_eq!(state.handle_control_message(&[0x14]), ControlAction::None);
+        assert!(!state.suspended);
+        assert_eq!(state.handle_control_message(&[0x15]), ControlAction::Unplug);
+        assert_eq!(
+            state.handle_control_message(&[0x90, 0]),
+            ControlAction::Reply(vec![0x03])
+        );
+    }
+
+    #[test]
+    fn sdp_record_carries_descriptor_and_escaped_name() {
+        let record = sdp_record("A&B <Pad>", &[0x05, 0x01, 0xc0], 0x21);
+        assert!(record.contains(r#"<text value="A&amp;B &lt;Pad&gt;" />"#));
+        assert!(record.contains(r#"<text encoding="hex" value="0501c0" />"#));
+        assert!(record.contains(r#"<uint8 value="0x21" />"#));
+        assert!(record.contains(r#"<uint16 value="0x0013" />"#));
+    }
+}
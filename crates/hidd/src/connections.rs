@@ -0,0 +1,228 @@
+//! Connection table: what hidd knows about each BlueZ device, keyed by its
+//! object path.
+//!
+//! Device1 signals keep it current. BlueZ's `StartNotify` does not say which
+//! device subscribed, so a subscription is recorded for every connected
+//! device; with one connected host, which is how HOGP is used, that is exact.
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::time::{Duration, Instant};
+
+use dbus::arg::{prop_cast, PropMap};
+use dbus::Path;
+
+/// A characteristic a host can subscribe to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Subscription {
+    InputReport(u8),
+    BatteryLevel,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Connection {
+    /// `XX:XX:XX:XX:XX:XX`, upper case as BlueZ reports it.
+    pub address: String,
+    pub name: String,
+    pub connected: bool,
+    pub bonded: bool,
+    /// When the current connection came up.
+    pub connected_since: Option<Instant>,
+    pub subscriptions: BTreeSet<Subscription>,
+}
+
+impl Connection {
+    fn new(address: String) -> Self {
+        Self {
+            name: address.clone(),
+            address,
+            connected: false,
+            bonded: false,
+            connected_since: None,
+            subscriptions: BTreeSet::new(),
+        }
+    }
+
+    /// How long the device has been connected.
+    pub fn connected_for(&self, now: Instant) -> Option<Duration> {
+        self.connected_since.map(|since| now.duration_since(since))
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct ConnectionTable {
+    devices: BTreeMap<Path<'static>, Connection>,
+}
+
+impl ConnectionTable {
+    /// Apply Device1 properties, from `GetManagedObjects`, `InterfacesAdded`
+    /// or `PropertiesChanged`. A device that connects is assumed to keep the
+    /// `restored` subscriptions: bonded hosts cache their CCCDs and do not
+    /// call `StartNotify` again after a reconnect.
+    pub fn update(
+        &mut self,
+        path: &Path<'static>,
+        props: &PropMap,
+        restored: &[Subscription],
+        now: Instant,
+    ) {
+        let Some(device) = self.entry(path, props) else {
+            return;
+        };
+        if let Some(name) =
+            prop_cast::<String>(props, "Alias").or_else(|| prop_cast::<String>(props, "Name"))
+        {
+            device.name = name.clone();
+        }
+        let flag = |name| prop_cast::<bool>(props, name).copied();
+        let bond = [flag("Bonded"), flag("Paired")];
+        if bond.iter().any(Option::is_some) {
+            device.bonded = bond.contains(&Some(true));
+        }
+        match flag("Connected") {
+            Some(true) if !device.connected => {
+                device.connected = true;
+                device.connected_since = Some(now);
+                device.subscriptions = restored.iter().copied().collect();
+            }
+            Some(false) => {
+                device.connected = false;
+                device.connected_since = None;
+                device.subscriptions.clear();
+            }
+            _ => {}
+        }
+    }
+
+    /// The entry for `path`, created when `props` or the path carry its
+    /// address.
+    fn entry(&mut self, path: &Path<'static>, props: &PropMap) -> Option<&mut Connection> {
+        if !self.devices.contains_key(path) {
+            let address = prop_cast::<String>(props, "Address")
+                .cloned()
+                .or_else(|| device_address(path))?;
+            self.devices.insert(path.clone(), Connection::new(address));
+        }
+        self.devices.get_mut(path)
+    }
+
+    /// BlueZ removed the device, e.g. after it was unpaired.
+    pub fn remove(&mut self, path: &Path<'static>) {
+        self.devices.remove(path);
+    }
+
+    pub fn subscribe(&mut self, subscription: Subscription) {
+        for device in self.devices.values_mut().filter(|d| d.connected) {
+            device.subscriptions.insert(subscription);
+        }
+    }
+
+    pub fn unsubscribe(&mut self, subscription: Subscription) {
+        for device in self.devices.values_mut() {
+            device.subscriptions.remove(&subscription);
+        }
+    }
+
+    pub fn any_connected(&self) -> bool {
+        self.devices.values().any(|d| d.connected)
+    }
+
+    /// A connected device subscribed to `subscription`.
+    pub fn subscribed(&self, subscription: Subscription) -> bool {
+        self.devices
+            .values()
+            .any(|d| d.connected && d.subscriptions.contains(&subscription))
+    }
+
+    /// The device that connected last.
+    pub fn current(&self) -> Option<&Connection> {
+        self.devices
+            .values()
+            .filter(|d| d.connected)
+            .max_by_key(|d| d.connected_since)
+    }
+
+    pub fn get(&self, path: &Path<'static>) -> Option<&Connection> {
+        self.devices.get(path)
+    }
+
+    /// Every known device, by object path.
+    pub fn iter(&self) -> impl Iterator<Item = (&Path<'static>, &Connection)> {
+        self.devices.iter()
+    }
+}
+
+/// Address from a BlueZ device path (`.../dev_98_B6_E9_01_02_03`).
+pub(crate) fn device_address(device_path: &str) -> Option<String> {
+    let address = device_path.rsplit('/').next()?.strip_prefix("dev_")?;
+    Some(address.replace('_', ":"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{device_address, ConnectionTable, Subscription};
+    use dbus::arg::{PropMap, RefArg, Variant};
+    use dbus::Path;
+    use std::time::{Duration, Instant};
+
+    fn props(entries: &[(&str, Box<dyn RefArg>)]) -> PropMap {
+        entries
+            .iter()
+            .map(|(name, value)| (name.to_string(), Variant(value.box_clone())))
+            .collect()
+    }
+
+    #[test]
+    fn tracks_each_device_separately() {
+        let now = Instant::now();
+        let restored = [Subscription::InputReport(1), Subscription::BatteryLevel];
+        let pc = Path::from("/org/bluez/hci0/dev_98_B6_E9_01_02_03");
+        let tv = Path::from("/org/bluez/hci0/dev_AA_BB_CC_DD_EE_FF");
+        let mut table = ConnectionTable::default();
+
+        table.update(
+            &pc,
+            &props(&[
+                ("Alias", Box::new("Living room PC".to_string())),
+                ("Paired", Box::new(true)),
+                ("Connected", Box::new(true)),
+            ]),
+            &restored,
+            now,
+        );
+        let later = now + Duration::from_secs(5);
+        table.update(&tv, &props(&[("Connected", Box::new(true))]), &[], later);
+
+        let current = table.current().expect("two devices connected");
+        assert_eq!(current.address, "AA:BB:CC:DD:EE:FF");
+        assert!(current.subscriptions.is_empty());
+        let first = table.get(&pc).unwrap();
+        assert_eq!(first.name, "Living room PC");
+        assert!(first.bonded);
+        assert_eq!(first.connected_for(later), Some(Duration::from_secs(5)));
+        assert!(table.subscribed(Subscription::BatteryLevel));
+
+        table.update(&pc, &props(&[("Connected", Box::new(false))]), &[], later);
+        assert!(!table.subscribed(Subscription::BatteryLevel));
+        assert!(table.get(&pc).unwrap().subscriptions.is_empty());
+        assert!(table.any_connected());
+
+        table.subscribe(Subscription::InputReport(1));
+        assert!(table.subscribed(Subscription::InputReport(1)));
+        assert!(table.get(&pc).unwrap().subscriptions.is_empty());
+        table.unsubscribe(Subscription::InputReport(1));
+        assert!(!table.subscribed(Subscription::InputReport(1)));
+
+        table.remove(&tv);
+        assert!(!table.any_connected());
+        assert!(table.current().is_none());
+    }
+
+    #[test]
+    fn device_address_comes_from_bluez_device_path() {
+        assert_eq!(
+            device_address("/org/bluez/hci0/dev_98_B6_E9_01_02_03").as_deref(),
+            Some("98:B6:E9:01:02:03")
+        );
+        assert_eq!(device_address("/org/bluez/hci0"), None);
+    }
+}
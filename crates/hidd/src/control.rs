@@ -0,0 +1,239 @@
+//! State behind the `org.controlleros.Hidd1` properties, and the requests its
+//! methods queue for the main loop.
+//!
+//! The main loop and the transport fill in [`DaemonStatus`]. Whenever it
+//! changes, the transport emits `PropertiesChanged` for the properties that
+//! differ from what was last published.
+
+use std::collections::VecDeque;
+use std::path::Path as FsPath;
+use std::sync::{Arc, Mutex};
+
+use anyhow::{anyhow, Result};
+use common::control::{CONTROL_INTERFACE, CONTROL_OBJECT_PATH};
+use common::hid::HidProfileMode;
+use dbus::arg::{PropMap, RefArg, Variant};
+use dbus::blocking::stdintf::org_freedesktop_dbus::PropertiesPropertiesChanged;
+use dbus::blocking::SyncConnection;
+use dbus::channel::Sender;
+use dbus::message::SignalArgs;
+
+use crate::hog::dbus_path;
+
+pub type SharedControl = Arc<Mutex<ControlState>>;
+
+/// Values of the control properties.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct DaemonStatus {
+    /// `hogp` or `bredr`.
+    pub transport: &'static str,
+    /// Active `HidProfileMode` name.
+    pub profile: &'static str,
+    /// Active `--mapping-config` path; empty in pattern mode.
+    pub mapping: String,
+    /// Every `--mapping-config` path, in cycle order.
+    pub mappings: Vec<String>,
+    /// Address of the connected host; empty when none is.
+    pub connected_host: String,
+    /// The host subscribed to input reports.
+    pub notifying: bool,
+    /// The host suspended HID input, so reports are not sent.
+    pub suspended: bool,
+    pub report_rate_hz: u16,
+    /// `InputHealth` name, or `pattern` without a controller.
+    pub input_health: &'static str,
+    /// The pairing window is open.
+    pub pairing: bool,
+    /// No controller input for `[power] idle_timeout_min`; Bluetooth sleeps.
+    pub idle: bool,
+}
+
+impl DaemonStatus {
+    /// Properties whose value differs from `old`, by D-Bus name.
+    fn changed_properties(&self, old: &DaemonStatus) -> PropMap {
+        let mut changed = PropMap::new();
+        let mut put = |name: &str, differs: bool, value: Box<dyn RefArg>| {
+            if differs {
+                changed.insert(name.to_string(), Variant(value));
+            }
+        };
+        put(
+            "Transport",
+            self.transport != old.transport,
+            Box::new(self.transport.to_string()),
+        );
+        put(
+            "Profile",
+            self.profile != old.profile,
+            Box::new(self.profile.to_string()),
+        );
+        put(
+            "Mapping",
+            self.mapping != old.mapping,
+            Box::new(self.mapping.clone()),
+        );
+        put(
+            "Mappings",
+            self.mappings != old.mappings,
+            Box::new(self.mappings.clone()),
+        );
+        put(
+            "ConnectedHost",
+            self.connected_host != old.connected_host,
+            Box::new(self.connected_host.clone()),
+        );
+        put(
+            "Notifying",
+            self.notifying != old.notifying,
+            Box::new(self.notifying),
+        );
+        put(
+            "Suspended",
+            self.suspended != old.suspended,
+            Box::new(self.suspended),
+        );
+        put(
+            "ReportRate",
+            self.report_rate_hz != old.report_rate_hz,
+            Box::new(self.report_rate_hz),
+        );
+        put(
+            "InputHealth",
+            self.input_health != old.input_health,
+            Box::new(self.input_health.to_string()),
+        );
+        put(
+            "Pairing",
+            self.pairing != old.pairing,
+            Box::new(self.pairing),
+        );
+        put("Idle", self.idle != old.idle, Box::new(self.idle));
+        changed
+    }
+}
+
+/// Work for the main loop, queued by control methods.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ControlRequest {
+    /// Switch to `DaemonStatus::mappings[index]`.
+    SelectMapping(usize),
+    /// Re-register the transport with another profile.
+    SelectProfile(HidProfileMode),
+    /// Disconnect the connected host.
+    Disconnect,
+}
+
+#[derive(Debug, Default)]
+pub struct ControlState {
+    pub status: DaemonStatus,
+    /// What `PropertiesChanged` last announced.
+    published: DaemonStatus,
+    requests: VecDeque<ControlRequest>,
+}
+
+impl ControlState {
+    pub fn shared() -> SharedControl {
+        Arc::new(Mutex::new(Self::default()))
+    }
+
+    pub fn push_request(&mut self, request: ControlRequest) {
+        self.requests.push_back(request);
+    }
+
+    pub fn take_requests(&mut self) -> Vec<ControlRequest> {
+        self.requests.drain(..).collect()
+    }
+
+    /// Index of the mapping named by its path or file stem (`xbox` for
+    /// `/etc/controlleros/mapping/xbox.toml`).
+    pub fn mapping_index(&self, name: &str) -> Option<usize> {
+        self.status.mappings.iter().position(|path| {
+            path == name
+                || FsPath::new(path)
+                    .file_stem()
+                    .is_some_and(|stem| stem == name)
+        })
+    }
+
+    /// Properties changed since the last call, if any.
+    fn take_changes(&mut self) -> Option<PropMap> {
+        if self.status == self.published {
+            return None;
+        }
+        let changed = self.status.changed_properties(&self.published);
+        self.published = self.status.clone();
+        Some(changed)
+    }
+}
+
+/// Apply `update` and announce the properties it changed.
+pub fn publish_status(
+    conn: &SyncConnection,
+    control: &SharedControl,
+    update: impl FnOnce(&mut DaemonStatus),
+) -> Result<()> {
+    let changes = {
+        let mut control = control
+            .lock()
+            .map_err(|_| anyhow!("failed to lock control state"))?;
+        update(&mut control.status);
+        control.take_changes()
+    };
+    let Some(changed_properties) = changes else {
+        return Ok(());
+    };
+    let signal = PropertiesPropertiesChanged {
+        interface_name: CONTROL_INTERFACE.to_string(),
+        changed_properties,
+        invalidated_properties: Vec::new(),
+    };
+    conn.send(signal.to_emit_message(&dbus_path(CONTROL_OBJECT_PATH)?))
+        .map_err(|_| anyhow!("failed to emit control PropertiesChanged"))?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{ControlState, DaemonStatus};
+
+    #[test]
+    fn changes_are_announced_once() {
+        let mut control = ControlState {
+            status: DaemonStatus {
+                transport: "hogp",
+                profile: "xbox_one_s_1708",
+                report_rate_hz: 125,
+                input_health: "ok",
+                ..DaemonStatus::default()
+            },
+            ..ControlState::default()
+        };
+        let changed = control.take_changes().expect("initial values are news");
+        assert!(changed.contains_key("Profile"));
+        assert!(changed.contains_key("ReportRate"));
+        assert!(!changed.contains_key("Mapping"));
+        assert!(control.take_changes().is_none());
+
+        control.status.connected_host = "98:B6:E9:01:02:03".to_string();
+        control.status.notifying = true;
+        let changed = control.take_changes().expect("host connected");
+        let mut names = changed.keys().map(String::as_str).collect::<Vec<_>>();
+        names.sort_unstable();
+        assert_eq!(names, ["ConnectedHost", "Notifying"]);
+    }
+
+    #[test]
+    fn mappings_match_by_path_or_stem() {
+        let mut control = ControlState::default();
+        control.status.mappings = vec![
+            "/etc/controlleros/mapping/xbox.toml".to_string(),
+            "/etc/controlleros/mapping/fps.toml".to_string(),
+        ];
+        assert_eq!(control.mapping_index("fps"), Some(1));
+        assert_eq!(
+            control.mapping_index("/etc/controlleros/mapping/xbox.toml"),
+            Some(0)
+        );
+        assert_eq!(control.mapping_index("racing"), None);
+    }
+}
@@ -1,33 +1,39 @@
-use std::collections::HashMap;
+use std::collections::{HashMap, VecDeque};
 use std::sync::atomic::{AtomicBool, AtomicU32, Ordering};
 use std::sync::{Arc, Mutex};
 use std::time::{Duration, Instant};
 
 use anyhow::{anyhow, Result};
-use common::config::HidConfig;
-use common::hid::{
-    OutputReport, XBOX_EXTRA_INPUT_PAYLOAD_LEN, XBOX_EXTRA_INPUT_REPORT_ID, XBOX_INPUT_PAYLOAD_LEN,
-    XBOX_INPUT_REPORT_ID, XBOX_OUTPUT_PAYLOAD_LEN, XBOX_OUTPUT_REPORT_ID, XBOX_OUTPUT_REPORT_LEN,
-    XBOX_STATUS_INPUT_PAYLOAD_LEN, XBOX_STATUS_INPUT_REPORT_ID,
+use common::config::{
+    AdvertisingConfig, AgentCapability, DeviceInfo, HidConfig, IdleAction, ReconnectMode,
 };
-use dbus::arg::{Append, AppendAll, Arg, IterAppend, PropMap, RefArg, Variant};
+use common::control::{CONTROL_BUS_NAME, CONTROL_INTERFACE, CONTROL_OBJECT_PATH};
+use common::hid::{HidProfile, HidProfileMode, ReportType};
+use dbus::arg::{prop_cast, Append, AppendAll, Arg, IterAppend, PropMap, RefArg, Variant};
 use dbus::blocking::stdintf::org_freedesktop_dbus::{
-    ObjectManager as OrgFreedesktopObjectManager, PropertiesPropertiesChanged,
+    ObjectManager as OrgFreedesktopObjectManager, ObjectManagerInterfacesAdded,
+    ObjectManagerInterfacesRemoved, Properties, PropertiesPropertiesChanged,
 };
 use dbus::blocking::SyncConnection;
 use dbus::channel::{MatchingReceiver, Sender};
 use dbus::message::{MatchRule, SignalArgs};
 use dbus::strings::{BusName, Interface, Member};
 use dbus::{Message, MessageType, Path};
-use dbus_crossroads::{Crossroads, IfaceToken, MethodErr};
+use dbus_crossroads::{Context, Crossroads, IfaceToken, MethodErr};
 
-const BLUEZ_SERVICE: &str = "org.bluez";
-const BLUEZ_ROOT_PATH: &str = "/";
-const BLUEZ_ADAPTER_IFACE: &str = "org.bluez.Adapter1";
+use crate::connections::{device_address, ConnectionTable, Subscription};
+use crate::control::{publish_status, ControlRequest, ControlState, DaemonStatus, SharedControl};
+use crate::hosts::{BondedHost, HostEvent};
+use crate::pairing::{held_call, AdapterMode, PairingPolicy, SharedPolicy};
+
+pub(crate) const BLUEZ_SERVICE: &str = "org.bluez";
+pub(crate) const BLUEZ_ROOT_PATH: &str = "/";
+pub(crate) const BLUEZ_ADAPTER_IFACE: &str = "org.bluez.Adapter1";
 const BLUEZ_GATT_MANAGER_IFACE: &str = "org.bluez.GattManager1";
 const BLUEZ_LE_ADV_MANAGER_IFACE: &str = "org.bluez.LEAdvertisingManager1";
-const BLUEZ_DEVICE_IFACE: &str = "org.bluez.Device1";
+pub(crate) const BLUEZ_DEVICE_IFACE: &str = "org.bluez.Device1";
 const DBUS_PROPERTIES_IFACE: &str = "org.freedesktop.DBus.Properties";
+const DBUS_SERVICE: &str = "org.freedesktop.DBus";
 
 const GATT_SERVICE_IFACE: &str = "org.bluez.GattService1";
 const GATT_CHARACTERISTIC_IFACE: &str = "org.bluez.GattCharacteristic1";
@@ -39,26 +45,24 @@ const BLUEZ_AGENT_IFACE: &str = "org.bluez.Agent1";
 
 const APP_PATH: &str = "/org/controlleros/hid";
 const ADVERTISEMENT_PATH: &str = "/org/controlleros/advertisement0";
-const AGENT_PATH: &str = "/org/controlleros/agent";
+pub(crate) const AGENT_PATH: &str = "/org/controlleros/agent";
 
 const HID_SERVICE_PATH: &str = "/org/controlleros/hid/service0";
 const HID_PROTOCOL_MODE_CHAR_PATH: &str = "/org/controlleros/hid/service0/char0";
 const HID_REPORT_MAP_CHAR_PATH: &str = "/org/controlleros/hid/service0/char1";
 const HID_INFO_CHAR_PATH: &str = "/org/controlleros/hid/service0/char2";
 const HID_CONTROL_POINT_CHAR_PATH: &str = "/org/controlleros/hid/service0/char3";
-const HID_INPUT_REPORT_CHAR_PATH: &str = "/org/controlleros/hid/service0/char4";
-const HID_OUTPUT_REPORT_CHAR_PATH: &str = "/org/controlleros/hid/service0/char5";
-const HID_EXTRA_INPUT_REPORT_CHAR_PATH: &str = "/org/controlleros/hid/service0/char6";
-const HID_STATUS_INPUT_REPORT_CHAR_PATH: &str = "/org/controlleros/hid/service0/char7";
-const HID_INPUT_REPORT_REF_DESC_PATH: &str = "/org/controlleros/hid/service0/char4/desc0";
-const HID_OUTPUT_REPORT_REF_DESC_PATH: &str = "/org/controlleros/hid/service0/char5/desc0";
-const HID_EXTRA_INPUT_REPORT_REF_DESC_PATH: &str = "/org/controlleros/hid/service0/char6/desc0";
-const HID_STATUS_INPUT_REPORT_REF_DESC_PATH: &str = "/org/controlleros/hid/service0/char7/desc0";
+/// Report characteristics follow the fixed HID characteristics, one per
+/// report in `HidProfileMode::reports` order (char4, char5, ...).
+const HID_FIRST_REPORT_CHAR_INDEX: usize = 4;
 
 const BATTERY_SERVICE_PATH: &str = "/org/controlleros/hid/service1";
 const BATTERY_LEVEL_CHAR_PATH: &str = "/org/controlleros/hid/service1/char0";
 const DEVICE_INFO_SERVICE_PATH: &str = "/org/controlleros/hid/service2";
 const PNP_ID_CHAR_PATH: &str = "/org/controlleros/hid/service2/char0";
+/// Device Information strings follow the PnP ID (char1, char2, ...), one per
+/// string present in `DeviceInfo`.
+const DEVICE_INFO_FIRST_STRING_CHAR_INDEX: usize = 1;
 
 const HID_SERVICE_UUID: &str = "00001812-0000-1000-8000-00805f9b34fb";
 const BATTERY_SERVICE_UUID: &str = "0000180f-0000-1000-8000-00805f9b34fb";
@@ -70,70 +74,226 @@ const HID_CONTROL_POINT_UUID: &str = "00002a4c-0000-1000-8000-00805f9b34fb";
 const HID_REPORT_UUID: &str = "00002a4d-0000-1000-8000-00805f9b34fb";
 const BATTERY_LEVEL_UUID: &str = "00002a19-0000-1000-8000-00805f9b34fb";
 const PNP_ID_UUID: &str = "00002a50-0000-1000-8000-00805f9b34fb";
+const MANUFACTURER_NAME_UUID: &str = "00002a29-0000-1000-8000-00805f9b34fb";
+const MODEL_NUMBER_UUID: &str = "00002a24-0000-1000-8000-00805f9b34fb";
+const SERIAL_NUMBER_UUID: &str = "00002a25-0000-1000-8000-00805f9b34fb";
+const FIRMWARE_REVISION_UUID: &str = "00002a26-0000-1000-8000-00805f9b34fb";
+const HARDWARE_REVISION_UUID: &str = "00002a27-0000-1000-8000-00805f9b34fb";
+const SOFTWARE_REVISION_UUID: &str = "00002a28-0000-1000-8000-00805f9b34fb";
 const REPORT_REFERENCE_UUID: &str = "00002908-0000-1000-8000-00805f9b34fb";
 
-const HID_GAMEPAD_APPEARANCE: u16 = 0x03c4;
 const REPORT_TYPE_INPUT: u8 = 0x01;
 const REPORT_TYPE_OUTPUT: u8 = 0x02;
+const REPORT_TYPE_FEATURE: u8 = 0x03;
 const HID_INFO_BCD: [u8; 2] = [0x11, 0x01];
 const HID_FLAG_REMOTE_WAKE: u8 = 0x01;
 const PNP_ID_VENDOR_SOURCE_USB: u8 = 0x01;
+const HID_CONTROL_POINT_SUSPEND: u8 = 0x00;
+const HID_CONTROL_POINT_EXIT_SUSPEND: u8 = 0x01;
+/// Output reports waiting for the main loop; older ones are dropped first.
+pub(crate) const MAX_PENDING_OUTPUT_REPORTS: usize = 32;
+/// Host events waiting for the main loop; older ones are dropped first.
+const MAX_PENDING_HOST_EVENTS: usize = 16;
 
 type SharedState = Arc<Mutex<HogState>>;
 
+/// `ListHosts` entry: address, name, connected, current.
+type HostListEntry = (String, String, bool, bool);
+
+/// `ListConnections` entry: address, name, connected, bonded, seconds
+/// connected, subscribed input report IDs, subscribed to battery level.
+type ConnectionListEntry = (String, String, bool, bool, u32, Vec<u8>, bool);
+
 #[derive(Debug)]
 struct InputReportState {
-    notifying: bool,
     value: Vec<u8>,
 }
 
 #[derive(Debug)]
 struct HogState {
+    profile: HidProfile,
     protocol_mode: u8,
     control_point: u8,
-    battery_notifying: bool,
+    /// The host wrote Suspend to the HID Control Point; input reports are
+    /// stored but not notified until it writes Exit Suspend or disconnects.
+    suspended: bool,
     input_reports: HashMap<u8, InputReportState>,
     output_reports: HashMap<u8, Vec<u8>>,
+    /// Last value written by the host, zeroed until then.
+    feature_reports: HashMap<u8, Vec<u8>>,
+    /// Output reports (with report ID) not yet taken by the main loop.
+    pending_outputs: VecDeque<Vec<u8>>,
     battery_level: u8,
+    /// Host changes and control requests not yet taken by the main loop.
+    host_events: VecDeque<HostEvent>,
+    /// Bonded hosts as last listed by the main loop.
+    hosts: Vec<HostListEntry>,
+    /// `reconnect = "bonded"` after a bonded host dropped: advertise without
+    /// the discoverable flag and refuse new pairings.
+    reconnect_only: bool,
+    /// When `reconnect_only` ends; `None` keeps it until restart.
+    reconnect_deadline: Option<Instant>,
+    /// Discoverable/Pairable last applied to the adapter.
+    adapter_mode: AdapterMode,
+    /// Devices on the adapter, kept current by Device1 signals.
+    connections: ConnectionTable,
+    /// The controller went idle and Bluetooth was put to sleep: nothing is
+    /// advertised and the adapter mode is left alone until it wakes.
+    idle: Option<IdleAction>,
 }
 
 impl HogState {
-    fn new() -> Self {
+    fn new(profile: HidProfile) -> Self {
         let mut input_reports = HashMap::new();
-        input_reports.insert(
-            XBOX_INPUT_REPORT_ID,
-            InputReportState {
-                notifying: false,
-                value: vec![0; XBOX_INPUT_PAYLOAD_LEN],
-            },
-        );
-        input_reports.insert(
-            XBOX_EXTRA_INPUT_REPORT_ID,
-            InputReportState {
-                notifying: false,
-                value: vec![0; XBOX_EXTRA_INPUT_PAYLOAD_LEN],
-            },
-        );
-        input_reports.insert(
-            XBOX_STATUS_INPUT_REPORT_ID,
-            InputReportState {
-                notifying: false,
-                value: vec![0; XBOX_STATUS_INPUT_PAYLOAD_LEN],
-            },
-        );
-
         let mut output_reports = HashMap::new();
-        output_reports.insert(XBOX_OUTPUT_REPORT_ID, vec![0; XBOX_OUTPUT_PAYLOAD_LEN]);
+        let mut feature_reports = HashMap::new();
+        for report in profile.reports() {
+            match report.report_type {
+                ReportType::Input => {
+                    input_reports.insert(
+                        report.id,
+                        InputReportState {
+                            value: vec![0; report.payload_len],
+                        },
+                    );
+                }
+                ReportType::Output => {
+                    output_reports.insert(report.id, vec![0; report.payload_len]);
+                }
+                ReportType::Feature => {
+                    feature_reports.insert(report.id, vec![0; report.payload_len]);
+                }
+            }
+        }
 
         Self {
+            profile,
             protocol_mode: 0x01, // Report protocol mode
             control_point: 0,
-            battery_notifying: false,
+            suspended: false,
             input_reports,
             output_reports,
+            feature_reports,
+            pending_outputs: VecDeque::new(),
             battery_level: 100,
+            host_events: VecDeque::new(),
+            hosts: Vec::new(),
+            reconnect_only: false,
+            reconnect_deadline: None,
+            adapter_mode: AdapterMode {
+                discoverable: true,
+                pairable: true,
+            },
+            connections: ConnectionTable::default(),
+            idle: None,
+        }
+    }
+
+    /// Everything a host can subscribe to, restored when a bonded host
+    /// reconnects.
+    fn all_subscriptions(&self) -> Vec<Subscription> {
+        let mut subscriptions = self
+            .input_reports
+            .keys()
+            .map(|&id| Subscription::InputReport(id))
+            .collect::<Vec<_>>();
+        subscriptions.push(Subscription::BatteryLevel);
+        subscriptions
+    }
+
+    /// Apply Device1 properties of `path` to the connection table.
+    fn update_connection(&mut self, path: &Path<'static>, props: &PropMap) {
+        let restored = self.all_subscriptions();
+        self.connections
+            .update(path, props, &restored, Instant::now());
+        if self.suspended && !self.connections.any_connected() {
+            eprintln!("hidd: suspended host disconnected; resuming input reports");
+            self.suspended = false;
         }
     }
+
+    /// Apply a HID Control Point write.
+    fn write_control_point(&mut self, value: u8) -> Result<(), &'static str> {
+        let suspended = match value {
+            HID_CONTROL_POINT_SUSPEND => true,
+            HID_CONTROL_POINT_EXIT_SUSPEND => false,
+            _ => return Err("HID Control Point value must be 0x00 or 0x01"),
+        };
+        if suspended != self.suspended {
+            eprintln!(
+                "hidd: host {} suspend",
+                if suspended { "entered" } else { "exited" }
+            );
+        }
+        self.control_point = value;
+        self.suspended = suspended;
+        Ok(())
+    }
+
+    /// The connection table for `ListConnections`.
+    fn connection_list(&self) -> Vec<ConnectionListEntry> {
+        let now = Instant::now();
+        self.connections
+            .iter()
+            .map(|(_, c)| {
+                let reports = c
+                    .subscriptions
+                    .iter()
+                    .filter_map(|s| match s {
+                        Subscription::InputReport(id) => Some(*id),
+                        Subscription::BatteryLevel => None,
+                    })
+                    .collect();
+                (
+                    c.address.clone(),
+                    c.name.clone(),
+                    c.connected,
+                    c.bonded,
+                    c.connected_for(now)
+                        .map_or(0, |d| u32::try_from(d.as_secs()).unwrap_or(u32::MAX)),
+                    reports,
+                    c.subscriptions.contains(&Subscription::BatteryLevel),
+                )
+            })
+            .collect()
+    }
+
+    /// Store a feature report payload written by the host. Short payloads
+    /// are zero-padded; unknown IDs and overlong payloads are refused.
+    fn write_feature_report(&mut self, report_id: u8, payload: &[u8]) -> Result<(), &'static str> {
+        let slot = self
+            .feature_reports
+            .get_mut(&report_id)
+            .ok_or("unknown feature report")?;
+        if payload.len() > slot.len() {
+            return Err("feature report is longer than the descriptor declares");
+        }
+        slot[..payload.len()].copy_from_slice(payload);
+        slot[payload.len()..].fill(0);
+        Ok(())
+    }
+
+    fn queue_output_report(&mut self, report: Vec<u8>) {
+        if self.pending_outputs.len() == MAX_PENDING_OUTPUT_REPORTS {
+            self.pending_outputs.pop_front();
+        }
+        self.pending_outputs.push_back(report);
+    }
+
+    fn queue_host_event(&mut self, event: HostEvent) {
+        if self.host_events.len() == MAX_PENDING_HOST_EVENTS {
+            self.host_events.pop_front();
+        }
+        self.host_events.push_back(event);
+    }
+
+    /// Only let bonded hosts back in, for `timeout_s` seconds (0: until
+    /// restart).
+    fn enter_reconnect_only(&mut self, timeout_s: u32) {
+        self.reconnect_only = true;
+        self.reconnect_deadline =
+            (timeout_s > 0).then(|| Instant::now() + Duration::from_secs(u64::from(timeout_s)));
+    }
 }
 
 #[derive(Debug, Clone)]
@@ -161,8 +321,10 @@ enum CharacteristicKind {
     ControlPoint,
     InputReport { report_id: u8 },
     OutputReport { report_id: u8 },
+    FeatureReport { report_id: u8 },
     BatteryLevel,
     PnpId { value: [u8; 7] },
+    DeviceInfoString { value: String },
 }
 
 #[derive(Debug, Clone)]
@@ -182,44 +344,171 @@ enum DescriptorKind {
 struct AdvertisementData {
     local_name: String,
     service_uuids: Vec<String>,
+    scan_response_uuids: Vec<String>,
     appearance: u16,
+    manufacturer_data: Option<(u16, Vec<u8>)>,
+    /// Manufacturer data goes in the scan response.
+    scan_response: bool,
+    include_tx_power: bool,
+    min_interval_ms: Option<u32>,
+    max_interval_ms: Option<u32>,
+    timeout_s: Option<u16>,
+    /// Set the `Discoverable` flag from the lock and reconnect state instead
+    /// of leaving it to the adapter.
+    manage_discoverable: bool,
+    state: SharedState,
+    policy: SharedPolicy,
+}
+
+impl AdvertisementData {
+    fn new(cfg: &HidConfig, state: SharedState, policy: SharedPolicy) -> Self {
+        let adv: &AdvertisingConfig = &cfg.advertising;
+        let mut service_uuids = vec![HID_SERVICE_UUID.to_string()];
+        let secondary = [BATTERY_SERVICE_UUID, DEVICE_INFO_SERVICE_UUID].map(str::to_string);
+        let mut scan_response_uuids = Vec::new();
+        if adv.scan_response {
+            scan_response_uuids.extend(secondary);
+        } else {
+            service_uuids.extend(secondary);
+        }
+        Self {
+            local_name: cfg.device.name.clone(),
+            service_uuids,
+            scan_response_uuids,
+            appearance: cfg.appearance(),
+            manufacturer_data: adv
+                .manufacturer_id
+                .map(|id| (id, adv.manufacturer_data.clone())),
+            scan_response: adv.scan_response,
+            include_tx_power: adv.include_tx_power,
+            min_interval_ms: adv.min_interval_ms,
+            max_interval_ms: adv.max_interval_ms,
+            timeout_s: adv.timeout_s,
+            manage_discoverable: adv.reconnect == ReconnectMode::Bonded || cfg.pairing.locked,
+            state,
+            policy,
+        }
+    }
+
+    /// `ManufacturerData` / `ScanResponseManufacturerData` value.
+    fn manufacturer_data_property(
+        &self,
+        in_scan_response: bool,
+    ) -> Result<ManufacturerData, MethodErr> {
+        match &self.manufacturer_data {
+            Some((id, data)) if self.scan_response == in_scan_response => {
+                Ok(HashMap::from([(*id, Variant(data.clone()))]))
+            }
+            _ => Err(MethodErr::no_property(&"ManufacturerData")),
+        }
+    }
+}
+
+/// BlueZ `a{qv}` manufacturer data: company ID to payload.
+type ManufacturerData = HashMap<u16, Variant<Vec<u8>>>;
+
+/// Data of the control object. `state` is `None` on BR/EDR, which has no
+/// host switching.
+pub(crate) struct ControlData {
+    state: Option<SharedState>,
+    policy: SharedPolicy,
+    control: SharedControl,
+}
+
+impl ControlData {
+    pub(crate) fn without_hosts(policy: SharedPolicy, control: SharedControl) -> Self {
+        Self {
+            state: None,
+            policy,
+            control,
+        }
+    }
+
+    fn hog_state(&self) -> Result<std::sync::MutexGuard<'_, HogState>, MethodErr> {
+        self.state
+            .as_ref()
+            .ok_or_else(|| {
+                MethodErr::from((
+                    "org.freedesktop.DBus.Error.NotSupported",
+                    "host switching needs the HOGP transport",
+                ))
+            })?
+            .lock()
+            .map_err(|_| MethodErr::failed(&"failed to lock HOG state"))
+    }
+
+    fn policy(&self) -> Result<std::sync::MutexGuard<'_, PairingPolicy>, MethodErr> {
+        self.policy
+            .lock()
+            .map_err(|_| MethodErr::failed(&"failed to lock pairing policy"))
+    }
+
+    fn control(&self) -> Result<std::sync::MutexGuard<'_, ControlState>, MethodErr> {
+        self.control
+            .lock()
+            .map_err(|_| MethodErr::failed(&"failed to lock control state"))
+    }
 }
 
 pub struct HogRuntime {
     conn: SyncConnection,
     state: SharedState,
+    policy: SharedPolicy,
+    control: SharedControl,
     input_report_char_paths: HashMap<u8, Path<'static>>,
     adapter_path: Path<'static>,
-    connected: Arc<AtomicBool>,
+    adapter_address: [u8; 6],
     adv_needs_retry: Arc<AtomicBool>,
+    /// Set by `watch_bluez`; every registration is stale.
+    bluez_lost: Arc<AtomicBool>,
 }
 
 impl HogRuntime {
-    pub fn register(cfg: &HidConfig) -> Result<Self> {
-        let conn = SyncConnection::new_system().map_err(|e| anyhow!("system bus: {e}"))?;
+    pub fn register(
+        cfg: &HidConfig,
+        device_info: &DeviceInfo,
+        control: SharedControl,
+    ) -> Result<Self> {
+        let conn = system_bus().map_err(|e| anyhow!("system bus: {e}"))?;
+        // watch_bluez and track_devices both follow InterfacesRemoved; by
+        // default only the first matching receiver sees a signal.
+        conn.set_signal_match_mode(true);
         eprintln!(
             "hidd: opened system D-Bus connection (unique name: {:?})",
             conn.unique_name()
         );
         let adapter_path = find_adapter_path(&conn)?;
         eprintln!("hidd: using BlueZ adapter {adapter_path}");
-        configure_adapter_for_hog(&conn, &adapter_path, cfg)?;
+        let adapter_address = read_adapter_address(&conn, &adapter_path).unwrap_or_else(|e| {
+            eprintln!("hidd: {e}; reporting 00:00:00:00:00:00 as device address");
+            [0; 6]
+        });
+        configure_adapter(&conn, &adapter_path, cfg)?;
         let app_path = dbus_path(APP_PATH)?;
         let advertisement_path = dbus_path(ADVERTISEMENT_PATH)?;
-        let input_report_char_path = dbus_path(HID_INPUT_REPORT_CHAR_PATH)?;
-        let extra_input_report_char_path = dbus_path(HID_EXTRA_INPUT_REPORT_CHAR_PATH)?;
-        let status_input_report_char_path = dbus_path(HID_STATUS_INPUT_REPORT_CHAR_PATH)?;
         let mut input_report_char_paths = HashMap::new();
-        input_report_char_paths.insert(XBOX_INPUT_REPORT_ID, input_report_char_path.clone());
-        input_report_char_paths.insert(
-            XBOX_EXTRA_INPUT_REPORT_ID,
-            extra_input_report_char_path.clone(),
-        );
-        input_report_char_paths.insert(
-            XBOX_STATUS_INPUT_REPORT_ID,
-            status_input_report_char_path.clone(),
-        );
-        let state = Arc::new(Mutex::new(HogState::new()));
+        let profile = cfg.profile.hid_profile();
+        let state = Arc::new(Mutex::new(HogState::new(profile.clone())));
+        let policy = PairingPolicy::shared(&cfg.pairing);
+        {
+            let mut s = state
+                .lock()
+                .map_err(|_| anyhow!("failed to lock HOG state"))?;
+            if cfg.advertising.reconnect == ReconnectMode::Bonded
+                && !list_bonded_hosts(&conn, &adapter_path)?.is_empty()
+            {
+                eprintln!("hidd: bonded hosts present, advertising for reconnection only");
+                s.enter_reconnect_only(cfg.advertising.reconnect_timeout_s);
+            }
+            let mode = policy
+                .lock()
+                .map_err(|_| anyhow!("failed to lock pairing policy"))?
+                .adapter_mode(Instant::now(), s.reconnect_only);
+            if mode != s.adapter_mode {
+                set_adapter_mode(&conn, &adapter_path, mode)?;
+                s.adapter_mode = mode;
+            }
+        }
         let country_code = u8::try_from(cfg.profile.country)
             .map_err(|_| anyhow!("profile.country must be in 0..=255 for HID Information"))?;
         let pnp_id_value = encode_pnp_id(
@@ -235,6 +524,7 @@ impl HogRuntime {
         let descriptor_iface = register_descriptor_iface(&mut crossroads);
         let advertisement_iface = register_advertisement_iface(&mut crossroads);
         let agent_iface = register_agent_iface(&mut crossroads);
+        let control_iface = register_control_iface(&mut crossroads);
 
         crossroads.insert(APP_PATH, &[crossroads.object_manager()], ());
 
@@ -286,7 +576,7 @@ impl HogRuntime {
                 service: dbus_path(HID_SERVICE_PATH)?,
                 flags: vec!["encrypt-read".to_string()],
                 descriptors: Vec::new(),
-                kind: CharacteristicKind::ReportMap(cfg.profile.mode.report_descriptor().to_vec()),
+                kind: CharacteristicKind::ReportMap(profile.report_descriptor().to_vec()),
                 state: Arc::clone(&state),
             },
         );
@@ -314,62 +604,6 @@ impl HogRuntime {
                 state: Arc::clone(&state),
             },
         );
-        crossroads.insert(
-            HID_INPUT_REPORT_CHAR_PATH,
-            &[characteristic_iface],
-            GattCharacteristicData {
-                uuid: HID_REPORT_UUID.to_string(),
-                service: dbus_path(HID_SERVICE_PATH)?,
-                flags: vec!["encrypt-read".to_string(), "encrypt-notify".to_string()],
-                descriptors: vec![dbus_path(HID_INPUT_REPORT_REF_DESC_PATH)?],
-                kind: CharacteristicKind::InputReport {
-                    report_id: XBOX_INPUT_REPORT_ID,
-                },
-                state: Arc::clone(&state),
-            },
-        );
-        crossroads.insert(
-            HID_OUTPUT_REPORT_CHAR_PATH,
-            &[characteristic_iface],
-            GattCharacteristicData {
-                uuid: HID_REPORT_UUID.to_string(),
-                service: dbus_path(HID_SERVICE_PATH)?,
-                flags: vec!["encrypt-read".to_string(), "encrypt-write".to_string()],
-                descriptors: vec![dbus_path(HID_OUTPUT_REPORT_REF_DESC_PATH)?],
-                kind: CharacteristicKind::OutputReport {
-                    report_id: XBOX_OUTPUT_REPORT_ID,
-                },
-                state: Arc::clone(&state),
-            },
-        );
-        crossroads.insert(
-            HID_EXTRA_INPUT_REPORT_CHAR_PATH,
-            &[characteristic_iface],
-            GattCharacteristicData {
-                uuid: HID_REPORT_UUID.to_string(),
-                service: dbus_path(HID_SERVICE_PATH)?,
-                flags: vec!["encrypt-read".to_string(), "encrypt-notify".to_string()],
-                descriptors: vec![dbus_path(HID_EXTRA_INPUT_REPORT_REF_DESC_PATH)?],
-                kind: CharacteristicKind::InputReport {
-                    report_id: XBOX_EXTRA_INPUT_REPORT_ID,
-                },
-                state: Arc::clone(&state),
-            },
-        );
-        crossroads.insert(
-            HID_STATUS_INPUT_REPORT_CHAR_PATH,
-            &[characteristic_iface],
-            GattCharacteristicData {
-                uuid: HID_REPORT_UUID.to_string(),
-                service: dbus_path(HID_SERVICE_PATH)?,
-                flags: vec!["encrypt-read".to_string(), "encrypt-notify".to_string()],
-                descriptors: vec![dbus_path(HID_STATUS_INPUT_REPORT_REF_DESC_PATH)?],
-                kind: CharacteristicKind::InputReport {
-                    report_id: XBOX_STATUS_INPUT_REPORT_ID,
-                },
-                state: Arc::clone(&state),
-            },
-        );
         crossroads.insert(
             BATTERY_LEVEL_CHAR_PATH,
             &[characteristic_iface],
@@ -396,75 +630,99 @@ impl HogRuntime {
                 state: Arc::clone(&state),
             },
         );
-
-        crossroads.insert(
-            HID_INPUT_REPORT_REF_DESC_PATH,
-            &[descriptor_iface],
-            GattDescriptorData {
-                uuid: REPORT_REFERENCE_UUID.to_string(),
-                characteristic: dbus_path(HID_INPUT_REPORT_CHAR_PATH)?,
-                flags: vec!["encrypt-read".to_string()],
-                kind: DescriptorKind::ReportReference {
-                    report_id: XBOX_INPUT_REPORT_ID,
-                    report_type: REPORT_TYPE_INPUT,
-                },
-            },
-        );
-        crossroads.insert(
-            HID_OUTPUT_REPORT_REF_DESC_PATH,
-            &[descriptor_iface],
-            GattDescriptorData {
-                uuid: REPORT_REFERENCE_UUID.to_string(),
-                characteristic: dbus_path(HID_OUTPUT_REPORT_CHAR_PATH)?,
-                flags: vec!["encrypt-read".to_string()],
-                kind: DescriptorKind::ReportReference {
-                    report_id: XBOX_OUTPUT_REPORT_ID,
-                    report_type: REPORT_TYPE_OUTPUT,
+        for (index, (uuid, value)) in device_info_strings(device_info).into_iter().enumerate() {
+            crossroads.insert(
+                dbus_path(&format!(
+                    "{DEVICE_INFO_SERVICE_PATH}/char{}",
+                    DEVICE_INFO_FIRST_STRING_CHAR_INDEX + index
+                ))?,
+                &[characteristic_iface],
+                GattCharacteristicData {
+                    uuid: uuid.to_string(),
+                    service: dbus_path(DEVICE_INFO_SERVICE_PATH)?,
+                    flags: vec!["read".to_string()],
+                    descriptors: Vec::new(),
+                    kind: CharacteristicKind::DeviceInfoString { value },
+                    state: Arc::clone(&state),
                 },
-            },
-        );
-        crossroads.insert(
-            HID_EXTRA_INPUT_REPORT_REF_DESC_PATH,
-            &[descriptor_iface],
-            GattDescriptorData {
-                uuid: REPORT_REFERENCE_UUID.to_string(),
-                characteristic: dbus_path(HID_EXTRA_INPUT_REPORT_CHAR_PATH)?,
-                flags: vec!["encrypt-read".to_string()],
-                kind: DescriptorKind::ReportReference {
-                    report_id: XBOX_EXTRA_INPUT_REPORT_ID,
-                    report_type: REPORT_TYPE_INPUT,
+            );
+        }
+
+        for (index, report) in profile.reports().iter().enumerate() {
+            let char_path = dbus_path(&format!(
+                "{HID_SERVICE_PATH}/char{}",
+                HID_FIRST_REPORT_CHAR_INDEX + index
+            ))?;
+            let desc_path = dbus_path(&format!("{char_path}/desc0"))?;
+            let (flags, kind, report_type) = match report.report_type {
+                ReportType::Input => {
+                    input_report_char_paths.insert(report.id, char_path.clone());
+                    (
+                        vec!["encrypt-read".to_string(), "encrypt-notify".to_string()],
+                        CharacteristicKind::InputReport {
+                            report_id: report.id,
+                        },
+                        REPORT_TYPE_INPUT,
+                    )
+                }
+                ReportType::Output => (
+                    vec!["encrypt-read".to_string(), "encrypt-write".to_string()],
+                    CharacteristicKind::OutputReport {
+                        report_id: report.id,
+                    },
+                    REPORT_TYPE_OUTPUT,
+                ),
+                ReportType::Feature => (
+                    vec!["encrypt-read".to_string(), "encrypt-write".to_string()],
+                    CharacteristicKind::FeatureReport {
+                        report_id: report.id,
+                    },
+                    REPORT_TYPE_FEATURE,
+                ),
+            };
+            crossroads.insert(
+                char_path.clone(),
+                &[characteristic_iface],
+                GattCharacteristicData {
+                    uuid: HID_REPORT_UUID.to_string(),
+                    service: dbus_path(HID_SERVICE_PATH)?,
+                    flags,
+                    descriptors: vec![desc_path.clone()],
+                    kind,
+                    state: Arc::clone(&state),
                 },
-            },
-        );
-        crossroads.insert(
-            HID_STATUS_INPUT_REPORT_REF_DESC_PATH,
-            &[descriptor_iface],
-            GattDescriptorData {
-                uuid: REPORT_REFERENCE_UUID.to_string(),
-                characteristic: dbus_path(HID_STATUS_INPUT_REPORT_CHAR_PATH)?,
-                flags: vec!["encrypt-read".to_string()],
-                kind: DescriptorKind::ReportReference {
-                    report_id: XBOX_STATUS_INPUT_REPORT_ID,
-                    report_type: REPORT_TYPE_INPUT,
+            );
+            crossroads.insert(
+                desc_path,
+                &[descriptor_iface],
+                GattDescriptorData {
+                    uuid: REPORT_REFERENCE_UUID.to_string(),
+                    characteristic: char_path,
+                    flags: vec!["encrypt-read".to_string()],
+                    kind: DescriptorKind::ReportReference {
+                        report_id: report.id,
+                        report_type,
+                    },
                 },
-            },
-        );
+            );
+        }
 
         crossroads.insert(
             ADVERTISEMENT_PATH,
             &[advertisement_iface],
-            AdvertisementData {
-                local_name: cfg.device.name.clone(),
-                service_uuids: vec![
-                    HID_SERVICE_UUID.to_string(),
-                    BATTERY_SERVICE_UUID.to_string(),
-                    DEVICE_INFO_SERVICE_UUID.to_string(),
-                ],
-                appearance: HID_GAMEPAD_APPEARANCE,
-            },
+            AdvertisementData::new(cfg, Arc::clone(&state), Arc::clone(&policy)),
         );
 
-        crossroads.insert(AGENT_PATH, &[agent_iface], ());
+        crossroads.insert(AGENT_PATH, &[agent_iface], Arc::clone(&policy));
+        crossroads.insert(
+            CONTROL_OBJECT_PATH,
+            &[control_iface],
+            ControlData {
+                state: Some(Arc::clone(&state)),
+                policy: Arc::clone(&policy),
+                control: Arc::clone(&control),
+            },
+        );
 
         let crossroads = Arc::new(Mutex::new(crossroads));
         let crossroads_for_dispatch = Arc::clone(&crossroads);
@@ -493,9 +751,12 @@ impl HogRuntime {
         );
 
         // Listen for BlueZ property changes (connect/disconnect, adapter state).
-        // On disconnect, re-register the LE advertisement so the controller is
-        // discoverable again. BlueZ does NOT automatically resume advertising
-        // after disconnect even though it keeps the registration (no Release call).
+        // On disconnect, have process_pending_messages re-register the LE
+        // advertisement so the controller is discoverable again. BlueZ does NOT
+        // automatically resume advertising after disconnect even though it keeps
+        // the registration (no Release call). Registering from there rather than
+        // with fire-and-forget calls here keeps error replies with the call that
+        // waits for them, and retries until BlueZ accepts it.
         //
         // Debounce: only re-register after a disconnect that follows a STABLE
         // connection (ServicesResolved=true). Brief bounce connections (connect →
@@ -504,17 +765,17 @@ impl HogRuntime {
         // repeated HCI advertising commands that corrupt the controller state
         // ("LE_SET_EXT_ADV_ENABLE failed", "Unexpected advertising set terminated").
         let adv_adapter_path = adapter_path.clone();
-        let adv_advertisement_path = advertisement_path.clone();
         // Tracks whether a stable connection was established since the last
         // advertisement re-registration. Set to true on ServicesResolved=true,
         // cleared when we re-register. Starts true so the first disconnect
         // after initial pairing triggers re-registration.
         let had_stable_connection = Arc::new(AtomicBool::new(true));
-        let connected = Arc::new(AtomicBool::new(false));
-        let connected_for_closure = Arc::clone(&connected);
         let disconnect_state = Arc::clone(&state);
+        let paired_policy = Arc::clone(&policy);
         let adv_needs_retry = Arc::new(AtomicBool::new(false));
         let adv_needs_retry_for_closure = Arc::clone(&adv_needs_retry);
+        let reconnect_mode = cfg.advertising.reconnect;
+        let reconnect_timeout_s = cfg.advertising.reconnect_timeout_s;
         let mut device_prop_rule =
             PropertiesPropertiesChanged::match_rule(None, None).static_clone();
         device_prop_rule.sender = Some(
@@ -526,7 +787,7 @@ impl HogRuntime {
             .map_err(|e| anyhow!("failed to add D-Bus match for device signals: {e}"))?;
         conn.start_receive(
             device_prop_rule,
-            Box::new(move |msg, conn| {
+            Box::new(move |msg, _| {
                 if let Ok(signal) = msg.read_all::<PropertiesPropertiesChanged>() {
                     let obj_path = msg
                         .path()
@@ -540,6 +801,33 @@ impl HogRuntime {
                                  {prop}={val:?}"
                             );
                         }
+                        if let Some(path) = msg.path() {
+                            if let Ok(mut s) = disconnect_state.lock() {
+                                s.update_connection(
+                                    &path.into_static(),
+                                    &signal.changed_properties,
+                                );
+                            }
+                        }
+
+                        let host_changed = ["Connected", "Paired", "Bonded"]
+                            .iter()
+                            .any(|prop| signal.changed_properties.contains_key(*prop));
+                        if host_changed {
+                            if let Ok(mut s) = disconnect_state.lock() {
+                                s.queue_host_event(HostEvent::Changed);
+                            }
+                        }
+
+                        // A new host paired: close the pairing window and
+                        // take the passkey off the screen.
+                        let paired = signal.changed_properties.get("Paired");
+                        if paired.and_then(|v| v.as_i64()) == Some(1) {
+                            if let Ok(mut policy) = paired_policy.lock() {
+                                policy.stop_pairing();
+                                policy.cancel_prompt();
+                            }
+                        }
 
                         // Track stable connections via ServicesResolved.
                         if let Some(sr) = signal.changed_properties.get("ServicesResolved") {
@@ -553,25 +841,33 @@ impl HogRuntime {
                         {
                             let is_connected = connected_variant.as_i64() == Some(1);
                             if is_connected {
-                                connected_for_closure.store(true, Ordering::Release);
-                                // Restore notifying flags for bonded reconnections.
-                                // Bonded hosts cache CCCD subscriptions and don't
-                                // re-call StartNotify after reconnect. The connected
-                                // gate (Fix 3) prevents sending while disconnected,
-                                // so it's safe to assume subscriptions persist.
-                                if let Ok(mut s) = disconnect_state.lock() {
-                                    for slot in s.input_reports.values_mut() {
-                                        slot.notifying = true;
+                                // The connection table restored the
+                                // subscriptions: bonded hosts cache CCCDs and
+                                // don't re-call StartNotify after reconnect.
+                                eprintln!("hidd: device connected: {obj_path}");
+                            } else {
+                                if reconnect_mode == ReconnectMode::Bonded {
+                                    if let Ok(mut s) = disconnect_state.lock() {
+                                        let bonded = msg
+                                            .path()
+                                            .and_then(|p| s.connections.get(&p.into_static()))
+                                            .is_some_and(|c| c.bonded);
+                                        if bonded {
+                                            eprintln!(
+                                                "hidd: bonded host dropped, advertising \
+                                                 for reconnection only"
+                                            );
+                                            s.enter_reconnect_only(reconnect_timeout_s);
+                                        }
                                     }
-                                    s.battery_notifying = true;
+                                }
+                                let idle = disconnect_state.lock().is_ok_and(|s| s.idle.is_some());
+                                if idle {
                                     eprintln!(
-                                        "hidd: restored notifying flags on connect"
+                                        "hidd: device disconnected: {obj_path} \
+                                         (idle, not advertising)"
                                     );
-                                }
-                                eprintln!("hidd: device connected: {obj_path}");
-                            } else {
-                                connected_for_closure.store(false, Ordering::Release);
-                                if had_stable_connection
+                                } else if had_stable_connection
                                     .compare_exchange(
                                         true,
                                         false,
@@ -585,57 +881,7 @@ impl HogRuntime {
                                          re-registering advertisement on {}",
                                         adv_adapter_path
                                     );
-                                    let mut unreg = Message::method_call(
-                                        &BusName::new(BLUEZ_SERVICE).unwrap(),
-                                        &adv_adapter_path,
-                                        &Interface::new(BLUEZ_LE_ADV_MANAGER_IFACE)
-                                            .unwrap(),
-                                        &Member::new("UnregisterAdvertisement")
-                                            .unwrap(),
-                                    );
-                                    AppendAll::append(
-                                        &(adv_advertisement_path.clone(),),
-                                        &mut IterAppend::new(&mut unreg),
-                                    );
-                                    match conn.send(unreg) {
-                                        Ok(serial) => eprintln!(
-                                            "hidd: sent UnregisterAdvertisement \
-                                             (serial={serial})"
-                                        ),
-                                        Err(_) => eprintln!(
-                                            "hidd: failed to send \
-                                             UnregisterAdvertisement"
-                                        ),
-                                    }
-
-                                    let options: PropMap = HashMap::new();
-                                    let mut reg = Message::method_call(
-                                        &BusName::new(BLUEZ_SERVICE).unwrap(),
-                                        &adv_adapter_path,
-                                        &Interface::new(BLUEZ_LE_ADV_MANAGER_IFACE)
-                                            .unwrap(),
-                                        &Member::new("RegisterAdvertisement")
-                                            .unwrap(),
-                                    );
-                                    AppendAll::append(
-                                        &(adv_advertisement_path.clone(), options),
-                                        &mut IterAppend::new(&mut reg),
-                                    );
-                                    match conn.send(reg) {
-                                        Ok(serial) => eprintln!(
-                                            "hidd: sent RegisterAdvertisement \
-                                             (serial={serial})"
-                                        ),
-                                        Err(_) => {
-                                            eprintln!(
-                                                "hidd: failed to send \
-                                                 RegisterAdvertisement, \
-                                                 will retry"
-                                            );
-                                            adv_needs_retry_for_closure
-                                                .store(true, Ordering::Release);
-                                        }
-                                    }
+                                    adv_needs_retry_for_closure.store(true, Ordering::Release);
                                 } else {
                                     eprintln!(
                                         "hidd: device disconnected: {obj_path} \
@@ -658,48 +904,23 @@ impl HogRuntime {
             }),
         );
 
-        // Log any D-Bus error replies that arrive asynchronously (e.g. from
-        // fire-and-forget RegisterAdvertisement calls in the disconnect handler).
-        let mut async_error_rule = MatchRule::new();
-        async_error_rule.msg_type = Some(MessageType::Error);
-        let adv_needs_retry_for_errors = Arc::clone(&adv_needs_retry);
-        conn.start_receive(
-            async_error_rule,
-            Box::new(move |mut msg, _| {
-                let error_body = msg
-                    .as_result()
-                    .err()
-                    .map(|e: dbus::Error| e.to_string())
-                    .unwrap_or_default();
-                let reply_serial = msg.get_reply_serial().unwrap_or(0);
-                eprintln!(
-                    "hidd: D-Bus error reply (reply_serial={reply_serial}): \
-                     {error_body}"
-                );
-                // If this looks like a failed advertisement-related reply,
-                // schedule a retry on the next process_pending_messages call.
-                if error_body.contains("dvertis") {
-                    eprintln!(
-                        "hidd: advertisement error detected, will retry"
-                    );
-                    adv_needs_retry_for_errors
-                        .store(true, Ordering::Release);
-                }
-                true
-            }),
-        );
-
+        let bluez_lost = watch_bluez(&conn, &adapter_path)?;
+        track_devices(&conn, &adapter_path, &state)?;
         register_gatt_application(&conn, &adapter_path, &app_path)?;
         register_advertisement(&conn, &adapter_path, &advertisement_path)?;
-        register_agent(&conn, &agent_path)?;
+        register_agent(&conn, &agent_path, cfg.pairing.agent_capability)?;
+        request_control_name(&conn);
 
         Ok(Self {
             conn,
             state,
+            policy,
+            control,
             input_report_char_paths,
             adapter_path,
-            connected,
+            adapter_address,
             adv_needs_retry,
+            bluez_lost,
         })
     }
 
@@ -707,10 +928,226 @@ impl HogRuntime {
         &self.adapter_path
     }
 
-    pub fn publish_input_report(&self, report: &[u8]) -> Result<()> {
-        if !self.connected.load(Ordering::Acquire) {
-            return self.process_pending_messages();
+    /// bluetoothd restarted or the adapter went away; the runtime must be
+    /// registered again.
+    pub fn bluez_lost(&self) -> bool {
+        self.bluez_lost.load(Ordering::Acquire)
+    }
+
+    /// Adapter Bluetooth address, most significant byte first.
+    pub fn adapter_address(&self) -> [u8; 6] {
+        self.adapter_address
+    }
+
+    /// The host wrote Suspend to the HID Control Point.
+    pub fn host_suspended(&self) -> Result<bool> {
+        Ok(self.lock_state()?.suspended)
+    }
+
+    /// Address of the host that connected last.
+    pub fn current_host(&self) -> Result<Option<String>> {
+        Ok(self
+            .lock_state()?
+            .connections
+            .current()
+            .map(|c| c.address.clone()))
+    }
+
+    /// Put Bluetooth to sleep for an idle controller: disconnect hosts, stop
+    /// advertising and, for `AdapterOff`, power the adapter down.
+    pub fn enter_idle(&self, action: IdleAction) -> Result<()> {
+        self.lock_state()?.idle = Some(action);
+        self.disconnect_hosts()?;
+        let adv_path = dbus_path(ADVERTISEMENT_PATH)?;
+        if let Err(e) = unregister_advertisement(&self.conn, &self.adapter_path, &adv_path) {
+            eprintln!("hidd: {e}");
+        }
+        if action == IdleAction::AdapterOff {
+            set_adapter_powered(&self.conn, &self.adapter_path, false)?;
+        }
+        Ok(())
+    }
+
+    /// Undo `enter_idle`. Bonded hosts reconnect to the advertisement.
+    pub fn leave_idle(&self) -> Result<()> {
+        let Some(action) = self.lock_state()?.idle.take() else {
+            return Ok(());
+        };
+        if action == IdleAction::AdapterOff {
+            set_adapter_powered(&self.conn, &self.adapter_path, true)?;
         }
+        let adv_path = dbus_path(ADVERTISEMENT_PATH)?;
+        if let Err(e) = register_advertisement(&self.conn, &self.adapter_path, &adv_path) {
+            eprintln!("hidd: {e}, will retry");
+            self.adv_needs_retry.store(true, Ordering::Release);
+        }
+        Ok(())
+    }
+
+    fn lock_state(&self) -> Result<std::sync::MutexGuard<'_, HogState>> {
+        self.state
+            .lock()
+            .map_err(|_| anyhow!("failed to lock HOG state"))
+    }
+
+    /// Output reports written by the host since the last call, oldest first,
+    /// each including its report ID byte.
+    pub fn take_output_reports(&self) -> Result<Vec<Vec<u8>>> {
+        let mut state = self
+            .state
+            .lock()
+            .map_err(|_| anyhow!("failed to lock HOG state"))?;
+        Ok(state.pending_outputs.drain(..).collect())
+    }
+
+    /// Set the value a host reads from a feature report (with report ID).
+    pub fn set_feature_report(&self, report: &[u8]) -> Result<()> {
+        let (&report_id, payload) = report
+            .split_first()
+            .ok_or_else(|| anyhow!("empty feature report"))?;
+        self.lock_state()?
+            .write_feature_report(report_id, payload)
+            .map_err(|e| anyhow!("feature report id=0x{report_id:02x}: {e}"))
+    }
+
+    /// Host changes and control requests since the last call, oldest first.
+    pub fn take_host_events(&self) -> Result<Vec<HostEvent>> {
+        let mut state = self
+            .state
+            .lock()
+            .map_err(|_| anyhow!("failed to lock HOG state"))?;
+        Ok(state.host_events.drain(..).collect())
+    }
+
+    pub fn queue_host_event(&self, event: HostEvent) -> Result<()> {
+        let mut state = self
+            .state
+            .lock()
+            .map_err(|_| anyhow!("failed to lock HOG state"))?;
+        state.queue_host_event(event);
+        Ok(())
+    }
+
+    /// Bonded devices on this adapter, sorted by address.
+    pub fn bonded_hosts(&self) -> Result<Vec<BondedHost>> {
+        list_bonded_hosts(&self.conn, &self.adapter_path)
+    }
+
+    /// Disconnect hosts that connected without a bond while the pairing lock
+    /// does not admit them.
+    pub fn refuse_unadmitted_hosts(&self) -> Result<()> {
+        let now = Instant::now();
+        for (host, bonded) in adapter_devices(&self.conn, &self.adapter_path)? {
+            if !host.connected || bonded {
+                continue;
+            }
+            let admitted = self
+                .policy
+                .lock()
+                .map_err(|_| anyhow!("failed to lock pairing policy"))?
+                .admits(&host.address, now);
+            if !admitted {
+                eprintln!(
+                    "hidd: refusing connection from unknown host {} ({}): pairing is locked",
+                    host.name, host.address
+                );
+                self.disconnect_host(&host.path)?;
+            }
+        }
+        Ok(())
+    }
+
+    pub fn start_pairing(&self, timeout: Option<Duration>) -> Result<()> {
+        self.policy
+            .lock()
+            .map_err(|_| anyhow!("failed to lock pairing policy"))?
+            .start_pairing(Instant::now(), timeout);
+        Ok(())
+    }
+
+    /// Apply `update` to the control properties, fill in the connection
+    /// state, and announce what changed.
+    pub fn publish_status(&self, update: impl FnOnce(&mut DaemonStatus)) -> Result<()> {
+        let (connected_host, notifying, suspended) = {
+            let state = self
+                .state
+                .lock()
+                .map_err(|_| anyhow!("failed to lock HOG state"))?;
+            let notifying = state
+                .input_reports
+                .keys()
+                .any(|&id| state.connections.subscribed(Subscription::InputReport(id)));
+            let host = state.connections.current().map(|c| c.address.clone());
+            (host, notifying, state.suspended)
+        };
+        let pairing = self
+            .policy
+            .lock()
+            .map_err(|_| anyhow!("failed to lock pairing policy"))?
+            .pairing_open(Instant::now());
+        publish_status(&self.conn, &self.control, |status| {
+            update(status);
+            status.transport = "hogp";
+            status.connected_host = connected_host.unwrap_or_default();
+            status.notifying = notifying;
+            status.suspended = suspended;
+            status.pairing = pairing;
+        })
+    }
+
+    pub fn take_control_requests(&self) -> Result<Vec<ControlRequest>> {
+        Ok(self
+            .control
+            .lock()
+            .map_err(|_| anyhow!("failed to lock control state"))?
+            .take_requests())
+    }
+
+    /// Disconnect every connected host.
+    pub fn disconnect_hosts(&self) -> Result<()> {
+        for (host, _) in adapter_devices(&self.conn, &self.adapter_path)? {
+            if host.connected {
+                eprintln!("hidd: disconnecting {} ({})", host.name, host.address);
+                self.disconnect_host(&host.path)?;
+            }
+        }
+        Ok(())
+    }
+
+    pub fn disconnect_host(&self, device_path: &Path<'static>) -> Result<()> {
+        call_method_with_dispatch(
+            &self.conn,
+            BLUEZ_SERVICE,
+            device_path,
+            BLUEZ_DEVICE_IFACE,
+            "Disconnect",
+            (),
+            Duration::from_secs(5),
+        )
+        .map_err(|e| anyhow!("failed to disconnect {device_path}: {e}"))
+    }
+
+    /// Publish the host list returned by `ListHosts`.
+    pub fn set_host_list(&self, hosts: &[BondedHost], current: Option<&str>) -> Result<()> {
+        let mut state = self
+            .state
+            .lock()
+            .map_err(|_| anyhow!("failed to lock HOG state"))?;
+        state.hosts = hosts
+            .iter()
+            .map(|h| {
+                (
+                    h.address.clone(),
+                    h.name.clone(),
+                    h.connected,
+                    Some(h.address.as_str()) == current,
+                )
+            })
+            .collect();
+        Ok(())
+    }
+
+    pub fn publish_input_report(&self, report: &[u8]) -> Result<()> {
         let (report_id, ble_payload) = ble_input_payload_from_uhid(report)?;
         let notifying = {
             let mut state = self
@@ -723,7 +1160,10 @@ impl HogRuntime {
                 .ok_or_else(|| anyhow!("unsupported input report id=0x{report_id:02x}"))?;
             slot.value.clear();
             slot.value.extend_from_slice(ble_payload);
-            slot.notifying
+            !state.suspended
+                && state
+                    .connections
+                    .subscribed(Subscription::InputReport(report_id))
         };
 
         if !notifying {
@@ -737,10 +1177,40 @@ impl HogRuntime {
                     anyhow!("missing characteristic path for report id=0x{report_id:02x}")
                 })?;
 
+        self.emit_value_changed(input_report_char_path, ble_payload)?;
+        self.process_pending_messages()?;
+        Ok(())
+    }
+
+    /// Update the Battery Level characteristic (percent, clamped to 100),
+    /// notifying the host when the value changed and it is subscribed.
+    pub fn set_battery_level(&self, level: u8) -> Result<()> {
+        let level = level.min(100);
+        let notify = {
+            let mut state = self
+                .state
+                .lock()
+                .map_err(|_| anyhow!("failed to lock HOG state"))?;
+            if state.battery_level == level {
+                return Ok(());
+            }
+            state.battery_level = level;
+            state.connections.subscribed(Subscription::BatteryLevel)
+        };
+
+        if !notify {
+            return self.process_pending_messages();
+        }
+        let path = dbus_path(BATTERY_LEVEL_CHAR_PATH)?;
+        self.emit_value_changed(&path, &[level])?;
+        self.process_pending_messages()
+    }
+
+    fn emit_value_changed(&self, char_path: &Path<'static>, value: &[u8]) -> Result<()> {
         let mut changed: PropMap = HashMap::new();
         changed.insert(
             "Value".to_string(),
-            Variant(Box::new(ble_payload.to_vec()) as Box<dyn RefArg>),
+            Variant(Box::new(value.to_vec()) as Box<dyn RefArg>),
         );
 
         let signal = PropertiesPropertiesChanged {
@@ -750,13 +1220,12 @@ impl HogRuntime {
         };
 
         self.conn
-            .send(signal.to_emit_message(input_report_char_path))
-            .map_err(|_| anyhow!("failed to emit input report notification"))?;
-        self.process_pending_messages()?;
+            .send(signal.to_emit_message(char_path))
+            .map_err(|_| anyhow!("failed to emit notification on {char_path}"))?;
         Ok(())
     }
 
-    fn process_pending_messages(&self) -> Result<()> {
+    pub(crate) fn process_pending_messages(&self) -> Result<()> {
         for _ in 0..8 {
             let had_message = self
                 .conn
@@ -766,13 +1235,18 @@ impl HogRuntime {
                 break;
             }
         }
-        // Retry advertisement registration if a previous attempt failed.
+        if self.bluez_lost() || self.lock_state()?.idle.is_some() {
+            return Ok(());
+        }
+        self.sync_adapter_mode()?;
+        // Register the advertisement again after a disconnect, or retry a
+        // registration that failed.
         if self
             .adv_needs_retry
             .compare_exchange(true, false, Ordering::AcqRel, Ordering::Relaxed)
             .is_ok()
         {
-            eprintln!("hidd: retrying advertisement registration");
+            eprintln!("hidd: registering advertisement again");
             if let Ok(adv_path) = dbus_path(ADVERTISEMENT_PATH) {
                 match register_advertisement(&self.conn, &self.adapter_path, &adv_path) {
                     Ok(()) => eprintln!("hidd: advertisement retry succeeded"),
@@ -785,10 +1259,60 @@ impl HogRuntime {
         }
         Ok(())
     }
+
+    /// End expired reconnect-only and pairing windows and bring the
+    /// adapter's Discoverable/Pairable in line. The advertisement is
+    /// registered again so its flags follow, unless a host is connected.
+    fn sync_adapter_mode(&self) -> Result<()> {
+        let now = Instant::now();
+        expire_pairing_prompt(&self.conn, &self.policy, now)?;
+        let (mode, connected) = {
+            let mut state = self
+                .state
+                .lock()
+                .map_err(|_| anyhow!("failed to lock HOG state"))?;
+            let mut policy = self
+                .policy
+                .lock()
+                .map_err(|_| anyhow!("failed to lock pairing policy"))?;
+            if state.reconnect_only
+                && state
+                    .reconnect_deadline
+                    .is_some_and(|deadline| now >= deadline)
+            {
+                eprintln!("hidd: reconnect window over, advertising to all hosts");
+                state.reconnect_only = false;
+                state.reconnect_deadline = None;
+            }
+            if policy.pairing_open(now) {
+                state.reconnect_only = false;
+            }
+            policy.expire(now);
+            let mode = policy.adapter_mode(now, state.reconnect_only);
+            if mode == state.adapter_mode {
+                return Ok(());
+            }
+            state.adapter_mode = mode;
+            (mode, state.connections.any_connected())
+        };
+
+        set_adapter_mode(&self.conn, &self.adapter_path, mode)?;
+        if !connected {
+            let adv_path = dbus_path(ADVERTISEMENT_PATH)?;
+            register_advertisement(&self.conn, &self.adapter_path, &adv_path)?;
+        }
+        Ok(())
+    }
 }
 
 impl Drop for HogRuntime {
     fn drop(&mut self) {
+        if self.bluez_lost() {
+            // BlueZ dropped our registrations with the adapter, and closing
+            // the connection releases the agent.
+            eprintln!("hidd: dropping HogRuntime registered with a lost adapter");
+            return;
+        }
         eprintln!("hidd: shutting down HogRuntime, unregistering from BlueZ");
         if let Ok(agent_path) = dbus_path(AGENT_PATH) {
             eprintln!("hidd: unregistering agent {agent_path}");
@@ -806,6 +1330,197 @@ impl Drop for HogRuntime {
     }
 }
 
+/// Watch for bluetoothd changing owner (crash, restart) and for
+/// `adapter_path` disappearing (adapter reset or unplugged). The returned
+/// flag is set when either happens.
+pub(crate) fn watch_bluez(
+    conn: &SyncConnection,
+    adapter_path: &Path<'static>,
+) -> Result<Arc<AtomicBool>> {
+    let lost = Arc::new(AtomicBool::new(false));
+
+    let owner_rule = MatchRule::new_signal(DBUS_SERVICE, "NameOwnerChanged")
+        .with_sender(DBUS_SERVICE)
+        .static_clone();
+    conn.add_match_no_cb(&owner_rule.match_str())
+        .map_err(|e| anyhow!("failed to add D-Bus match for NameOwnerChanged: {e}"))?;
+    let lost_for_owner = Arc::clone(&lost);
+    conn.start_receive(
+        owner_rule,
+        Box::new(move |msg, _| {
+            if let Ok((name, old_owner, new_owner)) = msg.read3::<&str, &str, &str>() {
+                if name == BLUEZ_SERVICE {
+                    eprintln!(
+                        "hidd: {BLUEZ_SERVICE} owner changed ({old_owner:?} -> {new_owner:?})"
+                    );
+                    lost_for_owner.store(true, Ordering::Release);
+                }
+            }
+            true
+        }),
+    );
+
+    let removed_rule = ObjectManagerInterfacesRemoved::match_rule(None, None)
+        .with_sender(BLUEZ_SERVICE)
+        .static_clone();
+    conn.add_match_no_cb(&removed_rule.match_str())
+        .map_err(|e| anyhow!("failed to add D-Bus match for InterfacesRemoved: {e}"))?;
+    let lost_for_removed = Arc::clone(&lost);
+    let adapter_path = adapter_path.clone();
+    conn.start_receive(
+        removed_rule,
+        Box::new(move |msg, _| {
+            if let Ok(signal) = msg.read_all::<ObjectManagerInterfacesRemoved>() {
+                if signal.object == adapter_path
+                    && signal.interfaces.iter().any(|i| i == BLUEZ_ADAPTER_IFACE)
+                {
+                    eprintln!("hidd: adapter {adapter_path} removed");
+                    lost_for_removed.store(true, Ordering::Release);
+                }
+            }
+            true
+        }),
+    );
+    Ok(lost)
+}
+
+/// Fill the connection table with the adapter's devices and keep it current
+/// as BlueZ adds and removes them. Property changes arrive with the other
+/// Device1 signals.
+fn track_devices(
+    conn: &SyncConnection,
+    adapter_path: &Path<'static>,
+    state: &SharedState,
+) -> Result<()> {
+    let on_adapter = {
+        let prefix = format!("{adapter_path}/");
+        move |path: &Path| path.starts_with(&prefix)
+    };
+
+    let added_rule = ObjectManagerInterfacesAdded::match_rule(None, None)
+        .with_sender(BLUEZ_SERVICE)
+        .static_clone();
+    conn.add_match_no_cb(&added_rule.match_str())
+        .map_err(|e| anyhow!("failed to add D-Bus match for InterfacesAdded: {e}"))?;
+    let added_state = Arc::clone(state);
+    let added_on_adapter = on_adapter.clone();
+    conn.start_receive(
+        added_rule,
+        Box::new(move |msg, _| {
+            if let Ok(signal) = msg.read_all::<ObjectManagerInterfacesAdded>() {
+                if let Some(device) = signal.interfaces.get(BLUEZ_DEVICE_IFACE) {
+                    if added_on_adapter(&signal.object) {
+                        if let Ok(mut s) = added_state.lock() {
+                            s.update_connection(&signal.object, device);
+                        }
+                    }
+                }
+            }
+            true
+        }),
+    );
+
+    let removed_rule = ObjectManagerInterfacesRemoved::match_rule(None, None)
+        .with_sender(BLUEZ_SERVICE)
+        .static_clone();
+    conn.add_match_no_cb(&removed_rule.match_str())
+        .map_err(|e| anyhow!("failed to add D-Bus match for InterfacesRemoved: {e}"))?;
+    let removed_state = Arc::clone(state);
+    conn.start_receive(
+        removed_rule,
+        Box::new(move |msg, _| {
+            if let Ok(signal) = msg.read_all::<ObjectManagerInterfacesRemoved>() {
+                if signal.interfaces.iter().any(|i| i == BLUEZ_DEVICE_IFACE) {
+                    if let Ok(mut s) = removed_state.lock() {
+                        s.connections.remove(&signal.object);
+                    }
+                }
+            }
+            true
+        }),
+    );
+
+    let proxy = conn.with_proxy(BLUEZ_SERVICE, BLUEZ_ROOT_PATH, Duration::from_secs(5));
+    let objects: HashMap<Path<'static>, HashMap<String, PropMap>> = proxy
+        .get_managed_objects()
+        .map_err(|e| anyhow!("GetManagedObjects on org.bluez failed: {e}"))?;
+    let mut state = state
+        .lock()
+        .map_err(|_| anyhow!("failed to lock HOG state"))?;
+    for (path, ifaces) in &objects {
+        if let Some(device) = ifaces.get(BLUEZ_DEVICE_IFACE) {
+            if on_adapter(path) {
+                state.update_connection(path, device);
+            }
+        }
+    }
+    Ok(())
+}
+
+/// A new connection to the system bus. Tests get the mock BlueZ's private
+/// bus instead.
+pub(crate) fn system_bus() -> Result<SyncConnection, dbus::Error> {
+    #[cfg(test)]
+    return crate::mock_bluez::connect();
+    #[cfg(not(test))]
+    SyncConnection::new_system()
+}
+
+/// Block until bluetoothd takes its bus name or exports a new adapter, or
+/// `timeout` passes.
+pub(crate) fn wait_for_bluez(timeout: Duration) -> Result<()> {
+    let conn = system_bus().map_err(|e| anyhow!("system bus: {e}"))?;
+    let appeared = Arc::new(AtomicBool::new(false));
+
+    let owner_rule = MatchRule::new_signal(DBUS_SERVICE, "NameOwnerChanged")
+        .with_sender(DBUS_SERVICE)
+        .static_clone();
+    conn.add_match_no_cb(&owner_rule.match_str())
+        .map_err(|e| anyhow!("failed to add D-Bus match for NameOwnerChanged: {e}"))?;
+    let appeared_for_owner = Arc::clone(&appeared);
+    conn.start_receive(
+        owner_rule,
+        Box::new(move |msg, _| {
+            if let Ok((name, _, new_owner)) = msg.read3::<&str, &str, &str>() {
+                if name == BLUEZ_SERVICE && !new_owner.is_empty() {
+                    appeared_for_owner.store(true, Ordering::Release);
+                }
+            }
+            true
+        }),
+    );
+
+    let added_rule = ObjectManagerInterfacesAdded::match_rule(None, None)
+        .with_sender(BLUEZ_SERVICE)
+        .static_clone();
+    conn.add_match_no_cb(&added_rule.match_str())
+        .map_err(|e| anyhow!("failed to add D-Bus match for InterfacesAdded: {e}"))?;
+    let appeared_for_added = Arc::clone(&appeared);
+    conn.start_receive(
+        added_rule,
+        Box::new(move |msg, _| {
+            if let Ok(signal) = msg.read_all::<ObjectManagerInterfacesAdded>() {
+                if signal.interfaces.contains_key(BLUEZ_ADAPTER_IFACE) {
+                    eprintln!("hidd: adapter {} appeared", signal.object);
+                    appeared_for_added.store(true, Ordering::Release);
+                }
+            }
+            true
+        }),
+    );
+
+    let deadline = Instant::now() + timeout;
+    while !appeared.load(Ordering::Acquire) {
+        let now = Instant::now();
+        if now >= deadline {
+            break;
+        }
+        conn.process(deadline - now)
+            .map_err(|e| anyhow!("failed to process D-Bus traffic: {e}"))?;
+    }
+    Ok(())
+}
+
 fn find_adapter_path(conn: &SyncConnection) -> Result<Path<'static>> {
     let proxy = conn.with_proxy(BLUEZ_SERVICE, BLUEZ_ROOT_PATH, Duration::from_secs(10));
     let objects: HashMap<Path<'static>, HashMap<String, PropMap>> = proxy
@@ -834,7 +1549,78 @@ fn find_adapter_path(conn: &SyncConnection) -> Result<Path<'static>> {
     ))
 }
 
-fn configure_adapter_for_hog(
+/// Bonded devices on the adapter, sorted by address.
+fn list_bonded_hosts(
+    conn: &SyncConnection,
+    adapter_path: &Path<'static>,
+) -> Result<Vec<BondedHost>> {
+    let hosts = adapter_devices(conn, adapter_path)?
+        .into_iter()
+        .filter_map(|(host, bonded)| bonded.then_some(host))
+        .collect();
+    Ok(hosts)
+}
+
+/// Every device known to the adapter, with whether it is bonded, sorted by
+/// address.
+fn adapter_devices(
+    conn: &SyncConnection,
+    adapter_path: &Path<'static>,
+) -> Result<Vec<(BondedHost, bool)>> {
+    let proxy = conn.with_proxy(BLUEZ_SERVICE, BLUEZ_ROOT_PATH, Duration::from_secs(5));
+    let objects: HashMap<Path<'static>, HashMap<String, PropMap>> = proxy
+        .get_managed_objects()
+        .map_err(|e| anyhow!("GetManagedObjects on org.bluez failed: {e}"))?;
+
+    let mut hosts = objects
+        .into_iter()
+        .filter_map(|(path, ifaces)| {
+            let device = ifaces.get(BLUEZ_DEVICE_IFACE)?;
+            let flag = |name| prop_cast::<bool>(device, name).copied().unwrap_or(false);
+            let adapter = prop_cast::<Path>(device, "Adapter")?;
+            if *adapter != *adapter_path {
+                return None;
+            }
+            let address = prop_cast::<String>(device, "Address")?.clone();
+            let name = prop_cast::<String>(device, "Alias")
+                .or_else(|| prop_cast::<String>(device, "Name"))
+                .cloned()
+                .unwrap_or_else(|| address.clone());
+            let host = BondedHost {
+                path,
+                address,
+                name,
+                connected: flag("Connected"),
+            };
+            Some((host, flag("Bonded") || flag("Paired")))
+        })
+        .collect::<Vec<_>>();
+    hosts.sort_by(|a, b| a.0.address.cmp(&b.0.address));
+    Ok(hosts)
+}
+
+pub(crate) fn read_adapter_address(
+    conn: &SyncConnection,
+    adapter_path: &Path<'static>,
+) -> Result<[u8; 6]> {
+    let proxy = conn.with_proxy(BLUEZ_SERVICE, adapter_path, Duration::from_secs(5));
+    let address: String = proxy
+        .get(BLUEZ_ADAPTER_IFACE, "Address")
+        .map_err(|e| anyhow!("failed to read adapter Address: {e}"))?;
+    parse_bd_address(&address).ok_or_else(|| anyhow!("invalid adapter Address {address:?}"))
+}
+
+/// Parse a `XX:XX:XX:XX:XX:XX` Bluetooth address, most significant byte first.
+fn parse_bd_address(address: &str) -> Option<[u8; 6]> {
+    let mut out = [0u8; 6];
+    let mut parts = address.split(':');
+    for byte in &mut out {
+        *byte = u8::from_str_radix(parts.next()?, 16).ok()?;
+    }
+    parts.next().is_none().then_some(out)
+}
+
+pub(crate) fn configure_adapter(
     conn: &SyncConnection,
     adapter_path: &Path<'static>,
     cfg: &HidConfig,
@@ -854,13 +1640,59 @@ fn configure_adapter_for_hog(
     if let Err(err) = set_adapter_property(
         conn,
         adapter_path,
-        "Alias",
-        Variant(cfg.device.name.clone()),
-    ) {
-        eprintln!("hidd: unable to set adapter Alias: {err}");
-    } else {
-        eprintln!("hidd: adapter {adapter_path}: Alias={}", cfg.device.name);
-    }
+        "Alias",
+        Variant(cfg.device.name.clone()),
+    ) {
+        eprintln!("hidd: unable to set adapter Alias: {err}");
+    } else {
+        eprintln!("hidd: adapter {adapter_path}: Alias={}", cfg.device.name);
+    }
+    Ok(())
+}
+
+/// Reject a numeric comparison the Deck did not answer in time.
+pub(crate) fn expire_pairing_prompt(
+    conn: &SyncConnection,
+    policy: &SharedPolicy,
+    now: Instant,
+) -> Result<()> {
+    let reply = policy
+        .lock()
+        .map_err(|_| anyhow!("failed to lock pairing policy"))?
+        .expire_prompt(now);
+    if let Some(reply) = reply {
+        conn.send(reply)
+            .map_err(|_| anyhow!("failed to reply to RequestConfirmation"))?;
+    }
+    Ok(())
+}
+
+pub(crate) fn set_adapter_mode(
+    conn: &SyncConnection,
+    adapter_path: &Path<'static>,
+    mode: AdapterMode,
+) -> Result<()> {
+    set_adapter_property(
+        conn,
+        adapter_path,
+        "Discoverable",
+        Variant(mode.discoverable),
+    )?;
+    set_adapter_property(conn, adapter_path, "Pairable", Variant(mode.pairable))?;
+    eprintln!(
+        "hidd: adapter {adapter_path}: Discoverable={} Pairable={}",
+        mode.discoverable, mode.pairable
+    );
+    Ok(())
+}
+
+pub(crate) fn set_adapter_powered(
+    conn: &SyncConnection,
+    adapter_path: &Path<'static>,
+    powered: bool,
+) -> Result<()> {
+    set_adapter_property(conn, adapter_path, "Powered", Variant(powered))?;
+    eprintln!("hidd: adapter {adapter_path}: Powered={powered}");
     Ok(())
 }
 
@@ -884,7 +1716,7 @@ fn set_adapter_property<T: Arg + Append + Send + 'static>(
 
 fn trust_device(device_path: &Path<'static>) {
     eprintln!("hidd: setting Trusted=true on {device_path}");
-    let Ok(conn) = SyncConnection::new_system() else {
+    let Ok(conn) = system_bus() else {
         eprintln!("hidd: failed to open D-Bus for auto-trust");
         return;
     };
@@ -992,7 +1824,7 @@ fn unregister_advertisement(
     .map_err(|e| anyhow!("UnregisterAdvertisement failed: {e}"))
 }
 
-fn call_method_with_dispatch<A: AppendAll>(
+pub(crate) fn call_method_with_dispatch<A: AppendAll>(
     conn: &SyncConnection,
     destination: &str,
     path: &Path<'static>,
@@ -1179,6 +2011,21 @@ fn register_characteristic_iface(cr: &mut Crossroads) -> IfaceToken<GattCharacte
                                 ))
                             })?
                     }
+                    CharacteristicKind::FeatureReport { report_id } => {
+                        let state = data
+                            .state
+                            .lock()
+                            .map_err(|_| MethodErr::failed(&"failed to lock HOG state"))?;
+                        state
+                            .feature_reports
+                            .get(report_id)
+                            .cloned()
+                            .ok_or_else(|| {
+                                MethodErr::failed(&format!(
+                                    "missing feature report slot for report_id=0x{report_id:02x}"
+                                ))
+                            })?
+                    }
                     CharacteristicKind::BatteryLevel => {
                         let state = data
                             .state
@@ -1190,6 +2037,7 @@ fn register_characteristic_iface(cr: &mut Crossroads) -> IfaceToken<GattCharacte
                         return Err(bluez_not_supported("HID control point is write-only"));
                     }
                     CharacteristicKind::PnpId { value } => value.to_vec(),
+                    CharacteristicKind::DeviceInfoString { value } => value.as_bytes().to_vec(),
                 };
                 Ok((value,))
             },
@@ -1221,21 +2069,29 @@ fn register_characteristic_iface(cr: &mut Crossroads) -> IfaceToken<GattCharacte
                         .state
                         .lock()
                         .map_err(|_| MethodErr::failed(&"failed to lock HOG state"))?;
-                    state.control_point = value[0];
-                    Ok(())
+                    state
+                        .write_control_point(value[0])
+                        .map_err(bluez_invalid_arguments)
                 }
                 CharacteristicKind::OutputReport { report_id } => {
-                    let normalized = normalize_ble_output_value(*report_id, &value);
                     let mut state = data
                         .state
                         .lock()
                         .map_err(|_| MethodErr::failed(&"failed to lock HOG state"))?;
+                    let payload_len = state
+                        .profile
+                        .report(ReportType::Output, *report_id)
+                        .map_or(value.len(), |r| r.payload_len);
+                    let normalized = normalize_ble_output_value(*report_id, payload_len, &value);
                     state
                         .output_reports
                         .insert(*report_id, normalized.characteristic_value);
-                    if let Some(parsed) = OutputReport::parse(&normalized.parser_value) {
+                    state.queue_output_report(normalized.parser_value.clone());
+                    if let Some(parsed) =
+                        state.profile.parse_output_report(&normalized.parser_value)
+                    {
                         eprintln!(
-                            "hidd: dropped BLE output report rumble={{lt:{}, rt:{}, weak:{}, strong:{}}}",
+                            "hidd: BLE output report rumble={{lt:{}, rt:{}, weak:{}, strong:{}}}",
                             parsed.left_trigger_magnitude,
                             parsed.right_trigger_magnitude,
                             parsed.weak_motor_magnitude,
@@ -1244,12 +2100,30 @@ fn register_characteristic_iface(cr: &mut Crossroads) -> IfaceToken<GattCharacte
                     } else {
                         let report_id = normalized.parser_value.first().copied().unwrap_or(0);
                         eprintln!(
-                            "hidd: dropped BLE output report report_id=0x{report_id:02x} size={}",
+                            "hidd: BLE output report report_id=0x{report_id:02x} size={}",
                             normalized.parser_value.len()
                         );
                     }
                     Ok(())
                 }
+                CharacteristicKind::FeatureReport { report_id } => {
+                    let mut state = data
+                        .state
+                        .lock()
+                        .map_err(|_| MethodErr::failed(&"failed to lock HOG state"))?;
+                    let payload_len = state
+                        .profile
+                        .report(ReportType::Feature, *report_id)
+                        .map_or(value.len(), |r| r.payload_len);
+                    let normalized = normalize_ble_output_value(*report_id, payload_len, &value);
+                    eprintln!(
+                        "hidd: BLE feature report report_id=0x{report_id:02x} size={}",
+                        normalized.characteristic_value.len()
+                    );
+                    state
+                        .write_feature_report(*report_id, &normalized.characteristic_value)
+                        .map_err(bluez_invalid_arguments)
+                }
                 _ => Err(bluez_not_supported("characteristic is not writable")),
             },
         );
@@ -1260,13 +2134,14 @@ fn register_characteristic_iface(cr: &mut Crossroads) -> IfaceToken<GattCharacte
                     .state
                     .lock()
                     .map_err(|_| MethodErr::failed(&"failed to lock HOG state"))?;
-                if let Some(slot) = state.input_reports.get_mut(&report_id) {
-                    slot.notifying = true;
-                } else {
+                if !state.input_reports.contains_key(&report_id) {
                     return Err(MethodErr::failed(&format!(
                         "missing input report slot for report_id=0x{report_id:02x}"
                     )));
                 }
+                state
+                    .connections
+                    .subscribe(Subscription::InputReport(report_id));
                 eprintln!(
                     "hidd: BLE StartNotify input_report report_id=0x{report_id:02x}"
                 );
@@ -1277,7 +2152,7 @@ fn register_characteristic_iface(cr: &mut Crossroads) -> IfaceToken<GattCharacte
                     .state
                     .lock()
                     .map_err(|_| MethodErr::failed(&"failed to lock HOG state"))?;
-                state.battery_notifying = true;
+                state.connections.subscribe(Subscription::BatteryLevel);
                 eprintln!("hidd: BLE StartNotify battery_level");
                 Ok(())
             }
@@ -1290,13 +2165,14 @@ fn register_characteristic_iface(cr: &mut Crossroads) -> IfaceToken<GattCharacte
                     .state
                     .lock()
                     .map_err(|_| MethodErr::failed(&"failed to lock HOG state"))?;
-                if let Some(slot) = state.input_reports.get_mut(&report_id) {
-                    slot.notifying = false;
-                } else {
+                if !state.input_reports.contains_key(&report_id) {
                     return Err(MethodErr::failed(&format!(
                         "missing input report slot for report_id=0x{report_id:02x}"
                     )));
                 }
+                state
+                    .connections
+                    .unsubscribe(Subscription::InputReport(report_id));
                 eprintln!(
                     "hidd: BLE StopNotify input_report report_id=0x{report_id:02x}"
                 );
@@ -1307,7 +2183,7 @@ fn register_characteristic_iface(cr: &mut Crossroads) -> IfaceToken<GattCharacte
                     .state
                     .lock()
                     .map_err(|_| MethodErr::failed(&"failed to lock HOG state"))?;
-                state.battery_notifying = false;
+                state.connections.unsubscribe(Subscription::BatteryLevel);
                 eprintln!("hidd: BLE StopNotify battery_level");
                 Ok(())
             }
@@ -1363,6 +2239,59 @@ fn register_advertisement_iface(cr: &mut Crossroads) -> IfaceToken<Advertisement
             .get(|_, data: &mut AdvertisementData| Ok(data.local_name.clone()));
         b.property::<u16, _>("Appearance")
             .get(|_, data: &mut AdvertisementData| Ok(data.appearance));
+        b.property::<Vec<String>, _>("ScanResponseServiceUUIDs")
+            .get(|_, data: &mut AdvertisementData| {
+                if data.scan_response_uuids.is_empty() {
+                    return Err(MethodErr::no_property(&"ScanResponseServiceUUIDs"));
+                }
+                Ok(data.scan_response_uuids.clone())
+            });
+        b.property::<ManufacturerData, _>("ManufacturerData")
+            .get(|_, data: &mut AdvertisementData| data.manufacturer_data_property(false));
+        b.property::<ManufacturerData, _>("ScanResponseManufacturerData")
+            .get(|_, data: &mut AdvertisementData| data.manufacturer_data_property(true));
+        b.property::<Vec<String>, _>("Includes")
+            .get(|_, data: &mut AdvertisementData| {
+                Ok(if data.include_tx_power {
+                    vec!["tx-power".to_string()]
+                } else {
+                    Vec::new()
+                })
+            });
+        b.property::<u32, _>("MinInterval")
+            .get(|_, data: &mut AdvertisementData| {
+                data.min_interval_ms
+                    .ok_or_else(|| MethodErr::no_property(&"MinInterval"))
+            });
+        b.property::<u32, _>("MaxInterval")
+            .get(|_, data: &mut AdvertisementData| {
+                data.max_interval_ms
+                    .ok_or_else(|| MethodErr::no_property(&"MaxInterval"))
+            });
+        b.property::<u16, _>("Timeout")
+            .get(|_, data: &mut AdvertisementData| {
+                data.timeout_s
+                    .ok_or_else(|| MethodErr::no_property(&"Timeout"))
+            });
+        // Only set when locked or in bonded reconnect mode; otherwise BlueZ
+        // follows the adapter's Discoverable setting.
+        b.property::<bool, _>("Discoverable")
+            .get(|_, data: &mut AdvertisementData| {
+                if !data.manage_discoverable {
+                    return Err(MethodErr::no_property(&"Discoverable"));
+                }
+                let state = data
+                    .state
+                    .lock()
+                    .map_err(|_| MethodErr::failed(&"failed to lock HOG state"))?;
+                let policy = data
+                    .policy
+                    .lock()
+                    .map_err(|_| MethodErr::failed(&"failed to lock pairing policy"))?;
+                Ok(policy
+                    .adapter_mode(Instant::now(), state.reconnect_only)
+                    .discoverable)
+            });
         b.method("Release", (), (), |_, _, ()| {
             eprintln!("hidd: BlueZ released LE advertisement");
             Ok(())
@@ -1370,7 +2299,7 @@ fn register_advertisement_iface(cr: &mut Crossroads) -> IfaceToken<Advertisement
     })
 }
 
-fn register_agent_iface(cr: &mut Crossroads) -> IfaceToken<()> {
+pub(crate) fn register_agent_iface(cr: &mut Crossroads) -> IfaceToken<SharedPolicy> {
     cr.register(BLUEZ_AGENT_IFACE, |b| {
         b.method("Release", (), (), |_, _, ()| {
             eprintln!("hidd: BlueZ released pairing agent");
@@ -1382,7 +2311,7 @@ fn register_agent_iface(cr: &mut Crossroads) -> IfaceToken<()> {
             ("pincode",),
             |_, _, (device,): (Path,)| -> Result<(String,), MethodErr> {
                 eprintln!("hidd: agent rejecting RequestPinCode for {device}");
-                Err(("org.bluez.Error.Rejected", "NoInputNoOutput agent").into())
+                Err(("org.bluez.Error.Rejected", "agent has no input").into())
             },
         );
         b.method(
@@ -1391,7 +2320,7 @@ fn register_agent_iface(cr: &mut Crossroads) -> IfaceToken<()> {
             (),
             |_, _, (device, pincode): (Path, String)| -> Result<(), MethodErr> {
                 eprintln!("hidd: agent rejecting DisplayPinCode for {device} (pin={pincode})");
-                Err(("org.bluez.Error.Rejected", "NoInputNoOutput agent").into())
+                Err(("org.bluez.Error.Rejected", "legacy PIN pairing unsupported").into())
             },
         );
         b.method(
@@ -1400,38 +2329,78 @@ fn register_agent_iface(cr: &mut Crossroads) -> IfaceToken<()> {
             ("passkey",),
             |_, _, (device,): (Path,)| -> Result<(u32,), MethodErr> {
                 eprintln!("hidd: agent rejecting RequestPasskey for {device}");
-                Err(("org.bluez.Error.Rejected", "NoInputNoOutput agent").into())
+                Err(("org.bluez.Error.Rejected", "agent has no input").into())
             },
         );
         b.method(
             "DisplayPasskey",
             ("device", "passkey", "entered"),
             (),
-            |_, _, (device, passkey, entered): (Path, u32, u16)| {
+            |_, policy: &mut SharedPolicy, (device, passkey, entered): (Path, u32, u16)| {
+                agent_admit(policy, &device)?;
                 eprintln!(
                     "hidd: agent DisplayPasskey for {device} \
-                     (passkey={passkey}, entered={entered})"
+                     (passkey={passkey:06}, entered={entered})"
                 );
+                let address = device_address(&device).unwrap_or_default();
+                // Called again for every digit the host enters; keep the
+                // original deadline.
+                let mut policy = policy
+                    .lock()
+                    .map_err(|_| MethodErr::failed(&"failed to lock pairing policy"))?;
+                let shown = policy
+                    .prompt()
+                    .is_some_and(|p| p.address == address && p.passkey == passkey);
+                if !shown {
+                    policy.show_passkey(&address, passkey, Instant::now());
+                }
                 Ok(())
             },
         );
-        b.method(
+        // The reply is held until the GUI answers ConfirmPairing or the
+        // prompt times out.
+        b.method_with_cr_custom::<(Path<'static>, u32), (), _, _>(
             "RequestConfirmation",
             ("device", "passkey"),
             (),
-            |_, _, (device, passkey): (Path, u32)| -> Result<(), MethodErr> {
+            |mut ctx: Context, cr, (device, passkey): (Path<'static>, u32)| {
                 eprintln!(
-                    "hidd: agent rejecting RequestConfirmation for {device} \
-                     (passkey={passkey})"
+                    "hidd: agent RequestConfirmation for {device} (passkey={passkey:06}), \
+                     asking the Deck"
                 );
-                Err(("org.bluez.Error.Rejected", "NoInputNoOutput agent").into())
+                let policy = cr.data_mut::<SharedPolicy>(ctx.path()).cloned();
+                let call = held_call(ctx.message());
+                let (Some(policy), Some(call)) = (policy, call) else {
+                    ctx.reply::<()>(Err(MethodErr::failed(&"pairing agent unavailable")));
+                    return Some(ctx);
+                };
+                if let Err(err) = agent_admit(&policy, &device) {
+                    ctx.reply::<()>(Err(err));
+                    return Some(ctx);
+                }
+                let address = device_address(&device).unwrap_or_default();
+                let held = policy.lock().map(|mut policy| {
+                    policy.request_confirmation(&address, passkey, call, Instant::now())
+                });
+                match held {
+                    Ok(None) => None,
+                    Ok(Some(replaced)) => {
+                        ctx.push_msg(replaced);
+                        Some(ctx)
+                    }
+                    Err(_) => {
+                        ctx.reply::<()>(Err(MethodErr::failed(&"failed to lock pairing policy")));
+                        Some(ctx)
+                    }
+                }
             },
         );
         b.method(
             "RequestAuthorization",
             ("device",),
             (),
-            |_, _, (device,): (Path,)| {
+            |_, policy: &mut SharedPolicy, (device,): (Path,)| {
+                agent_admit(policy, &device)?;
                 eprintln!("hidd: agent authorized device {device}, setting Trusted=true");
                 if let Ok(static_path) = dbus_path(&device) {
                     trust_device(&static_path);
@@ -1443,23 +2412,194 @@ fn register_agent_iface(cr: &mut Crossroads) -> IfaceToken<()> {
             "AuthorizeService",
             ("device", "uuid"),
             (),
-            |_, _, (device, uuid): (Path, String)| {
+            |_, policy: &mut SharedPolicy, (device, uuid): (Path, String)| {
+                agent_admit(policy, &device)?;
                 eprintln!("hidd: agent authorized service {uuid} for {device}");
                 Ok(())
             },
         );
-        b.method("Cancel", (), (), |_, _, ()| {
+        b.method("Cancel", (), (), |_, policy: &mut SharedPolicy, ()| {
             eprintln!("hidd: agent pairing cancelled");
+            if let Ok(mut policy) = policy.lock() {
+                policy.cancel_prompt();
+            }
+            Ok(())
+        });
+    })
+}
+
+/// Refuse pairing and service access for hosts the pairing lock does not admit.
+fn agent_admit(policy: &SharedPolicy, device: &Path) -> Result<(), MethodErr> {
+    let address = device_address(device).unwrap_or_default();
+    let policy = policy
+        .lock()
+        .map_err(|_| MethodErr::failed(&"failed to lock pairing policy"))?;
+    if policy.admits(&address, Instant::now()) {
+        return Ok(());
+    }
+    eprintln!("hidd: agent refusing {device}: pairing is locked and host is not allowlisted");
+    Err(("org.bluez.Error.Rejected", "pairing is locked").into())
+}
+
+pub(crate) fn register_control_iface(cr: &mut Crossroads) -> IfaceToken<ControlData> {
+    cr.register(CONTROL_INTERFACE, |b| {
+        b.method(
+            "ListHosts",
+            (),
+            ("hosts",),
+            |_, data: &mut ControlData, ()| Ok((data.hog_state()?.hosts.clone(),)),
+        );
+        b.method(
+            "ListConnections",
+            (),
+            ("connections",),
+            |_, data: &mut ControlData, ()| Ok((data.hog_state()?.connection_list(),)),
+        );
+        b.method("NextHost", (), (), |_, data: &mut ControlData, ()| {
+            data.hog_state()?.queue_host_event(HostEvent::Next);
+            Ok(())
+        });
+        b.method(
+            "SelectHost",
+            ("address",),
+            (),
+            |_, data: &mut ControlData, (address,): (String,)| {
+                let mut state = data.hog_state()?;
+                if !state
+                    .hosts
+                    .iter()
+                    .any(|h| h.0.eq_ignore_ascii_case(&address))
+                {
+                    return Err((
+                        "org.freedesktop.DBus.Error.InvalidArgs",
+                        format!("no bonded host {address}"),
+                    )
+                        .into());
+                }
+                state.queue_host_event(HostEvent::Select(address));
+                Ok(())
+            },
+        );
+        b.method(
+            "StartPairing",
+            ("timeout_s",),
+            (),
+            |_, data: &mut ControlData, (timeout_s,): (u32,)| {
+                let timeout = (timeout_s > 0).then(|| Duration::from_secs(u64::from(timeout_s)));
+                data.policy()?.start_pairing(Instant::now(), timeout);
+                Ok(())
+            },
+        );
+        b.method("StopPairing", (), (), |_, data: &mut ControlData, ()| {
+            data.policy()?.stop_pairing();
+            Ok(())
+        });
+        b.method(
+            "PairingPrompt",
+            (),
+            ("address", "passkey", "confirm"),
+            |_, data: &mut ControlData, ()| {
+                Ok(match data.policy()?.prompt() {
+                    Some(p) => (p.address.clone(), p.passkey, p.confirm),
+                    None => (String::new(), 0, false),
+                })
+            },
+        );
+        b.method(
+            "ConfirmPairing",
+            ("accept",),
+            (),
+            |ctx, data: &mut ControlData, (accept,): (bool,)| {
+                let mut policy = data.policy()?;
+                if policy.prompt().is_none() {
+                    return Err(("org.freedesktop.DBus.Error.Failed", "no pairing prompt").into());
+                }
+                if let Some(reply) = policy.answer(accept) {
+                    ctx.push_msg(reply);
+                }
+                Ok(())
+            },
+        );
+        b.method(
+            "SelectMapping",
+            ("name",),
+            (),
+            |_, data: &mut ControlData, (name,): (String,)| {
+                let mut control = data.control()?;
+                let Some(index) = control.mapping_index(&name) else {
+                    return Err((
+                        "org.freedesktop.DBus.Error.InvalidArgs",
+                        format!("no mapping {name}"),
+                    )
+                        .into());
+                };
+                control.push_request(ControlRequest::SelectMapping(index));
+                Ok(())
+            },
+        );
+        b.method(
+            "SelectProfile",
+            ("profile",),
+            (),
+            |_, data: &mut ControlData, (profile,): (String,)| {
+                let Some(mode) = HidProfileMode::from_name(&profile) else {
+                    return Err((
+                        "org.freedesktop.DBus.Error.InvalidArgs",
+                        format!("unknown profile {profile}"),
+                    )
+                        .into());
+                };
+                data.control()?
+                    .push_request(ControlRequest::SelectProfile(mode));
+                Ok(())
+            },
+        );
+        b.method("Disconnect", (), (), |_, data: &mut ControlData, ()| {
+            data.control()?.push_request(ControlRequest::Disconnect);
             Ok(())
         });
+        b.property("Transport")
+            .get(|_, data: &mut ControlData| Ok(data.control()?.status.transport.to_string()));
+        b.property("Profile")
+            .get(|_, data: &mut ControlData| Ok(data.control()?.status.profile.to_string()));
+        b.property("Mapping")
+            .get(|_, data: &mut ControlData| Ok(data.control()?.status.mapping.clone()));
+        b.property("Mappings")
+            .get(|_, data: &mut ControlData| Ok(data.control()?.status.mappings.clone()));
+        b.property("ConnectedHost")
+            .get(|_, data: &mut ControlData| Ok(data.control()?.status.connected_host.clone()));
+        b.property("Notifying")
+            .get(|_, data: &mut ControlData| Ok(data.control()?.status.notifying));
+        b.property("Suspended")
+            .get(|_, data: &mut ControlData| Ok(data.control()?.status.suspended));
+        b.property("ReportRate")
+            .get(|_, data: &mut ControlData| Ok(data.control()?.status.report_rate_hz));
+        b.property("InputHealth")
+            .get(|_, data: &mut ControlData| Ok(data.control()?.status.input_health.to_string()));
+        b.property("Pairing")
+            .get(|_, data: &mut ControlData| Ok(data.control()?.status.pairing));
+        b.property("Idle")
+            .get(|_, data: &mut ControlData| Ok(data.control()?.status.idle));
     })
 }
 
-fn register_agent(conn: &SyncConnection, agent_path: &Path<'static>) -> Result<()> {
+pub(crate) fn request_control_name(conn: &SyncConnection) {
+    match conn.request_name(CONTROL_BUS_NAME, false, true, true) {
+        Ok(_) => eprintln!("hidd: control interface on {CONTROL_BUS_NAME}"),
+        Err(e) => eprintln!("hidd: cannot own {CONTROL_BUS_NAME}: {e}"),
+    }
+}
+
+pub(crate) fn register_agent(
+    conn: &SyncConnection,
+    agent_path: &Path<'static>,
+    capability: AgentCapability,
+) -> Result<()> {
     eprintln!("hidd: unregistering previous agent (if any)");
     let _ = unregister_agent(conn, agent_path);
 
-    eprintln!("hidd: registering pairing agent {agent_path} (NoInputNoOutput)");
+    let capability = capability.as_bluez();
+    eprintln!("hidd: registering pairing agent {agent_path} ({capability})");
     let bluez_path = dbus_path("/org/bluez")?;
     call_method_with_dispatch(
         conn,
@@ -1467,7 +2607,7 @@ fn register_agent(conn: &SyncConnection, agent_path: &Path<'static>) -> Result<(
         &bluez_path,
         BLUEZ_AGENT_MANAGER_IFACE,
         "RegisterAgent",
-        (agent_path.clone(), "NoInputNoOutput".to_string()),
+        (agent_path.clone(), capability.to_string()),
         Duration::from_secs(5),
     )
     .map_err(|e| anyhow!("RegisterAgent failed: {e}"))?;
@@ -1488,7 +2628,7 @@ fn register_agent(conn: &SyncConnection, agent_path: &Path<'static>) -> Result<(
     Ok(())
 }
 
-fn unregister_agent(conn: &SyncConnection, agent_path: &Path<'static>) -> Result<()> {
+pub(crate) fn unregister_agent(conn: &SyncConnection, agent_path: &Path<'static>) -> Result<()> {
     let bluez_path = dbus_path("/org/bluez")?;
     call_method_with_dispatch(
         conn,
@@ -1510,24 +2650,18 @@ fn bluez_invalid_arguments(message: &'static str) -> MethodErr {
     ("org.bluez.Error.InvalidArguments", message).into()
 }
 
-fn dbus_path(path: &str) -> Result<Path<'static>> {
+pub(crate) fn dbus_path(path: &str) -> Result<Path<'static>> {
     Path::new(path)
         .map(|p| p.into_static())
         .map_err(|e| anyhow!("invalid D-Bus object path {path}: {e}"))
 }
 
+/// Split a UHID-style input report into its report ID and BLE payload.
+/// Unknown IDs are rejected by the caller's slot lookup.
 fn ble_input_payload_from_uhid(report: &[u8]) -> Result<(u8, &[u8])> {
     let (report_id, payload) = report
         .split_first()
         .ok_or_else(|| anyhow!("UHID input report is empty"))?;
-    if !matches!(
-        *report_id,
-        XBOX_INPUT_REPORT_ID | XBOX_EXTRA_INPUT_REPORT_ID | XBOX_STATUS_INPUT_REPORT_ID
-    ) {
-        return Err(anyhow!(
-            "unexpected UHID input report id=0x{report_id:02x}; expected one of 0x{XBOX_INPUT_REPORT_ID:02x}/0x{XBOX_EXTRA_INPUT_REPORT_ID:02x}/0x{XBOX_STATUS_INPUT_REPORT_ID:02x}"
-        ));
-    }
     Ok((*report_id, payload))
 }
 
@@ -1536,9 +2670,13 @@ struct NormalizedBleOutputValue {
     parser_value: Vec<u8>,
 }
 
-fn normalize_ble_output_value(report_id: u8, value: &[u8]) -> NormalizedBleOutputValue {
-    if value.len() == XBOX_OUTPUT_PAYLOAD_LEN {
-        let mut parser_value = Vec::with_capacity(XBOX_OUTPUT_REPORT_LEN);
+fn normalize_ble_output_value(
+    report_id: u8,
+    payload_len: usize,
+    value: &[u8],
+) -> NormalizedBleOutputValue {
+    if value.len() == payload_len {
+        let mut parser_value = Vec::with_capacity(payload_len + 1);
         parser_value.push(report_id);
         parser_value.extend_from_slice(value);
         return NormalizedBleOutputValue {
@@ -1547,19 +2685,40 @@ fn normalize_ble_output_value(report_id: u8, value: &[u8]) -> NormalizedBleOutpu
         };
     }
 
-    if value.len() == XBOX_OUTPUT_REPORT_LEN && value[0] == report_id {
+    if value.len() == payload_len + 1 && value[0] == report_id {
         return NormalizedBleOutputValue {
             characteristic_value: value[1..].to_vec(),
             parser_value: value.to_vec(),
         };
     }
 
+    // Hosts may write shorter reports than the descriptor declares (Switch
+    // subcommands are sent unpadded); BLE values never carry the ID.
+    let mut parser_value = Vec::with_capacity(value.len() + 1);
+    parser_value.push(report_id);
+    parser_value.extend_from_slice(value);
     NormalizedBleOutputValue {
         characteristic_value: value.to_vec(),
-        parser_value: value.to_vec(),
+        parser_value,
     }
 }
 
+/// Device Information string characteristics in GATT order, skipping the
+/// optional strings that are unset.
+fn device_info_strings(info: &DeviceInfo) -> Vec<(&'static str, String)> {
+    [
+        (MANUFACTURER_NAME_UUID, Some(&info.manufacturer)),
+        (MODEL_NUMBER_UUID, Some(&info.model)),
+        (SERIAL_NUMBER_UUID, info.serial.as_ref()),
+        (FIRMWARE_REVISION_UUID, Some(&info.firmware_revision)),
+        (HARDWARE_REVISION_UUID, info.hardware_revision.as_ref()),
+        (SOFTWARE_REVISION_UUID, Some(&info.software_revision)),
+    ]
+    .into_iter()
+    .filter_map(|(uuid, value)| Some((uuid, value?.clone())))
+    .collect()
+}
+
 fn encode_pnp_id(vendor_id: u16, product_id: u16, version: u16) -> [u8; 7] {
     let vendor = vendor_id.to_le_bytes();
     let product = product_id.to_le_bytes();
@@ -1577,11 +2736,36 @@ fn encode_pnp_id(vendor_id: u16, product_id: u16, version: u16) -> [u8; 7] {
 
 #[cfg(test)]
 mod tests {
-    use super::{ble_input_payload_from_uhid, encode_pnp_id, normalize_ble_output_value};
+    use super::{
+        ble_input_payload_from_uhid, device_info_strings, encode_pnp_id,
+        normalize_ble_output_value, parse_bd_address, system_bus, AdvertisementData,
+        ConnectionListEntry, HogRuntime, HogState, ADVERTISEMENT_PATH, AGENT_PATH,
+        BATTERY_LEVEL_CHAR_PATH, BATTERY_SERVICE_UUID, DEVICE_INFO_SERVICE_UUID,
+        HARDWARE_REVISION_UUID, HID_CONTROL_POINT_CHAR_PATH, HID_FIRST_REPORT_CHAR_INDEX,
+        HID_REPORT_MAP_CHAR_PATH, HID_REPORT_UUID, HID_SERVICE_PATH, HID_SERVICE_UUID,
+        MANUFACTURER_NAME_UUID, MAX_PENDING_HOST_EVENTS, MAX_PENDING_OUTPUT_REPORTS,
+        REPORT_REFERENCE_UUID, SERIAL_NUMBER_UUID, SOFTWARE_REVISION_UUID,
+    };
+    use crate::control::ControlState;
+    use crate::hosts::HostEvent;
+    use crate::mock_bluez::MockBluez;
+    use crate::pairing::PairingPolicy;
+    use common::config::{DeviceInfo, HidConfig};
+    use common::control::{CONTROL_BUS_NAME, CONTROL_INTERFACE, CONTROL_OBJECT_PATH};
+    use common::hid::builder::{
+        Collection, DescriptorBuilder, FieldSpec, PAGE_GENERIC_DESKTOP, USAGE_GAMEPAD, USAGE_X,
+    };
+    use common::hid::ds4::{DS4_INPUT_PAYLOAD_LEN, DS4_INPUT_REPORT_ID, DS4_OUTPUT_REPORT_ID};
     use common::hid::{
-        XBOX_EXTRA_INPUT_REPORT_ID, XBOX_INPUT_REPORT_ID, XBOX_OUTPUT_PAYLOAD_LEN,
-        XBOX_OUTPUT_REPORT_ID, XBOX_OUTPUT_REPORT_LEN, XBOX_STATUS_INPUT_REPORT_ID,
+        HidProfile, HidProfileMode, ReportType, XBOX_EXTRA_INPUT_REPORT_ID, XBOX_INPUT_REPORT_ID,
+        XBOX_OUTPUT_PAYLOAD_LEN, XBOX_OUTPUT_REPORT_ID, XBOX_OUTPUT_REPORT_LEN,
+        XBOX_STATUS_INPUT_REPORT_ID,
     };
+    use dbus::arg::{PropMap, Variant};
+    use dbus::Path;
+    use std::sync::{Arc, Mutex};
+    use std::thread;
+    use std::time::{Duration, Instant};
 
     #[test]
     fn input_payload_strips_uhid_report_id_for_ble() {
@@ -1595,7 +2779,8 @@ mod tests {
     #[test]
     fn output_payload_adds_report_id_for_parser() {
         let raw = [7u8; XBOX_OUTPUT_PAYLOAD_LEN];
-        let normalized = normalize_ble_output_value(XBOX_OUTPUT_REPORT_ID, &raw);
+        let normalized =
+            normalize_ble_output_value(XBOX_OUTPUT_REPORT_ID, XBOX_OUTPUT_PAYLOAD_LEN, &raw);
         assert_eq!(normalized.characteristic_value, raw);
         assert_eq!(normalized.parser_value.len(), XBOX_OUTPUT_REPORT_LEN);
         assert_eq!(normalized.parser_value[0], XBOX_OUTPUT_REPORT_ID);
@@ -1608,7 +2793,8 @@ mod tests {
         raw.push(XBOX_OUTPUT_REPORT_ID);
         raw.extend_from_slice(&[9u8; XBOX_OUTPUT_PAYLOAD_LEN]);
 
-        let normalized = normalize_ble_output_value(XBOX_OUTPUT_REPORT_ID, &raw);
+        let normalized =
+            normalize_ble_output_value(XBOX_OUTPUT_REPORT_ID, XBOX_OUTPUT_PAYLOAD_LEN, &raw);
         assert_eq!(
             normalized.characteristic_value.len(),
             XBOX_OUTPUT_PAYLOAD_LEN
@@ -1635,9 +2821,623 @@ mod tests {
         assert_eq!(payload, &[0xbb]);
     }
 
+    #[test]
+    fn state_slots_follow_profile_reports() {
+        let xbox = HogState::new(HidProfile::from(HidProfileMode::XboxOneS1708));
+        assert_eq!(xbox.input_reports.len(), 3);
+        assert!(xbox.output_reports.contains_key(&XBOX_OUTPUT_REPORT_ID));
+
+        let ds4 = HogState::new(HidProfile::from(HidProfileMode::DualShock4));
+        assert_eq!(ds4.input_reports.len(), 1);
+        assert_eq!(
+            ds4.input_reports[&DS4_INPUT_REPORT_ID].value.len(),
+            DS4_INPUT_PAYLOAD_LEN
+        );
+        assert!(ds4.output_reports.contains_key(&DS4_OUTPUT_REPORT_ID));
+
+        let composite = HogState::new(HidProfile::from(HidProfileMode::Composite));
+        assert_eq!(composite.input_reports.len(), 3);
+        assert_eq!(composite.input_reports[&0x03].value.len(), 8);
+        assert_eq!(composite.output_reports[&0x03].len(), 1);
+    }
+
+    #[test]
+    fn feature_reports_get_state_slots() {
+        let mut b = DescriptorBuilder::new();
+        b.usage_page(PAGE_GENERIC_DESKTOP)
+            .usage(USAGE_GAMEPAD)
+            .begin_collection(Collection::Application)
+            .report_id(0x01)
+            .usage(USAGE_X)
+            .input("x", FieldSpec::variable(8, 0, 255))
+            .report_id(0x05)
+            .usage(USAGE_X)
+            .feature("calibration", FieldSpec::variable(8, 0, 255).count(4))
+            .end_collection();
+        let profile =
+            HidProfile::with_descriptor(HidProfileMode::Generic, b.build().descriptor).unwrap();
+
+        let state = HogState::new(profile);
+        assert_eq!(state.input_reports.len(), 1);
+        assert!(state.output_reports.is_empty());
+        assert_eq!(state.feature_reports[&0x05], vec![0; 4]);
+    }
+
+    #[test]
+    fn feature_report_writes_keep_the_declared_length() {
+        let mut b = DescriptorBuilder::new();
+        b.usage_page(PAGE_GENERIC_DESKTOP)
+            .usage(USAGE_GAMEPAD)
+            .begin_collection(Collection::Application)
+            .report_id(0x01)
+            .usage(USAGE_X)
+            .input("x", FieldSpec::variable(8, 0, 255))
+            .report_id(0x05)
+            .usage(USAGE_X)
+            .feature("calibration", FieldSpec::variable(8, 0, 255).count(4))
+            .end_collection();
+        let profile =
+            HidProfile::with_descriptor(HidProfileMode::Generic, b.build().descriptor).unwrap();
+        let mut state = HogState::new(profile);
+
+        state.write_feature_report(0x05, &[1, 2, 3, 4]).unwrap();
+        state.write_feature_report(0x05, &[9]).unwrap();
+        assert_eq!(state.feature_reports[&0x05], vec![9, 0, 0, 0]);
+        assert!(state.write_feature_report(0x05, &[1; 5]).is_err());
+        assert!(state.write_feature_report(0x06, &[1]).is_err());
+        assert_eq!(state.feature_reports[&0x05], vec![9, 0, 0, 0]);
+    }
+
+    #[test]
+    fn control_point_suspends_until_exit_or_disconnect() {
+        let host = Path::from("/org/bluez/hci0/dev_98_B6_E9_01_02_03");
+        let connected = |value: bool| -> PropMap {
+            PropMap::from([("Connected".to_string(), Variant(Box::new(value) as Box<_>))])
+        };
+        let mut state = HogState::new(HidProfile::from(HidProfileMode::XboxOneS1708));
+        assert!(!state.suspended);
+        state.update_connection(&host, &connected(true));
+
+        state.write_control_point(0x00).unwrap();
+        assert!(state.suspended);
+        state.write_control_point(0x01).unwrap();
+        assert!(!state.suspended);
+        assert!(state.write_control_point(0x02).is_err());
+
+        state.write_control_point(0x00).unwrap();
+        state.update_connection(&host, &connected(false));
+        assert!(!state.suspended);
+    }
+
+    #[test]
+    fn short_output_payload_gets_report_id_prefix() {
+        let normalized = normalize_ble_output_value(0x01, 48, &[0x00, 0x02]);
+        assert_eq!(normalized.characteristic_value, vec![0x00, 0x02]);
+        assert_eq!(normalized.parser_value, vec![0x01, 0x00, 0x02]);
+    }
+
+    #[test]
+    fn pending_output_queue_drops_oldest_when_full() {
+        let mut state = HogState::new(HidProfile::from(HidProfileMode::SwitchPro));
+        for i in 0..=MAX_PENDING_OUTPUT_REPORTS {
+            state.queue_output_report(vec![i as u8]);
+        }
+        assert_eq!(state.pending_outputs.len(), MAX_PENDING_OUTPUT_REPORTS);
+        assert_eq!(state.pending_outputs.front(), Some(&vec![1u8]));
+    }
+
+    #[test]
+    fn pending_host_events_drop_oldest_when_full() {
+        let mut state = HogState::new(HidProfile::from(HidProfileMode::XboxOneS1708));
+        state.queue_host_event(HostEvent::Select("first".to_string()));
+        for _ in 0..MAX_PENDING_HOST_EVENTS {
+            state.queue_host_event(HostEvent::Next);
+        }
+        assert_eq!(state.host_events.len(), MAX_PENDING_HOST_EVENTS);
+        assert!(state.host_events.iter().all(|e| *e == HostEvent::Next));
+    }
+
+    #[test]
+    fn bd_address_parses_most_significant_byte_first() {
+        assert_eq!(
+            parse_bd_address("98:B6:E9:01:02:0a"),
+            Some([0x98, 0xb6, 0xe9, 0x01, 0x02, 0x0a])
+        );
+        assert_eq!(parse_bd_address("98:B6:E9:01:02"), None);
+        assert_eq!(parse_bd_address("98:B6:E9:01:02:03:04"), None);
+    }
+
     #[test]
     fn pnp_id_encoding_uses_usb_source_and_little_endian_fields() {
         let pnp = encode_pnp_id(0x045e, 0x02fd, 0x0408);
         assert_eq!(pnp, [0x01, 0x5e, 0x04, 0xfd, 0x02, 0x08, 0x04]);
     }
+
+    #[test]
+    fn device_info_strings_skip_unset_optional_fields() {
+        let mut info = DeviceInfo {
+            manufacturer: "Microsoft".to_string(),
+            model: "Xbox Wireless Controller".to_string(),
+            serial: None,
+            firmware_revision: "4.8".to_string(),
+            hardware_revision: None,
+            software_revision: "0.1.0".to_string(),
+        };
+        let strings = device_info_strings(&info);
+        assert_eq!(strings.len(), 4);
+        assert_eq!(
+            strings[0],
+            (MANUFACTURER_NAME_UUID, "Microsoft".to_string())
+        );
+        assert_eq!(strings[3], (SOFTWARE_REVISION_UUID, "0.1.0".to_string()));
+
+        info.serial = Some("FVAA123456".to_string());
+        info.hardware_revision = Some("LCD".to_string());
+        let uuids = device_info_strings(&info)
+            .into_iter()
+            .map(|(uuid, _)| uuid)
+            .collect::<Vec<_>>();
+        assert_eq!(uuids.len(), 6);
+        assert_eq!(uuids[2], SERIAL_NUMBER_UUID);
+        assert_eq!(uuids[4], HARDWARE_REVISION_UUID);
+    }
+
+    #[test]
+    fn scan_response_takes_secondary_uuids_and_manufacturer_data() {
+        let cfg = HidConfig::from_toml_str(
+            r#"
+            [device]
+            name = "ControllerOS Xbox Controller"
+
+            [report]
+            rate_hz = 125
+
+            [pattern]
+            kind = "button_toggle"
+            button_index = 0
+            period_reports = 30
+
+            [advertising]
+            manufacturer_id = 0x045e
+            manufacturer_data = [1, 2]
+            scan_response = true
+            "#,
+        )
+        .expect("config should parse");
+        let state = Arc::new(Mutex::new(HogState::new(cfg.profile.hid_profile())));
+        let policy = PairingPolicy::shared(&cfg.pairing);
+        let adv = AdvertisementData::new(&cfg, state, policy);
+        assert_eq!(adv.service_uuids, [HID_SERVICE_UUID]);
+        assert_eq!(adv.scan_response_uuids[0], BATTERY_SERVICE_UUID);
+        assert!(adv.manufacturer_data_property(false).is_err());
+        let data = adv
+            .manufacturer_data_property(true)
+            .expect("manufacturer data in scan response");
+        assert_eq!(data[&0x045e].0, [1, 2]);
+    }
+
+    const HOST: &str = "98:B6:E9:01:02:03";
+
+    fn mock_config(extra: &str) -> HidConfig {
+        HidConfig::from_toml_str(&format!(
+            r#"
+            [device]
+            name = "ControllerOS Test Pad"
+
+            [report]
+            rate_hz = 125
+
+            [pattern]
+            kind = "button_toggle"
+            button_index = 0
+            period_reports = 30
+
+            {extra}
+            "#
+        ))
+        .expect("config should parse")
+    }
+
+    fn register(cfg: &HidConfig) -> HogRuntime {
+        HogRuntime::register(cfg, &cfg.device_info(), ControlState::shared())
+            .expect("register with mock BlueZ")
+    }
+
+    fn report_char_path(profile: &HidProfile, report_type: ReportType, id: u8) -> String {
+        let index = profile
+            .reports()
+            .iter()
+            .position(|r| r.report_type == report_type && r.id == id)
+            .expect("report in profile");
+        format!(
+            "{HID_SERVICE_PATH}/char{}",
+            HID_FIRST_REPORT_CHAR_INDEX + index
+        )
+    }
+
+    /// Dispatch the runtime's D-Bus traffic, as the main loop does between
+    /// reports, until `done` or five seconds pass.
+    fn pump_until(runtime: &HogRuntime, mut done: impl FnMut() -> bool) -> bool {
+        let deadline = Instant::now() + Duration::from_secs(5);
+        while Instant::now() < deadline {
+            runtime
+                .process_pending_messages()
+                .expect("dispatch D-Bus traffic");
+            if done() {
+                return true;
+            }
+            thread::sleep(Duration::from_millis(2));
+        }
+        false
+    }
+
+    /// Make a blocking call into the runtime from another thread, as a host
+    /// or the GUI would, while the runtime dispatches.
+    fn while_dispatching<T: Send>(runtime: &HogRuntime, call: impl FnOnce() -> T + Send) -> T {
+        thread::scope(|scope| {
+            let call = scope.spawn(call);
+            assert!(pump_until(runtime, || call.is_finished()), "call timed out");
+            call.join().expect("call panicked")
+        })
+    }
+
+    #[test]
+    fn registers_gatt_application_advertisement_and_agent_with_bluez() {
+        let Some(bluez) = MockBluez::start() else {
+            return;
+        };
+        let cfg = mock_config("");
+        let runtime = register(&cfg);
+        assert_eq!(
+            runtime.adapter_address(),
+            [0x00, 0x1a, 0x7d, 0xda, 0x71, 0x13]
+        );
+
+        {
+            let state = bluez.state();
+            let adapter = &state.adapter;
+            assert!(adapter.powered && adapter.discoverable && adapter.pairable);
+            assert_eq!(adapter.discoverable_timeout, 0);
+            assert_eq!(adapter.alias, "ControllerOS Test Pad");
+
+            let services = state
+                .gatt
+                .values()
+                .filter(|o| o.interface == "org.bluez.GattService1")
+                .map(|o| o.uuid.as_str())
+                .collect::<Vec<_>>();
+            assert_eq!(
+                services,
+                [
+                    HID_SERVICE_UUID,
+                    BATTERY_SERVICE_UUID,
+                    DEVICE_INFO_SERVICE_UUID
+                ]
+            );
+            let profile = cfg.profile.hid_profile();
+            for report in profile.reports() {
+                let path = report_char_path(&profile, report.report_type, report.id);
+                assert_eq!(state.gatt[&path].uuid, HID_REPORT_UUID);
+                assert_eq!(
+                    state.gatt[&format!("{path}/desc0")].uuid,
+                    REPORT_REFERENCE_UUID
+                );
+            }
+
+            let advertisement = state.advertisement.as_ref().expect("advertisement");
+            assert_eq!(advertisement.path, ADVERTISEMENT_PATH);
+            assert_eq!(advertisement.local_name, "ControllerOS Test Pad");
+            assert_eq!(advertisement.service_uuids, services);
+
+            let agent = state.agent.as_ref().expect("agent");
+            assert_eq!(agent.path, AGENT_PATH);
+            assert_eq!(agent.capability, cfg.pairing.agent_capability.as_bluez());
+            assert!(agent.default);
+        }
+
+        let report_map = while_dispatching(&runtime, || bluez.read_value(HID_REPORT_MAP_CHAR_PATH));
+        assert_eq!(
+            report_map.expect("read Report Map"),
+            cfg.profile.hid_profile().report_descriptor()
+        );
+
+        drop(runtime);
+        let state = bluez.state();
+        assert!(state.gatt.is_empty());
+        assert!(state.advertisement.is_none());
+        assert!(state.agent.is_none());
+    }
+
+    #[test]
+    fn stores_report_and_battery_values_before_a_host_connects() {
+        let Some(bluez) = MockBluez::start() else {
+            return;
+        };
+        let cfg = mock_config("");
+        let runtime = register(&cfg);
+        let profile = cfg.profile.hid_profile();
+        let path = report_char_path(&profile, ReportType::Input, XBOX_INPUT_REPORT_ID);
+        let payload_len = profile
+            .report(ReportType::Input, XBOX_INPUT_REPORT_ID)
+            .expect("Xbox input report")
+            .payload_len;
+
+        let mut report = vec![5; payload_len + 1];
+        report[0] = XBOX_INPUT_REPORT_ID;
+        runtime.publish_input_report(&report).expect("publish");
+        runtime.set_battery_level(42).expect("battery level");
+
+        let read = while_dispatching(&runtime, || bluez.read_value(&path));
+        assert_eq!(read.expect("read input report"), vec![5; payload_len]);
+        let battery = while_dispatching(&runtime, || bluez.read_value(BATTERY_LEVEL_CHAR_PATH));
+        assert_eq!(battery.expect("read battery level"), vec![42]);
+        assert!(bluez.state().notifications.is_empty());
+    }
+
+    #[test]
+    fn notifies_input_reports_to_subscribed_hosts_unless_suspended() {
+        let Some(bluez) = MockBluez::start() else {
+            return;
+        };
+        let cfg = mock_config("");
+        let runtime = register(&cfg);
+        let profile = cfg.profile.hid_profile();
+        let path = report_char_path(&profile, ReportType::Input, XBOX_INPUT_REPORT_ID);
+        let payload_len = profile
+            .report(ReportType::Input, XBOX_INPUT_REPORT_ID)
+            .expect("Xbox input report")
+            .payload_len;
+        let publish = |fill: u8| {
+            let mut report = vec![fill; payload_len + 1];
+            report[0] = XBOX_INPUT_REPORT_ID;
+            runtime.publish_input_report(&report).expect("publish");
+        };
+        let notified = || {
+            bluez
+                .state()
+                .notifications
+                .iter()
+                .filter(|(p, _)| *p == path)
+                .map(|(_, value)| value[0])
+                .collect::<Vec<_>>()
+        };
+
+        // Stored but not notified without a connected host.
+        publish(1);
+        let host = bluez.add_device(HOST, "Host", true);
+        bluez.set_connected(&host, true);
+        assert!(pump_until(&runtime, || runtime
+            .lock_state()
+            .unwrap()
+            .connections
+            .any_connected()));
+        let read = while_dispatching(&runtime, || bluez.read_value(&path));
+        assert_eq!(read.expect("read input report"), vec![1; payload_len]);
+
+        // Connecting restores the subscription.
+        publish(2);
+        let stop = while_dispatching(&runtime, || {
+            bluez.call_application::<_, ()>(&path, "StopNotify", ())
+        });
+        stop.expect("StopNotify");
+        publish(3);
+        let start = while_dispatching(&runtime, || {
+            bluez.call_application::<_, ()>(&path, "StartNotify", ())
+        });
+        start.expect("StartNotify");
+
+        let suspend = while_dispatching(&runtime, || {
+            bluez.write_value(HID_CONTROL_POINT_CHAR_PATH, &[0x00])
+        });
+        suspend.expect("suspend");
+        publish(4);
+        let resume = while_dispatching(&runtime, || {
+            bluez.write_value(HID_CONTROL_POINT_CHAR_PATH, &[0x01])
+        });
+        resume.expect("exit suspend");
+        publish(5);
+
+        assert!(pump_until(&runtime, || notified().last() == Some(&5)));
+        assert_eq!(notified(), [2, 5]);
+    }
+
+    #[test]
+    fn host_output_writes_reach_the_main_loop() {
+        let Some(bluez) = MockBluez::start() else {
+            return;
+        };
+        let cfg = mock_config("");
+        let runtime = register(&cfg);
+        let profile = cfg.profile.hid_profile();
+        let path = report_char_path(&profile, ReportType::Output, XBOX_OUTPUT_REPORT_ID);
+        let mut rumble = vec![0u8; XBOX_OUTPUT_PAYLOAD_LEN];
+        rumble[0] = 0x0f;
+        rumble[3] = 40;
+        rumble[4] = 80;
+
+        let written = while_dispatching(&runtime, || bluez.write_value(&path, &rumble));
+        written.expect("write output report");
+        let mut expected = vec![XBOX_OUTPUT_REPORT_ID];
+        expected.extend_from_slice(&rumble);
+        assert_eq!(runtime.take_output_reports().unwrap(), [expected]);
+        let read = while_dispatching(&runtime, || bluez.read_value(&path));
+        assert_eq!(read.expect("read output report"), rumble);
+
+        let refused = while_dispatching(&runtime, || {
+            bluez.write_value(HID_REPORT_MAP_CHAR_PATH, &[0])
+        });
+        let err = refused.expect_err("Report Map is read-only");
+        assert_eq!(err.name(), Some("org.bluez.Error.NotSupported"));
+        assert!(runtime.take_output_reports().unwrap().is_empty());
+    }
+
+    #[test]
+    fn removed_devices_leave_the_connection_list() {
+        let Some(bluez) = MockBluez::start() else {
+            return;
+        };
+        let cfg = mock_config("");
+        let runtime = register(&cfg);
+        let control = system_bus().expect("private bus");
+        let list_connections = || {
+            while_dispatching(&runtime, || {
+                control
+                    .with_proxy(
+                        CONTROL_BUS_NAME,
+                        CONTROL_OBJECT_PATH,
+                        Duration::from_secs(5),
+                    )
+                    .method_call::<(Vec<ConnectionListEntry>,), _, _, _>(
+                        CONTROL_INTERFACE,
+                        "ListConnections",
+                        (),
+                    )
+            })
+            .expect("ListConnections")
+            .0
+        };
+
+        let host = bluez.add_device(HOST, "Host", true);
+        bluez.set_connected(&host, true);
+        assert!(pump_until(&runtime, || runtime
+            .lock_state()
+            .unwrap()
+            .connections
+            .any_connected()));
+        let connections = list_connections();
+        assert_eq!(connections.len(), 1);
+        assert_eq!(connections[0].0, HOST);
+
+        bluez.set_connected(&host, false);
+        bluez.remove_device(&host);
+        assert!(pump_until(&runtime, || runtime
+            .lock_state()
+            .unwrap()
+            .connections
+            .iter()
+            .next()
+            .is_none()));
+        assert!(list_connections().is_empty());
+        assert!(!runtime.bluez_lost());
+    }
+
+    #[test]
+    fn agent_follows_pairing_lock_and_holds_confirmation_for_the_deck() {
+        let Some(bluez) = MockBluez::start() else {
+            return;
+        };
+        let cfg = mock_config("[pairing]\nlocked = true\nagent_capability = \"display_yes_no\"");
+        let runtime = register(&cfg);
+        assert_eq!(
+            bluez.state().agent.as_ref().unwrap().capability,
+            "DisplayYesNo"
+        );
+        let host = bluez.add_device(HOST, "Host", false);
+
+        let refused = while_dispatching(&runtime, || {
+            bluez.call_agent("RequestAuthorization", (host.clone(),))
+        });
+        let err = refused.expect_err("locked pairing refuses new hosts");
+        assert_eq!(err.name(), Some("org.bluez.Error.Rejected"));
+        assert!(!bluez.state().devices[&host].trusted);
+        let passkey = while_dispatching(&runtime, || {
+            bluez.call_agent("DisplayPasskey", (host.clone(), 123_456u32, 0u16))
+        });
+        let err = passkey.expect_err("no passkey for a refused host");
+        assert_eq!(err.name(), Some("org.bluez.Error.Rejected"));
+        let confirmation = while_dispatching(&runtime, || {
+            bluez.call_agent("RequestConfirmation", (host.clone(), 123_456u32))
+        });
+        let err = confirmation.expect_err("no confirmation for a refused host");
+        assert_eq!(err.name(), Some("org.bluez.Error.Rejected"));
+        assert!(runtime.policy.lock().unwrap().prompt().is_none());
+
+        runtime.start_pairing(None).unwrap();
+        let authorized = while_dispatching(&runtime, || {
+            bluez.call_agent("RequestAuthorization", (host.clone(),))
+        });
+        authorized.expect("pairing mode admits the host");
+        assert!(bluez.state().devices[&host].trusted);
+
+        let control = system_bus().expect("private bus");
+        thread::scope(|scope| {
+            let confirmation =
+                scope.spawn(|| bluez.call_agent("RequestConfirmation", (host.clone(), 123_456u32)));
+            assert!(pump_until(&runtime, || runtime
+                .policy
+                .lock()
+                .unwrap()
+                .prompt()
+                .is_some()));
+            {
+                let policy = runtime.policy.lock().unwrap();
+                let prompt = policy.prompt().unwrap();
+                assert_eq!(prompt.address, HOST);
+                assert_eq!(prompt.passkey, 123_456);
+                assert!(prompt.confirm);
+            }
+            assert!(!confirmation.is_finished());
+
+            let answered = while_dispatching(&runtime, || {
+                control
+                    .with_proxy(
+                        CONTROL_BUS_NAME,
+                        CONTROL_OBJECT_PATH,
+                        Duration::from_secs(5),
+                    )
+                    .method_call::<(), _, _, _>(CONTROL_INTERFACE, "ConfirmPairing", (true,))
+            });
+            answered.expect("ConfirmPairing");
+            assert!(pump_until(&runtime, || confirmation.is_finished()));
+            let confirmed = confirmation.join().unwrap();
+            confirmed.expect("the Deck accepted the passkey");
+        });
+    }
+
+    #[test]
+    fn bluez_errors_reach_the_call_waiting_for_them() {
+        let Some(_bluez) = MockBluez::start() else {
+            return;
+        };
+        let cfg = mock_config("");
+        // Registering first unregisters the application, advertisement and
+        // agent of an earlier run, which BlueZ answers with errors. Each
+        // waits out its timeout if the reply is taken by anything else.
+        let started = Instant::now();
+        let _runtime = register(&cfg);
+        assert!(started.elapsed() < Duration::from_secs(1));
+    }
+
+    #[test]
+    fn advertisement_is_registered_again_after_disconnect_until_it_succeeds() {
+        let Some(bluez) = MockBluez::start() else {
+            return;
+        };
+        let cfg = mock_config("");
+        let runtime = register(&cfg);
+        assert_eq!(bluez.state().advertisement_registrations, 1);
+
+        let host = bluez.add_device(HOST, "Host", true);
+        bluez.set_connected(&host, true);
+        assert!(pump_until(&runtime, || runtime
+            .lock_state()
+            .unwrap()
+            .connections
+            .any_connected()));
+
+        // The controller refuses the first attempt after the host leaves.
+        bluez.state().failing_advertisements = 1;
+        runtime.disconnect_hosts().expect("disconnect hosts");
+        assert!(!bluez.state().devices[&host].connected);
+        assert!(pump_until(&runtime, || bluez
+            .state()
+            .advertisement_registrations
+            == 2));
+        let state = bluez.state();
+        assert_eq!(state.failing_advertisements, 0);
+        assert_eq!(
+            state.advertisement.as_ref().map(|a| a.path.as_str()),
+            Some(ADVERTISEMENT_PATH)
+        );
+    }
 }
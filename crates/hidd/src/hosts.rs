@@ -0,0 +1,271 @@
+//! Switching between bonded hosts.
+//!
+//! BlueZ keeps a bond per host, but an LE peripheral can only serve the
+//! central that connects to it. The "current host" is the one in use; after a
+//! switch (and at startup, for the host remembered from the last run) other
+//! bonded hosts are disconnected for `HOST_SWITCH_WINDOW` so the chosen one
+//! can reconnect to the advertisement. Hosts without a bond are never
+//! refused, so new hosts can still pair.
+
+use std::fs;
+use std::io;
+use std::path::{Path as FsPath, PathBuf};
+use std::time::{Duration, Instant};
+
+use anyhow::Result;
+use common::config::is_bd_address;
+use dbus::Path;
+
+use crate::hog::HogRuntime;
+
+/// Address of the last used host, kept across reboots.
+pub const DEFAULT_HOST_STATE_PATH: &str = "/var/lib/controlleros/last_host";
+
+/// How long other bonded hosts are refused while the chosen host reconnects.
+pub const HOST_SWITCH_WINDOW: Duration = Duration::from_secs(60);
+
+/// A host BlueZ holds a bond for.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BondedHost {
+    pub path: Path<'static>,
+    /// `XX:XX:XX:XX:XX:XX`, upper case as BlueZ reports it.
+    pub address: String,
+    pub name: String,
+    pub connected: bool,
+}
+
+/// Host changes and control requests queued for the main loop.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum HostEvent {
+    /// A device connected, disconnected or changed its bond.
+    Changed,
+    /// Switch to the bonded host after the current one.
+    Next,
+    /// Switch to the bonded host with this address.
+    Select(String),
+}
+
+pub struct HostManager {
+    state_path: PathBuf,
+    /// Host in use, or the one being switched to.
+    current: Option<String>,
+    /// Other bonded hosts are refused until then.
+    exclusive_until: Option<Instant>,
+    /// Reconcile on the first poll, for hosts connected before startup.
+    needs_refresh: bool,
+}
+
+impl HostManager {
+    pub fn new(state_path: impl Into<PathBuf>) -> Self {
+        let state_path = state_path.into();
+        let current = load_last_host(&state_path);
+        if let Some(address) = &current {
+            eprintln!("hidd: last host {address}, waiting for it to reconnect");
+        }
+        Self {
+            exclusive_until: current
+                .as_ref()
+                .map(|_| Instant::now() + HOST_SWITCH_WINDOW),
+            current,
+            state_path,
+            needs_refresh: true,
+        }
+    }
+
+    /// Handle queued host events: switch on request, track the connected
+    /// host and refuse other bonded hosts during a switch.
+    pub fn poll(&mut self, hog: &HogRuntime) -> Result<()> {
+        let events = hog.take_host_events()?;
+        if events.is_empty() && !self.needs_refresh {
+            return Ok(());
+        }
+        self.needs_refresh = false;
+
+        hog.refuse_unadmitted_hosts()?;
+        let hosts = hog.bonded_hosts()?;
+        for event in events {
+            let target = match &event {
+                HostEvent::Changed => continue,
+                HostEvent::Next => next_host(&hosts, self.current.as_deref()),
+                HostEvent::Select(address) => hosts
+                    .iter()
+                    .find(|h| h.address.eq_ignore_ascii_case(address)),
+            };
+            match target {
+                Some(target) => self.switch_to(hog, &hosts, target)?,
+                None => eprintln!("hidd: {event:?} ignored; no matching bonded host"),
+            }
+        }
+
+        let hosts = hog.bonded_hosts()?;
+        let exclusive = self
+            .exclusive_until
+            .is_some_and(|until| Instant::now() < until);
+        let reconciled = reconcile(&hosts, self.current.as_deref(), exclusive);
+        for host in reconciled.refuse {
+            eprintln!(
+                "hidd: refusing host {} ({}) while switching to {}",
+                host.address,
+                host.name,
+                self.current.as_deref().unwrap_or("-")
+            );
+            hog.disconnect_host(&host.path)?;
+        }
+        if let Some(host) = reconciled.current {
+            self.exclusive_until = None;
+            self.set_current(&host.address);
+        }
+        hog.set_host_list(&hosts, self.current.as_deref())
+    }
+
+    fn switch_to(
+        &mut self,
+        hog: &HogRuntime,
+        hosts: &[BondedHost],
+        target: &BondedHost,
+    ) -> Result<()> {
+        eprintln!(
+            "hidd: switching to host {} ({})",
+            target.address, target.name
+        );
+        self.set_current(&target.address);
+        self.exclusive_until = Some(Instant::now() + HOST_SWITCH_WINDOW);
+        for host in hosts
+            .iter()
+            .filter(|h| h.connected && h.address != target.address)
+        {
+            eprintln!("hidd: disconnecting host {} ({})", host.address, host.name);
+            hog.disconnect_host(&host.path)?;
+        }
+        Ok(())
+    }
+
+    fn set_current(&mut self, address: &str) {
+        if self.current.as_deref() == Some(address) {
+            return;
+        }
+        self.current = Some(address.to_string());
+        if let Err(e) = save_last_host(&self.state_path, address) {
+            eprintln!(
+                "hidd: cannot save last host to {}: {e}",
+                self.state_path.display()
+            );
+        }
+    }
+}
+
+/// The bonded host after `current` in address order, wrapping around, or
+/// the first one when `current` is not bonded.
+fn next_host<'a>(hosts: &'a [BondedHost], current: Option<&str>) -> Option<&'a BondedHost> {
+    match current.and_then(|c| hosts.iter().position(|h| h.address == c)) {
+        Some(index) => hosts.get((index + 1) % hosts.len()),
+        None => hosts.first(),
+    }
+}
+
+#[derive(Debug, Default)]
+struct Reconciled<'a> {
+    /// Connected host that is (or becomes) the current host.
+    current: Option<&'a BondedHost>,
+    /// Connected hosts to disconnect.
+    refuse: Vec<&'a BondedHost>,
+}
+
+/// Decide what to do with the connected bonded hosts. While `exclusive` and
+/// the current host is still bonded, only it may stay connected; otherwise
+/// the first connected host becomes current.
+fn reconcile<'a>(
+    hosts: &'a [BondedHost],
+    current: Option<&str>,
+    exclusive: bool,
+) -> Reconciled<'a> {
+    let exclusive = exclusive && hosts.iter().any(|h| Some(h.address.as_str()) == current);
+    let mut out = Reconciled::default();
+    for host in hosts.iter().filter(|h| h.connected) {
+        if Some(host.address.as_str()) == current {
+            out.current = Some(host);
+        } else if exclusive {
+            out.refuse.push(host);
+        } else if out.current.is_none() {
+            out.current = Some(host);
+        }
+    }
+    out
+}
+
+fn load_last_host(path: &FsPath) -> Option<String> {
+    let address = fs::read_to_string(path).ok()?.trim().to_ascii_uppercase();
+    is_bd_address(&address).then_some(address)
+}
+
+fn save_last_host(path: &FsPath, address: &str) -> io::Result<()> {
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    let tmp = path.with_extension("tmp");
+    fs::write(&tmp, format!("{address}\n"))?;
+    fs::rename(tmp, path)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{next_host, reconcile, BondedHost};
+    use dbus::Path;
+
+    fn host(address: &str, connected: bool) -> BondedHost {
+        BondedHost {
+            path: Path::new(format!("/org/bluez/hci0/dev_{}", address.replace(':', "_"))).unwrap(),
+            address: address.to_string(),
+            name: format!("host {address}"),
+            connected,
+        }
+    }
+
+    #[test]
+    fn next_host_wraps_in_address_order() {
+        let hosts = [
+            host("00:00:00:00:00:01", false),
+            host("00:00:00:00:00:02", false),
+            host("00:00:00:00:00:03", false),
+        ];
+        let next = |current| next_host(&hosts, current).map(|h| h.address.as_str());
+        assert_eq!(next(None), Some("00:00:00:00:00:01"));
+        assert_eq!(next(Some("00:00:00:00:00:01")), Some("00:00:00:00:00:02"));
+        assert_eq!(next(Some("00:00:00:00:00:03")), Some("00:00:00:00:00:01"));
+        assert_eq!(next(Some("AA:AA:AA:AA:AA:AA")), Some("00:00:00:00:00:01"));
+        assert_eq!(next_host(&[], None), None);
+    }
+
+    #[test]
+    fn reconcile_refuses_other_hosts_only_while_exclusive() {
+        let hosts = [
+            host("00:00:00:00:00:01", true),
+            host("00:00:00:00:00:02", false),
+        ];
+        let current = Some("00:00:00:00:00:02");
+
+        let exclusive = reconcile(&hosts, current, true);
+        assert!(exclusive.current.is_none());
+        assert_eq!(exclusive.refuse, [&hosts[0]]);
+
+        let open = reconcile(&hosts, current, false);
+        assert_eq!(open.current, Some(&hosts[0]));
+        assert!(open.refuse.is_empty());
+
+        // A current host that lost its bond does not lock others out.
+        let unbonded = reconcile(&hosts, Some("AA:AA:AA:AA:AA:AA"), true);
+        assert_eq!(unbonded.current, Some(&hosts[0]));
+        assert!(unbonded.refuse.is_empty());
+    }
+
+    #[test]
+    fn reconcile_keeps_connected_current_host() {
+        let hosts = [
+            host("00:00:00:00:00:01", true),
+            host("00:00:00:00:00:02", true),
+        ];
+        let reconciled = reconcile(&hosts, Some("00:00:00:00:00:02"), true);
+        assert_eq!(reconciled.current, Some(&hosts[1]));
+        assert_eq!(reconciled.refuse, [&hosts[0]]);
+    }
+}
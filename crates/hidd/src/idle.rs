@@ -0,0 +1,173 @@
+//! Idle detection for `[power] idle_timeout_min`.
+//!
+//! The controller is idle once its buttons, sticks, triggers and trackpads
+//! have not changed for the timeout. Motion sensors are ignored: a Deck left
+//! on a table still reports noise. While idle only the Steam button wakes
+//! it, whatever the mapping binds it to, so a Deck moved around in a bag
+//! stays asleep.
+
+use std::fs;
+use std::io;
+use std::time::{Duration, Instant};
+
+use common::hid::InputReport;
+
+/// Writing `mem` here suspends the system to RAM; the write returns after
+/// resume.
+const SYSTEM_SLEEP_PATH: &str = "/sys/power/state";
+
+/// Stick movement (of ±32767) that counts as input.
+const STICK_SLACK: i32 = 2048;
+/// Trigger movement (of 1023) that counts as input.
+const TRIGGER_SLACK: i32 = 64;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum IdleChange {
+    /// No input for the timeout.
+    Idle,
+    /// The Steam button was pressed while idle.
+    Wake,
+}
+
+pub struct IdleMonitor {
+    /// `None` when idling is disabled.
+    timeout: Option<Duration>,
+    /// Report at the last input; small stick and trigger noise is measured
+    /// against it.
+    reference: InputReport,
+    last_input: Instant,
+    idle: bool,
+}
+
+impl IdleMonitor {
+    /// Idle after `timeout_min` minutes without input; 0 never idles.
+    pub fn new(timeout_min: u32, now: Instant) -> Self {
+        Self {
+            timeout: (timeout_min > 0).then(|| Duration::from_secs(u64::from(timeout_min) * 60)),
+            reference: InputReport::default(),
+            last_input: now,
+            idle: false,
+        }
+    }
+
+    pub fn is_idle(&self) -> bool {
+        self.idle
+    }
+
+    /// Feed the current report and whether the Steam button was pressed
+    /// since the last poll; returns the change to act on, if any.
+    pub fn poll(
+        &mut self,
+        report: &InputReport,
+        steam_pressed: bool,
+        now: Instant,
+    ) -> Option<IdleChange> {
+        let timeout = self.timeout?;
+        if self.idle {
+            if !steam_pressed {
+                return None;
+            }
+            self.wake(report, now);
+            return Some(IdleChange::Wake);
+        }
+        if has_input(&self.reference, report) {
+            self.reference = *report;
+            self.last_input = now;
+            return None;
+        }
+        if now.duration_since(self.last_input) < timeout {
+            return None;
+        }
+        self.idle = true;
+        Some(IdleChange::Idle)
+    }
+
+    /// Leave the idle state without the Steam button, e.g. after the Deck
+    /// resumed from suspend.
+    pub fn wake(&mut self, report: &InputReport, now: Instant) {
+        self.idle = false;
+        self.reference = *report;
+        self.last_input = now;
+    }
+}
+
+/// `report` differs from `reference` by more than sensor noise.
+fn has_input(reference: &InputReport, report: &InputReport) -> bool {
+    let moved = |a: i32, b: i32, slack: i32| (a - b).abs() > slack;
+    reference.buttons != report.buttons
+        || reference.hat != report.hat
+        || reference.share != report.share
+        || reference.extra_buttons != report.extra_buttons
+        || reference.keys != report.keys
+        || report.touch.iter().any(|t| t.active || t.clicked)
+        || [
+            (reference.lx, report.lx),
+            (reference.ly, report.ly),
+            (reference.rx, report.rx),
+            (reference.ry, report.ry),
+        ]
+        .iter()
+        .any(|&(a, b)| moved(i32::from(a), i32::from(b), STICK_SLACK))
+        || [(reference.lt, report.lt), (reference.rt, report.rt)]
+            .iter()
+            .any(|&(a, b)| moved(i32::from(a), i32::from(b), TRIGGER_SLACK))
+}
+
+/// Suspend the Deck to RAM. Blocks until it resumes.
+pub fn suspend_system() -> io::Result<()> {
+    fs::write(SYSTEM_SLEEP_PATH, "mem")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{IdleChange, IdleMonitor};
+    use common::hid::{InputReport, XBOX_BUTTON_A, XBOX_BUTTON_HOME};
+    use std::time::{Duration, Instant};
+
+    #[test]
+    fn idles_after_timeout_and_wakes_on_steam() {
+        let start = Instant::now();
+        let at = |minutes: u64| start + Duration::from_secs(minutes * 60);
+        let mut idle = IdleMonitor::new(10, start);
+        let rest = InputReport::default();
+        let noisy = InputReport {
+            lx: 300,
+            gyro: [40, -12, 7],
+            ..rest
+        };
+        let pressed = InputReport {
+            buttons: XBOX_BUTTON_A,
+            ..rest
+        };
+        let home = InputReport {
+            buttons: XBOX_BUTTON_HOME,
+            ..rest
+        };
+
+        assert_eq!(idle.poll(&noisy, false, at(5)), None);
+        assert_eq!(idle.poll(&pressed, false, at(8)), None);
+        assert_eq!(idle.poll(&rest, false, at(9)), None);
+        assert_eq!(idle.poll(&noisy, false, at(18)), None);
+        assert_eq!(idle.poll(&rest, false, at(19)), Some(IdleChange::Idle));
+        assert!(idle.is_idle());
+
+        assert_eq!(idle.poll(&pressed, false, at(20)), None);
+        // Home from a binding is not the Steam button.
+        assert_eq!(idle.poll(&home, false, at(20)), None);
+        // A tap already released by the time of the poll.
+        assert_eq!(idle.poll(&rest, true, at(21)), Some(IdleChange::Wake));
+        assert!(!idle.is_idle());
+        assert_eq!(idle.poll(&home, false, at(30)), None);
+        assert_eq!(idle.poll(&home, false, at(40)), Some(IdleChange::Idle));
+        // Still held: Steam must be pressed again.
+        assert_eq!(idle.poll(&home, false, at(41)), None);
+    }
+
+    #[test]
+    fn zero_timeout_never_idles() {
+        let start = Instant::now();
+        let mut idle = IdleMonitor::new(0, start);
+        let later = start + Duration::from_secs(24 * 3600);
+        assert_eq!(idle.poll(&InputReport::default(), false, later), None);
+    }
+}
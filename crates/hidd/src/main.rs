@@ -1,18 +1,41 @@
 #![forbid(unsafe_code)]
 
+use std::collections::HashMap;
 use std::env;
 use std::fs::OpenOptions;
 use std::io::{Read, Write};
 use std::process::ExitCode;
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::sync::{Arc, Mutex, MutexGuard};
 use std::thread;
 use std::time::{Duration, Instant};
 
 use anyhow::{anyhow, Result};
-use common::config::{AxisName, HidConfig, PatternConfig, DEFAULT_HID_CONFIG_PATH};
-use common::hid::{InputReport, OutputReport};
-
+use common::config::{
+    AxisName, BluetoothTransport, DeviceInfo, HidConfig, IdleAction, OutputSink, PatternConfig,
+    DEFAULT_HID_CONFIG_PATH,
+};
+use common::hid::{HidProfile, HidProfileMode, InputReport, ProfileSession, ReportType};
+use dbus::Path;
+
+mod battery;
+mod bredr;
+mod connections;
+mod control;
 mod hog;
+mod hosts;
+mod idle;
+#[cfg(test)]
+mod mock_bluez;
+mod net;
+mod pairing;
+use battery::{BatteryMonitor, POWER_SUPPLY_ROOT};
+use bredr::BredrRuntime;
+use control::{ControlRequest, ControlState, DaemonStatus, SharedControl};
 use hog::HogRuntime;
+use hosts::{HostEvent, HostManager, DEFAULT_HOST_STATE_PATH};
+use idle::{IdleChange, IdleMonitor};
+use net::NetStream;
 
 const DEV_UHID: &str = "/dev/uhid";
 const UHID_DESTROY: u32 = 1;
@@ -30,10 +53,20 @@ const UHID_SET_REPORT_REPLY: u32 = 14;
 
 const UHID_EVENT_SIZE: usize = 4376;
 const UHID_OUTPUT_DATA_MAX: usize = 4096;
+const UHID_ERR_INVALID: u16 = 22;
 const UHID_ERR_NOT_SUPPORTED: u16 = 95;
 
+const UHID_FEATURE_REPORT: u8 = 0;
+const UHID_OUTPUT_REPORT: u8 = 1;
+const UHID_INPUT_REPORT: u8 = 2;
+
+const BUS_USB: u16 = 0x03;
 const BUS_BLUETOOTH: u16 = 0x05;
 
+/// Longest wait for BlueZ between attempts to register again.
+const BLUEZ_RETRY_INTERVAL: Duration = Duration::from_secs(5);
+const POWEROFF_COMMAND: &str = "/sbin/poweroff";
+
 fn main() -> ExitCode {
     match run() {
         Ok(()) => ExitCode::SUCCESS,
@@ -48,7 +81,8 @@ fn run() -> Result<()> {
     let mut validate_config = false;
     let mut self_test = false;
     let mut config_path = DEFAULT_HID_CONFIG_PATH.to_string();
-    let mut mapping_config_path: Option<String> = None;
+    let mut mapping_config_paths: Vec<String> = Vec::new();
+    let mut sink = None;
 
     let mut args = env::args().skip(1);
     while let Some(arg) = args.next() {
@@ -61,11 +95,19 @@ fn run() -> Result<()> {
                     .ok_or_else(|| anyhow!("missing value for --config"))?;
             }
             "--mapping-config" => {
-                mapping_config_path = Some(
+                mapping_config_paths.push(
                     args.next()
                         .ok_or_else(|| anyhow!("missing value for --mapping-config"))?,
                 );
             }
+            "--sink" => {
+                let name = args
+                    .next()
+                    .ok_or_else(|| anyhow!("missing value for --sink"))?;
+                sink = Some(OutputSink::from_name(&name).ok_or_else(|| {
+                    anyhow!("unknown sink {name}; expected bluetooth, uhid or both")
+                })?);
+            }
             "--help" | "-h" => {
                 print_help();
                 return Ok(());
@@ -76,7 +118,10 @@ fn run() -> Result<()> {
         }
     }
 
-    let cfg = HidConfig::load_from_path(&config_path)?;
+    let mut cfg = HidConfig::load_from_path(&config_path)?;
+    if let Some(sink) = sink {
+        cfg.output.sink = sink;
+    }
     if validate_config {
         println!(
             "HID config OK: name=\"{}\" profile={} vid=0x{:04x} pid=0x{:04x} version=0x{:04x} rate={}Hz",
@@ -95,9 +140,11 @@ fn run() -> Result<()> {
         return Ok(());
     }
 
-    match mapping_config_path {
-        Some(path) => run_daemon_live(&cfg, &path),
-        None => run_daemon_pattern(&cfg),
+    if mapping_config_paths.is_empty() {
+        check_pattern_sink(&cfg, sink)?;
+        run_daemon_pattern(&cfg)
+    } else {
+        run_daemon_live(&cfg, &mapping_config_paths)
     }
 }
 
@@ -105,10 +152,31 @@ fn print_help() {
     println!("Usage:");
     println!("  hidd --validate-config [--config <path>]");
     println!("  hidd --self-test [--config <path>]");
-    println!("  hidd [--config <path>] [--mapping-config <path>]");
+    println!("  hidd [--config <path>] [--sink <sink>] [--mapping-config <path>]...");
     println!("Defaults:");
     println!("  --config {}", DEFAULT_HID_CONFIG_PATH);
     println!("  --mapping-config  (none; uses synthetic pattern when omitted)");
+    println!("  --sink            ([output] sink: bluetooth, uhid or both)");
+    println!("Repeat --mapping-config to list mappings for the cycle_mapping action.");
+    println!("Pattern mode always sends to both Bluetooth and UHID.");
+}
+
+/// Pattern mode always sends to both sinks; refuse a sink it would ignore.
+/// `[output] sink = "bluetooth"` is the default and cannot be told apart.
+fn check_pattern_sink(cfg: &HidConfig, flag: Option<OutputSink>) -> Result<()> {
+    if flag.is_some() {
+        return Err(anyhow!(
+            "--sink needs --mapping-config; pattern mode sends to both Bluetooth and UHID"
+        ));
+    }
+    if cfg.output.sink != OutputSink::default() {
+        return Err(anyhow!(
+            "[output] sink = \"{}\" needs --mapping-config; pattern mode sends to both \
+             Bluetooth and UHID",
+            cfg.output.sink.as_str()
+        ));
+    }
+    Ok(())
 }
 
 fn run_self_test(cfg: &HidConfig) -> Result<()> {
@@ -119,34 +187,323 @@ fn run_self_test(cfg: &HidConfig) -> Result<()> {
     Ok(())
 }
 
-/// Production mode: read real controller input via hidraw and publish via BLE GATT HOG.
-/// UHID is not used — BLE is the only output path.
-fn run_daemon_live(cfg: &HidConfig, mapping_config_path: &str) -> Result<()> {
-    let mapping = input::MappingConfig::from_file(mapping_config_path)
+/// Production mode: read real controller input via hidraw and publish to the
+/// `[output] sink`: Bluetooth (HOGP or BR/EDR), a local UHID device, or both.
+fn run_daemon_live(cfg: &HidConfig, mapping_config_paths: &[String]) -> Result<()> {
+    let mapping = input::MappingConfig::from_file(&mapping_config_paths[0])
         .map_err(|e| anyhow!("mapping config: {e}"))?;
-    let reader = input::InputReader::new(mapping).map_err(|e| anyhow!("{e}"))?;
-
-    let hog = HogRuntime::register(cfg)?;
+    let mut reader = input::InputReader::new(mapping).map_err(|e| anyhow!("{e}"))?;
+    let mut mapping_index = 0;
+    let mut idle = IdleMonitor::new(cfg.power.idle_timeout_min, Instant::now());
+    let control = ControlState::shared();
+    let mut cfg = cfg.clone();
 
     println!(
-        "hidd started: name=\"{}\" profile={} vid=0x{:04x} pid=0x{:04x} version=0x{:04x} rate={}Hz mapping={}",
+        "hidd started: name=\"{}\" profile={} vid=0x{:04x} pid=0x{:04x} version=0x{:04x} rate={}Hz mapping={} sink={}",
         cfg.device.name,
         cfg.profile.mode.as_str(),
         cfg.profile.vendor_id,
         cfg.profile.product_id,
         cfg.profile.version,
         cfg.report.rate_hz,
-        mapping_config_path,
+        mapping_config_paths[0],
+        cfg.output.sink.as_str(),
     );
-    println!("hidd BLE HOGP registered: adapter={}", hog.adapter_path());
+
+    let sink = cfg.output.sink;
+    let mut uhid = if sink.uses_uhid() {
+        Some(UhidLoopback::create(&cfg)?)
+    } else {
+        None
+    };
+    let mut link = if sink.uses_bluetooth() {
+        Some(BluetoothLink::register(&cfg, Arc::clone(&control))?)
+    } else {
+        None
+    };
+    let mut net = NetStream::open(&cfg.network);
+    if let Some(receiver) = &cfg.network.receiver {
+        println!(
+            "hidd streaming to {receiver} over {}",
+            cfg.network.transport.as_str()
+        );
+    }
+    loop {
+        if let Some(link) = &link {
+            println!(
+                "hidd {} registered: adapter={}",
+                link.description(),
+                link.adapter_path()
+            );
+        }
+        let mut outputs = Outputs {
+            link: link.as_ref(),
+            uhid: uhid.as_mut(),
+            net: net.as_mut(),
+        };
+        let mut source = ReportSource::Live {
+            reader: &mut reader,
+            idle: &mut idle,
+            mapping_config_paths,
+            mapping_index: &mut mapping_index,
+        };
+        let end = run_session(&cfg, &mut outputs, &mut source)?;
+        // The next registration needs the adapter powered.
+        if idle.is_idle() {
+            if let Err(e) = outputs.leave_idle() {
+                eprintln!("hidd: cannot wake Bluetooth: {e}");
+            }
+            idle.wake(&reader.current_report(), Instant::now());
+        }
+        drop(link.take());
+        if let SessionEnd::SwitchProfile(mode) = end {
+            cfg.profile = cfg.profile.with_mode(mode);
+            println!("hidd profile switched: {}", mode.as_str());
+            if uhid.is_some() {
+                drop(uhid);
+                uhid = Some(UhidLoopback::create(&cfg)?);
+            }
+        }
+        if sink.uses_bluetooth() {
+            link = Some(register_again(&cfg, &control, Some(&reader)));
+        }
+    }
+}
+
+/// Why a session with the Bluetooth link ended.
+enum SessionEnd {
+    /// A control client selected another profile.
+    SwitchProfile(HidProfileMode),
+    /// bluetoothd restarted or the adapter went away.
+    BluezLost,
+}
+
+/// Register the link after a session ended, waiting for BlueZ until it
+/// succeeds. The controller keeps being read meanwhile, and holding power
+/// still powers off.
+fn register_again(
+    cfg: &HidConfig,
+    control: &SharedControl,
+    reader: Option<&input::InputReader>,
+) -> BluetoothLink {
+    loop {
+        let actions = reader.map(input::InputReader::take_actions);
+        for action in actions.unwrap_or_default() {
+            match action {
+                input::KeyAction::Poweroff => power_off(),
+                other => eprintln!("hidd: {other:?} ignored while BlueZ is away"),
+            }
+        }
+        match BluetoothLink::register(cfg, Arc::clone(control)) {
+            Ok(link) => return link,
+            Err(e) => eprintln!("hidd: cannot register with BlueZ: {e}; waiting"),
+        }
+        if let Err(e) = hog::wait_for_bluez(BLUEZ_RETRY_INTERVAL) {
+            eprintln!("hidd: {e}");
+            thread::sleep(BLUEZ_RETRY_INTERVAL);
+        }
+    }
+}
+
+/// Run `POWEROFF_COMMAND` for the power key's long press.
+fn power_off() {
+    eprintln!("hidd: powering off");
+    if let Err(e) = std::process::Command::new(POWEROFF_COMMAND).spawn() {
+        eprintln!("hidd: failed to run {POWEROFF_COMMAND}: {e}");
+    }
+}
+
+/// Where a session's input reports come from.
+enum ReportSource<'a> {
+    /// The Deck's controller, read through the active mapping.
+    Live {
+        reader: &'a mut input::InputReader,
+        idle: &'a mut IdleMonitor,
+        mapping_config_paths: &'a [String],
+        mapping_index: &'a mut usize,
+    },
+    /// A synthetic test pattern.
+    Pattern(PatternState),
+}
+
+impl ReportSource<'_> {
+    /// Status fields set once per session.
+    fn describe(&self, status: &mut DaemonStatus) {
+        match self {
+            Self::Live {
+                mapping_config_paths,
+                mapping_index,
+                ..
+            } => {
+                status.mapping = mapping_config_paths[**mapping_index].clone();
+                status.mappings = mapping_config_paths.to_vec();
+            }
+            Self::Pattern(_) => status.input_health = "pattern",
+        }
+    }
+
+    /// Status fields that change from tick to tick.
+    fn update_status(&self, status: &mut DaemonStatus) {
+        if let Self::Live {
+            reader,
+            idle,
+            mapping_config_paths,
+            mapping_index,
+        } = self
+        {
+            status.mapping = mapping_config_paths[**mapping_index].clone();
+            status.input_health = reader.health().as_str();
+            status.idle = idle.is_idle();
+        }
+    }
+
+    /// Follow the host's suspend state and run the actions bound to keys.
+    fn poll_keys(&mut self, cfg: &HidConfig, outputs: &Outputs) -> Result<()> {
+        let Self::Live {
+            reader,
+            mapping_config_paths,
+            mapping_index,
+            ..
+        } = self
+        else {
+            return Ok(());
+        };
+        let suspended = cfg.power.slow_input_on_suspend && outputs.host_suspended()?;
+        // Not while idle: the Steam button has to wake hidd promptly.
+        reader.set_low_power(suspended);
+        for action in reader.take_actions() {
+            match action {
+                input::KeyAction::CycleMapping => {
+                    let next = (**mapping_index + 1) % mapping_config_paths.len();
+                    if next == **mapping_index {
+                        eprintln!("hidd: cycle_mapping ignored; only one --mapping-config given");
+                    } else {
+                        **mapping_index =
+                            select_mapping(reader, mapping_config_paths, **mapping_index, next);
+                    }
+                }
+                input::KeyAction::NextHost => match outputs.hog() {
+                    Some(hog) => hog.queue_host_event(HostEvent::Next)?,
+                    None => eprintln!("hidd: next_host ignored; host switching needs HOGP"),
+                },
+                input::KeyAction::PairingMode => outputs.start_pairing()?,
+                input::KeyAction::Poweroff => power_off(),
+            }
+        }
+        Ok(())
+    }
+
+    fn select_mapping(&mut self, index: usize) {
+        match self {
+            Self::Live {
+                reader,
+                mapping_config_paths,
+                mapping_index,
+                ..
+            } => {
+                **mapping_index =
+                    select_mapping(reader, mapping_config_paths, **mapping_index, index);
+            }
+            // No mappings in pattern mode, so SelectMapping never queues one.
+            Self::Pattern(_) => {}
+        }
+    }
+
+    /// This tick's report. Live input also drives the idle policy.
+    fn next_report(&mut self, cfg: &HidConfig, outputs: &Outputs) -> Result<InputReport> {
+        match self {
+            Self::Live { reader, idle, .. } => {
+                let report = reader.current_report();
+                match idle.poll(&report, reader.take_steam_press(), Instant::now()) {
+                    Some(IdleChange::Idle) => enter_idle(cfg, outputs, reader, idle)?,
+                    Some(IdleChange::Wake) => {
+                        eprintln!("hidd: woke up, Steam button pressed");
+                        outputs.leave_idle()?;
+                    }
+                    None => {}
+                }
+                Ok(report)
+            }
+            Self::Pattern(pattern) => Ok(pattern.next_report()),
+        }
+    }
+}
+
+/// Publish `source` to `outputs` until a control client selects another
+/// profile or BlueZ goes away.
+fn run_session(
+    cfg: &HidConfig,
+    outputs: &mut Outputs,
+    source: &mut ReportSource,
+) -> Result<SessionEnd> {
+    outputs.publish_status(|status| {
+        status.profile = cfg.profile.mode.as_str();
+        status.report_rate_hz = cfg.report.rate_hz;
+        source.describe(status);
+    })?;
+    if let ReportSource::Live { reader, .. } = source {
+        reader.set_low_power(false);
+    }
 
     let period = Duration::from_nanos(1_000_000_000u64 / u64::from(cfg.report.rate_hz));
+    let mut session = cfg
+        .profile
+        .hid_profile()
+        .new_session(outputs.adapter_address());
+    outputs.set_feature_reports(&session)?;
+    let mut session_host = None;
+    let mut battery = BatteryMonitor::new(POWER_SUPPLY_ROOT);
+    let mut hosts = HostManager::new(DEFAULT_HOST_STATE_PATH);
     let mut next_tick = Instant::now();
 
     loop {
-        let report = reader.current_report();
-        let report_bytes = report.to_bytes();
-        hog.publish_input_report(&report_bytes)?;
+        if outputs.bluez_lost() {
+            eprintln!("hidd: BlueZ went away; registering again");
+            return Ok(SessionEnd::BluezLost);
+        }
+        if let Some(level) = battery.poll(Instant::now()) {
+            outputs.set_battery_level(level)?;
+            if let Some(status) = session.battery_report(level) {
+                outputs.publish_input_report(&status)?;
+            }
+        }
+        source.poll_keys(cfg, outputs)?;
+        for request in outputs.take_control_requests()? {
+            match request {
+                ControlRequest::SelectMapping(index) => source.select_mapping(index),
+                ControlRequest::SelectProfile(mode) if mode == cfg.profile.mode => {}
+                ControlRequest::SelectProfile(mode) => return Ok(SessionEnd::SwitchProfile(mode)),
+                ControlRequest::Disconnect => outputs.disconnect_hosts()?,
+            }
+        }
+        if let Some(Err(e)) = outputs.hog().map(|hog| hosts.poll(hog)) {
+            eprintln!("hidd: host management: {e}");
+        }
+        // A new host, or none, starts the profile handshake from scratch.
+        let host = outputs.current_host()?;
+        if host != session_host {
+            session = cfg
+                .profile
+                .hid_profile()
+                .new_session(outputs.adapter_address());
+            outputs.set_feature_reports(&session)?;
+            if let Some(status) = battery.level().and_then(|l| session.battery_report(l)) {
+                outputs.publish_input_report(&status)?;
+            }
+            session_host = host;
+        }
+        let report = source.next_report(cfg, outputs)?;
+        outputs.publish_status(|status| source.update_status(status))?;
+
+        for report_bytes in session.input_reports(&report) {
+            outputs.publish_input_report(&report_bytes)?;
+        }
+        outputs.stream_report(&report);
+        for output in outputs.take_output_reports()? {
+            if let Some(reply) = session.handle_output_report(&output, &report) {
+                outputs.publish_input_report(&reply)?;
+            }
+        }
         next_tick += period;
 
         let now = Instant::now();
@@ -158,13 +515,339 @@ fn run_daemon_live(cfg: &HidConfig, mapping_config_path: &str) -> Result<()> {
     }
 }
 
-/// Pattern mode: generate synthetic test patterns and publish via both UHID and BLE.
+/// Put Bluetooth to sleep after `[power] idle_timeout_min` without input, and
+/// suspend the Deck if configured. A Deck woken from suspend wakes hidd too.
+fn enter_idle(
+    cfg: &HidConfig,
+    outputs: &Outputs,
+    reader: &input::InputReader,
+    idle: &mut IdleMonitor,
+) -> Result<()> {
+    let action = cfg.power.idle_action;
+    eprintln!(
+        "hidd: idle, no input for {} min, action={}",
+        cfg.power.idle_timeout_min,
+        action.as_str()
+    );
+    outputs.enter_idle(action)?;
+    if !cfg.power.idle_suspend {
+        return Ok(());
+    }
+    eprintln!("hidd: idle, suspending the Deck");
+    match idle::suspend_system() {
+        Ok(()) => {
+            eprintln!("hidd: woke up, Deck resumed");
+            outputs.leave_idle()?;
+            idle.wake(&reader.current_report(), Instant::now());
+        }
+        Err(e) => eprintln!("hidd: cannot suspend: {e}"),
+    }
+    Ok(())
+}
+
+/// Device Information strings from the config, with the serial read from the
+/// Deck controller when `device.serial_from_controller` is set. The configured
+/// serial is kept if the controller cannot be read.
+fn device_info(cfg: &HidConfig) -> DeviceInfo {
+    let mut info = cfg.device_info();
+    if cfg.device.serial_from_controller {
+        match input::read_deck_serial() {
+            Ok(serial) => info.serial = Some(serial),
+            Err(e) => eprintln!("hidd: cannot read controller serial: {e}"),
+        }
+    }
+    info
+}
+
+/// Bluetooth transport selected by `[bluetooth] transport`.
+enum BluetoothLink {
+    Hogp(HogRuntime),
+    Bredr(BredrRuntime),
+}
+
+impl BluetoothLink {
+    fn register(cfg: &HidConfig, control: SharedControl) -> Result<Self> {
+        Ok(match cfg.bluetooth.transport {
+            BluetoothTransport::Hogp => {
+                Self::Hogp(HogRuntime::register(cfg, &device_info(cfg), control)?)
+            }
+            BluetoothTransport::Bredr => Self::Bredr(BredrRuntime::register(cfg, control)?),
+        })
+    }
+
+    fn description(&self) -> &'static str {
+        match self {
+            Self::Hogp(_) => "BLE HOGP",
+            Self::Bredr(_) => "BR/EDR HID",
+        }
+    }
+
+    /// The HOGP runtime, which also owns host switching.
+    fn hog(&self) -> Option<&HogRuntime> {
+        match self {
+            Self::Hogp(hog) => Some(hog),
+            Self::Bredr(_) => None,
+        }
+    }
+
+    fn adapter_path(&self) -> &Path<'static> {
+        match self {
+            Self::Hogp(hog) => hog.adapter_path(),
+            Self::Bredr(bredr) => bredr.adapter_path(),
+        }
+    }
+
+    fn adapter_address(&self) -> [u8; 6] {
+        match self {
+            Self::Hogp(hog) => hog.adapter_address(),
+            Self::Bredr(bredr) => bredr.adapter_address(),
+        }
+    }
+
+    fn publish_input_report(&self, report: &[u8]) -> Result<()> {
+        match self {
+            Self::Hogp(hog) => hog.publish_input_report(report),
+            Self::Bredr(bredr) => bredr.publish_input_report(report),
+        }
+    }
+
+    fn take_output_reports(&self) -> Result<Vec<Vec<u8>>> {
+        match self {
+            Self::Hogp(hog) => hog.take_output_reports(),
+            Self::Bredr(bredr) => bredr.take_output_reports(),
+        }
+    }
+
+    fn set_feature_report(&self, report: &[u8]) -> Result<()> {
+        match self {
+            Self::Hogp(hog) => hog.set_feature_report(report),
+            Self::Bredr(bredr) => bredr.set_feature_report(report),
+        }
+    }
+
+    /// Open the pairing window for the configured `[pairing]` timeout.
+    fn start_pairing(&self) -> Result<()> {
+        match self {
+            Self::Hogp(hog) => hog.start_pairing(None),
+            Self::Bredr(bredr) => bredr.start_pairing(None),
+        }
+    }
+
+    /// The host suspended HID input; reports are kept but not sent.
+    fn host_suspended(&self) -> Result<bool> {
+        match self {
+            Self::Hogp(hog) => hog.host_suspended(),
+            Self::Bredr(bredr) => bredr.host_suspended(),
+        }
+    }
+
+    fn current_host(&self) -> Result<Option<String>> {
+        match self {
+            Self::Hogp(hog) => hog.current_host(),
+            Self::Bredr(bredr) => bredr.current_host(),
+        }
+    }
+
+    fn enter_idle(&self, action: IdleAction) -> Result<()> {
+        match self {
+            Self::Hogp(hog) => hog.enter_idle(action),
+            Self::Bredr(bredr) => bredr.enter_idle(action),
+        }
+    }
+
+    fn leave_idle(&self) -> Result<()> {
+        match self {
+            Self::Hogp(hog) => hog.leave_idle(),
+            Self::Bredr(bredr) => bredr.leave_idle(),
+        }
+    }
+
+    fn bluez_lost(&self) -> bool {
+        match self {
+            Self::Hogp(hog) => hog.bluez_lost(),
+            Self::Bredr(bredr) => bredr.bluez_lost(),
+        }
+    }
+
+    fn publish_status(&self, update: impl FnOnce(&mut DaemonStatus)) -> Result<()> {
+        match self {
+            Self::Hogp(hog) => hog.publish_status(update),
+            Self::Bredr(bredr) => bredr.publish_status(update),
+        }
+    }
+
+    fn take_control_requests(&self) -> Result<Vec<ControlRequest>> {
+        match self {
+            Self::Hogp(hog) => hog.take_control_requests(),
+            Self::Bredr(bredr) => bredr.take_control_requests(),
+        }
+    }
+
+    fn disconnect_hosts(&self) -> Result<()> {
+        match self {
+            Self::Hogp(hog) => hog.disconnect_hosts(),
+            Self::Bredr(bredr) => bredr.disconnect_host(),
+        }
+    }
+
+    /// Battery Service level; BR/EDR hosts only see the profile's status report.
+    fn set_battery_level(&self, level: u8) -> Result<()> {
+        match self {
+            Self::Hogp(hog) => hog.set_battery_level(level),
+            Self::Bredr(_) => Ok(()),
+        }
+    }
+}
+
+/// Outputs selected by `[output] sink`, plus the `[network]` stream. Pattern
+/// mode always has Bluetooth and UHID and no stream. Without Bluetooth there
+/// are no hosts, no control interface and no BlueZ to lose.
+struct Outputs<'a> {
+    link: Option<&'a BluetoothLink>,
+    uhid: Option<&'a mut UhidLoopback>,
+    net: Option<&'a mut NetStream>,
+}
+
+impl Outputs<'_> {
+    fn hog(&self) -> Option<&HogRuntime> {
+        self.link.and_then(BluetoothLink::hog)
+    }
+
+    /// The adapter address, or zeros without Bluetooth.
+    fn adapter_address(&self) -> [u8; 6] {
+        self.link.map_or([0; 6], BluetoothLink::adapter_address)
+    }
+
+    fn publish_input_report(&mut self, report: &[u8]) -> Result<()> {
+        if let Some(uhid) = self.uhid.as_mut() {
+            uhid.device.send_input_report(report)?;
+        }
+        match self.link {
+            Some(link) => link.publish_input_report(report),
+            None => Ok(()),
+        }
+    }
+
+    /// Give every sink the session's feature reports for hosts to read.
+    fn set_feature_reports(&mut self, session: &ProfileSession) -> Result<()> {
+        for report in session.feature_reports() {
+            if let Some(uhid) = self.uhid.as_mut() {
+                uhid.device.set_feature_report(&report)?;
+            }
+            if let Some(link) = self.link {
+                link.set_feature_report(&report)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Send the controller state to the `[network]` receiver. The rumble it
+    /// sends back is only logged; the Deck's haptics are not driven.
+    fn stream_report(&mut self, report: &InputReport) {
+        let Some(net) = self.net.as_mut() else {
+            return;
+        };
+        net.send_report(report);
+        for rumble in net.take_rumble() {
+            eprintln!(
+                "hidd: network rumble={{lt:{}, rt:{}, weak:{}, strong:{}}}",
+                rumble.left_trigger_magnitude,
+                rumble.right_trigger_magnitude,
+                rumble.weak_motor_magnitude,
+                rumble.strong_motor_magnitude
+            );
+        }
+    }
+
+    /// Output reports from every sink, UHID first.
+    fn take_output_reports(&self) -> Result<Vec<Vec<u8>>> {
+        let mut outputs: Vec<Vec<u8>> = self
+            .uhid
+            .as_ref()
+            .map(|uhid| uhid.outputs.try_iter().collect())
+            .unwrap_or_default();
+        if let Some(link) = self.link {
+            outputs.extend(link.take_output_reports()?);
+        }
+        Ok(outputs)
+    }
+
+    fn start_pairing(&self) -> Result<()> {
+        match self.link {
+            Some(link) => link.start_pairing(),
+            None => {
+                eprintln!("hidd: pairing_mode ignored; no Bluetooth sink");
+                Ok(())
+            }
+        }
+    }
+
+    fn host_suspended(&self) -> Result<bool> {
+        self.link.map_or(Ok(false), BluetoothLink::host_suspended)
+    }
+
+    fn current_host(&self) -> Result<Option<String>> {
+        self.link.map_or(Ok(None), BluetoothLink::current_host)
+    }
+
+    fn enter_idle(&self, action: IdleAction) -> Result<()> {
+        self.link.map_or(Ok(()), |link| link.enter_idle(action))
+    }
+
+    fn leave_idle(&self) -> Result<()> {
+        self.link.map_or(Ok(()), BluetoothLink::leave_idle)
+    }
+
+    fn bluez_lost(&self) -> bool {
+        self.link.is_some_and(BluetoothLink::bluez_lost)
+    }
+
+    fn publish_status(&self, update: impl FnOnce(&mut DaemonStatus)) -> Result<()> {
+        self.link.map_or(Ok(()), |link| link.publish_status(update))
+    }
+
+    fn take_control_requests(&self) -> Result<Vec<ControlRequest>> {
+        self.link
+            .map_or(Ok(Vec::new()), BluetoothLink::take_control_requests)
+    }
+
+    fn disconnect_hosts(&self) -> Result<()> {
+        self.link.map_or(Ok(()), BluetoothLink::disconnect_hosts)
+    }
+
+    fn set_battery_level(&self, level: u8) -> Result<()> {
+        self.link
+            .map_or(Ok(()), |link| link.set_battery_level(level))
+    }
+}
+
+/// Switch to mapping `next` in `paths`, returning the new active index.
+/// If the mapping fails to load, the current one stays active.
+fn select_mapping(
+    reader: &mut input::InputReader,
+    paths: &[String],
+    current: usize,
+    next: usize,
+) -> usize {
+    let result = input::MappingConfig::from_file(&paths[next])
+        .and_then(|mapping| reader.set_mapping(mapping));
+    match result {
+        Ok(()) => {
+            println!("hidd mapping switched: {}", paths[next]);
+            next
+        }
+        Err(e) => {
+            eprintln!("hidd: cannot switch to mapping {}: {e}", paths[next]);
+            current
+        }
+    }
+}
+
+/// Pattern mode: generate synthetic test patterns and publish via both UHID and Bluetooth.
 /// Used when no --mapping-config is provided (backwards compatible with checkpoint 03).
 fn run_daemon_pattern(cfg: &HidConfig) -> Result<()> {
-    let mut uhid = UhidDevice::open()?;
-    uhid.create(cfg)?;
-    uhid.start_event_drain()?;
-    let hog = HogRuntime::register(cfg)?;
+    let control = ControlState::shared();
+    let mut cfg = cfg.clone();
 
     println!(
         "hidd started: name=\"{}\" profile={} vid=0x{:04x} pid=0x{:04x} version=0x{:04x} rate={}Hz pattern={}",
@@ -179,31 +862,122 @@ fn run_daemon_pattern(cfg: &HidConfig) -> Result<()> {
             PatternConfig::AxisSweep { axis, .. } => format!("axis_sweep({axis:?})"),
         },
     );
-    println!("hidd BLE HOGP registered: adapter={}", hog.adapter_path());
-
-    let period = Duration::from_nanos(1_000_000_000u64 / u64::from(cfg.report.rate_hz));
-    let mut pattern = PatternState::new(&cfg.pattern);
-    let mut next_tick = Instant::now();
 
+    let mut uhid = UhidLoopback::create(&cfg)?;
+    let mut link = BluetoothLink::register(&cfg, Arc::clone(&control))?;
     loop {
-        let report = pattern.next_report();
-        let report_bytes = report.to_bytes();
-        uhid.send_input_report(&report_bytes)?;
-        hog.publish_input_report(&report_bytes)?;
-        next_tick += period;
-
-        let now = Instant::now();
-        if next_tick > now {
-            thread::sleep(next_tick - now);
-        } else {
-            next_tick = now;
+        println!(
+            "hidd {} registered: adapter={}",
+            link.description(),
+            link.adapter_path()
+        );
+        let mut outputs = Outputs {
+            link: Some(&link),
+            uhid: Some(&mut uhid),
+            net: None,
+        };
+        let mut source = ReportSource::Pattern(PatternState::new(&cfg.pattern));
+        let end = run_session(&cfg, &mut outputs, &mut source)?;
+        drop(link);
+        if let SessionEnd::SwitchProfile(mode) = end {
+            drop(uhid);
+            cfg.profile = cfg.profile.with_mode(mode);
+            println!("hidd profile switched: {}", mode.as_str());
+            uhid = UhidLoopback::create(&cfg)?;
         }
+        link = register_again(&cfg, &control, None);
     }
 }
 
 struct UhidDevice {
     file: std::fs::File,
     created: bool,
+    /// Shared with the event thread, which answers UHID_GET_REPORT from it.
+    reports: Arc<Mutex<UhidReports>>,
+}
+
+/// Last value of each report, without the report ID: input reports as sent,
+/// output and feature reports as the kernel wrote them. Feature reports start
+/// with the profile's own values and the rest are zeroed until set.
+#[derive(Debug, Default)]
+struct UhidReports {
+    input: HashMap<u8, Vec<u8>>,
+    output: HashMap<u8, Vec<u8>>,
+    feature: HashMap<u8, Vec<u8>>,
+}
+
+impl UhidReports {
+    fn new(profile: &HidProfile) -> Self {
+        let mut reports = Self::default();
+        for report in profile.reports() {
+            let slots = reports.slots_mut(report.report_type);
+            slots.insert(report.id, vec![0; report.payload_len]);
+        }
+        // The kernel driver may read them while the device is probed, before
+        // the session knows the adapter address.
+        for feature in profile.new_session([0; 6]).feature_reports() {
+            let _ = reports.set(ReportType::Feature, &feature);
+        }
+        reports
+    }
+
+    fn slots_mut(&mut self, report_type: ReportType) -> &mut HashMap<u8, Vec<u8>> {
+        match report_type {
+            ReportType::Input => &mut self.input,
+            ReportType::Output => &mut self.output,
+            ReportType::Feature => &mut self.feature,
+        }
+    }
+
+    /// The report with ID `report_id`, report ID included.
+    fn get(&mut self, report_type: ReportType, report_id: u8) -> Option<Vec<u8>> {
+        let payload = self.slots_mut(report_type).get(&report_id)?;
+        let mut report = Vec::with_capacity(payload.len() + 1);
+        report.push(report_id);
+        report.extend_from_slice(payload);
+        Some(report)
+    }
+
+    /// Store a report that starts with its report ID. Short reports are
+    /// zero-padded; unknown IDs and overlong reports are refused.
+    fn set(&mut self, report_type: ReportType, report: &[u8]) -> Result<(), u16> {
+        let (&report_id, payload) = report.split_first().ok_or(UHID_ERR_INVALID)?;
+        let slot = self
+            .slots_mut(report_type)
+            .get_mut(&report_id)
+            .ok_or(UHID_ERR_INVALID)?;
+        if payload.len() > slot.len() {
+            return Err(UHID_ERR_INVALID);
+        }
+        slot[..payload.len()].copy_from_slice(payload);
+        slot[payload.len()..].fill(0);
+        Ok(())
+    }
+}
+
+fn uhid_report_type(rtype: u8) -> Option<ReportType> {
+    match rtype {
+        UHID_FEATURE_REPORT => Some(ReportType::Feature),
+        UHID_OUTPUT_REPORT => Some(ReportType::Output),
+        UHID_INPUT_REPORT => Some(ReportType::Input),
+        _ => None,
+    }
+}
+
+/// A UHID device created from the config, with the output reports the
+/// kernel sends it.
+struct UhidLoopback {
+    device: UhidDevice,
+    outputs: Receiver<Vec<u8>>,
+}
+
+impl UhidLoopback {
+    fn create(cfg: &HidConfig) -> Result<Self> {
+        let mut device = UhidDevice::open()?;
+        device.create(cfg)?;
+        let outputs = device.start_event_drain(cfg.profile.hid_profile())?;
+        Ok(Self { device, outputs })
+    }
 }
 
 impl UhidDevice {
@@ -216,6 +990,7 @@ impl UhidDevice {
         Ok(Self {
             file,
             created: false,
+            reports: Arc::default(),
         })
     }
 
@@ -225,21 +1000,27 @@ impl UhidDevice {
             .write_all(&event)
             .map_err(|e| anyhow!("failed to write UHID_CREATE2 event: {e}"))?;
         self.created = true;
+        self.reports = Arc::new(Mutex::new(UhidReports::new(&cfg.profile.hid_profile())));
         Ok(())
     }
 
-    fn start_event_drain(&self) -> Result<()> {
+    /// Spawn the UHID event thread. Output reports from the kernel, whether
+    /// sent as UHID_OUTPUT or UHID_SET_REPORT, are forwarded on the returned
+    /// channel, report ID included.
+    fn start_event_drain(&self, profile: HidProfile) -> Result<Receiver<Vec<u8>>> {
         let mut io = self
             .file
             .try_clone()
             .map_err(|e| anyhow!("failed to clone UHID fd for event drain: {e}"))?;
+        let (outputs_tx, outputs_rx) = mpsc::channel();
+        let reports = Arc::clone(&self.reports);
 
         thread::Builder::new()
             .name("hidd-uhid-events".to_string())
-            .spawn(move || drain_uhid_events(&mut io))
+            .spawn(move || drain_uhid_events(&mut io, &profile, &reports, &outputs_tx))
             .map_err(|e| anyhow!("failed to spawn UHID event drain thread: {e}"))?;
 
-        Ok(())
+        Ok(outputs_rx)
     }
 
     fn send_input_report(&mut self, report: &[u8]) -> Result<()> {
@@ -250,6 +1031,9 @@ impl UhidDevice {
             ));
         }
 
+        // GET_REPORT reads it back; reports the profile lacks are still sent.
+        let _ = lock_uhid_reports(&self.reports)?.set(ReportType::Input, report);
+
         let mut event = Vec::with_capacity(6 + report.len());
         event.extend_from_slice(&UHID_INPUT2.to_ne_bytes());
         event.extend_from_slice(&(report.len() as u16).to_ne_bytes());
@@ -261,6 +1045,12 @@ impl UhidDevice {
         Ok(())
     }
 
+    fn set_feature_report(&mut self, report: &[u8]) -> Result<()> {
+        lock_uhid_reports(&self.reports)?
+            .set(ReportType::Feature, report)
+            .map_err(|err| anyhow!("failed to store UHID feature report ({err})"))
+    }
+
     fn destroy(&mut self) -> Result<()> {
         if !self.created {
             return Ok(());
@@ -299,7 +1089,8 @@ fn build_create2_event(cfg: &HidConfig) -> Vec<u8> {
     const OFF_COUNTRY: usize = 272;
     const OFF_RD_DATA: usize = 276;
 
-    let descriptor = cfg.profile.mode.report_descriptor();
+    let profile = cfg.profile.hid_profile();
+    let descriptor = profile.report_descriptor();
 
     let mut payload = vec![0u8; CREATE2_PAYLOAD_LEN];
     write_padded(&mut payload[OFF_NAME..OFF_PHYS], &cfg.device.name);
@@ -310,7 +1101,12 @@ fn build_create2_event(cfg: &HidConfig) -> Vec<u8> {
     );
 
     payload[OFF_RD_SIZE..OFF_RD_SIZE + 2].copy_from_slice(&(descriptor.len() as u16).to_ne_bytes());
-    payload[OFF_BUS..OFF_BUS + 2].copy_from_slice(&BUS_BLUETOOTH.to_ne_bytes());
+    // hid-playstation only accepts the DS4's USB reports from a USB device.
+    let bus = match profile.mode() {
+        HidProfileMode::DualShock4 => BUS_USB,
+        _ => BUS_BLUETOOTH,
+    };
+    payload[OFF_BUS..OFF_BUS + 2].copy_from_slice(&bus.to_ne_bytes());
     payload[OFF_VENDOR..OFF_VENDOR + 4]
         .copy_from_slice(&(u32::from(cfg.profile.vendor_id)).to_ne_bytes());
     payload[OFF_PRODUCT..OFF_PRODUCT + 4]
@@ -327,14 +1123,27 @@ fn build_create2_event(cfg: &HidConfig) -> Vec<u8> {
     event
 }
 
-fn drain_uhid_events(io: &mut std::fs::File) {
+fn drain_uhid_events(
+    io: &mut std::fs::File,
+    profile: &HidProfile,
+    reports: &Mutex<UhidReports>,
+    outputs: &Sender<Vec<u8>>,
+) {
     let mut event = [0u8; UHID_EVENT_SIZE];
-    let mut dropped_output_reports = 0u64;
+    let mut output_report_count = 0u64;
 
     loop {
         match io.read_exact(&mut event) {
             Ok(()) => {
-                if let Err(err) = handle_uhid_event(io, &event, &mut dropped_output_reports) {
+                let handled = handle_uhid_event(
+                    io,
+                    profile,
+                    reports,
+                    &event,
+                    outputs,
+                    &mut output_report_count,
+                );
+                if let Err(err) = handled {
                     eprintln!("hidd: failed to handle UHID event: {err}");
                 }
             }
@@ -349,8 +1158,11 @@ fn drain_uhid_events(io: &mut std::fs::File) {
 
 fn handle_uhid_event(
     io: &mut std::fs::File,
+    profile: &HidProfile,
+    reports: &Mutex<UhidReports>,
     event: &[u8],
-    dropped_output_reports: &mut u64,
+    outputs: &Sender<Vec<u8>>,
+    output_report_count: &mut u64,
 ) -> Result<()> {
     let event_type = read_u32(event, 0).ok_or_else(|| anyhow!("short UHID event"))?;
 
@@ -379,23 +1191,26 @@ fn handle_uhid_event(
                 .copied()
                 .unwrap_or(0);
             let data = &event[4..4 + size];
+            let _ = lock_uhid_reports(reports)?.set(ReportType::Output, data);
+            // The main loop may have exited; nothing to forward to then.
+            let _ = outputs.send(data.to_vec());
 
-            *dropped_output_reports += 1;
-            if *dropped_output_reports <= 3 || *dropped_output_reports % 100 == 0 {
-                if let Some(parsed) = OutputReport::parse(data) {
+            *output_report_count += 1;
+            if *output_report_count <= 3 || *output_report_count % 100 == 0 {
+                if let Some(parsed) = profile.parse_output_report(data) {
                     eprintln!(
-                        "hidd: dropped UHID_OUTPUT rtype={rtype} rumble={{lt:{}, rt:{}, weak:{}, strong:{}}} count={}",
+                        "hidd: UHID_OUTPUT rtype={rtype} rumble={{lt:{}, rt:{}, weak:{}, strong:{}}} count={}",
                         parsed.left_trigger_magnitude,
                         parsed.right_trigger_magnitude,
                         parsed.weak_motor_magnitude,
                         parsed.strong_motor_magnitude,
-                        *dropped_output_reports
+                        *output_report_count
                     );
                 } else {
                     let report_id = data.first().copied().unwrap_or(0);
                     eprintln!(
-                        "hidd: dropped UHID_OUTPUT rtype={rtype} report_id=0x{report_id:02x} size={size} count={}",
-                        *dropped_output_reports
+                        "hidd: UHID_OUTPUT rtype={rtype} report_id=0x{report_id:02x} size={size} count={}",
+                        *output_report_count
                     );
                 }
             }
@@ -404,21 +1219,46 @@ fn handle_uhid_event(
             let id = read_u32(event, 4).unwrap_or(0);
             let rnum = event.get(8).copied().unwrap_or(0);
             let rtype = event.get(9).copied().unwrap_or(0);
-            eprintln!(
-                "hidd: UHID_GET_REPORT id={id} rnum={rnum} rtype={rtype}; replying not supported"
-            );
-            write_get_report_reply(io, id, UHID_ERR_NOT_SUPPORTED, &[])?;
+            let Some(report_type) = uhid_report_type(rtype) else {
+                eprintln!("hidd: UHID_GET_REPORT id={id} rtype={rtype}; unknown report type");
+                return write_get_report_reply(io, id, UHID_ERR_NOT_SUPPORTED, &[]);
+            };
+            match lock_uhid_reports(reports)?.get(report_type, rnum) {
+                Some(report) => write_get_report_reply(io, id, 0, &report)?,
+                None => {
+                    eprintln!(
+                        "hidd: UHID_GET_REPORT id={id} unknown {} report 0x{rnum:02x}",
+                        report_type.as_str()
+                    );
+                    write_get_report_reply(io, id, UHID_ERR_INVALID, &[])?;
+                }
+            }
         }
         UHID_SET_REPORT => {
             let id = read_u32(event, 4).unwrap_or(0);
             let rnum = event.get(8).copied().unwrap_or(0);
             let rtype = event.get(9).copied().unwrap_or(0);
-            let size = read_u16(event, 10).map(usize::from).unwrap_or(0);
-            eprintln!(
-                "hidd: UHID_SET_REPORT id={id} rnum={rnum} rtype={rtype} size={size}; dropping payload"
-            );
-            // Accept writes but ignore payload in MVP (no haptics implementation).
-            write_set_report_reply(io, id, 0)?;
+            let size = read_u16(event, 10)
+                .map(usize::from)
+                .unwrap_or(0)
+                .min(UHID_OUTPUT_DATA_MAX);
+            let data = &event[12..12 + size];
+            let result = match uhid_report_type(rtype) {
+                Some(report_type @ (ReportType::Output | ReportType::Feature)) => {
+                    lock_uhid_reports(reports)?.set(report_type, data)
+                }
+                Some(ReportType::Input) | None => Err(UHID_ERR_NOT_SUPPORTED),
+            };
+            match result {
+                Ok(()) if rtype == UHID_OUTPUT_REPORT => {
+                    let _ = outputs.send(data.to_vec());
+                }
+                Ok(()) => {}
+                Err(err) => eprintln!(
+                    "hidd: UHID_SET_REPORT id={id} rnum={rnum} rtype={rtype} size={size} refused ({err})"
+                ),
+            }
+            write_set_report_reply(io, id, result.err().unwrap_or(0))?;
         }
         _ => {}
     }
@@ -426,6 +1266,12 @@ fn handle_uhid_event(
     Ok(())
 }
 
+fn lock_uhid_reports(reports: &Mutex<UhidReports>) -> Result<MutexGuard<'_, UhidReports>> {
+    reports
+        .lock()
+        .map_err(|_| anyhow!("failed to lock UHID reports"))
+}
+
 fn write_get_report_reply(io: &mut std::fs::File, id: u32, err: u16, data: &[u8]) -> Result<()> {
     if data.len() > UHID_OUTPUT_DATA_MAX {
         return Err(anyhow!("get-report reply too large: {}", data.len()));
@@ -575,8 +1421,24 @@ fn apply_axis_value(report: &mut InputReport, axis: AxisName, value: i16) {
 
 #[cfg(test)]
 mod tests {
-    use super::PatternState;
-    use common::config::{AxisName, PatternConfig};
+    use super::{
+        check_pattern_sink, register_again, BluetoothLink, PatternState, UhidReports,
+        UHID_ERR_INVALID,
+    };
+    use crate::control::ControlState;
+    use crate::mock_bluez::MockBluez;
+    use common::config::{AxisName, HidConfig, OutputSink, PatternConfig, ProfileConfig};
+    use common::hid::ds4::{
+        DS4_CALIBRATION_REPORT_ID, DS4_FEATURE_REPORT_IDS, DS4_FIRMWARE_REPORT_ID,
+        DS4_MAC_REPORT_ID,
+    };
+    use common::hid::{
+        HidProfile, HidProfileMode, ReportType, XBOX_INPUT_PAYLOAD_LEN, XBOX_INPUT_REPORT_ID,
+        XBOX_OUTPUT_PAYLOAD_LEN, XBOX_OUTPUT_REPORT_ID,
+    };
+    use std::sync::Arc;
+    use std::thread;
+    use std::time::{Duration, Instant};
 
     #[test]
     fn button_toggle_changes_state_over_time() {
@@ -615,4 +1477,138 @@ mod tests {
 
         assert_eq!(values, vec![-3, 0, 3, 0, -3, 0]);
     }
+
+    #[test]
+    fn uhid_reports_answer_get_report_with_last_value() {
+        let mut reports = UhidReports::new(&ProfileConfig::default().hid_profile());
+
+        let input = reports
+            .get(ReportType::Input, XBOX_INPUT_REPORT_ID)
+            .unwrap();
+        assert_eq!(input.len(), 1 + XBOX_INPUT_PAYLOAD_LEN);
+        assert!(input[1..].iter().all(|&b| b == 0));
+
+        reports
+            .set(ReportType::Output, &[XBOX_OUTPUT_REPORT_ID, 0x0f, 0x20])
+            .unwrap();
+        let output = reports
+            .get(ReportType::Output, XBOX_OUTPUT_REPORT_ID)
+            .unwrap();
+        assert_eq!(output.len(), 1 + XBOX_OUTPUT_PAYLOAD_LEN);
+        assert_eq!(output[..3], [XBOX_OUTPUT_REPORT_ID, 0x0f, 0x20]);
+        assert!(output[3..].iter().all(|&b| b == 0));
+
+        let overlong = vec![XBOX_OUTPUT_REPORT_ID; 2 + XBOX_OUTPUT_PAYLOAD_LEN];
+        assert_eq!(
+            reports.set(ReportType::Output, &overlong),
+            Err(UHID_ERR_INVALID)
+        );
+        assert_eq!(
+            reports.set(ReportType::Feature, &[0x42, 1]),
+            Err(UHID_ERR_INVALID)
+        );
+        assert_eq!(reports.get(ReportType::Feature, 0x42), None);
+    }
+
+    #[test]
+    fn uhid_reports_answer_ds4_feature_reports() {
+        let profile = HidProfile::from(HidProfileMode::DualShock4);
+        let mut reports = UhidReports::new(&profile);
+
+        for report_id in DS4_FEATURE_REPORT_IDS {
+            let info = profile.report(ReportType::Feature, report_id).unwrap();
+            let report = reports.get(ReportType::Feature, report_id).unwrap();
+            assert_eq!(report.len(), 1 + info.payload_len);
+            assert_eq!(report[0], report_id);
+        }
+        for report_id in [DS4_CALIBRATION_REPORT_ID, DS4_FIRMWARE_REPORT_ID] {
+            let report = reports.get(ReportType::Feature, report_id).unwrap();
+            assert!(report[1..].iter().any(|&b| b != 0), "0x{report_id:02x}");
+        }
+
+        // The session's address replaces the placeholder one.
+        let mac = [0x98, 0xb6, 0xe9, 0x01, 0x02, 0x03];
+        for feature in profile.new_session(mac).feature_reports() {
+            reports.set(ReportType::Feature, &feature).unwrap();
+        }
+        let mac_report = reports.get(ReportType::Feature, DS4_MAC_REPORT_ID).unwrap();
+        assert_eq!(mac_report[1..], [0x03, 0x02, 0x01, 0xe9, 0xb6, 0x98]);
+    }
+
+    fn test_config() -> HidConfig {
+        HidConfig::from_toml_str(
+            r#"
+            [device]
+            name = "ControllerOS Test Pad"
+
+            [report]
+            rate_hz = 125
+
+            [pattern]
+            kind = "button_toggle"
+            button_index = 0
+            period_reports = 30
+            "#,
+        )
+        .expect("config should parse")
+    }
+
+    #[test]
+    fn pattern_mode_refuses_a_sink() {
+        let mut cfg = test_config();
+        assert!(check_pattern_sink(&cfg, None).is_ok());
+        let err = check_pattern_sink(&cfg, Some(OutputSink::Bluetooth)).unwrap_err();
+        assert!(err.to_string().contains("--sink"), "{err}");
+        cfg.output.sink = OutputSink::Uhid;
+        let err = check_pattern_sink(&cfg, None).unwrap_err();
+        assert!(err.to_string().contains("sink = \"uhid\""), "{err}");
+    }
+
+    /// Dispatch `link` until BlueZ is reported lost or five seconds pass.
+    fn notices_bluez_lost(link: &BluetoothLink) -> bool {
+        let hog = link.hog().expect("HOGP link");
+        let deadline = Instant::now() + Duration::from_secs(5);
+        while Instant::now() < deadline {
+            hog.process_pending_messages()
+                .expect("dispatch D-Bus traffic");
+            if link.bluez_lost() {
+                return true;
+            }
+            thread::sleep(Duration::from_millis(2));
+        }
+        false
+    }
+
+    #[test]
+    fn registers_again_after_bluez_restarts_or_the_adapter_goes() {
+        let Some(bluez) = MockBluez::start() else {
+            return;
+        };
+        let cfg = test_config();
+        let control = ControlState::shared();
+        let link = BluetoothLink::register(&cfg, Arc::clone(&control)).expect("register");
+        assert!(!link.bluez_lost());
+
+        bluez.restart();
+        assert!(notices_bluez_lost(&link));
+        assert!(bluez.state().agent.is_none());
+        drop(link);
+        let link = register_again(&cfg, &control, None);
+        assert!(!link.bluez_lost());
+        {
+            let state = bluez.state();
+            assert!(!state.gatt.is_empty());
+            assert!(state.advertisement.is_some());
+            assert!(state.agent.is_some());
+        }
+
+        bluez.remove_adapter();
+        assert!(notices_bluez_lost(&link));
+        drop(link);
+        let link = register_again(&cfg, &control, None);
+        assert!(!link.bluez_lost());
+        let state = bluez.state();
+        assert!(!state.gatt.is_empty());
+        assert!(state.advertisement.is_some());
+    }
 }
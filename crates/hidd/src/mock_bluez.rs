@@ -0,0 +1,829 @@
+//! A stand-in for bluetoothd, for testing `HogRuntime` without an adapter.
+//!
+//! [`MockBluez::start`] runs a private `dbus-daemon` and owns `org.bluez`
+//! there with one adapter, `/org/bluez/hci0`. In tests, `hog::system_bus`
+//! connects to that daemon instead of the system bus.
+//! Like bluetoothd it walks the GATT application and reads the
+//! advertisement when they are registered, and it plays the host side:
+//! devices connect and disconnect, characteristics are read, written and
+//! subscribed to, and the agent is asked to pair.
+//!
+//! Every test shares one daemon and takes its turn with the well-known
+//! names. Without `dbus-daemon` (or `$DBUS_DAEMON`), `start` fails the test,
+//! unless `HIDD_SKIP_MOCK_BLUEZ` is set; then it returns `None` and the test
+//! is skipped.
+//!
+//! The interface names are spelled out here rather than taken from `hog`,
+//! so a typo there fails the tests instead of passing them.
+
+use std::collections::{BTreeMap, HashMap};
+use std::env;
+use std::fs;
+use std::io::{self, BufRead, BufReader, ErrorKind};
+use std::process::{self, ChildStdin, ChildStdout, Command, Stdio};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex, MutexGuard, OnceLock};
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+
+use dbus::arg::{prop_cast, AppendAll, PropMap, ReadAll, RefArg, Variant};
+use dbus::blocking::stdintf::org_freedesktop_dbus::{
+    ObjectManager, ObjectManagerInterfacesAdded, ObjectManagerInterfacesRemoved, Properties,
+    PropertiesPropertiesChanged,
+};
+use dbus::blocking::SyncConnection;
+use dbus::channel::{Channel, MatchingReceiver, Sender};
+use dbus::message::{MatchRule, SignalArgs};
+use dbus::{Message, Path};
+
+const ADAPTER_PATH: &str = "/org/bluez/hci0";
+const ADAPTER_ADDRESS: &str = "00:1A:7D:DA:71:13";
+
+const ADAPTER_IFACE: &str = "org.bluez.Adapter1";
+const DEVICE_IFACE: &str = "org.bluez.Device1";
+const GATT_MANAGER_IFACE: &str = "org.bluez.GattManager1";
+const ADV_MANAGER_IFACE: &str = "org.bluez.LEAdvertisingManager1";
+const AGENT_MANAGER_IFACE: &str = "org.bluez.AgentManager1";
+const AGENT_IFACE: &str = "org.bluez.Agent1";
+const ADVERTISEMENT_IFACE: &str = "org.bluez.LEAdvertisement1";
+const GATT_CHARACTERISTIC_IFACE: &str = "org.bluez.GattCharacteristic1";
+const PROPERTIES_IFACE: &str = "org.freedesktop.DBus.Properties";
+const OBJECT_MANAGER_IFACE: &str = "org.freedesktop.DBus.ObjectManager";
+
+const ERROR_FAILED: &str = "org.bluez.Error.Failed";
+const ERROR_DOES_NOT_EXIST: &str = "org.bluez.Error.DoesNotExist";
+const ERROR_ALREADY_EXISTS: &str = "org.bluez.Error.AlreadyExists";
+const ERROR_INVALID_ARGUMENTS: &str = "org.bluez.Error.InvalidArguments";
+
+const CALL_TIMEOUT: Duration = Duration::from_secs(5);
+
+const BUS_CONFIG: &str = r#"<!DOCTYPE busconfig PUBLIC "-//freedesktop//DTD D-Bus Bus Configuration 1.0//EN"
+ "http://www.freedesktop.org/standards/dbus/1.0/busconfig.dtd">
+<busconfig>
+  <listen>unix:tmpdir=/tmp</listen>
+  <auth>EXTERNAL</auth>
+  <policy context="default">
+    <allow user="*"/>
+    <allow own="*"/>
+    <allow send_destination="*"/>
+    <allow receive_sender="*"/>
+  </policy>
+</busconfig>
+"#;
+
+/// Set to skip the tests that need the mock when `dbus-daemon` is missing.
+const SKIP_ENV: &str = "HIDD_SKIP_MOCK_BLUEZ";
+
+/// Serializes tests: they share `org.bluez` and our own object paths.
+static TURN: Mutex<()> = Mutex::new(());
+
+/// The private daemon, started by the first test that needs it.
+static BUS: OnceLock<Option<PrivateBus>> = OnceLock::new();
+
+struct PrivateBus {
+    address: String,
+    /// The daemon is stopped when this closes, as the test process exits.
+    _stdin: ChildStdin,
+    _stdout: BufReader<ChildStdout>,
+}
+
+/// What bluetoothd would know, for tests to check and change.
+#[derive(Debug, Default)]
+pub struct MockState {
+    pub adapter: Adapter,
+    /// Objects of the registered GATT application, by path.
+    pub gatt: BTreeMap<String, GattObject>,
+    application: Option<(String, Path<'static>)>,
+    pub advertisement: Option<Advertisement>,
+    /// Successful `RegisterAdvertisement` calls.
+    pub advertisement_registrations: u32,
+    /// `RegisterAdvertisement` calls still to fail, as when the controller
+    /// is out of advertising sets.
+    pub failing_advertisements: u32,
+    pub agent: Option<Agent>,
+    pub devices: BTreeMap<Path<'static>, Device>,
+    /// Characteristic values the application notified, oldest first.
+    pub notifications: Vec<(String, Vec<u8>)>,
+}
+
+#[derive(Debug)]
+pub struct Adapter {
+    pub address: String,
+    pub alias: String,
+    pub powered: bool,
+    pub discoverable: bool,
+    pub pairable: bool,
+    pub discoverable_timeout: u32,
+}
+
+impl Default for Adapter {
+    fn default() -> Self {
+        Self {
+            address: ADAPTER_ADDRESS.to_string(),
+            alias: "mock".to_string(),
+            powered: false,
+            discoverable: false,
+            pairable: false,
+            discoverable_timeout: 180,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct GattObject {
+    pub interface: String,
+    pub uuid: String,
+    pub flags: Vec<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Advertisement {
+    pub path: String,
+    pub local_name: String,
+    pub service_uuids: Vec<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Agent {
+    pub owner: String,
+    pub path: String,
+    pub capability: String,
+    pub default: bool,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Device {
+    pub address: String,
+    pub name: String,
+    pub paired: bool,
+    pub trusted: bool,
+    pub connected: bool,
+    pub services_resolved: bool,
+}
+
+pub struct MockBluez {
+    service: Arc<SyncConnection>,
+    /// bluetoothd's side of calls into the application and agent.
+    client: SyncConnection,
+    state: Arc<Mutex<MockState>>,
+    stop: Arc<AtomicBool>,
+    thread: Option<JoinHandle<()>>,
+    _turn: MutexGuard<'static, ()>,
+}
+
+impl MockBluez {
+    /// Own `org.bluez` on the private bus. Without `dbus-daemon` this
+    /// panics, or returns `None` when `HIDD_SKIP_MOCK_BLUEZ` is set.
+    pub fn start() -> Option<Self> {
+        let turn = TURN.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+        BUS.get_or_init(start_bus).as_ref()?;
+        let service = Arc::new(connect().expect("private bus"));
+        service
+            .request_name("org.bluez", false, true, true)
+            .expect("own org.bluez");
+        let client = connect().expect("private bus");
+        let state = Arc::new(Mutex::new(MockState::default()));
+
+        let calls_state = Arc::clone(&state);
+        let calls_client = connect().expect("private bus");
+        service.start_receive(
+            MatchRule::new_method_call(),
+            Box::new(move |call, conn| {
+                let reply = handle(&call, conn, &calls_state, &calls_client)
+                    .unwrap_or_else(|(name, text)| error_reply(&call, name, &text));
+                let _ = conn.send(reply);
+                true
+            }),
+        );
+
+        let changed_rule = PropertiesPropertiesChanged::match_rule(None, None).static_clone();
+        service
+            .add_match_no_cb(&changed_rule.match_str())
+            .expect("match PropertiesChanged");
+        let notify_state = Arc::clone(&state);
+        service.start_receive(
+            changed_rule,
+            Box::new(move |msg, _| {
+                record_notification(&msg, &notify_state);
+                true
+            }),
+        );
+
+        let stop = Arc::new(AtomicBool::new(false));
+        let thread = {
+            let service = Arc::clone(&service);
+            let stop = Arc::clone(&stop);
+            thread::Builder::new()
+                .name("mock-bluez".to_string())
+                .spawn(move || {
+                    while !stop.load(Ordering::Acquire) {
+                        if let Err(e) = service.process(Duration::from_millis(5)) {
+                            eprintln!("mock bluez: {e}");
+                            return;
+                        }
+                    }
+                })
+                .expect("spawn mock bluez")
+        };
+        Some(Self {
+            service,
+            client,
+            state,
+            stop,
+            thread: Some(thread),
+            _turn: turn,
+        })
+    }
+
+    pub fn state(&self) -> MutexGuard<'_, MockState> {
+        lock(&self.state)
+    }
+
+    /// Add a device to the adapter and announce it, as after discovery.
+    pub fn add_device(&self, address: &str, name: &str, paired: bool) -> Path<'static> {
+        let path = Path::new(format!("{ADAPTER_PATH}/dev_{}", address.replace(':', "_")))
+            .expect("device path");
+        let device = Device {
+            address: address.to_string(),
+            name: name.to_string(),
+            paired,
+            trusted: false,
+            connected: false,
+            services_resolved: false,
+        };
+        let signal = ObjectManagerInterfacesAdded {
+            object: path.clone(),
+            interfaces: HashMap::from([(DEVICE_IFACE.to_string(), device.props())]),
+        };
+        self.state().devices.insert(path.clone(), device);
+        let _ = self.service.send(signal.to_emit_message(&Path::from("/")));
+        path
+    }
+
+    /// Forget `device` and announce it, as after the host is removed.
+    pub fn remove_device(&self, device: &Path<'static>) {
+        self.state().devices.remove(device);
+        let signal = ObjectManagerInterfacesRemoved {
+            object: device.clone(),
+            interfaces: vec![DEVICE_IFACE.to_string()],
+        };
+        let _ = self.service.send(signal.to_emit_message(&Path::from("/")));
+    }
+
+    /// Give up `org.bluez` and own it again, forgetting the application,
+    /// advertisement and agent, as when bluetoothd restarts.
+    pub fn restart(&self) {
+        {
+            let mut state = self.state();
+            state.application = None;
+            state.gatt.clear();
+            state.advertisement = None;
+            state.agent = None;
+        }
+        // Not waited for: the dispatch thread would take the replies. The
+        // bus handles both in order.
+        let bus_call = |method: &str| {
+            Message::new_method_call(
+                "org.freedesktop.DBus",
+                "/org/freedesktop/DBus",
+                "org.freedesktop.DBus",
+                method,
+            )
+            .expect("bus call")
+        };
+        let release = bus_call("ReleaseName").append1("org.bluez");
+        // DBUS_NAME_FLAG_REPLACE_EXISTING | DBUS_NAME_FLAG_DO_NOT_QUEUE
+        let request = bus_call("RequestName").append2("org.bluez", 0x6u32);
+        for call in [release, request] {
+            self.service.send(call).expect("send to the bus");
+        }
+    }
+
+    /// Announce that the adapter is gone, as when its controller is
+    /// unplugged, dropping the application and advertisement registered
+    /// with it. Calls to it keep working, as if it was plugged back in.
+    pub fn remove_adapter(&self) {
+        {
+            let mut state = self.state();
+            state.application = None;
+            state.gatt.clear();
+            state.advertisement = None;
+        }
+        let signal = ObjectManagerInterfacesRemoved {
+            object: Path::from(ADAPTER_PATH),
+            interfaces: [ADAPTER_IFACE, GATT_MANAGER_IFACE, ADV_MANAGER_IFACE]
+                .map(str::to_string)
+                .to_vec(),
+        };
+        let _ = self.service.send(signal.to_emit_message(&Path::from("/")));
+    }
+
+    /// Connect or disconnect `device` the way bluetoothd reports it:
+    /// `Connected` first and `ServicesResolved` once GATT is up, in reverse
+    /// on disconnect.
+    pub fn set_connected(&self, device: &Path<'static>, connected: bool) {
+        set_connected(&self.service, &self.state, device, connected);
+    }
+
+    /// Call the registered GATT application, as bluetoothd does for a host.
+    pub fn call_application<A: AppendAll, R: ReadAll + 'static>(
+        &self,
+        path: &str,
+        method: &str,
+        args: A,
+    ) -> Result<R, dbus::Error> {
+        let owner = self
+            .state()
+            .application
+            .as_ref()
+            .map(|(owner, _)| owner.clone())
+            .expect("no GATT application registered");
+        self.client
+            .with_proxy(owner.as_str(), path, CALL_TIMEOUT)
+            .method_call(GATT_CHARACTERISTIC_IFACE, method, args)
+    }
+
+    pub fn read_value(&self, path: &str) -> Result<Vec<u8>, dbus::Error> {
+        self.call_application(path, "ReadValue", (PropMap::new(),))
+            .map(|(value,)| value)
+    }
+
+    pub fn write_value(&self, path: &str, value: &[u8]) -> Result<(), dbus::Error> {
+        self.call_application(path, "WriteValue", (value.to_vec(), PropMap::new()))
+    }
+
+    /// Call the registered agent, as bluetoothd does while pairing.
+    pub fn call_agent<A: AppendAll>(&self, method: &str, args: A) -> Result<(), dbus::Error> {
+        let agent = self.state().agent.clone().expect("no agent registered");
+        self.client
+            .with_proxy(agent.owner.as_str(), agent.path.as_str(), CALL_TIMEOUT)
+            .method_call(AGENT_IFACE, method, args)
+    }
+}
+
+impl Drop for MockBluez {
+    fn drop(&mut self) {
+        self.stop.store(true, Ordering::Release);
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
+
+impl MockState {
+    fn properties(&self, path: &str, interface: &str) -> Result<PropMap, (&'static str, String)> {
+        match interface {
+            ADAPTER_IFACE if path == ADAPTER_PATH => Ok(self.adapter.props()),
+            DEVICE_IFACE => self
+                .devices
+                .get(&Path::from(path.to_string()))
+                .map(Device::props)
+                .ok_or_else(|| unknown_object(path)),
+            _ => Err((
+                "org.freedesktop.DBus.Error.UnknownInterface",
+                format!("{path} has no {interface}"),
+            )),
+        }
+    }
+
+    fn managed_objects(&self) -> HashMap<Path<'static>, HashMap<String, PropMap>> {
+        let mut objects = HashMap::new();
+        objects.insert(
+            Path::from("/org/bluez"),
+            HashMap::from([(AGENT_MANAGER_IFACE.to_string(), PropMap::new())]),
+        );
+        objects.insert(
+            Path::from(ADAPTER_PATH),
+            HashMap::from([
+                (ADAPTER_IFACE.to_string(), self.adapter.props()),
+                (GATT_MANAGER_IFACE.to_string(), PropMap::new()),
+                (ADV_MANAGER_IFACE.to_string(), PropMap::new()),
+            ]),
+        );
+        for (path, device) in &self.devices {
+            objects.insert(
+                path.clone(),
+                HashMap::from([(DEVICE_IFACE.to_string(), device.props())]),
+            );
+        }
+        objects
+    }
+}
+
+impl Adapter {
+    fn props(&self) -> PropMap {
+        PropMap::from([
+            variant("Address", self.address.clone()),
+            variant("Alias", self.alias.clone()),
+            variant("Powered", self.powered),
+            variant("Discoverable", self.discoverable),
+            variant("Pairable", self.pairable),
+            variant("DiscoverableTimeout", self.discoverable_timeout),
+        ])
+    }
+
+    fn set(&mut self, name: &str, value: &dyn RefArg) -> Result<(), (&'static str, String)> {
+        let flag = || value.as_i64().map(|v| v != 0);
+        let set = match name {
+            "Alias" => value.as_str().map(|v| self.alias = v.to_string()),
+            "Powered" => flag().map(|v| self.powered = v),
+            "Discoverable" => flag().map(|v| self.discoverable = v),
+            "Pairable" => flag().map(|v| self.pairable = v),
+            "DiscoverableTimeout" => value
+                .as_u64()
+                .and_then(|v| u32::try_from(v).ok())
+                .map(|v| self.discoverable_timeout = v),
+            _ => None,
+        };
+        set.ok_or_else(|| invalid_arguments(format!("cannot set Adapter1.{name}")))
+    }
+}
+
+impl Device {
+    fn props(&self) -> PropMap {
+        PropMap::from([
+            variant("Address", self.address.clone()),
+            variant("Name", self.name.clone()),
+            variant("Alias", self.name.clone()),
+            variant("Adapter", Path::from(ADAPTER_PATH)),
+            variant("Paired", self.paired),
+            variant("Bonded", self.paired),
+            variant("Trusted", self.trusted),
+            variant("Connected", self.connected),
+            variant("ServicesResolved", self.services_resolved),
+        ])
+    }
+}
+
+/// A new connection to the private daemon, as `SyncConnection::new_system`
+/// would open to the system bus.
+pub(crate) fn connect() -> Result<SyncConnection, dbus::Error> {
+    let Some(Some(bus)) = BUS.get() else {
+        return Err(dbus::Error::new_failed("the mock BlueZ bus is not running"));
+    };
+    let mut channel = Channel::open_private(&bus.address)?;
+    channel.register()?;
+    Ok(channel.into())
+}
+
+fn start_bus() -> Option<PrivateBus> {
+    match spawn_bus() {
+        Ok(bus) => Some(bus),
+        Err(e) if env::var_os(SKIP_ENV).is_some() => {
+            eprintln!("mock bluez: skipping, no private dbus-daemon: {e}");
+            None
+        }
+        Err(e) => panic!("mock bluez: no private dbus-daemon: {e}; set {SKIP_ENV}=1 to skip"),
+    }
+}
+
+fn spawn_bus() -> io::Result<PrivateBus> {
+    let daemon = env::var("DBUS_DAEMON").unwrap_or_else(|_| "dbus-daemon".to_string());
+    let config = env::temp_dir().join(format!("hidd-mock-bluez-{}.conf", process::id()));
+    fs::write(&config, BUS_CONFIG)?;
+    // The shell kills the daemon once its stdin closes, which happens when
+    // the test process exits, however it exits. It closes its own stdout so
+    // a daemon that fails to start ends the address read.
+    let mut shell = Command::new("sh")
+        .arg("-c")
+        .arg(r#""$0" --config-file="$1" --print-address=1 --nofork & exec >&-; read -r _; kill $!"#)
+        .arg(&daemon)
+        .arg(&config)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .spawn()?;
+    let stdin = shell.stdin.take().expect("piped stdin");
+    let mut stdout = BufReader::new(shell.stdout.take().expect("piped stdout"));
+    let mut address = String::new();
+    let read = stdout.read_line(&mut address);
+    let _ = fs::remove_file(&config);
+    read?;
+    let address = address.trim();
+    if address.is_empty() {
+        return Err(io::Error::new(
+            ErrorKind::NotFound,
+            format!("{daemon} did not start"),
+        ));
+    }
+    Ok(PrivateBus {
+        address: address.to_string(),
+        _stdin: stdin,
+        _stdout: stdout,
+    })
+}
+
+/// Answer one call to `org.bluez`.
+fn handle(
+    call: &Message,
+    conn: &SyncConnection,
+    state: &Mutex<MockState>,
+    client: &SyncConnection,
+) -> Result<Message, (&'static str, String)> {
+    let path = call.path().map(|p| p.to_string()).unwrap_or_default();
+    let interface = call.interface().map(|i| i.to_string()).unwrap_or_default();
+    let member = call.member().map(|m| m.to_string()).unwrap_or_default();
+    let sender = call.sender().map(|s| s.to_string()).unwrap_or_default();
+    let args = |e: dbus::arg::TypeMismatchError| invalid_arguments(e.to_string());
+
+    match (interface.as_str(), member.as_str()) {
+        (OBJECT_MANAGER_IFACE, "GetManagedObjects") if path == "/" => {
+            Ok(call.method_return().append1(lock(state).managed_objects()))
+        }
+        (PROPERTIES_IFACE, "Get") => {
+            let (iface, name): (&str, &str) = call.read2().map_err(args)?;
+            let value = lock(state)
+                .properties(&path, iface)?
+                .remove(name)
+                .ok_or_else(|| invalid_arguments(format!("no property {iface}.{name}")))?;
+            Ok(call.method_return().append1(value))
+        }
+        (PROPERTIES_IFACE, "GetAll") => {
+            let iface: &str = call.read1().map_err(args)?;
+            Ok(call
+                .method_return()
+                .append1(lock(state).properties(&path, iface)?))
+        }
+        (PROPERTIES_IFACE, "Set") => {
+            let (iface, name, value): (&str, &str, Variant<Box<dyn RefArg>>) =
+                call.read3().map_err(args)?;
+            {
+                let mut state = lock(state);
+                match iface {
+                    ADAPTER_IFACE if path == ADAPTER_PATH => state.adapter.set(name, &*value.0)?,
+                    DEVICE_IFACE if name == "Trusted" => {
+                        let device = state
+                            .devices
+                            .get_mut(&Path::from(path.clone()))
+                            .ok_or_else(|| unknown_object(&path))?;
+                        device.trusted = value.0.as_i64().is_some_and(|v| v != 0);
+                    }
+                    _ => return Err(invalid_arguments(format!("cannot set {iface}.{name}"))),
+                }
+            }
+            emit_changed(
+                conn,
+                &path,
+                iface,
+                PropMap::from([(name.to_string(), value)]),
+            );
+            Ok(call.method_return())
+        }
+        (GATT_MANAGER_IFACE, "RegisterApplication") if path == ADAPTER_PATH => {
+            let (application, _options): (Path, PropMap) = call.read2().map_err(args)?;
+            if lock(state).application.is_some() {
+                return Err((ERROR_ALREADY_EXISTS, "Already Exists".to_string()));
+            }
+            // Walked before replying, so the application must dispatch
+            // while it waits, as with bluetoothd.
+            let objects = client
+                .with_proxy(sender.as_str(), &application, CALL_TIMEOUT)
+                .get_managed_objects()
+                .map_err(|e| (ERROR_FAILED, format!("failed to read application: {e}")))?;
+            let gatt = gatt_objects(&objects);
+            if gatt.is_empty() {
+                return Err((ERROR_FAILED, "No object received".to_string()));
+            }
+            let mut state = lock(state);
+            state.gatt = gatt;
+            state.application = Some((sender, application.into_static()));
+            Ok(call.method_return())
+        }
+        (GATT_MANAGER_IFACE, "UnregisterApplication") if path == ADAPTER_PATH => {
+            let application: Path = call.read1().map_err(args)?;
+            let mut state = lock(state);
+            if state
+                .application
+                .as_ref()
+                .is_none_or(|(owner, registered)| *owner != sender || *registered != application)
+            {
+                return Err(does_not_exist());
+            }
+            state.application = None;
+            state.gatt.clear();
+            Ok(call.method_return())
+        }
+        (ADV_MANAGER_IFACE, "RegisterAdvertisement") if path == ADAPTER_PATH => {
+            let (advertisement, _options): (Path, PropMap) = call.read2().map_err(args)?;
+            {
+                let mut state = lock(state);
+                if state.failing_advertisements > 0 {
+                    state.failing_advertisements -= 1;
+                    return Err((ERROR_FAILED, "Failed to register advertisement".to_string()));
+                }
+                if state.advertisement.is_some() {
+                    return Err((ERROR_ALREADY_EXISTS, "Already Exists".to_string()));
+                }
+            }
+            let props = client
+                .with_proxy(sender.as_str(), &advertisement, CALL_TIMEOUT)
+                .get_all(ADVERTISEMENT_IFACE)
+                .map_err(|e| (ERROR_FAILED, format!("failed to read advertisement: {e}")))?;
+            let mut state = lock(state);
+            state.advertisement = Some(Advertisement {
+                path: advertisement.to_string(),
+                local_name: prop_cast::<String>(&props, "LocalName")
+                    .cloned()
+                    .unwrap_or_default(),
+                service_uuids: strings(&props, "ServiceUUIDs"),
+            });
+            state.advertisement_registrations += 1;
+            Ok(call.method_return())
+        }
+        (ADV_MANAGER_IFACE, "UnregisterAdvertisement") if path == ADAPTER_PATH => {
+            let advertisement: Path = call.read1().map_err(args)?;
+            let mut state = lock(state);
+            if state
+                .advertisement
+                .as_ref()
+                .is_none_or(|a| a.path != *advertisement)
+            {
+                return Err(does_not_exist());
+            }
+            state.advertisement = None;
+            Ok(call.method_return())
+        }
+        (AGENT_MANAGER_IFACE, "RegisterAgent") if path == "/org/bluez" => {
+            let (agent, capability): (Path, String) = call.read2().map_err(args)?;
+            let mut state = lock(state);
+            if state.agent.as_ref().is_some_and(|a| a.owner == sender) {
+                return Err((ERROR_ALREADY_EXISTS, "Already Exists".to_string()));
+            }
+            state.agent = Some(Agent {
+                owner: sender,
+                path: agent.to_string(),
+                capability,
+                default: false,
+            });
+            Ok(call.method_return())
+        }
+        (AGENT_MANAGER_IFACE, "UnregisterAgent" | "RequestDefaultAgent")
+            if path == "/org/bluez" =>
+        {
+            let agent: Path = call.read1().map_err(args)?;
+            let mut state = lock(state);
+            let registered = state
+                .agent
+                .as_mut()
+                .filter(|a| a.owner == sender && a.path == *agent)
+                .ok_or_else(does_not_exist)?;
+            if member == "RequestDefaultAgent" {
+                registered.default = true;
+            } else {
+                state.agent = None;
+            }
+            Ok(call.method_return())
+        }
+        (DEVICE_IFACE, "Connect" | "Disconnect") => {
+            let device = Path::from(path.clone());
+            if !lock(state).devices.contains_key(&device) {
+                return Err(unknown_object(&path));
+            }
+            set_connected(conn, state, &device, member == "Connect");
+            Ok(call.method_return())
+        }
+        _ => Err((
+            "org.freedesktop.DBus.Error.UnknownMethod",
+            format!("{interface}.{member} on {path} is not mocked"),
+        )),
+    }
+}
+
+fn set_connected(
+    conn: &SyncConnection,
+    state: &Mutex<MockState>,
+    device: &Path<'static>,
+    connected: bool,
+) {
+    let steps = if connected {
+        ["Connected", "ServicesResolved"]
+    } else {
+        ["ServicesResolved", "Connected"]
+    };
+    for step in steps {
+        {
+            let mut state = lock(state);
+            let Some(entry) = state.devices.get_mut(device) else {
+                return;
+            };
+            match step {
+                "Connected" => entry.connected = connected,
+                _ => entry.services_resolved = connected,
+            }
+        }
+        emit_changed(
+            conn,
+            device,
+            DEVICE_IFACE,
+            PropMap::from([variant(step, connected)]),
+        );
+    }
+}
+
+fn emit_changed(conn: &SyncConnection, path: &str, interface: &str, changed: PropMap) {
+    let signal = PropertiesPropertiesChanged {
+        interface_name: interface.to_string(),
+        changed_properties: changed,
+        invalidated_properties: Vec::new(),
+    };
+    let _ = conn.send(signal.to_emit_message(&Path::from(path)));
+}
+
+/// Keep `Value` changes the registered application sends on its
+/// characteristics; bluetoothd turns them into notifications.
+fn record_notification(msg: &Message, state: &Mutex<MockState>) {
+    let Ok(signal) = msg.read_all::<PropertiesPropertiesChanged>() else {
+        return;
+    };
+    if signal.interface_name != GATT_CHARACTERISTIC_IFACE {
+        return;
+    }
+    let Some(value) = signal
+        .changed_properties
+        .get("Value")
+        .and_then(|v| bytes(&*v.0))
+    else {
+        return;
+    };
+    let mut state = lock(state);
+    let sender = msg.sender().map(|s| s.to_string());
+    if state.application.as_ref().map(|(owner, _)| owner) == sender.as_ref() {
+        let path = msg.path().map(|p| p.to_string()).unwrap_or_default();
+        state.notifications.push((path, value));
+    }
+}
+
+fn gatt_objects(
+    objects: &HashMap<Path<'static>, HashMap<String, PropMap>>,
+) -> BTreeMap<String, GattObject> {
+    let mut gatt = BTreeMap::new();
+    for (path, interfaces) in objects {
+        for (interface, props) in interfaces {
+            if !interface.starts_with("org.bluez.Gatt") {
+                continue;
+            }
+            gatt.insert(
+                path.to_string(),
+                GattObject {
+                    interface: interface.clone(),
+                    uuid: prop_cast::<String>(props, "UUID")
+                        .cloned()
+                        .unwrap_or_default(),
+                    flags: strings(props, "Flags"),
+                },
+            );
+        }
+    }
+    gatt
+}
+
+fn strings(props: &PropMap, name: &str) -> Vec<String> {
+    props
+        .get(name)
+        .and_then(|v| v.0.as_iter())
+        .map(|items| {
+            items
+                .filter_map(|item| item.as_str().map(str::to_string))
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+fn bytes(value: &dyn RefArg) -> Option<Vec<u8>> {
+    value
+        .as_iter()?
+        .map(|byte| byte.as_u64().and_then(|b| u8::try_from(b).ok()))
+        .collect()
+}
+
+fn variant<T: RefArg + 'static>(name: &str, value: T) -> (String, Variant<Box<dyn RefArg>>) {
+    (name.to_string(), Variant(Box::new(value)))
+}
+
+fn error_reply(call: &Message, name: &str, text: &str) -> Message {
+    let name = dbus::strings::ErrorName::new(name).expect("valid error name");
+    let text = std::ffi::CString::new(text).unwrap_or_default();
+    call.error(&name, &text)
+}
+
+fn does_not_exist() -> (&'static str, String) {
+    (ERROR_DOES_NOT_EXIST, "Does Not Exist".to_string())
+}
+
+fn invalid_arguments(text: String) -> (&'static str, String) {
+    (ERROR_INVALID_ARGUMENTS, text)
+}
+
+fn unknown_object(path: &str) -> (&'static str, String) {
+    (
+        "org.freedesktop.DBus.Error.UnknownObject",
+        format!("no object {path}"),
+    )
+}
+
+fn lock(state: &Mutex<MockState>) -> MutexGuard<'_, MockState> {
+    // A failed assertion in a test holding the state must not take the
+    // service down with it.
+    state
+        .lock()
+        .unwrap_or_else(|poisoned| poisoned.into_inner())
+}
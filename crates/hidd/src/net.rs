@@ -0,0 +1,442 @@
+//! `[network]` report stream: a copy of every live input report, with the
+//! Deck's motion and trackpad data, sent to a receiver such as `netrecv`.
+//! Rumble the receiver's host asks for comes back on the same socket.
+//!
+//! The stream never holds up the main loop. The receiver's name is resolved,
+//! and a TCP connection made, on a helper thread; reports are dropped until
+//! it is done, and a failure is retried every few seconds. UDP drops what the
+//! network cannot take; TCP drops the connection once its send queue backs
+//! up and reconnects.
+
+use std::io::{ErrorKind, Read, Write};
+use std::net::{SocketAddr, TcpStream, ToSocketAddrs, UdpSocket};
+use std::sync::mpsc::{self, Receiver, TryRecvError};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use common::config::{NetTransport, NetworkConfig};
+use common::hid::{InputReport, OutputReport};
+use common::net::{NetError, NetMessage, NetPacket, NET_MAX_PACKET_LEN};
+
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(2);
+const RECONNECT_INTERVAL: Duration = Duration::from_secs(5);
+/// About a second of reports at 1000 Hz.
+const TCP_SEND_QUEUE_MAX: usize = 64 * 1024;
+
+pub struct NetStream {
+    receiver: String,
+    transport: NetTransport,
+    link: Link,
+    started: Instant,
+    sequence: u32,
+    /// Last error logged, so a receiver that is down is reported once.
+    last_error: Option<String>,
+}
+
+enum Link {
+    /// Try to open the socket again at this time.
+    Down(Instant),
+    /// A helper thread is resolving the receiver and connecting.
+    Opening(Receiver<std::io::Result<Socket>>),
+    Up(Socket),
+}
+
+enum Socket {
+    Udp(UdpSocket),
+    Tcp {
+        stream: TcpStream,
+        send_queue: Vec<u8>,
+        received: Vec<u8>,
+    },
+}
+
+impl NetStream {
+    /// Start the stream configured in `[network]`, or `None` without a
+    /// receiver. The socket is opened in the background.
+    pub fn open(cfg: &NetworkConfig) -> Option<Self> {
+        let receiver = cfg.receiver.clone()?;
+        Some(Self {
+            link: Link::Opening(open_in_background(&receiver, cfg.transport)),
+            receiver,
+            transport: cfg.transport,
+            started: Instant::now(),
+            sequence: 0,
+            last_error: None,
+        })
+    }
+
+    pub fn send_report(&mut self, report: &InputReport) {
+        let packet = NetPacket {
+            sequence: self.sequence,
+            message: NetMessage::Input {
+                sent_us: self.started.elapsed().as_micros() as u64,
+                report: *report,
+            },
+        };
+        self.sequence = self.sequence.wrapping_add(1);
+        let Some(socket) = self.socket() else {
+            return;
+        };
+        let result = socket.send(&packet.encode());
+        self.settle(result.is_err());
+        self.note(result);
+    }
+
+    /// Rumble received since the last call.
+    pub fn take_rumble(&mut self) -> Vec<OutputReport> {
+        let mut rumble = Vec::new();
+        let Some(socket) = self.socket() else {
+            return rumble;
+        };
+        let result = socket.receive(&mut |packet| match packet.message {
+            NetMessage::Rumble(report) => rumble.push(report),
+            NetMessage::Input { .. } => {}
+        });
+        self.settle(result.is_err());
+        if let Err(e) = result {
+            self.note(Err(e));
+        }
+        rumble
+    }
+
+    /// The open socket, starting or finishing an attempt to open it.
+    fn socket(&mut self) -> Option<&mut Socket> {
+        let now = Instant::now();
+        match &self.link {
+            Link::Down(next_attempt) if now >= *next_attempt => {
+                self.link = Link::Opening(open_in_background(&self.receiver, self.transport));
+            }
+            Link::Opening(opened) => match opened.try_recv() {
+                Ok(Ok(socket)) => self.link = Link::Up(socket),
+                Ok(Err(e)) => {
+                    self.link = Link::Down(now + RECONNECT_INTERVAL);
+                    self.note(Err(e));
+                }
+                Err(TryRecvError::Empty) => {}
+                Err(TryRecvError::Disconnected) => {
+                    self.link = Link::Down(now + RECONNECT_INTERVAL);
+                    self.note(Err(std::io::Error::other("network thread did not start")));
+                }
+            },
+            Link::Down(_) | Link::Up(_) => {}
+        }
+        match &mut self.link {
+            Link::Up(socket) => Some(socket),
+            Link::Down(_) | Link::Opening(_) => None,
+        }
+    }
+
+    /// Drop a TCP connection after `failed` and reconnect. A UDP socket has
+    /// no connection to lose.
+    fn settle(&mut self, failed: bool) {
+        if failed && matches!(self.link, Link::Up(Socket::Tcp { .. })) {
+            self.link = Link::Down(Instant::now());
+        }
+    }
+
+    /// Log the first of a run of errors, and the recovery after it.
+    fn note(&mut self, sent: std::io::Result<bool>) {
+        match sent {
+            Ok(false) => {}
+            Ok(true) => {
+                if self.last_error.take().is_some() {
+                    println!("hidd: streaming to {}", self.receiver);
+                }
+            }
+            Err(e) => {
+                let message = e.to_string();
+                if self.last_error.as_ref() != Some(&message) {
+                    eprintln!("hidd: network receiver {}: {message}", self.receiver);
+                    self.last_error = Some(message);
+                }
+            }
+        }
+    }
+}
+
+/// Resolve `receiver` and open a socket to it on a helper thread, which may
+/// block on DNS or a TCP handshake.
+fn open_in_background(
+    receiver: &str,
+    transport: NetTransport,
+) -> Receiver<std::io::Result<Socket>> {
+    let (opened, result) = mpsc::channel();
+    let receiver = receiver.to_string();
+    // Without the thread the sender is dropped, which reads as a failure.
+    let _ = thread::Builder::new()
+        .name("hidd-net-open".to_string())
+        .spawn(move || {
+            let _ = opened.send(Socket::open(&receiver, transport));
+        });
+    result
+}
+
+impl Socket {
+    fn open(receiver: &str, transport: NetTransport) -> std::io::Result<Self> {
+        let addr = receiver
+            .to_socket_addrs()?
+            .next()
+            .ok_or_else(|| std::io::Error::new(ErrorKind::NotFound, "no address"))?;
+        match transport {
+            NetTransport::Udp => {
+                let local: SocketAddr = if addr.is_ipv4() {
+                    ([0, 0, 0, 0], 0).into()
+                } else {
+                    ([0u16; 8], 0).into()
+                };
+                let socket = UdpSocket::bind(local)?;
+                socket.connect(addr)?;
+                socket.set_nonblocking(true)?;
+                Ok(Self::Udp(socket))
+            }
+            NetTransport::Tcp => {
+                let stream = TcpStream::connect_timeout(&addr, CONNECT_TIMEOUT)?;
+                stream.set_nodelay(true)?;
+                stream.set_nonblocking(true)?;
+                Ok(Self::Tcp {
+                    stream,
+                    send_queue: Vec::new(),
+                    received: Vec::new(),
+                })
+            }
+        }
+    }
+
+    /// Send `packet`, returning whether it went out.
+    fn send(&mut self, packet: &[u8]) -> std::io::Result<bool> {
+        match self {
+            Self::Udp(socket) => match socket.send(packet) {
+                // A full socket buffer drops this report; the next one
+                // carries the whole state anyway.
+                Err(e) if e.kind() == ErrorKind::WouldBlock => Ok(false),
+                other => other.map(|_| true),
+            },
+            Self::Tcp {
+                stream, send_queue, ..
+            } => {
+                send_queue.extend_from_slice(packet);
+                flush(stream, send_queue)?;
+                if send_queue.len() > TCP_SEND_QUEUE_MAX {
+                    return Err(std::io::Error::new(
+                        ErrorKind::TimedOut,
+                        "receiver stopped reading",
+                    ));
+                }
+                Ok(true)
+            }
+        }
+    }
+
+    fn receive(&mut self, on_packet: &mut dyn FnMut(NetPacket)) -> std::io::Result<()> {
+        let mut buf = [0u8; 1024];
+        match self {
+            Self::Udp(socket) => loop {
+                match socket.recv(&mut buf) {
+                    Ok(len) => {
+                        if let Ok((packet, _)) = NetPacket::decode(&buf[..len]) {
+                            on_packet(packet);
+                        }
+                    }
+                    Err(e) if e.kind() == ErrorKind::WouldBlock => return Ok(()),
+                    // ICMP port unreachable from a receiver that is not up.
+                    Err(e) if e.kind() == ErrorKind::ConnectionRefused => return Ok(()),
+                    Err(e) => return Err(e),
+                }
+            },
+            Self::Tcp {
+                stream, received, ..
+            } => {
+                let result = loop {
+                    match stream.read(&mut buf) {
+                        Ok(0) => {
+                            break Err(std::io::Error::new(
+                                ErrorKind::ConnectionAborted,
+                                "receiver closed the connection",
+                            ))
+                        }
+                        Ok(len) => received.extend_from_slice(&buf[..len]),
+                        Err(e) if e.kind() == ErrorKind::WouldBlock => break Ok(()),
+                        Err(e) => break Err(e),
+                    }
+                };
+                let mut used = 0;
+                loop {
+                    match NetPacket::decode(&received[used..]) {
+                        Ok((packet, len)) => {
+                            on_packet(packet);
+                            used += len;
+                        }
+                        Err(NetError::Incomplete) => break,
+                        Err(e) => return Err(std::io::Error::new(ErrorKind::InvalidData, e)),
+                    }
+                }
+                received.drain(..used);
+                if received.len() > NET_MAX_PACKET_LEN {
+                    received.clear();
+                }
+                result
+            }
+        }
+    }
+}
+
+/// Write as much of `queue` as the socket takes without blocking.
+fn flush(stream: &mut TcpStream, queue: &mut Vec<u8>) -> std::io::Result<()> {
+    let mut written = 0;
+    let result = loop {
+        if written == queue.len() {
+            break Ok(());
+        }
+        match stream.write(&queue[written..]) {
+            Ok(0) => break Err(ErrorKind::WriteZero.into()),
+            Ok(len) => written += len,
+            Err(e) if e.kind() == ErrorKind::WouldBlock => break Ok(()),
+            Err(e) => break Err(e),
+        }
+    };
+    queue.drain(..written);
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Link, NetStream};
+    use common::config::{NetTransport, NetworkConfig};
+    use common::hid::{InputReport, OutputReport, XBOX_BUTTON_A};
+    use common::net::{NetMessage, NetPacket, NET_MAX_PACKET_LEN};
+    use std::io::{Read, Write};
+    use std::net::{TcpListener, UdpSocket};
+    use std::thread;
+    use std::time::{Duration, Instant};
+
+    fn config(receiver: String, transport: NetTransport) -> NetworkConfig {
+        NetworkConfig {
+            receiver: Some(receiver),
+            transport,
+        }
+    }
+
+    fn rumble(strong: u8) -> OutputReport {
+        OutputReport {
+            strong_motor_magnitude: strong,
+            ..OutputReport::default()
+        }
+    }
+
+    /// Open `cfg` and wait for the helper thread to finish.
+    fn open_within(cfg: &NetworkConfig) -> NetStream {
+        let mut stream = NetStream::open(cfg).unwrap();
+        for _ in 0..500 {
+            stream.socket();
+            if !matches!(stream.link, Link::Opening(_)) {
+                break;
+            }
+            thread::sleep(Duration::from_millis(10));
+        }
+        stream
+    }
+
+    fn take_rumble_within(stream: &mut NetStream) -> Vec<OutputReport> {
+        for _ in 0..100 {
+            let rumble = stream.take_rumble();
+            if !rumble.is_empty() {
+                return rumble;
+            }
+            thread::sleep(Duration::from_millis(10));
+        }
+        Vec::new()
+    }
+
+    #[test]
+    fn udp_stream_reaches_receiver_and_takes_rumble_back() {
+        let receiver = UdpSocket::bind("127.0.0.1:0").unwrap();
+        receiver
+            .set_read_timeout(Some(Duration::from_secs(5)))
+            .unwrap();
+        let cfg = config(
+            receiver.local_addr().unwrap().to_string(),
+            NetTransport::Udp,
+        );
+        let mut stream = open_within(&cfg);
+
+        let report = InputReport {
+            buttons: XBOX_BUTTON_A,
+            ..InputReport::default()
+        };
+        stream.send_report(&InputReport::default());
+        stream.send_report(&report);
+
+        let mut buf = [0u8; 256];
+        let mut received = Vec::new();
+        let mut sender = None;
+        for _ in 0..2 {
+            let (len, from) = receiver.recv_from(&mut buf).unwrap();
+            received.push(NetPacket::decode(&buf[..len]).unwrap().0);
+            sender = Some(from);
+        }
+        assert_eq!(received[0].sequence, 0);
+        assert_eq!(received[1].sequence, 1);
+        assert!(matches!(
+            received[1].message,
+            NetMessage::Input { report: r, .. } if r == report
+        ));
+
+        let packet = NetPacket {
+            sequence: 0,
+            message: NetMessage::Rumble(rumble(200)),
+        };
+        receiver.send_to(&packet.encode(), sender.unwrap()).unwrap();
+        assert_eq!(take_rumble_within(&mut stream), vec![rumble(200)]);
+    }
+
+    #[test]
+    fn tcp_stream_reaches_receiver_and_takes_rumble_back() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let cfg = config(
+            listener.local_addr().unwrap().to_string(),
+            NetTransport::Tcp,
+        );
+        let mut stream = open_within(&cfg);
+
+        stream.send_report(&InputReport::default());
+        let (mut peer, _) = listener.accept().unwrap();
+        peer.set_read_timeout(Some(Duration::from_secs(5))).unwrap();
+        let mut buf = [0u8; NET_MAX_PACKET_LEN];
+        peer.read_exact(&mut buf).unwrap();
+        assert_eq!(NetPacket::decode(&buf).unwrap().0.sequence, 0);
+
+        // Two packets split across writes still decode.
+        let mut bytes = NetPacket {
+            sequence: 0,
+            message: NetMessage::Rumble(rumble(10)),
+        }
+        .encode();
+        bytes.extend(
+            NetPacket {
+                sequence: 1,
+                message: NetMessage::Rumble(rumble(20)),
+            }
+            .encode(),
+        );
+        peer.write_all(&bytes[..5]).unwrap();
+        thread::sleep(Duration::from_millis(20));
+        let mut rumble_received = stream.take_rumble();
+        peer.write_all(&bytes[5..]).unwrap();
+        while rumble_received.len() < 2 {
+            let more = take_rumble_within(&mut stream);
+            assert!(!more.is_empty(), "rumble did not arrive");
+            rumble_received.extend(more);
+        }
+        assert_eq!(rumble_received, vec![rumble(10), rumble(20)]);
+    }
+
+    #[test]
+    fn a_receiver_that_does_not_resolve_is_retried_later() {
+        let cfg = config("127.0.0.1:not-a-port".to_string(), NetTransport::Udp);
+        let mut stream = open_within(&cfg);
+        stream.send_report(&InputReport::default());
+        assert!(stream.take_rumble().is_empty());
+        assert!(matches!(stream.link, Link::Down(retry) if retry > Instant::now()));
+        assert!(stream.last_error.is_some());
+    }
+}
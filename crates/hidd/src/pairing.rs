@@ -0,0 +1,323 @@
+//! Pairing lock: who may pair while `[pairing] locked = true`.
+//!
+//! A locked controller is neither discoverable nor pairable, except for
+//! hosts on the allowlist and during a pairing window opened on request (a
+//! `pairing_mode` chord or `controllerosctl pairing start`). Bonded hosts can
+//! always reconnect; the agent refuses everyone else.
+//!
+//! With a display capability the agent also keeps the passkey prompt the GUI
+//! shows. A numeric comparison holds BlueZ's `RequestConfirmation` call until
+//! the GUI answers through `ConfirmPairing` or the prompt times out.
+
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use common::config::PairingConfig;
+use dbus::Message;
+use dbus_crossroads::MethodErr;
+
+pub type SharedPolicy = Arc<Mutex<PairingPolicy>>;
+
+/// Discoverable and Pairable as applied to the BlueZ adapter.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct AdapterMode {
+    pub discoverable: bool,
+    pub pairable: bool,
+}
+
+/// Passkey shown on the Deck during pairing.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PairingPrompt {
+    pub address: String,
+    pub passkey: u32,
+    /// The host shows the same passkey and the user must confirm it.
+    pub confirm: bool,
+}
+
+#[derive(Debug)]
+struct PendingPrompt {
+    prompt: PairingPrompt,
+    deadline: Instant,
+    /// The held `RequestConfirmation` call.
+    call: Option<Message>,
+}
+
+#[derive(Debug)]
+pub struct PairingPolicy {
+    locked: bool,
+    /// Upper case, as BlueZ reports addresses.
+    allowlist: Vec<String>,
+    default_timeout: Duration,
+    pairing_until: Option<Instant>,
+    prompt_timeout: Duration,
+    prompt: Option<PendingPrompt>,
+}
+
+impl PairingPolicy {
+    pub fn new(cfg: &PairingConfig) -> Self {
+        Self {
+            locked: cfg.locked,
+            allowlist: cfg
+                .allowlist
+                .iter()
+                .map(|a| a.to_ascii_uppercase())
+                .collect(),
+            default_timeout: Duration::from_secs(u64::from(cfg.pairing_timeout_s)),
+            pairing_until: None,
+            prompt_timeout: Duration::from_secs(u64::from(cfg.prompt_timeout_s)),
+            prompt: None,
+        }
+    }
+
+    pub fn shared(cfg: &PairingConfig) -> SharedPolicy {
+        Arc::new(Mutex::new(Self::new(cfg)))
+    }
+
+    /// Open the pairing window for `timeout`, or the configured default.
+    pub fn start_pairing(&mut self, now: Instant, timeout: Option<Duration>) {
+        let timeout = timeout.unwrap_or(self.default_timeout);
+        eprintln!("hidd: pairing mode open for {}s", timeout.as_secs());
+        self.pairing_until = Some(now + timeout);
+    }
+
+    pub fn stop_pairing(&mut self) {
+        if self.pairing_until.take().is_some() {
+            eprintln!("hidd: pairing mode closed");
+        }
+    }
+
+    pub fn pairing_open(&self, now: Instant) -> bool {
+        self.pairing_until.is_some_and(|until| now < until)
+    }
+
+    /// Close an expired pairing window. Returns true when it just closed.
+    pub fn expire(&mut self, now: Instant) -> bool {
+        if self.pairing_until.is_some() && !self.pairing_open(now) {
+            self.stop_pairing();
+            return true;
+        }
+        false
+    }
+
+    /// Whether a host without a bond may pair or stay connected.
+    pub fn admits(&self, address: &str, now: Instant) -> bool {
+        !self.locked
+            || self.pairing_open(now)
+            || self
+                .allowlist
+                .iter()
+                .any(|a| a.eq_ignore_ascii_case(address))
+    }
+
+    /// Show `passkey` for the host to type in.
+    pub fn show_passkey(&mut self, address: &str, passkey: u32, now: Instant) -> Option<Message> {
+        self.set_prompt(address, passkey, None, now)
+    }
+
+    /// Hold BlueZ's `RequestConfirmation` `call` until the user answers.
+    /// Returns the rejection for a confirmation it replaces.
+    pub fn request_confirmation(
+        &mut self,
+        address: &str,
+        passkey: u32,
+        call: Message,
+        now: Instant,
+    ) -> Option<Message> {
+        self.set_prompt(address, passkey, Some(call), now)
+    }
+
+    fn set_prompt(
+        &mut self,
+        address: &str,
+        passkey: u32,
+        call: Option<Message>,
+        now: Instant,
+    ) -> Option<Message> {
+        let replaced = self.answer(false);
+        self.prompt = Some(PendingPrompt {
+            prompt: PairingPrompt {
+                address: address.to_string(),
+                passkey,
+                confirm: call.is_some(),
+            },
+            deadline: now + self.prompt_timeout,
+            call,
+        });
+        replaced
+    }
+
+    pub fn prompt(&self) -> Option<&PairingPrompt> {
+        self.prompt.as_ref().map(|p| &p.prompt)
+    }
+
+    /// Close the prompt. Returns the reply for a held confirmation: success
+    /// when `accept`, otherwise `org.bluez.Error.Rejected`.
+    pub fn answer(&mut self, accept: bool) -> Option<Message> {
+        let call = self.prompt.take()?.call?;
+        if accept {
+            eprintln!("hidd: pairing confirmed on the Deck");
+            return Some(call.method_return());
+        }
+        eprintln!("hidd: pairing rejected on the Deck");
+        let err: MethodErr = ("org.bluez.Error.Rejected", "rejected on the Deck").into();
+        Some(err.to_message(&call))
+    }
+
+    /// Drop a prompt BlueZ cancelled; its call needs no reply.
+    pub fn cancel_prompt(&mut self) {
+        self.prompt = None;
+    }
+
+    /// Close a prompt nobody answered in time, rejecting a held
+    /// confirmation.
+    pub fn expire_prompt(&mut self, now: Instant) -> Option<Message> {
+        if self.prompt.as_ref().is_some_and(|p| now >= p.deadline) {
+            eprintln!("hidd: pairing prompt timed out");
+            return self.answer(false);
+        }
+        None
+    }
+
+    /// Adapter mode for the lock state. `reconnect_only` (after a bonded host
+    /// dropped) hides the controller unless pairing was requested.
+    pub fn adapter_mode(&self, now: Instant, reconnect_only: bool) -> AdapterMode {
+        if self.pairing_open(now) {
+            return AdapterMode {
+                discoverable: true,
+                pairable: true,
+            };
+        }
+        if reconnect_only {
+            return AdapterMode {
+                discoverable: false,
+                pairable: false,
+            };
+        }
+        AdapterMode {
+            discoverable: !self.locked,
+            // Allowlisted hosts still need to pair, by address.
+            pairable: !self.locked || !self.allowlist.is_empty(),
+        }
+    }
+}
+
+/// Copy of BlueZ's `RequestConfirmation` `call` to hold and answer later.
+/// `Message::duplicate` leaves out the serial the reply has to refer to.
+pub fn held_call(call: &Message) -> Option<Message> {
+    let mut held = call.duplicate().ok()?;
+    held.set_serial(call.get_serial()?);
+    Some(held)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{held_call, AdapterMode, PairingPolicy, PairingPrompt};
+    use common::config::PairingConfig;
+    use dbus::{Message, MessageType};
+    use std::time::{Duration, Instant};
+
+    fn locked(allowlist: &[&str]) -> PairingPolicy {
+        PairingPolicy::new(&PairingConfig {
+            locked: true,
+            allowlist: allowlist.iter().map(|a| a.to_string()).collect(),
+            ..PairingConfig::default()
+        })
+    }
+
+    /// A `RequestConfirmation` as the agent receives it, held the way the
+    /// handler holds it.
+    fn confirmation_call() -> Message {
+        let mut received = Message::new_method_call(
+            "org.controlleros.Hidd",
+            "/org/controlleros/agent",
+            "org.bluez.Agent1",
+            "RequestConfirmation",
+        )
+        .unwrap();
+        received.set_serial(7);
+        held_call(&received).expect("received call has a serial")
+    }
+
+    #[test]
+    fn locked_policy_admits_allowlist_and_pairing_window_only() {
+        let now = Instant::now();
+        let mut policy = locked(&["98:b6:e9:01:02:03"]);
+        assert!(policy.admits("98:B6:E9:01:02:03", now));
+        assert!(!policy.admits("AA:AA:AA:AA:AA:AA", now));
+
+        policy.start_pairing(now, Some(Duration::from_secs(30)));
+        assert!(policy.admits("AA:AA:AA:AA:AA:AA", now));
+        let later = now + Duration::from_secs(31);
+        assert!(!policy.admits("AA:AA:AA:AA:AA:AA", later));
+        assert!(policy.expire(later));
+        assert!(!policy.expire(later));
+
+        let open = PairingPolicy::new(&PairingConfig::default());
+        assert!(open.admits("AA:AA:AA:AA:AA:AA", now));
+    }
+
+    #[test]
+    fn adapter_mode_follows_lock_and_pairing_window() {
+        let now = Instant::now();
+        let hidden = AdapterMode {
+            discoverable: false,
+            pairable: false,
+        };
+        let visible = AdapterMode {
+            discoverable: true,
+            pairable: true,
+        };
+
+        let mut policy = locked(&[]);
+        assert_eq!(policy.adapter_mode(now, false), hidden);
+        assert_eq!(
+            locked(&["98:B6:E9:01:02:03"]).adapter_mode(now, false),
+            AdapterMode {
+                discoverable: false,
+                pairable: true,
+            }
+        );
+        policy.start_pairing(now, None);
+        assert_eq!(policy.adapter_mode(now, true), visible);
+
+        let open = PairingPolicy::new(&PairingConfig::default());
+        assert_eq!(open.adapter_mode(now, false), visible);
+        assert_eq!(open.adapter_mode(now, true), hidden);
+    }
+
+    #[test]
+    fn confirmation_is_held_until_answered_or_expired() {
+        let now = Instant::now();
+        let mut policy = PairingPolicy::new(&PairingConfig::default());
+        assert!(policy
+            .request_confirmation("AA:AA:AA:AA:AA:AA", 123456, confirmation_call(), now)
+            .is_none());
+        assert_eq!(
+            policy.prompt(),
+            Some(&PairingPrompt {
+                address: "AA:AA:AA:AA:AA:AA".to_string(),
+                passkey: 123456,
+                confirm: true,
+            })
+        );
+        let reply = policy.answer(true).expect("held call gets a reply");
+        assert_eq!(reply.msg_type(), MessageType::MethodReturn);
+        assert_eq!(reply.get_reply_serial(), Some(7));
+        assert!(policy.prompt().is_none());
+
+        policy.request_confirmation("AA:AA:AA:AA:AA:AA", 1, confirmation_call(), now);
+        assert!(policy
+            .expire_prompt(now + Duration::from_secs(29))
+            .is_none());
+        let reply = policy
+            .expire_prompt(now + Duration::from_secs(30))
+            .expect("expired call is rejected");
+        assert_eq!(reply.msg_type(), MessageType::Error);
+        assert!(policy.prompt().is_none());
+
+        assert!(policy.show_passkey("AA:AA:AA:AA:AA:AA", 42, now).is_none());
+        assert!(!policy.prompt().unwrap().confirm);
+        assert!(policy.answer(false).is_none());
+        assert!(policy.prompt().is_none());
+    }
+}
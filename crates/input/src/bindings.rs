@@ -0,0 +1,501 @@
+#![forbid(unsafe_code)]
+
+//! System key, layer and chord bindings.
+//!
+//! The binding engine combines the mapped hidraw report with the state of the
+//! Deck's power / volume keys and produces the report that is published to
+//! `hidd`. Time-based behavior (long presses, the reserved power-off hold) is
+//! evaluated on every update, so callers must keep calling
+//! [`BindingEngine::evaluate`] while keys are held.
+
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+
+use common::hid::{
+    InputReport, XBOX_BUTTON_A, XBOX_BUTTON_B, XBOX_BUTTON_HOME, XBOX_BUTTON_LB, XBOX_BUTTON_LS,
+    XBOX_BUTTON_RB, XBOX_BUTTON_RS, XBOX_BUTTON_SELECT, XBOX_BUTTON_START, XBOX_BUTTON_X,
+    XBOX_BUTTON_Y,
+};
+
+use crate::mapping::{key_target_usage, KeyAction, MappingConfig, SystemKey};
+use crate::reader::dpad_to_hat;
+
+/// Internal button set: Xbox button bits in the low 16 bits, d-pad directions
+/// above them so they can be remapped like any other button.
+type ButtonSet = u32;
+
+const DPAD_UP: ButtonSet = 1 << 16;
+const DPAD_DOWN: ButtonSet = 1 << 17;
+const DPAD_LEFT: ButtonSet = 1 << 18;
+const DPAD_RIGHT: ButtonSet = 1 << 19;
+const DPAD_MASK: ButtonSet = DPAD_UP | DPAD_DOWN | DPAD_LEFT | DPAD_RIGHT;
+
+fn button_bit(name: &str) -> ButtonSet {
+    match name {
+        "a" => ButtonSet::from(XBOX_BUTTON_A),
+        "b" => ButtonSet::from(XBOX_BUTTON_B),
+        "x" => ButtonSet::from(XBOX_BUTTON_X),
+        "y" => ButtonSet::from(XBOX_BUTTON_Y),
+        "lb" => ButtonSet::from(XBOX_BUTTON_LB),
+        "rb" => ButtonSet::from(XBOX_BUTTON_RB),
+        "back" => ButtonSet::from(XBOX_BUTTON_SELECT),
+        "start" => ButtonSet::from(XBOX_BUTTON_START),
+        "home" => ButtonSet::from(XBOX_BUTTON_HOME),
+        "ls" => ButtonSet::from(XBOX_BUTTON_LS),
+        "rs" => ButtonSet::from(XBOX_BUTTON_RS),
+        "dpad_up" => DPAD_UP,
+        "dpad_down" => DPAD_DOWN,
+        "dpad_left" => DPAD_LEFT,
+        "dpad_right" => DPAD_RIGHT,
+        _ => 0,
+    }
+}
+
+fn hat_to_dpad(hat: u8) -> ButtonSet {
+    match hat {
+        1 => DPAD_UP,
+        2 => DPAD_UP | DPAD_RIGHT,
+        3 => DPAD_RIGHT,
+        4 => DPAD_DOWN | DPAD_RIGHT,
+        5 => DPAD_DOWN,
+        6 => DPAD_DOWN | DPAD_LEFT,
+        7 => DPAD_LEFT,
+        8 => DPAD_UP | DPAD_LEFT,
+        _ => 0,
+    }
+}
+
+fn buttons_of(report: &InputReport) -> ButtonSet {
+    ButtonSet::from(report.buttons) | hat_to_dpad(report.hat)
+}
+
+fn store_buttons(report: &mut InputReport, set: ButtonSet) {
+    report.buttons = (set & 0xffff) as u16;
+    report.hat = dpad_to_hat([
+        set & DPAD_UP != 0,
+        set & DPAD_DOWN != 0,
+        set & DPAD_LEFT != 0,
+        set & DPAD_RIGHT != 0,
+    ]);
+}
+
+fn key_usage(name: &str) -> u8 {
+    key_target_usage(name).unwrap_or(0)
+}
+
+/// Fill the report's key array with up to six distinct held keys.
+fn store_keys(report: &mut InputReport, held: &[u8]) {
+    let mut n = 0;
+    for &usage in held {
+        if usage == 0 || report.keys[..n].contains(&usage) || n == report.keys.len() {
+            continue;
+        }
+        report.keys[n] = usage;
+        n += 1;
+    }
+}
+
+fn key_index(key: SystemKey) -> usize {
+    match key {
+        SystemKey::VolumeUp => 0,
+        SystemKey::VolumeDown => 1,
+        SystemKey::Power => 2,
+    }
+}
+
+#[derive(Debug, Clone)]
+struct KeyBinding {
+    button: ButtonSet,
+    /// Keyboard usage, 0 if the target is a button.
+    key: u8,
+    layer: Option<Vec<(ButtonSet, ButtonSet)>>,
+    long_press: Option<(KeyAction, Duration)>,
+}
+
+#[derive(Debug, Clone)]
+struct Chord {
+    buttons: ButtonSet,
+    keys: Vec<SystemKey>,
+    button: ButtonSet,
+    key: u8,
+    action: Option<KeyAction>,
+}
+
+/// Compiled bindings from a [`MappingConfig`].
+#[derive(Debug, Clone)]
+pub(crate) struct BindingEngine {
+    keys: [Option<KeyBinding>; 3],
+    chords: Vec<Chord>,
+    power_off_hold: Duration,
+}
+
+/// Runtime state of the system keys and active chords.
+#[derive(Debug, Default)]
+pub(crate) struct BindingState {
+    pressed_at: [Option<Instant>; 3],
+    long_press_fired: [bool; 3],
+    chords_active: Vec<bool>,
+}
+
+impl BindingState {
+    pub(crate) fn set_key(&mut self, key: SystemKey, pressed: bool, now: Instant) {
+        let i = key_index(key);
+        if pressed {
+            if self.pressed_at[i].is_none() {
+                self.pressed_at[i] = Some(now);
+                self.long_press_fired[i] = false;
+            }
+        } else {
+            self.pressed_at[i] = None;
+            self.long_press_fired[i] = false;
+        }
+    }
+
+    fn is_held(&self, key: SystemKey) -> bool {
+        self.pressed_at[key_index(key)].is_some()
+    }
+}
+
+impl BindingEngine {
+    pub(crate) fn new(config: &MappingConfig) -> Self {
+        let layers: HashMap<&str, Vec<(ButtonSet, ButtonSet)>> = config
+            .layers
+            .iter()
+            .map(|l| {
+                let remap = l
+                    .remap
+                    .iter()
+                    .map(|(from, to)| (button_bit(from), button_bit(to)))
+                    .collect();
+                (l.name.as_str(), remap)
+            })
+            .collect();
+
+        let mut keys: [Option<KeyBinding>; 3] = Default::default();
+        for binding in &config.system_keys {
+            keys[key_index(binding.key)] = Some(KeyBinding {
+                button: binding.button.as_deref().map_or(0, button_bit),
+                key: binding.button.as_deref().map_or(0, key_usage),
+                layer: binding
+                    .layer
+                    .as_deref()
+                    .and_then(|name| layers.get(name).cloned()),
+                long_press: binding
+                    .long_press
+                    .map(|a| (a, Duration::from_millis(u64::from(binding.long_press_ms)))),
+            });
+        }
+
+        let chords = config
+            .chords
+            .iter()
+            .map(|c| Chord {
+                buttons: c.inputs.iter().map(|i| button_bit(i)).fold(0, |a, b| a | b),
+                keys: c
+                    .inputs
+                    .iter()
+                    .filter_map(|i| SystemKey::from_name(i))
+                    .collect(),
+                button: c.button.as_deref().map_or(0, button_bit),
+                key: c.button.as_deref().map_or(0, key_usage),
+                action: c.action,
+            })
+            .collect();
+
+        Self {
+            keys,
+            chords,
+            power_off_hold: Duration::from_millis(u64::from(config.power_off_hold_ms)),
+        }
+    }
+
+    /// Combine `raw` with the current key state. Newly fired actions are
+    /// appended to `actions`.
+    pub(crate) fn evaluate(
+        &self,
+        raw: &InputReport,
+        state: &mut BindingState,
+        now: Instant,
+        actions: &mut Vec<KeyAction>,
+    ) -> InputReport {
+        state.chords_active.resize(self.chords.len(), false);
+        let mut source = buttons_of(raw);
+
+        // Chords are matched against the physical inputs, before layers.
+        let keys_held = SystemKey::ALL.map(|k| state.is_held(k));
+        let mut chord_buttons = 0;
+        let mut held_keys = Vec::new();
+        for (chord, active) in self.chords.iter().zip(state.chords_active.iter_mut()) {
+            let held = source & chord.buttons == chord.buttons
+                && chord.keys.iter().all(|k| keys_held[key_index(*k)]);
+            if held {
+                source &= !chord.buttons;
+                chord_buttons |= chord.button;
+                held_keys.push(chord.key);
+                if !*active {
+                    if let Some(action) = chord.action {
+                        actions.push(action);
+                    }
+                }
+            }
+            *active = held;
+        }
+
+        let mut out = source;
+        for key in SystemKey::ALL {
+            let i = key_index(key);
+            let Some(pressed_at) = state.pressed_at[i] else {
+                continue;
+            };
+            let held_for = now.saturating_duration_since(pressed_at);
+
+            if key == SystemKey::Power && held_for >= self.power_off_hold {
+                if !state.long_press_fired[i] {
+                    state.long_press_fired[i] = true;
+                    actions.push(KeyAction::Poweroff);
+                }
+                continue;
+            }
+
+            let Some(binding) = &self.keys[i] else {
+                continue;
+            };
+            if let Some(remap) = &binding.layer {
+                out = apply_layer(out, source, remap);
+            }
+            out |= binding.button;
+            held_keys.push(binding.key);
+            if let Some((action, hold)) = binding.long_press {
+                if held_for >= hold && !state.long_press_fired[i] {
+                    state.long_press_fired[i] = true;
+                    actions.push(action);
+                }
+            }
+        }
+        out |= chord_buttons;
+
+        let mut report = *raw;
+        store_buttons(&mut report, out & (0xffff | DPAD_MASK));
+        store_keys(&mut report, &held_keys);
+        report
+    }
+}
+
+fn apply_layer(
+    current: ButtonSet,
+    source: ButtonSet,
+    remap: &[(ButtonSet, ButtonSet)],
+) -> ButtonSet {
+    let mut out = current;
+    for (from, _) in remap {
+        out &= !from;
+    }
+    for (from, to) in remap {
+        if source & from != 0 {
+            out |= to;
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn engine(toml: &str) -> BindingEngine {
+        BindingEngine::new(&MappingConfig::from_toml(toml).unwrap())
+    }
+
+    const CONFIG: &str = r#"
+[device]
+
+[[system_keys]]
+key = "volume_up"
+button = "home"
+
+[[system_keys]]
+key = "volume_down"
+layer = "alt"
+long_press = "cycle_mapping"
+long_press_ms = 500
+
+[[layers]]
+name = "alt"
+remap = { a = "x", dpad_up = "y" }
+
+[[chords]]
+inputs = ["back", "start"]
+button = "home"
+
+[[chords]]
+inputs = ["volume_up", "lb"]
+action = "cycle_mapping"
+"#;
+
+    #[test]
+    fn system_key_acts_as_extra_button() {
+        let engine = engine(CONFIG);
+        let mut state = BindingState::default();
+        let mut actions = Vec::new();
+        let now = Instant::now();
+
+        state.set_key(SystemKey::VolumeUp, true, now);
+        let out = engine.evaluate(&InputReport::default(), &mut state, now, &mut actions);
+        assert_eq!(out.buttons, XBOX_BUTTON_HOME);
+        assert!(actions.is_empty());
+    }
+
+    #[test]
+    fn layer_remaps_buttons_and_dpad_while_held() {
+        let engine = engine(CONFIG);
+        let mut state = BindingState::default();
+        let mut actions = Vec::new();
+        let now = Instant::now();
+        let raw = InputReport {
+            buttons: XBOX_BUTTON_A | XBOX_BUTTON_B,
+            hat: 1,
+            ..InputReport::default()
+        };
+
+        let out = engine.evaluate(&raw, &mut state, now, &mut actions);
+        assert_eq!(out.buttons, XBOX_BUTTON_A | XBOX_BUTTON_B);
+        assert_eq!(out.hat, 1);
+
+        state.set_key(SystemKey::VolumeDown, true, now);
+        let out = engine.evaluate(&raw, &mut state, now, &mut actions);
+        assert_eq!(out.buttons, XBOX_BUTTON_X | XBOX_BUTTON_B | XBOX_BUTTON_Y);
+        assert_eq!(out.hat, 0);
+    }
+
+    #[test]
+    fn long_press_fires_once() {
+        let engine = engine(CONFIG);
+        let mut state = BindingState::default();
+        let mut actions = Vec::new();
+        let t0 = Instant::now();
+        let raw = InputReport::default();
+
+        state.set_key(SystemKey::VolumeDown, true, t0);
+        engine.evaluate(
+            &raw,
+            &mut state,
+            t0 + Duration::from_millis(100),
+            &mut actions,
+        );
+        assert!(actions.is_empty());
+        engine.evaluate(
+            &raw,
+            &mut state,
+            t0 + Duration::from_millis(600),
+            &mut actions,
+        );
+        engine.evaluate(
+            &raw,
+            &mut state,
+            t0 + Duration::from_millis(900),
+            &mut actions,
+        );
+        assert_eq!(actions, vec![KeyAction::CycleMapping]);
+
+        state.set_key(SystemKey::VolumeDown, false, t0 + Duration::from_secs(1));
+        state.set_key(SystemKey::VolumeDown, true, t0 + Duration::from_secs(2));
+        engine.evaluate(
+            &raw,
+            &mut state,
+            t0 + Duration::from_millis(2600),
+            &mut actions,
+        );
+        assert_eq!(actions.len(), 2);
+    }
+
+    #[test]
+    fn chord_replaces_member_buttons() {
+        let engine = engine(CONFIG);
+        let mut state = BindingState::default();
+        let mut actions = Vec::new();
+        let raw = InputReport {
+            buttons: XBOX_BUTTON_SELECT | XBOX_BUTTON_START | XBOX_BUTTON_A,
+            ..InputReport::default()
+        };
+        let out = engine.evaluate(&raw, &mut state, Instant::now(), &mut actions);
+        assert_eq!(out.buttons, XBOX_BUTTON_HOME | XBOX_BUTTON_A);
+    }
+
+    #[test]
+    fn chord_with_system_key_fires_action_on_activation_only() {
+        let engine = engine(CONFIG);
+        let mut state = BindingState::default();
+        let mut actions = Vec::new();
+        let now = Instant::now();
+        let raw = InputReport {
+            buttons: XBOX_BUTTON_LB,
+            ..InputReport::default()
+        };
+        state.set_key(SystemKey::VolumeUp, true, now);
+        let out = engine.evaluate(&raw, &mut state, now, &mut actions);
+        engine.evaluate(&raw, &mut state, now, &mut actions);
+        assert_eq!(actions, vec![KeyAction::CycleMapping]);
+        // LB is consumed by the chord; volume_up still contributes its button.
+        assert_eq!(out.buttons, XBOX_BUTTON_HOME);
+    }
+
+    #[test]
+    fn key_targets_fill_keyboard_array() {
+        let engine = engine(
+            r#"
+[device]
+
+[[system_keys]]
+key = "volume_up"
+button = "key_esc"
+
+[[chords]]
+inputs = ["back", "start"]
+button = "key_f11"
+"#,
+        );
+        let mut state = BindingState::default();
+        let mut actions = Vec::new();
+        let now = Instant::now();
+        let raw = InputReport {
+            buttons: XBOX_BUTTON_SELECT | XBOX_BUTTON_START,
+            ..InputReport::default()
+        };
+
+        state.set_key(SystemKey::VolumeUp, true, now);
+        let out = engine.evaluate(&raw, &mut state, now, &mut actions);
+        assert_eq!(out.buttons, 0);
+        assert_eq!(out.keys, [0x44, 0x29, 0, 0, 0, 0]);
+    }
+
+    #[test]
+    fn power_hold_always_powers_off() {
+        let engine = engine("[device]\n");
+        let mut state = BindingState::default();
+        let mut actions = Vec::new();
+        let t0 = Instant::now();
+        let raw = InputReport::default();
+
+        state.set_key(SystemKey::Power, true, t0);
+        engine.evaluate(
+            &raw,
+            &mut state,
+            t0 + Duration::from_millis(2999),
+            &mut actions,
+        );
+        assert!(actions.is_empty());
+        engine.evaluate(
+            &raw,
+            &mut state,
+            t0 + Duration::from_millis(3000),
+            &mut actions,
+        );
+        engine.evaluate(
+            &raw,
+            &mut state,
+            t0 + Duration::from_millis(3500),
+            &mut actions,
+        );
+        assert_eq!(actions, vec![KeyAction::Poweroff]);
+    }
+}
@@ -23,6 +23,8 @@ pub struct InputDeviceInfo {
     pub has_gamepad_keys: bool,
     /// Whether this device matches the Steam Deck gamepad heuristic.
     pub is_deck_gamepad: bool,
+    /// Whether this device reports the power button or volume keys.
+    pub has_system_keys: bool,
     /// Human-readable summary of capabilities.
     pub caps_summary: String,
 }
@@ -101,6 +103,12 @@ fn build_device_info(path: &Path, dev: &Device) -> InputDeviceInfo {
 
     let is_deck_gamepad = is_steam_deck_gamepad(&name, vendor, product, has_abs, has_gamepad_keys);
 
+    let has_system_keys = supported_keys.is_some_and(|keys| {
+        keys.contains(KeyCode::KEY_POWER)
+            || keys.contains(KeyCode::KEY_VOLUMEUP)
+            || keys.contains(KeyCode::KEY_VOLUMEDOWN)
+    });
+
     let caps_summary = build_caps_summary(dev);
 
     InputDeviceInfo {
@@ -112,6 +120,7 @@ fn build_device_info(path: &Path, dev: &Device) -> InputDeviceInfo {
         has_abs,
         has_gamepad_keys,
         is_deck_gamepad,
+        has_system_keys,
         caps_summary,
     }
 }
@@ -144,6 +153,20 @@ fn build_caps_summary(dev: &Device) -> String {
         if !found.is_empty() {
             parts.push(format!("buttons=[{}]", found.join(",")));
         }
+
+        let system_keys: &[(KeyCode, &str)] = &[
+            (KeyCode::KEY_POWER, "Power"),
+            (KeyCode::KEY_VOLUMEUP, "VolUp"),
+            (KeyCode::KEY_VOLUMEDOWN, "VolDown"),
+        ];
+        let found: Vec<&str> = system_keys
+            .iter()
+            .filter(|(code, _)| keys.contains(*code))
+            .map(|(_, label)| *label)
+            .collect();
+        if !found.is_empty() {
+            parts.push(format!("keys=[{}]", found.join(",")));
+        }
     }
 
     if let Some(axes) = dev.supported_absolute_axes() {
@@ -267,6 +290,7 @@ mod tests {
             has_abs: deck,
             has_gamepad_keys: deck,
             is_deck_gamepad: deck,
+            has_system_keys: false,
             caps_summary: String::new(),
         }
     }
@@ -0,0 +1,475 @@
+#![forbid(unsafe_code)]
+
+//! Per-input smoothing and anti-snapback filters.
+//!
+//! Filters run inside the hidraw reader thread on every Deck report, before
+//! the mapped state is published. Each filter is driven by the report's
+//! monotonic timestamp rather than a fixed sample period, so the output does
+//! not depend on how often `hidd` samples the state (`report.rate_hz`) or on
+//! jitter in the controller's own report interval.
+//!
+//! Values are processed in normalized units: sticks, trackpad coordinates
+//! and gyro rates in `-1.0..=1.0`, triggers in `0.0..=1.0`.
+
+use std::f32::consts::PI;
+
+use common::hid::{InputReport, TouchPoint, XBOX_STICK_MAX, XBOX_STICK_MIN, XBOX_TRIGGER_MAX};
+
+use crate::mapping::{FilterKind, FilterMapping};
+
+/// Smallest time step accepted by the filters. Reports arriving with the same
+/// (or a non-monotonic) timestamp are treated as this far apart.
+const MIN_DT_SECS: f32 = 1.0e-4;
+
+/// One stage of a per-input filter chain.
+#[derive(Debug, Clone)]
+enum Filter {
+    Ema(EmaFilter),
+    OneEuro(OneEuroFilter),
+    AntiSnapback(AntiSnapbackFilter),
+}
+
+impl Filter {
+    fn from_kind(kind: FilterKind) -> Self {
+        match kind {
+            FilterKind::Ema { time_constant_ms } => Self::Ema(EmaFilter::new(time_constant_ms)),
+            FilterKind::OneEuro {
+                min_cutoff,
+                beta,
+                d_cutoff,
+            } => Self::OneEuro(OneEuroFilter::new(min_cutoff, beta, d_cutoff)),
+            FilterKind::AntiSnapback {
+                window_ms,
+                arm_threshold,
+            } => Self::AntiSnapback(AntiSnapbackFilter::new(window_ms, arm_threshold)),
+        }
+    }
+
+    fn apply(&mut self, value: f32, t: f64) -> f32 {
+        match self {
+            Self::Ema(f) => f.apply(value, t),
+            Self::OneEuro(f) => f.apply(value, t),
+            Self::AntiSnapback(f) => f.apply(value, t),
+        }
+    }
+
+    /// Forget past samples, so the next one passes through unfiltered.
+    fn reset(&mut self) {
+        match self {
+            Self::Ema(f) => f.last = None,
+            Self::OneEuro(f) => f.last = None,
+            Self::AntiSnapback(f) => f.armed = None,
+        }
+    }
+}
+
+/// Time-constant exponential moving average.
+///
+/// `alpha = 1 - exp(-dt / tau)`, so the same `time_constant_ms` gives the same
+/// response whatever the sample interval is.
+#[derive(Debug, Clone)]
+struct EmaFilter {
+    tau_secs: f32,
+    last: Option<(f32, f64)>,
+}
+
+impl EmaFilter {
+    fn new(time_constant_ms: f32) -> Self {
+        Self {
+            tau_secs: time_constant_ms / 1000.0,
+            last: None,
+        }
+    }
+
+    fn apply(&mut self, value: f32, t: f64) -> f32 {
+        let out = match self.last {
+            None => value,
+            Some((prev, prev_t)) => {
+                let dt = elapsed_secs(prev_t, t);
+                let alpha = 1.0 - (-dt / self.tau_secs).exp();
+                prev + alpha * (value - prev)
+            }
+        };
+        self.last = Some((out, t));
+        out
+    }
+}
+
+/// 1€ filter (Casiez, Roussel, Vogel — CHI 2012).
+///
+/// Adapts its cutoff frequency to the signal speed: heavy smoothing when the
+/// input is nearly still (removes jitter), little smoothing when it moves
+/// fast (keeps latency low).
+#[derive(Debug, Clone)]
+struct OneEuroFilter {
+    min_cutoff: f32,
+    beta: f32,
+    d_cutoff: f32,
+    last: Option<OneEuroState>,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct OneEuroState {
+    value: f32,
+    derivative: f32,
+    t: f64,
+}
+
+impl OneEuroFilter {
+    fn new(min_cutoff: f32, beta: f32, d_cutoff: f32) -> Self {
+        Self {
+            min_cutoff,
+            beta,
+            d_cutoff,
+            last: None,
+        }
+    }
+
+    fn apply(&mut self, value: f32, t: f64) -> f32 {
+        let Some(prev) = self.last else {
+            self.last = Some(OneEuroState {
+                value,
+                derivative: 0.0,
+                t,
+            });
+            return value;
+        };
+
+        let dt = elapsed_secs(prev.t, t);
+        let raw_derivative = (value - prev.value) / dt;
+        let derivative = lerp(
+            prev.derivative,
+            raw_derivative,
+            smoothing_factor(dt, self.d_cutoff),
+        );
+        let cutoff = self.min_cutoff + self.beta * derivative.abs();
+        let out = lerp(prev.value, value, smoothing_factor(dt, cutoff));
+
+        self.last = Some(OneEuroState {
+            value: out,
+            derivative,
+            t,
+        });
+        out
+    }
+}
+
+/// Suppresses the short opposite-direction spike a stick produces when it is
+/// released from a large deflection and springs back past center.
+///
+/// The filter arms whenever the input is deflected beyond `arm_threshold`.
+/// For `window_ms` after the last armed sample, any value on the opposite side
+/// of center is reported as center instead.
+#[derive(Debug, Clone)]
+struct AntiSnapbackFilter {
+    window_secs: f64,
+    arm_threshold: f32,
+    armed: Option<(f32, f64)>,
+}
+
+impl AntiSnapbackFilter {
+    fn new(window_ms: f32, arm_threshold: f32) -> Self {
+        Self {
+            window_secs: f64::from(window_ms) / 1000.0,
+            arm_threshold,
+            armed: None,
+        }
+    }
+
+    fn apply(&mut self, value: f32, t: f64) -> f32 {
+        if value.abs() >= self.arm_threshold {
+            self.armed = Some((value.signum(), t));
+            return value;
+        }
+
+        if let Some((sign, armed_at)) = self.armed {
+            if t - armed_at > self.window_secs {
+                self.armed = None;
+            } else if value != 0.0 && value.signum() != sign {
+                return 0.0;
+            }
+        }
+        value
+    }
+}
+
+fn elapsed_secs(prev: f64, now: f64) -> f32 {
+    ((now - prev) as f32).max(MIN_DT_SECS)
+}
+
+fn smoothing_factor(dt: f32, cutoff: f32) -> f32 {
+    let r = 2.0 * PI * cutoff * dt;
+    r / (r + 1.0)
+}
+
+fn lerp(from: f32, to: f32, alpha: f32) -> f32 {
+    from + alpha * (to - from)
+}
+
+/// Filter chains for every mapped input, built from the mapping config.
+#[derive(Debug, Clone, Default)]
+pub(crate) struct FilterBank {
+    lx: Vec<Filter>,
+    ly: Vec<Filter>,
+    rx: Vec<Filter>,
+    ry: Vec<Filter>,
+    lt: Vec<Filter>,
+    rt: Vec<Filter>,
+    /// X and Y of the left and right trackpads.
+    pads: [[Vec<Filter>; 2]; 2],
+    /// Pitch, yaw and roll rates.
+    gyro: [Vec<Filter>; 3],
+}
+
+impl FilterBank {
+    pub(crate) fn new(filters: &[FilterMapping]) -> Self {
+        let mut bank = Self::default();
+        for mapping in filters {
+            if let Some(chain) = bank.chain_mut(&mapping.input) {
+                chain.push(Filter::from_kind(mapping.kind));
+            }
+        }
+        bank
+    }
+
+    fn chain_mut(&mut self, input: &str) -> Option<&mut Vec<Filter>> {
+        match input {
+            "lx" => Some(&mut self.lx),
+            "ly" => Some(&mut self.ly),
+            "rx" => Some(&mut self.rx),
+            "ry" => Some(&mut self.ry),
+            "lt" => Some(&mut self.lt),
+            "rt" => Some(&mut self.rt),
+            "lpad_x" => Some(&mut self.pads[0][0]),
+            "lpad_y" => Some(&mut self.pads[0][1]),
+            "rpad_x" => Some(&mut self.pads[1][0]),
+            "rpad_y" => Some(&mut self.pads[1][1]),
+            "gyro_pitch" => Some(&mut self.gyro[0]),
+            "gyro_yaw" => Some(&mut self.gyro[1]),
+            "gyro_roll" => Some(&mut self.gyro[2]),
+            _ => None,
+        }
+    }
+
+    /// Run every configured chain over `report`, sampled at `t` seconds on a
+    /// monotonic clock.
+    pub(crate) fn apply(&mut self, report: &mut InputReport, t: f64) {
+        report.lx = filter_stick(&mut self.lx, report.lx, t);
+        report.ly = filter_stick(&mut self.ly, report.ly, t);
+        report.rx = filter_stick(&mut self.rx, report.rx, t);
+        report.ry = filter_stick(&mut self.ry, report.ry, t);
+        report.lt = filter_trigger(&mut self.lt, report.lt, t);
+        report.rt = filter_trigger(&mut self.rt, report.rt, t);
+        for (chains, touch) in self.pads.iter_mut().zip(&mut report.touch) {
+            filter_touch(chains, touch, t);
+        }
+        for (chain, rate) in self.gyro.iter_mut().zip(&mut report.gyro) {
+            *rate = filter_signed(chain, *rate, t);
+        }
+    }
+}
+
+fn run_chain(chain: &mut [Filter], value: f32, t: f64) -> f32 {
+    chain.iter_mut().fold(value, |v, f| f.apply(v, t))
+}
+
+fn filter_stick(chain: &mut [Filter], value: i16, t: f64) -> i16 {
+    if chain.is_empty() {
+        return value;
+    }
+    let scale = f32::from(XBOX_STICK_MAX);
+    let out = run_chain(chain, f32::from(value) / scale, t);
+    (out * scale)
+        .round()
+        .clamp(f32::from(XBOX_STICK_MIN), f32::from(XBOX_STICK_MAX)) as i16
+}
+
+/// Trackpad and gyro values span the full `i16` range.
+fn filter_signed(chain: &mut [Filter], value: i16, t: f64) -> i16 {
+    if chain.is_empty() {
+        return value;
+    }
+    let scale = f32::from(i16::MAX);
+    let out = run_chain(chain, f32::from(value) / scale, t);
+    (out * scale)
+        .round()
+        .clamp(f32::from(i16::MIN), f32::from(i16::MAX)) as i16
+}
+
+/// A new contact starts where the finger lands, not where the last one
+/// lifted, so the chains restart whenever the pad is released.
+fn filter_touch(chains: &mut [Vec<Filter>; 2], touch: &mut TouchPoint, t: f64) {
+    if !touch.active {
+        chains.iter_mut().flatten().for_each(Filter::reset);
+        return;
+    }
+    touch.x = filter_signed(&mut chains[0], touch.x, t);
+    touch.y = filter_signed(&mut chains[1], touch.y, t);
+}
+
+fn filter_trigger(chain: &mut [Filter], value: u16, t: f64) -> u16 {
+    if chain.is_empty() {
+        return value;
+    }
+    let scale = f32::from(XBOX_TRIGGER_MAX);
+    let out = run_chain(chain, f32::from(value) / scale, t);
+    (out * scale).round().clamp(0.0, scale) as u16
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Feed a step from 0 to 1 sampled at `hz` and return the value reached
+    /// after `duration` seconds.
+    fn step_response(filter: &mut Filter, hz: f64, duration: f64) -> f32 {
+        let dt = 1.0 / hz;
+        let mut t = 0.0;
+        filter.apply(0.0, t);
+        let mut out = 0.0;
+        while t < duration - 1e-9 {
+            t += dt;
+            out = filter.apply(1.0, t);
+        }
+        out
+    }
+
+    #[test]
+    fn ema_is_rate_independent() {
+        let kind = FilterKind::Ema {
+            time_constant_ms: 20.0,
+        };
+        let slow = step_response(&mut Filter::from_kind(kind), 125.0, 0.04);
+        let fast = step_response(&mut Filter::from_kind(kind), 1000.0, 0.04);
+        assert!((slow - fast).abs() < 0.01, "slow={slow} fast={fast}");
+        // Two time constants → ~86% of the step.
+        assert!((fast - 0.865).abs() < 0.01, "fast={fast}");
+    }
+
+    #[test]
+    fn one_euro_is_rate_independent() {
+        let kind = FilterKind::OneEuro {
+            min_cutoff: 5.0,
+            beta: 0.0,
+            d_cutoff: 1.0,
+        };
+        let slow = step_response(&mut Filter::from_kind(kind), 250.0, 0.05);
+        let fast = step_response(&mut Filter::from_kind(kind), 1000.0, 0.05);
+        assert!((slow - fast).abs() < 0.05, "slow={slow} fast={fast}");
+    }
+
+    #[test]
+    fn one_euro_beta_reduces_lag_for_fast_motion() {
+        let still = FilterKind::OneEuro {
+            min_cutoff: 1.0,
+            beta: 0.0,
+            d_cutoff: 1.0,
+        };
+        let adaptive = FilterKind::OneEuro {
+            min_cutoff: 1.0,
+            beta: 5.0,
+            d_cutoff: 1.0,
+        };
+        let lagging = step_response(&mut Filter::from_kind(still), 250.0, 0.02);
+        let tracking = step_response(&mut Filter::from_kind(adaptive), 250.0, 0.02);
+        assert!(tracking > lagging, "tracking={tracking} lagging={lagging}");
+    }
+
+    #[test]
+    fn anti_snapback_suppresses_overshoot_after_release() {
+        let mut f = Filter::from_kind(FilterKind::AntiSnapback {
+            window_ms: 40.0,
+            arm_threshold: 0.6,
+        });
+        assert_eq!(f.apply(0.95, 0.000), 0.95);
+        // Released: springs through center and overshoots negative.
+        assert_eq!(f.apply(0.10, 0.004), 0.10);
+        assert_eq!(f.apply(-0.30, 0.008), 0.0);
+        assert_eq!(f.apply(-0.05, 0.020), 0.0);
+        // Outside the window, opposite values pass through again.
+        assert_eq!(f.apply(-0.30, 0.100), -0.30);
+    }
+
+    #[test]
+    fn anti_snapback_window_is_time_based() {
+        let mut f = Filter::from_kind(FilterKind::AntiSnapback {
+            window_ms: 40.0,
+            arm_threshold: 0.6,
+        });
+        f.apply(-0.9, 0.0);
+        // Many samples inside the window are all suppressed...
+        for i in 1..=30 {
+            assert_eq!(f.apply(0.2, f64::from(i) * 0.001), 0.0);
+        }
+        // ...and the first one past it is not.
+        assert_eq!(f.apply(0.2, 0.041), 0.2);
+    }
+
+    #[test]
+    fn anti_snapback_keeps_deliberate_same_direction_motion() {
+        let mut f = Filter::from_kind(FilterKind::AntiSnapback {
+            window_ms: 40.0,
+            arm_threshold: 0.6,
+        });
+        f.apply(0.9, 0.0);
+        assert_eq!(f.apply(0.4, 0.005), 0.4);
+    }
+
+    #[test]
+    fn bank_only_touches_configured_inputs() {
+        let mut bank = FilterBank::new(&[FilterMapping {
+            input: "lx".to_string(),
+            kind: FilterKind::Ema {
+                time_constant_ms: 1000.0,
+            },
+        }]);
+        let mut report = InputReport::default();
+        bank.apply(&mut report, 0.0);
+
+        report.lx = XBOX_STICK_MAX;
+        report.ly = XBOX_STICK_MAX;
+        bank.apply(&mut report, 0.004);
+        assert!(report.lx > 0 && report.lx < XBOX_STICK_MAX / 10);
+        assert_eq!(report.ly, XBOX_STICK_MAX);
+    }
+
+    #[test]
+    fn bank_filters_gyro_and_restarts_trackpads_on_release() {
+        let ema = FilterKind::Ema {
+            time_constant_ms: 1000.0,
+        };
+        let mut bank = FilterBank::new(&[
+            FilterMapping {
+                input: "rpad_x".to_string(),
+                kind: ema,
+            },
+            FilterMapping {
+                input: "gyro_yaw".to_string(),
+                kind: ema,
+            },
+        ]);
+        let touch = |x: i16| TouchPoint {
+            active: true,
+            clicked: false,
+            x,
+            y: 0,
+        };
+        let mut report = InputReport::default();
+        report.touch[1] = touch(0);
+        bank.apply(&mut report, 0.0);
+
+        report.touch[1] = touch(i16::MAX);
+        report.gyro = [i16::MAX; 3];
+        bank.apply(&mut report, 0.004);
+        assert!(report.touch[1].x > 0 && report.touch[1].x < i16::MAX / 10);
+        assert!(report.gyro[1] > 0 && report.gyro[1] < i16::MAX / 10);
+        assert_eq!(report.gyro[0], i16::MAX);
+
+        // Lifting the finger drops the history: the next touch lands as is.
+        report.touch[1] = TouchPoint::default();
+        bank.apply(&mut report, 0.008);
+        report.touch[1] = touch(20_000);
+        bank.apply(&mut report, 0.012);
+        assert_eq!(report.touch[1].x, 20_000);
+    }
+}
@@ -1,10 +1,12 @@
 //! Low-level hidraw interface for the Steam Deck controller.
 //!
 //! This module discovers the Deck's client hidraw device, sends HID feature
-//! reports to disable lizard mode, and reads raw 64-byte input reports.
+//! reports to disable lizard mode and read the unit serial, and reads raw
+//! 64-byte input reports.
 //!
 //! Safety: ioctl and poll calls require unsafe blocks. All unsafe usage is
-//! confined to `send_feature_report` and `read_report_timeout`.
+//! confined to `send_feature_report`, `get_feature_report` and
+//! `read_report_timeout`.
 
 use std::fs;
 use std::io::{self, Read};
@@ -17,6 +19,13 @@ const STEAM_DECK_PID: u16 = 0x1205;
 // HID command IDs from hid-steam.c
 const ID_CLEAR_DIGITAL_MAPPINGS: u8 = 0x81;
 const ID_SET_SETTINGS_VALUES: u8 = 0x87;
+const ID_GET_STRING_ATTRIBUTE: u8 = 0xAE;
+
+// String attribute IDs for ID_GET_STRING_ATTRIBUTE
+const ATTRIB_STR_UNIT_SERIAL: u8 = 0x01;
+
+/// Longest serial the controller reports (STEAM_SERIAL_LEN in hid-steam.c).
+const SERIAL_LEN: usize = 0x15;
 
 // Setting register IDs (sequential enum starting at 0 in kernel)
 const SETTING_LEFT_TRACKPAD_MODE: u8 = 7;
@@ -44,6 +53,12 @@ fn hidiocsfeature(len: usize) -> libc::c_ulong {
     ((dir << 30) | (size << 16) | (ty << 8) | nr) as libc::c_ulong
 }
 
+/// Compute HIDIOCGFEATURE ioctl number for a given buffer length.
+/// HIDIOCGFEATURE(len) = _IOC(_IOC_WRITE|_IOC_READ, 'H', 0x07, len)
+fn hidiocgfeature(len: usize) -> libc::c_ulong {
+    hidiocsfeature(len) + 1
+}
+
 pub struct HidrawDevice {
     file: fs::File,
 }
@@ -109,6 +124,12 @@ pub fn discover_deck_hidraw() -> Result<PathBuf, String> {
     Ok(candidates.last().unwrap().0.clone())
 }
 
+/// Read the unit serial number of the Deck controller.
+pub fn read_deck_serial() -> Result<String, String> {
+    let path = discover_deck_hidraw()?;
+    HidrawDevice::open(&path)?.read_serial()
+}
+
 impl HidrawDevice {
     pub fn open(path: &Path) -> Result<Self, String> {
         let file = fs::File::options()
@@ -142,6 +163,39 @@ impl HidrawDevice {
         Ok(())
     }
 
+    /// Read a HID feature report (GET_REPORT) from the controller.
+    ///
+    /// Returns the 64-byte payload after report ID 0x00, matching the
+    /// kernel's `steam_recv_report`.
+    fn get_feature_report(&self) -> Result<[u8; 64], String> {
+        let mut buf = [0u8; 65];
+
+        let fd = self.file.as_raw_fd();
+        // SAFETY: ioctl with HIDIOCGFEATURE fills the stack-allocated buffer,
+        // whose length is encoded in the request number.
+        let ret = unsafe { libc::ioctl(fd, hidiocgfeature(buf.len()), buf.as_mut_ptr()) };
+        if ret < 0 {
+            return Err(format!(
+                "HIDIOCGFEATURE failed: {}",
+                io::Error::last_os_error()
+            ));
+        }
+        let mut reply = [0u8; 64];
+        reply.copy_from_slice(&buf[1..]);
+        Ok(reply)
+    }
+
+    /// Read the controller's unit serial number, as `steam_get_serial` does.
+    pub fn read_serial(&self) -> Result<String, String> {
+        self.send_feature_report(&[
+            ID_GET_STRING_ATTRIBUTE,
+            SERIAL_LEN as u8 + 1,
+            ATTRIB_STR_UNIT_SERIAL,
+        ])?;
+        let reply = self.get_feature_report()?;
+        parse_serial_reply(&reply).ok_or_else(|| "unexpected serial number reply".to_string())
+    }
+
     /// Disable lizard mode on the Steam Deck controller.
     ///
     /// Sends two HID feature reports matching the kernel's
@@ -205,3 +259,48 @@ impl HidrawDevice {
         self.file.read(buf)
     }
 }
+
+/// Parse an `ID_GET_STRING_ATTRIBUTE` reply: `0xAE <len> <attrib> <string>`,
+/// where the string is NUL-terminated within `len` bytes.
+fn parse_serial_reply(reply: &[u8]) -> Option<String> {
+    let [id, len, attrib, rest @ ..] = reply else {
+        return None;
+    };
+    if *id != ID_GET_STRING_ATTRIBUTE || *attrib != ATTRIB_STR_UNIT_SERIAL {
+        return None;
+    }
+    let len = usize::from(*len).min(SERIAL_LEN).min(rest.len());
+    let raw = &rest[..len];
+    let end = raw.iter().position(|&b| b == 0).unwrap_or(raw.len());
+    let serial = std::str::from_utf8(&raw[..end]).ok()?.trim();
+    (!serial.is_empty()).then(|| serial.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{hidiocgfeature, hidiocsfeature, parse_serial_reply, ATTRIB_STR_UNIT_SERIAL};
+
+    #[test]
+    fn feature_ioctl_numbers_match_kernel_headers() {
+        assert_eq!(hidiocsfeature(65), 0xc041_4806);
+        assert_eq!(hidiocgfeature(65), 0xc041_4807);
+    }
+
+    #[test]
+    fn parses_serial_reply() {
+        let mut reply = [0u8; 64];
+        reply[..3].copy_from_slice(&[0xae, 0x16, ATTRIB_STR_UNIT_SERIAL]);
+        reply[3..13].copy_from_slice(b"FVAA123456");
+        assert_eq!(parse_serial_reply(&reply).as_deref(), Some("FVAA123456"));
+
+        reply[2] = 0x00; // board serial, not the unit serial
+        assert_eq!(parse_serial_reply(&reply), None);
+        reply[2] = ATTRIB_STR_UNIT_SERIAL;
+        reply[0] = 0x83;
+        assert_eq!(parse_serial_reply(&reply), None);
+        assert_eq!(
+            parse_serial_reply(&[0xae, 0x16, ATTRIB_STR_UNIT_SERIAL]),
+            None
+        );
+    }
+}
@@ -1,8 +1,15 @@
+mod bindings;
 mod discovery;
+mod filter;
 mod hidraw;
 mod mapping;
 mod reader;
+mod syskeys;
 
 pub use discovery::{discover_devices, select_device, InputDeviceInfo};
-pub use mapping::{AxisMapping, ButtonMapping, DeviceFilter, MappingConfig};
-pub use reader::InputReader;
+pub use hidraw::read_deck_serial;
+pub use mapping::{
+    AxisMapping, ButtonMapping, ChordMapping, DeviceFilter, FilterKind, FilterMapping, KeyAction,
+    LayerMapping, MappingConfig, SystemKey, SystemKeyBinding, DEFAULT_POWER_OFF_HOLD_MS,
+};
+pub use reader::{InputHealth, InputReader};
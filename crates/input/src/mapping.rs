@@ -1,7 +1,42 @@
 #![forbid(unsafe_code)]
 
+use std::collections::BTreeMap;
+
+use common::hid::composite::keyboard_usage;
 use serde::Deserialize;
 
+/// HID button names accepted by button mappings, bindings, layers and chords.
+pub(crate) const VALID_BUTTONS: [&str; 15] = [
+    "a",
+    "b",
+    "x",
+    "y",
+    "lb",
+    "rb",
+    "back",
+    "start",
+    "home",
+    "ls",
+    "rs",
+    "dpad_up",
+    "dpad_down",
+    "dpad_left",
+    "dpad_right",
+];
+
+/// Prefix for keyboard key targets (`key_a`, `key_enter`, ...). Keys are only
+/// sent by profiles with a keyboard report (`composite`).
+pub(crate) const KEY_TARGET_PREFIX: &str = "key_";
+
+/// Keyboard usage for a `key_<name>` binding target.
+pub(crate) fn key_target_usage(name: &str) -> Option<u8> {
+    name.strip_prefix(KEY_TARGET_PREFIX)
+        .and_then(keyboard_usage)
+}
+
+/// Default hold time on the power key before ControllerOS powers off.
+pub const DEFAULT_POWER_OFF_HOLD_MS: u32 = 3000;
+
 /// Top-level mapping configuration loaded from TOML.
 #[derive(Debug, Clone, Deserialize)]
 pub struct MappingConfig {
@@ -13,6 +48,23 @@ pub struct MappingConfig {
     /// Button mappings from evdev to HID report bits.
     #[serde(default)]
     pub buttons: Vec<ButtonMapping>,
+    /// Optional smoothing / anti-snapback filters, applied in order per input.
+    #[serde(default)]
+    pub filters: Vec<FilterMapping>,
+    /// Bindings for the Deck's power and volume keys. When non-empty, the key
+    /// devices are grabbed so the presses no longer reach the console.
+    #[serde(default)]
+    pub system_keys: Vec<SystemKeyBinding>,
+    /// Named button remap layers, activated by a system key binding.
+    #[serde(default)]
+    pub layers: Vec<LayerMapping>,
+    /// Multi-input chords that emit a button or trigger an action.
+    #[serde(default)]
+    pub chords: Vec<ChordMapping>,
+    /// Hold time on the power key that always powers the Deck off while the
+    /// key devices are grabbed. Cannot be rebound.
+    #[serde(default = "default_power_off_hold_ms")]
+    pub power_off_hold_ms: u32,
 }
 
 /// Criteria for selecting which evdev device to use.
@@ -54,6 +106,168 @@ pub struct ButtonMapping {
     pub hid_button: String,
 }
 
+/// A Deck key that is not part of the controller's hidraw report.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SystemKey {
+    VolumeUp,
+    VolumeDown,
+    Power,
+}
+
+impl SystemKey {
+    pub const ALL: [SystemKey; 3] = [Self::VolumeUp, Self::VolumeDown, Self::Power];
+
+    pub const fn as_str(self) -> &'static str {
+        match self {
+            Self::VolumeUp => "volume_up",
+            Self::VolumeDown => "volume_down",
+            Self::Power => "power",
+        }
+    }
+
+    pub(crate) fn from_name(name: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|k| k.as_str() == name)
+    }
+}
+
+/// Non-report action emitted by a binding, delivered to `hidd` through
+/// [`InputReader::take_actions`](crate::InputReader::take_actions).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum KeyAction {
+    /// Switch to the next configured mapping file.
+    CycleMapping,
+    /// Switch to the next bonded Bluetooth host.
+    NextHost,
+    /// Open the Bluetooth pairing window while pairing is locked.
+    PairingMode,
+    /// Power the Deck off.
+    Poweroff,
+}
+
+/// Binds one system key to a button, a layer and/or a long-press action.
+#[derive(Debug, Clone, Deserialize)]
+pub struct SystemKeyBinding {
+    /// Which key this binding applies to.
+    pub key: SystemKey,
+    /// HID button (or `key_<name>` keyboard key) held while the key is held.
+    pub button: Option<String>,
+    /// Layer active while the key is held.
+    pub layer: Option<String>,
+    /// Action fired once the key has been held for `long_press_ms`.
+    pub long_press: Option<KeyAction>,
+    /// Hold time for `long_press`, in milliseconds.
+    #[serde(default = "default_long_press_ms")]
+    pub long_press_ms: u32,
+}
+
+/// A named set of button remaps (`from = "to"`, HID button names).
+#[derive(Debug, Clone, Deserialize)]
+pub struct LayerMapping {
+    pub name: String,
+    #[serde(default)]
+    pub remap: BTreeMap<String, String>,
+}
+
+/// Fires when every listed input is held at the same time.
+///
+/// `inputs` may mix HID button names and system key names. While the chord
+/// is held, its member buttons are released and `button` (if any) is held
+/// instead; `action` fires once per activation. `button` may also be a
+/// `key_<name>` keyboard key.
+#[derive(Debug, Clone, Deserialize)]
+pub struct ChordMapping {
+    pub inputs: Vec<String>,
+    pub button: Option<String>,
+    pub action: Option<KeyAction>,
+}
+
+fn default_long_press_ms() -> u32 {
+    800
+}
+
+fn default_power_off_hold_ms() -> u32 {
+    DEFAULT_POWER_OFF_HOLD_MS
+}
+
+/// Inputs that take filters but have no `[[axes]]` mapping: trackpad
+/// coordinates and gyro rates.
+const FILTER_ONLY_INPUTS: [&str; 7] = [
+    "lpad_x",
+    "lpad_y",
+    "rpad_x",
+    "rpad_y",
+    "gyro_pitch",
+    "gyro_yaw",
+    "gyro_roll",
+];
+
+/// A filter stage applied to one mapped input.
+#[derive(Debug, Clone, Deserialize)]
+pub struct FilterMapping {
+    /// Target input name (e.g., "lx", "rt", "rpad_x", "gyro_yaw").
+    pub input: String,
+    /// Filter algorithm and parameters.
+    #[serde(flatten)]
+    pub kind: FilterKind,
+}
+
+/// Filter algorithms available in the reader pipeline.
+///
+/// All time parameters are wall-clock based, so a filter behaves the same at
+/// any `report.rate_hz` and any controller report interval.
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum FilterKind {
+    /// Exponential moving average with a fixed time constant.
+    Ema {
+        /// Time to reach ~63% of a step change, in milliseconds.
+        time_constant_ms: f32,
+    },
+    /// 1€ adaptive low-pass filter.
+    OneEuro {
+        /// Cutoff frequency (Hz) used when the input is still.
+        #[serde(default = "default_one_euro_min_cutoff")]
+        min_cutoff: f32,
+        /// How much the cutoff rises with input speed.
+        #[serde(default)]
+        beta: f32,
+        /// Cutoff frequency (Hz) for the speed estimate.
+        #[serde(default = "default_one_euro_d_cutoff")]
+        d_cutoff: f32,
+    },
+    /// Zeroes short opposite-direction spikes after a stick is released.
+    AntiSnapback {
+        /// How long after the last large deflection to suppress overshoot.
+        #[serde(default = "default_snapback_window_ms")]
+        window_ms: f32,
+        /// Normalized deflection (0..1) that arms the detector.
+        #[serde(default = "default_snapback_arm_threshold")]
+        arm_threshold: f32,
+    },
+}
+
+fn positive(value: f32) -> bool {
+    value.is_finite() && value > 0.0
+}
+
+fn default_one_euro_min_cutoff() -> f32 {
+    1.0
+}
+
+fn default_one_euro_d_cutoff() -> f32 {
+    1.0
+}
+
+fn default_snapback_window_ms() -> f32 {
+    40.0
+}
+
+fn default_snapback_arm_threshold() -> f32 {
+    0.6
+}
+
 impl MappingConfig {
     /// Load and validate a mapping config from a TOML string.
     pub fn from_toml(s: &str) -> Result<Self, String> {
@@ -84,30 +298,155 @@ impl MappingConfig {
             }
         }
 
-        let valid_buttons = [
-            "a",
-            "b",
-            "x",
-            "y",
-            "lb",
-            "rb",
-            "back",
-            "start",
-            "ls",
-            "rs",
-            "dpad_up",
-            "dpad_down",
-            "dpad_left",
-            "dpad_right",
-        ];
         for button in &self.buttons {
-            if !valid_buttons.contains(&button.hid_button.as_str()) {
+            if !VALID_BUTTONS.contains(&button.hid_button.as_str()) {
                 return Err(format!("unknown hid_button: {:?}", button.hid_button));
             }
         }
 
+        self.validate_bindings()?;
+
+        for filter in &self.filters {
+            if !valid_axes.contains(&filter.input.as_str())
+                && !FILTER_ONLY_INPUTS.contains(&filter.input.as_str())
+            {
+                return Err(format!("unknown filter input: {:?}", filter.input));
+            }
+            match filter.kind {
+                FilterKind::Ema { time_constant_ms } => {
+                    if !positive(time_constant_ms) {
+                        return Err(format!(
+                            "filter on {}: time_constant_ms must be > 0",
+                            filter.input
+                        ));
+                    }
+                }
+                FilterKind::OneEuro {
+                    min_cutoff,
+                    beta,
+                    d_cutoff,
+                } => {
+                    if !positive(min_cutoff)
+                        || !positive(d_cutoff)
+                        || !beta.is_finite()
+                        || beta < 0.0
+                    {
+                        return Err(format!(
+                            "filter on {}: one_euro needs min_cutoff > 0, d_cutoff > 0, beta >= 0",
+                            filter.input
+                        ));
+                    }
+                }
+                FilterKind::AntiSnapback {
+                    window_ms,
+                    arm_threshold,
+                } => {
+                    if !positive(window_ms) {
+                        return Err(format!("filter on {}: window_ms must be > 0", filter.input));
+                    }
+                    if !positive(arm_threshold) || arm_threshold > 1.0 {
+                        return Err(format!(
+                            "filter on {}: arm_threshold must be in (0, 1]",
+                            filter.input
+                        ));
+                    }
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    fn validate_bindings(&self) -> Result<(), String> {
+        let check_button = |context: &str, name: &str| {
+            if VALID_BUTTONS.contains(&name) {
+                Ok(())
+            } else {
+                Err(format!("{context}: unknown hid_button {name:?}"))
+            }
+        };
+        let check_target = |context: &str, name: &str| {
+            if name.starts_with(KEY_TARGET_PREFIX) {
+                key_target_usage(name)
+                    .map(|_| ())
+                    .ok_or_else(|| format!("{context}: unknown keyboard key {name:?}"))
+            } else {
+                check_button(context, name)
+            }
+        };
+
+        for layer in &self.layers {
+            if self.layers.iter().filter(|l| l.name == layer.name).count() > 1 {
+                return Err(format!("duplicate layer name: {:?}", layer.name));
+            }
+            for (from, to) in &layer.remap {
+                check_button(&format!("layer {}", layer.name), from)?;
+                check_button(&format!("layer {}", layer.name), to)?;
+            }
+        }
+
+        for binding in &self.system_keys {
+            let context = format!("system key {}", binding.key.as_str());
+            if self
+                .system_keys
+                .iter()
+                .filter(|b| b.key == binding.key)
+                .count()
+                > 1
+            {
+                return Err(format!("{context}: bound more than once"));
+            }
+            if let Some(button) = &binding.button {
+                check_target(&context, button)?;
+            }
+            if let Some(layer) = &binding.layer {
+                if !self.layers.iter().any(|l| &l.name == layer) {
+                    return Err(format!("{context}: unknown layer {layer:?}"));
+                }
+            }
+            if binding.key == SystemKey::Power && binding.long_press.is_some() {
+                return Err(format!(
+                    "{context}: long_press is reserved for power-off (see power_off_hold_ms)"
+                ));
+            }
+            if binding.long_press.is_some() && binding.long_press_ms == 0 {
+                return Err(format!("{context}: long_press_ms must be > 0"));
+            }
+        }
+
+        for chord in &self.chords {
+            if chord.inputs.len() < 2 {
+                return Err("chord needs at least two inputs".to_string());
+            }
+            for input in &chord.inputs {
+                if SystemKey::from_name(input).is_none() && !VALID_BUTTONS.contains(&input.as_str())
+                {
+                    return Err(format!("chord: unknown input {input:?}"));
+                }
+            }
+            if chord.button.is_none() && chord.action.is_none() {
+                return Err("chord needs a button or an action".to_string());
+            }
+            if let Some(button) = &chord.button {
+                check_target("chord", button)?;
+            }
+        }
+
+        if !(1000..=10_000).contains(&self.power_off_hold_ms) {
+            return Err("power_off_hold_ms must be in 1000..=10000".to_string());
+        }
+
         Ok(())
     }
+
+    /// Whether any binding needs the power / volume key devices.
+    pub fn uses_system_keys(&self) -> bool {
+        !self.system_keys.is_empty()
+            || self
+                .chords
+                .iter()
+                .any(|c| c.inputs.iter().any(|i| SystemKey::from_name(i).is_some()))
+    }
 }
 
 #[cfg(test)]
@@ -181,6 +520,188 @@ evdev_max = 100
         assert!(err.contains("evdev_min"));
     }
 
+    #[test]
+    fn parse_filters() {
+        let toml = r#"
+[device]
+
+[[filters]]
+input = "lx"
+kind = "anti_snapback"
+window_ms = 50
+
+[[filters]]
+input = "lx"
+kind = "one_euro"
+min_cutoff = 2.0
+beta = 0.5
+
+[[filters]]
+input = "rt"
+kind = "ema"
+time_constant_ms = 8
+
+[[filters]]
+input = "gyro_yaw"
+kind = "one_euro"
+"#;
+        let config = MappingConfig::from_toml(toml).unwrap();
+        assert_eq!(config.filters.len(), 4);
+        assert_eq!(config.filters[3].input, "gyro_yaw");
+        assert_eq!(
+            config.filters[0].kind,
+            FilterKind::AntiSnapback {
+                window_ms: 50.0,
+                arm_threshold: 0.6
+            }
+        );
+        assert_eq!(
+            config.filters[1].kind,
+            FilterKind::OneEuro {
+                min_cutoff: 2.0,
+                beta: 0.5,
+                d_cutoff: 1.0
+            }
+        );
+    }
+
+    #[test]
+    fn reject_invalid_filter() {
+        let toml = r#"
+[device]
+
+[[filters]]
+input = "lx"
+kind = "ema"
+time_constant_ms = 0
+"#;
+        let err = MappingConfig::from_toml(toml).unwrap_err();
+        assert!(err.contains("time_constant_ms"));
+
+        let toml = r#"
+[device]
+
+[[filters]]
+input = "touchscreen"
+kind = "ema"
+time_constant_ms = 5
+"#;
+        let err = MappingConfig::from_toml(toml).unwrap_err();
+        assert!(err.contains("unknown filter input"));
+    }
+
+    #[test]
+    fn parse_system_key_bindings() {
+        let toml = r#"
+power_off_hold_ms = 4000
+
+[device]
+
+[[system_keys]]
+key = "volume_down"
+layer = "alt"
+long_press = "cycle_mapping"
+
+[[system_keys]]
+key = "volume_up"
+button = "home"
+
+[[layers]]
+name = "alt"
+remap = { a = "x", b = "y" }
+
+[[chords]]
+inputs = ["back", "start"]
+button = "home"
+
+[[chords]]
+inputs = ["back", "volume_up"]
+action = "next_host"
+
+[[chords]]
+inputs = ["back", "volume_down"]
+action = "pairing_mode"
+"#;
+        let config = MappingConfig::from_toml(toml).unwrap();
+        assert_eq!(config.power_off_hold_ms, 4000);
+        assert_eq!(config.system_keys[0].key, SystemKey::VolumeDown);
+        assert_eq!(
+            config.system_keys[0].long_press,
+            Some(KeyAction::CycleMapping)
+        );
+        assert_eq!(config.system_keys[0].long_press_ms, 800);
+        assert_eq!(config.chords[1].action, Some(KeyAction::NextHost));
+        assert_eq!(config.chords[2].action, Some(KeyAction::PairingMode));
+        assert_eq!(
+            config.layers[0].remap.get("a").map(String::as_str),
+            Some("x")
+        );
+        assert!(config.uses_system_keys());
+    }
+
+    #[test]
+    fn reject_power_long_press_override() {
+        let toml = r#"
+[device]
+
+[[system_keys]]
+key = "power"
+long_press = "cycle_mapping"
+"#;
+        let err = MappingConfig::from_toml(toml).unwrap_err();
+        assert!(err.contains("reserved"));
+    }
+
+    #[test]
+    fn reject_unknown_layer_and_chord_input() {
+        let toml = r#"
+[device]
+
+[[system_keys]]
+key = "volume_up"
+layer = "missing"
+"#;
+        let err = MappingConfig::from_toml(toml).unwrap_err();
+        assert!(err.contains("unknown layer"));
+
+        let toml = r#"
+[device]
+
+[[chords]]
+inputs = ["volume_up", "turbo"]
+action = "cycle_mapping"
+"#;
+        let err = MappingConfig::from_toml(toml).unwrap_err();
+        assert!(err.contains("unknown input"));
+    }
+
+    #[test]
+    fn keyboard_key_targets() {
+        let toml = r#"
+[device]
+
+[[system_keys]]
+key = "volume_up"
+button = "key_esc"
+
+[[chords]]
+inputs = ["back", "start"]
+button = "key_f11"
+"#;
+        MappingConfig::from_toml(toml).unwrap();
+        assert_eq!(key_target_usage("key_a"), Some(0x04));
+
+        let toml = r#"
+[device]
+
+[[system_keys]]
+key = "volume_up"
+button = "key_turbo"
+"#;
+        let err = MappingConfig::from_toml(toml).unwrap_err();
+        assert!(err.contains("unknown keyboard key"));
+    }
+
     #[test]
     fn load_xbox_toml_from_repo() {
         let config = MappingConfig::from_file("../../configs/mapping/xbox.toml").unwrap();
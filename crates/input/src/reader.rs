@@ -1,21 +1,71 @@
+use crate::bindings::{BindingEngine, BindingState};
+use crate::filter::FilterBank;
 use crate::hidraw::{self, HidrawDevice, DECK_REPORT_TYPE, REPORT_SIZE};
-use crate::mapping::{AxisMapping, MappingConfig};
+use crate::mapping::{AxisMapping, KeyAction, MappingConfig};
+use crate::syskeys;
 use common::hid::{
-    InputReport, XBOX_BUTTON_A, XBOX_BUTTON_B, XBOX_BUTTON_HOME, XBOX_BUTTON_LB, XBOX_BUTTON_LS,
-    XBOX_BUTTON_RB, XBOX_BUTTON_RS, XBOX_BUTTON_SELECT, XBOX_BUTTON_START, XBOX_BUTTON_X,
-    XBOX_BUTTON_Y, XBOX_STICK_MAX, XBOX_STICK_MIN, XBOX_TRIGGER_MAX, XBOX_TRIGGER_MIN,
+    InputReport, TouchPoint, EXTRA_BUTTON_L4, EXTRA_BUTTON_L5, EXTRA_BUTTON_QAM, EXTRA_BUTTON_R4,
+    EXTRA_BUTTON_R5, XBOX_BUTTON_A, XBOX_BUTTON_B, XBOX_BUTTON_HOME, XBOX_BUTTON_LB,
+    XBOX_BUTTON_LS, XBOX_BUTTON_RB, XBOX_BUTTON_RS, XBOX_BUTTON_SELECT, XBOX_BUTTON_START,
+    XBOX_BUTTON_X, XBOX_BUTTON_Y, XBOX_STICK_MAX, XBOX_STICK_MIN, XBOX_TRIGGER_MAX,
+    XBOX_TRIGGER_MIN,
 };
 use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::{Arc, Mutex};
 use std::thread;
-use std::time::Duration;
+use std::time::{Duration, Instant};
+
+/// The Deck sends a report every 4 ms while lizard mode is off; this much
+/// silence means the controller stopped talking.
+const STALE_AFTER: Duration = Duration::from_secs(1);
+/// How often the queued hidraw reports are read in low power mode.
+const LOW_POWER_INTERVAL: Duration = Duration::from_millis(100);
+
+/// Whether the controller is delivering reports.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum InputHealth {
+    /// No report yet since the reader started.
+    Waiting,
+    Ok,
+    /// Reports stopped arriving.
+    Stale,
+    /// The last hidraw read failed.
+    Error,
+}
+
+impl InputHealth {
+    pub const fn as_str(self) -> &'static str {
+        match self {
+            Self::Waiting => "waiting",
+            Self::Ok => "ok",
+            Self::Stale => "stale",
+            Self::Error => "error",
+        }
+    }
+
+    fn classify(last_report: Option<Instant>, read_failed: bool, now: Instant) -> Self {
+        if read_failed {
+            return Self::Error;
+        }
+        match last_report {
+            None => Self::Waiting,
+            Some(at) if now.duration_since(at) > STALE_AFTER => Self::Stale,
+            Some(_) => Self::Ok,
+        }
+    }
+}
 
 /// Reads raw HID reports from the Steam Deck controller via hidraw and
 /// maintains the current mapped gamepad state as an Xbox-style `InputReport`.
+///
+/// When the mapping binds the Deck's power / volume keys, their evdev devices
+/// are grabbed too and folded into the same state.
 pub struct InputReader {
-    state: Arc<Mutex<InputReport>>,
+    shared: Arc<Mutex<Shared>>,
     running: Arc<AtomicBool>,
+    low_power: Arc<AtomicBool>,
     _thread: thread::JoinHandle<()>,
+    key_thread: Option<thread::JoinHandle<()>>,
 }
 
 /// Axis normalization config extracted from MappingConfig, keyed by axis name.
@@ -28,6 +78,65 @@ struct AxisConfig {
     rt: AxisMapping,
 }
 
+/// Everything derived from a `MappingConfig`, swapped as a unit on reload.
+struct Pipeline {
+    axis_config: AxisConfig,
+    filters: FilterBank,
+    bindings: BindingEngine,
+}
+
+impl Pipeline {
+    fn new(config: &MappingConfig) -> Result<Self, String> {
+        Ok(Self {
+            axis_config: build_axis_config(config)?,
+            filters: FilterBank::new(&config.filters),
+            bindings: BindingEngine::new(config),
+        })
+    }
+}
+
+/// State shared between the hidraw thread, the system key thread and callers.
+struct Shared {
+    pipeline: Pipeline,
+    /// Mapped and filtered hidraw state, before bindings.
+    raw: InputReport,
+    /// Published state, after bindings.
+    report: InputReport,
+    keys: BindingState,
+    actions: Vec<KeyAction>,
+    /// The Steam button went down since the last `take_steam_press`.
+    steam_pressed: bool,
+    last_report: Option<Instant>,
+    /// The last hidraw read failed; cleared by the next report.
+    read_failed: bool,
+}
+
+impl Shared {
+    /// Take a report read from the controller.
+    fn update(&mut self, report: InputReport, now: Instant) {
+        // `raw` always has the Steam button as Home; bindings may move it.
+        if report.buttons & XBOX_BUTTON_HOME != 0 && self.raw.buttons & XBOX_BUTTON_HOME == 0 {
+            self.steam_pressed = true;
+        }
+        self.raw = report;
+        self.last_report = Some(now);
+        self.read_failed = false;
+        self.refresh(now);
+    }
+
+    fn refresh(&mut self, now: Instant) {
+        let mut fired = Vec::new();
+        self.report = self
+            .pipeline
+            .bindings
+            .evaluate(&self.raw, &mut self.keys, now, &mut fired);
+        for action in fired {
+            eprintln!("input: binding action {action:?}");
+            self.actions.push(action);
+        }
+    }
+}
+
 impl InputReader {
     /// Create a new reader that discovers the Deck's hidraw device, disables
     /// lizard mode, and starts reading raw input reports on a background thread.
@@ -40,27 +149,103 @@ impl InputReader {
         dev.disable_lizard_mode()?;
         eprintln!("input: lizard mode disabled");
 
-        let axis_config = build_axis_config(&config)?;
-
-        let state = Arc::new(Mutex::new(InputReport::default()));
+        let shared = Arc::new(Mutex::new(Shared {
+            pipeline: Pipeline::new(&config)?,
+            raw: InputReport::default(),
+            report: InputReport::default(),
+            keys: BindingState::default(),
+            actions: Vec::new(),
+            steam_pressed: false,
+            last_report: None,
+            read_failed: false,
+        }));
         let running = Arc::new(AtomicBool::new(true));
+        let low_power = Arc::new(AtomicBool::new(false));
 
-        let thread_state = Arc::clone(&state);
+        let thread_shared = Arc::clone(&shared);
         let thread_running = Arc::clone(&running);
+        let thread_low_power = Arc::clone(&low_power);
         let handle = thread::spawn(move || {
-            hidraw_loop(dev, axis_config, thread_state, thread_running);
+            hidraw_loop(dev, thread_shared, thread_running, thread_low_power);
         });
 
-        Ok(Self {
-            state,
+        let mut reader = Self {
+            shared,
             running,
+            low_power,
             _thread: handle,
-        })
+            key_thread: None,
+        };
+        if config.uses_system_keys() {
+            reader.start_system_keys()?;
+        }
+        Ok(reader)
     }
 
     /// Returns the latest mapped input state as an HID `InputReport`.
     pub fn current_report(&self) -> InputReport {
-        *self.state.lock().unwrap()
+        self.shared.lock().unwrap().report
+    }
+
+    /// Drain binding actions (e.g. [`KeyAction::CycleMapping`]) fired since
+    /// the last call.
+    pub fn take_actions(&self) -> Vec<KeyAction> {
+        std::mem::take(&mut self.shared.lock().unwrap().actions)
+    }
+
+    /// Whether the Steam button was pressed since the last call, however
+    /// briefly and whatever the mapping binds it to.
+    pub fn take_steam_press(&self) -> bool {
+        std::mem::take(&mut self.shared.lock().unwrap().steam_pressed)
+    }
+
+    pub fn health(&self) -> InputHealth {
+        let shared = self.shared.lock().unwrap();
+        InputHealth::classify(shared.last_report, shared.read_failed, Instant::now())
+    }
+
+    /// Read the controller every `LOW_POWER_INTERVAL` instead of as each
+    /// report arrives. Queued reports are still all processed, so bindings
+    /// see every press, just late.
+    pub fn set_low_power(&self, enabled: bool) {
+        if self.low_power.swap(enabled, Ordering::Relaxed) != enabled {
+            eprintln!(
+                "input: low power reading {}",
+                if enabled { "on" } else { "off" }
+            );
+        }
+    }
+
+    /// Replace the active mapping without reopening the controller.
+    ///
+    /// Filter state restarts from the next report. System key devices are
+    /// grabbed the first time a mapping needs them and stay grabbed.
+    pub fn set_mapping(&mut self, config: MappingConfig) -> Result<(), String> {
+        let pipeline = Pipeline::new(&config)?;
+        {
+            let mut shared = self.shared.lock().unwrap();
+            shared.pipeline = pipeline;
+            shared.refresh(Instant::now());
+        }
+        if config.uses_system_keys() && self.key_thread.is_none() {
+            self.start_system_keys()?;
+        }
+        Ok(())
+    }
+
+    fn start_system_keys(&mut self) -> Result<(), String> {
+        let key_shared = Arc::clone(&self.shared);
+        let tick_shared = Arc::clone(&self.shared);
+        self.key_thread = syskeys::spawn_key_thread(
+            Arc::clone(&self.running),
+            move |key, pressed, now| {
+                let mut shared = key_shared.lock().unwrap();
+                shared.keys.set_key(key, pressed, now);
+                shared.refresh(now);
+            },
+            move |now| tick_shared.lock().unwrap().refresh(now),
+        )?;
+        Ok(())
     }
 }
 
@@ -91,20 +276,35 @@ fn build_axis_config(config: &MappingConfig) -> Result<AxisConfig, String> {
 
 fn hidraw_loop(
     mut dev: HidrawDevice,
-    axis_config: AxisConfig,
-    state: Arc<Mutex<InputReport>>,
+    shared: Arc<Mutex<Shared>>,
     running: Arc<AtomicBool>,
+    low_power: Arc<AtomicBool>,
 ) {
     let mut buf = [0u8; REPORT_SIZE];
+    let epoch = Instant::now();
 
     while running.load(Ordering::Relaxed) {
-        match dev.read_report_timeout(&mut buf, 100) {
-            Ok(0) => continue, // timeout, check running flag
+        // In low power mode, drain the queue without waiting, then sleep.
+        let low_power = low_power.load(Ordering::Relaxed);
+        let timeout_ms = if low_power { 0 } else { 100 };
+        match dev.read_report_timeout(&mut buf, timeout_ms) {
+            Ok(0) => {
+                // timeout, check running flag
+                if low_power {
+                    thread::sleep(LOW_POWER_INTERVAL);
+                }
+            }
             Ok(n) if n >= 56 => {
                 // Validate report header: data[0]=0x01, data[1]=0x00, data[2]=type
                 if buf[0] == 0x01 && buf[1] == 0x00 && buf[2] == DECK_REPORT_TYPE {
-                    let report = parse_deck_report(&buf, &axis_config);
-                    *state.lock().unwrap() = report;
+                    let now = Instant::now();
+                    let mut shared = shared.lock().unwrap();
+                    let pipeline = &mut shared.pipeline;
+                    let elapsed = now.duration_since(epoch);
+                    let mut report = parse_deck_report(&buf, &pipeline.axis_config);
+                    report.timestamp_us = elapsed.as_micros() as u32;
+                    pipeline.filters.apply(&mut report, elapsed.as_secs_f64());
+                    shared.update(report, now);
                 }
             }
             Ok(_) => {} // short read, ignore
@@ -113,6 +313,7 @@ fn hidraw_loop(
                     break;
                 }
                 eprintln!("input: hidraw read error: {e}");
+                shared.lock().unwrap().read_failed = true;
                 thread::sleep(Duration::from_millis(10));
             }
         }
@@ -127,8 +328,17 @@ fn hidraw_loop(
 ///   data[8]:  A(7) X(6) B(5) Y(4) LB(3) RB(2)
 ///   data[9]:  DPAD_UP(0) DPAD_RIGHT(1) DPAD_LEFT(2) DPAD_DOWN(3)
 ///             SELECT(4) HOME(5) START(6)
-///   data[10]: LS(6)
+///   data[9]:  L5(7)
+///   data[10]: R5(0) LPAD_CLICK(1) RPAD_CLICK(2) LPAD_TOUCH(3) RPAD_TOUCH(4) LS(6)
 ///   data[11]: RS(2)
+///   data[13]: L4(1) R4(2)
+///   data[14]: QAM(2)
+///
+/// Trackpads and IMU (little-endian i16):
+///   data[16..20]: left pad X, Y
+///   data[20..24]: right pad X, Y
+///   data[24..30]: accel X, Y, Z
+///   data[30..36]: gyro X, Y, Z
 ///
 /// Axes (little-endian i16):
 ///   data[48..50]: left stick X
@@ -195,6 +405,19 @@ fn parse_deck_report(data: &[u8; REPORT_SIZE], config: &AxisConfig) -> InputRepo
         report.buttons |= XBOX_BUTTON_RS;
     }
 
+    // --- Back grips and QAM, for profiles with spare buttons ---
+    for (pressed, bit) in [
+        (data[13] & (1 << 1) != 0, EXTRA_BUTTON_L4),
+        (data[13] & (1 << 2) != 0, EXTRA_BUTTON_R4),
+        (b9 & (1 << 7) != 0, EXTRA_BUTTON_L5),
+        (b10 & (1 << 0) != 0, EXTRA_BUTTON_R5),
+        (data[14] & (1 << 2) != 0, EXTRA_BUTTON_QAM),
+    ] {
+        if pressed {
+            report.extra_buttons |= bit;
+        }
+    }
+
     // --- D-pad → hat switch ---
     let dpad = [
         b9 & (1 << 0) != 0, // up
@@ -204,9 +427,45 @@ fn parse_deck_report(data: &[u8; REPORT_SIZE], config: &AxisConfig) -> InputRepo
     ];
     report.hat = dpad_to_hat(dpad);
 
+    // --- Trackpads (raw Y-up positive, negate for Y-down) ---
+    report.touch = [
+        TouchPoint {
+            active: b10 & (1 << 3) != 0,
+            clicked: b10 & (1 << 1) != 0,
+            x: read_i16(data, 16),
+            y: negate(read_i16(data, 18)),
+        },
+        TouchPoint {
+            active: b10 & (1 << 4) != 0,
+            clicked: b10 & (1 << 2) != 0,
+            x: read_i16(data, 20),
+            y: negate(read_i16(data, 22)),
+        },
+    ];
+
+    // --- IMU: reorder Deck axes to (pitch, yaw, roll) / (x, y up, z back) ---
+    report.accel = [
+        read_i16(data, 24),
+        read_i16(data, 28),
+        negate(read_i16(data, 26)),
+    ];
+    report.gyro = [
+        read_i16(data, 30),
+        read_i16(data, 34),
+        negate(read_i16(data, 32)),
+    ];
+
     report
 }
 
+fn read_i16(data: &[u8; REPORT_SIZE], offset: usize) -> i16 {
+    i16::from_le_bytes([data[offset], data[offset + 1]])
+}
+
+fn negate(value: i16) -> i16 {
+    value.saturating_neg()
+}
+
 /// Normalize a stick axis value to the HID i16 range (-32768..32767).
 /// Applies deadzone: values within the deadzone radius from center are zeroed.
 fn normalize_stick(raw: i32, mapping: &AxisMapping) -> i16 {
@@ -251,7 +510,7 @@ fn normalize_trigger(raw: i32, mapping: &AxisMapping) -> u16 {
 
 /// Convert d-pad button state [up, down, left, right] to Xbox hat switch value.
 /// Hat values: 0=none, 1=N, 2=NE, 3=E, 4=SE, 5=S, 6=SW, 7=W, 8=NW.
-fn dpad_to_hat(dpad: [bool; 4]) -> u8 {
+pub(crate) fn dpad_to_hat(dpad: [bool; 4]) -> u8 {
     let [up, down, left, right] = dpad;
     match (up, down, left, right) {
         (true, false, false, false) => 1, // N
@@ -408,6 +667,53 @@ mod tests {
         assert_eq!(report.hat, 2); // NE
     }
 
+    #[test]
+    fn parse_report_pads_and_imu() {
+        let config = test_axis_config();
+        let mut data = [0u8; REPORT_SIZE];
+        data[0] = 0x01;
+        data[2] = DECK_REPORT_TYPE;
+
+        // Right pad touched and clicked at (1000, up 2000).
+        data[10] = (1 << 4) | (1 << 2);
+        data[20..22].copy_from_slice(&1000_i16.to_le_bytes());
+        data[22..24].copy_from_slice(&2000_i16.to_le_bytes());
+        // Accel Z = 16384 (1 g), gyro X/Y/Z = 10/20/30.
+        data[28..30].copy_from_slice(&16384_i16.to_le_bytes());
+        data[30..32].copy_from_slice(&10_i16.to_le_bytes());
+        data[32..34].copy_from_slice(&20_i16.to_le_bytes());
+        data[34..36].copy_from_slice(&30_i16.to_le_bytes());
+
+        let report = parse_deck_report(&data, &config);
+        assert!(!report.touch[0].active);
+        assert!(report.touch[1].active && report.touch[1].clicked);
+        assert_eq!((report.touch[1].x, report.touch[1].y), (1000, -2000));
+        assert_eq!(report.accel, [0, 16384, 0]);
+        assert_eq!(report.gyro, [10, 30, -20]);
+    }
+
+    #[test]
+    fn parse_report_back_grips_and_qam() {
+        let config = test_axis_config();
+        let mut data = [0u8; REPORT_SIZE];
+        data[0] = 0x01;
+        data[2] = DECK_REPORT_TYPE;
+        data[9] = 1 << 7; // L5
+        data[13] = 1 << 2; // R4
+        data[14] = 1 << 2; // QAM
+
+        let report = parse_deck_report(&data, &config);
+        assert_eq!(
+            report.extra_buttons,
+            EXTRA_BUTTON_L5 | EXTRA_BUTTON_R4 | EXTRA_BUTTON_QAM
+        );
+        assert_eq!(
+            report.extra_buttons & (EXTRA_BUTTON_L4 | EXTRA_BUTTON_R5),
+            0
+        );
+        assert_eq!(report.buttons, 0);
+    }
+
     #[test]
     fn parse_report_axes() {
         let config = test_axis_config();
@@ -453,6 +759,65 @@ mod tests {
         );
     }
 
+    #[test]
+    fn health_follows_report_arrival() {
+        let now = Instant::now();
+        assert_eq!(
+            InputHealth::classify(None, false, now),
+            InputHealth::Waiting
+        );
+        assert_eq!(
+            InputHealth::classify(Some(now), false, now),
+            InputHealth::Ok
+        );
+        assert_eq!(
+            InputHealth::classify(Some(now), false, now + Duration::from_secs(2)),
+            InputHealth::Stale
+        );
+        assert_eq!(
+            InputHealth::classify(Some(now), true, now),
+            InputHealth::Error
+        );
+    }
+
+    #[test]
+    fn steam_presses_are_latched_whatever_the_mapping() {
+        // A chord takes Home out of the published report while Start is
+        // held.
+        let config = MappingConfig::from_toml(&format!(
+            "{}\n[[chords]]\ninputs = [\"home\", \"start\"]\nbutton = \"a\"\n",
+            include_str!("../../../configs/mapping/xbox.toml")
+        ))
+        .unwrap();
+        let mut shared = Shared {
+            pipeline: Pipeline::new(&config).unwrap(),
+            raw: InputReport::default(),
+            report: InputReport::default(),
+            keys: BindingState::default(),
+            actions: Vec::new(),
+            steam_pressed: false,
+            last_report: None,
+            read_failed: false,
+        };
+        let now = Instant::now();
+        let buttons = |buttons| InputReport {
+            buttons,
+            ..InputReport::default()
+        };
+
+        // A tap between two polls.
+        shared.update(buttons(XBOX_BUTTON_HOME), now);
+        shared.update(buttons(0), now);
+        assert!(std::mem::take(&mut shared.steam_pressed));
+
+        shared.update(buttons(XBOX_BUTTON_HOME | XBOX_BUTTON_START), now);
+        assert_eq!(shared.report.buttons & XBOX_BUTTON_HOME, 0);
+        assert!(std::mem::take(&mut shared.steam_pressed));
+        // Held, not pressed again.
+        shared.update(buttons(XBOX_BUTTON_HOME), now);
+        assert!(!shared.steam_pressed);
+    }
+
     fn test_axis_config() -> AxisConfig {
         AxisConfig {
             lx: stick_mapping(4000),
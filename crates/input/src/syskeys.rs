@@ -0,0 +1,134 @@
+#![forbid(unsafe_code)]
+
+//! Power and volume key capture.
+//!
+//! On the Deck the power button and the volume rocker are separate evdev
+//! keyboard devices (ACPI "Power Button" and the "AT Translated Set 2
+//! keyboard"). When the mapping binds any of these keys, the devices are
+//! grabbed with `EVIOCGRAB` so presses stop reaching the console and logind,
+//! and key state is forwarded to the binding engine instead.
+
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use evdev::{Device, EventType, KeyCode};
+
+use crate::discovery::{discover_devices, InputDeviceInfo};
+use crate::mapping::SystemKey;
+
+/// Poll interval for grabbed key devices. Also bounds how late a long press
+/// can fire after its hold time elapses.
+const POLL_INTERVAL: Duration = Duration::from_millis(10);
+
+/// Valve's VID; the controller's own lizard-mode keyboard is never grabbed.
+const STEAM_VID: u16 = 0x28DE;
+
+pub(crate) fn key_for_code(code: u16) -> Option<SystemKey> {
+    match KeyCode::new(code) {
+        KeyCode::KEY_VOLUMEUP => Some(SystemKey::VolumeUp),
+        KeyCode::KEY_VOLUMEDOWN => Some(SystemKey::VolumeDown),
+        KeyCode::KEY_POWER => Some(SystemKey::Power),
+        _ => None,
+    }
+}
+
+/// Evdev devices that deliver the Deck's power or volume keys.
+pub(crate) fn system_key_devices() -> Vec<InputDeviceInfo> {
+    discover_devices()
+        .into_iter()
+        .filter(|d| d.has_system_keys && d.vendor != STEAM_VID)
+        .collect()
+}
+
+/// Open and grab every system key device, then poll them on a background
+/// thread. `on_key` is called for each press / release; `on_tick` is called
+/// after every poll so time-based bindings keep running while keys are held.
+pub(crate) fn spawn_key_thread<K, T>(
+    running: Arc<AtomicBool>,
+    mut on_key: K,
+    mut on_tick: T,
+) -> Result<Option<thread::JoinHandle<()>>, String>
+where
+    K: FnMut(SystemKey, bool, Instant) + Send + 'static,
+    T: FnMut(Instant) + Send + 'static,
+{
+    let infos = system_key_devices();
+    if infos.is_empty() {
+        eprintln!("input: no power/volume key devices found; system key bindings inactive");
+        return Ok(None);
+    }
+
+    let mut devices: Vec<(PathBuf, Device)> = Vec::new();
+    for info in infos {
+        let mut dev = Device::open(&info.path)
+            .map_err(|e| format!("cannot open {}: {e}", info.path.display()))?;
+        dev.grab()
+            .map_err(|e| format!("cannot grab {}: {e}", info.path.display()))?;
+        dev.set_nonblocking(true)
+            .map_err(|e| format!("cannot set {} non-blocking: {e}", info.path.display()))?;
+        eprintln!(
+            "input: grabbed system keys on {} (\"{}\")",
+            info.path.display(),
+            info.name
+        );
+        devices.push((info.path, dev));
+    }
+
+    let handle = thread::Builder::new()
+        .name("input-syskeys".to_string())
+        .spawn(move || {
+            while running.load(Ordering::Relaxed) {
+                // A device that fails with anything but "no events yet" is
+                // gone (unplugged or revoked); stop polling it.
+                devices.retain_mut(|(path, dev)| match dev.fetch_events() {
+                    Ok(events) => {
+                        let now = Instant::now();
+                        for ev in events {
+                            if ev.event_type() != EventType::KEY {
+                                continue;
+                            }
+                            // value: 0 = release, 1 = press, 2 = autorepeat.
+                            if let Some(key) = key_for_code(ev.code()) {
+                                if ev.value() != 2 {
+                                    on_key(key, ev.value() == 1, now);
+                                }
+                            }
+                        }
+                        true
+                    }
+                    Err(e) if e.kind() == std::io::ErrorKind::WouldBlock => true,
+                    Err(e) => {
+                        eprintln!(
+                            "input: read error on {}: {e}; no longer reading it",
+                            path.display()
+                        );
+                        false
+                    }
+                });
+                on_tick(Instant::now());
+                thread::sleep(POLL_INTERVAL);
+            }
+            for (_, dev) in &mut devices {
+                let _ = dev.ungrab();
+            }
+        })
+        .map_err(|e| format!("failed to spawn system key thread: {e}"))?;
+
+    Ok(Some(handle))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn maps_power_and_volume_codes() {
+        assert_eq!(key_for_code(114), Some(SystemKey::VolumeDown));
+        assert_eq!(key_for_code(115), Some(SystemKey::VolumeUp));
+        assert_eq!(key_for_code(116), Some(SystemKey::Power));
+        assert_eq!(key_for_code(0x130), None);
+    }
+}
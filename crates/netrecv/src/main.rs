@@ -0,0 +1,350 @@
+//! Reference receiver for the `hidd` `[network]` report stream. Plays the
+//! received reports into a local UHID gamepad, sends the rumble its host asks
+//! for back to the Deck, and prints stream health every few seconds.
+
+use std::env;
+use std::io::{ErrorKind, Read, Write};
+use std::net::{TcpListener, TcpStream, UdpSocket};
+use std::process::ExitCode;
+use std::sync::mpsc::Receiver;
+use std::time::{Duration, Instant};
+
+use anyhow::{anyhow, Result};
+use common::config::NetTransport;
+use common::hid::OutputReport;
+use common::net::{NetError, NetMessage, NetPacket, DEFAULT_NET_PORT};
+
+mod uhid;
+use uhid::Gamepad;
+
+const DEFAULT_NAME: &str = "ControllerOS Network Controller";
+/// How long a read waits before rumble is checked.
+const POLL_INTERVAL: Duration = Duration::from_millis(2);
+const STATS_INTERVAL: Duration = Duration::from_secs(5);
+/// A sequence number this far behind the expected one means `hidd`
+/// restarted rather than a late packet.
+const RESTART_GAP: u32 = 1000;
+/// A report sent this much earlier than the newest one seen comes from a
+/// restarted `hidd`, whose clock starts again at zero, even while its
+/// sequence numbers still look like late packets.
+const RESTART_REWIND_US: u64 = 1_000_000;
+
+fn main() -> ExitCode {
+    match run() {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(err) => {
+            eprintln!("netrecv: {err}");
+            ExitCode::from(2)
+        }
+    }
+}
+
+fn run() -> Result<()> {
+    let mut listen = format!("0.0.0.0:{DEFAULT_NET_PORT}");
+    let mut transport = NetTransport::Udp;
+    let mut name = DEFAULT_NAME.to_string();
+
+    let mut args = env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--listen" => {
+                listen = args
+                    .next()
+                    .ok_or_else(|| anyhow!("missing value for --listen"))?;
+            }
+            "--transport" => {
+                transport = match args.next().as_deref() {
+                    Some("udp") => NetTransport::Udp,
+                    Some("tcp") => NetTransport::Tcp,
+                    Some(other) => {
+                        return Err(anyhow!("unknown transport {other}; expected udp or tcp"))
+                    }
+                    None => return Err(anyhow!("missing value for --transport")),
+                };
+            }
+            "--name" => {
+                name = args
+                    .next()
+                    .ok_or_else(|| anyhow!("missing value for --name"))?;
+            }
+            "--help" | "-h" => {
+                print_help();
+                return Ok(());
+            }
+            other => return Err(anyhow!("unknown argument: {other}")),
+        }
+    }
+
+    let (mut pad, rumble) = Gamepad::create(&name)?;
+    println!(
+        "netrecv listening on {listen} over {}: name=\"{name}\"",
+        transport.as_str()
+    );
+    match transport {
+        NetTransport::Udp => run_udp(&listen, &mut pad, &rumble),
+        NetTransport::Tcp => run_tcp(&listen, &mut pad, &rumble),
+    }
+}
+
+fn print_help() {
+    println!("Usage:");
+    println!("  netrecv [--listen <addr:port>] [--transport udp|tcp] [--name <name>]");
+    println!("Defaults:");
+    println!("  --listen 0.0.0.0:{DEFAULT_NET_PORT}");
+    println!("  --transport udp");
+    println!("  --name \"{DEFAULT_NAME}\"");
+    println!("Point hidd's [network] receiver at this address with the same transport.");
+}
+
+fn run_udp(listen: &str, pad: &mut Gamepad, rumble: &Receiver<OutputReport>) -> Result<()> {
+    let socket = UdpSocket::bind(listen).map_err(|e| anyhow!("failed to bind {listen}: {e}"))?;
+    socket.set_read_timeout(Some(POLL_INTERVAL))?;
+    let mut peer = None;
+    let mut stats = Stats::new(Instant::now());
+    let mut sequence = 0;
+    let mut buf = [0u8; 2048];
+
+    loop {
+        match socket.recv_from(&mut buf) {
+            Ok((len, from)) => match NetPacket::decode(&buf[..len]) {
+                Ok((packet, _)) => {
+                    if peer != Some(from) {
+                        println!("netrecv: receiving from {from}");
+                        peer = Some(from);
+                    }
+                    play(&packet, pad, &mut stats)?;
+                }
+                Err(e) => eprintln!("netrecv: dropping datagram from {from}: {e}"),
+            },
+            Err(e) if is_timeout(&e) => {}
+            Err(e) => return Err(anyhow!("receive failed: {e}")),
+        }
+        for report in rumble.try_iter() {
+            if let Some(peer) = peer {
+                socket.send_to(&rumble_packet(&mut sequence, report), peer)?;
+            }
+        }
+        stats.print_if_due(Instant::now());
+    }
+}
+
+/// Serve one `hidd` connection at a time.
+fn run_tcp(listen: &str, pad: &mut Gamepad, rumble: &Receiver<OutputReport>) -> Result<()> {
+    let listener =
+        TcpListener::bind(listen).map_err(|e| anyhow!("failed to bind {listen}: {e}"))?;
+    loop {
+        let (mut stream, from) = listener.accept()?;
+        println!("netrecv: {from} connected");
+        if let Err(e) = serve_tcp(&mut stream, pad, rumble) {
+            eprintln!("netrecv: {from}: {e}");
+        }
+        println!("netrecv: {from} disconnected");
+    }
+}
+
+fn serve_tcp(
+    stream: &mut TcpStream,
+    pad: &mut Gamepad,
+    rumble: &Receiver<OutputReport>,
+) -> Result<()> {
+    stream.set_nodelay(true)?;
+    stream.set_read_timeout(Some(POLL_INTERVAL))?;
+    let mut stats = Stats::new(Instant::now());
+    let mut sequence = 0;
+    let mut received = Vec::new();
+    let mut buf = [0u8; 2048];
+
+    loop {
+        match stream.read(&mut buf) {
+            Ok(0) => return Ok(()),
+            Ok(len) => received.extend_from_slice(&buf[..len]),
+            Err(e) if is_timeout(&e) => {}
+            Err(e) => return Err(e.into()),
+        }
+        let mut used = 0;
+        loop {
+            match NetPacket::decode(&received[used..]) {
+                Ok((packet, len)) => {
+                    play(&packet, pad, &mut stats)?;
+                    used += len;
+                }
+                Err(NetError::Incomplete) => break,
+                Err(e) => return Err(e.into()),
+            }
+        }
+        received.drain(..used);
+        for report in rumble.try_iter() {
+            stream.write_all(&rumble_packet(&mut sequence, report))?;
+        }
+        stats.print_if_due(Instant::now());
+    }
+}
+
+fn is_timeout(e: &std::io::Error) -> bool {
+    matches!(e.kind(), ErrorKind::WouldBlock | ErrorKind::TimedOut)
+}
+
+fn play(packet: &NetPacket, pad: &mut Gamepad, stats: &mut Stats) -> Result<()> {
+    if let NetMessage::Input { sent_us, report } = packet.message {
+        pad.send(&report)?;
+        stats.record(packet.sequence, sent_us, Instant::now());
+    }
+    Ok(())
+}
+
+fn rumble_packet(sequence: &mut u32, report: OutputReport) -> Vec<u8> {
+    let packet = NetPacket {
+        sequence: *sequence,
+        message: NetMessage::Rumble(report),
+    };
+    *sequence = sequence.wrapping_add(1);
+    packet.encode()
+}
+
+/// Stream health per interval. The sender's and our clocks are unrelated,
+/// so delay is measured as jitter: arrival minus send time, over the
+/// smallest such difference seen since the stream started.
+struct Stats {
+    started: Instant,
+    last_print: Instant,
+    next_sequence: Option<u32>,
+    newest_sent_us: Option<u64>,
+    min_offset_us: Option<i64>,
+    packets: u64,
+    lost: u64,
+    late: u64,
+    jitter_total_us: u64,
+    jitter_max_us: u64,
+}
+
+impl Stats {
+    fn new(now: Instant) -> Self {
+        Self {
+            started: now,
+            last_print: now,
+            next_sequence: None,
+            newest_sent_us: None,
+            min_offset_us: None,
+            packets: 0,
+            lost: 0,
+            late: 0,
+            jitter_total_us: 0,
+            jitter_max_us: 0,
+        }
+    }
+
+    fn record(&mut self, sequence: u32, sent_us: u64, now: Instant) {
+        if let Some(newest) = self.newest_sent_us {
+            if sent_us + RESTART_REWIND_US < newest {
+                println!("netrecv: sender restarted");
+                self.next_sequence = None;
+                self.newest_sent_us = None;
+                self.min_offset_us = None;
+            }
+        }
+        self.newest_sent_us = Some(self.newest_sent_us.map_or(sent_us, |n| n.max(sent_us)));
+        if let Some(expected) = self.next_sequence {
+            let behind = expected.wrapping_sub(sequence);
+            if behind > 0 && behind <= RESTART_GAP {
+                // Counted as lost when a later packet overtook it.
+                self.packets += 1;
+                self.late += 1;
+                self.lost = self.lost.saturating_sub(1);
+                return;
+            }
+            if behind > RESTART_GAP {
+                let ahead = sequence.wrapping_sub(expected);
+                if ahead <= u32::MAX / 2 {
+                    self.lost += u64::from(ahead);
+                } else {
+                    println!("netrecv: sender restarted");
+                    self.min_offset_us = None;
+                }
+            }
+        }
+        self.next_sequence = Some(sequence.wrapping_add(1));
+        self.packets += 1;
+
+        let offset = now.duration_since(self.started).as_micros() as i64 - sent_us as i64;
+        let min = self.min_offset_us.map_or(offset, |m| m.min(offset));
+        self.min_offset_us = Some(min);
+        let jitter = (offset - min) as u64;
+        self.jitter_total_us += jitter;
+        self.jitter_max_us = self.jitter_max_us.max(jitter);
+    }
+
+    fn print_if_due(&mut self, now: Instant) {
+        if now.duration_since(self.last_print) < STATS_INTERVAL {
+            return;
+        }
+        if self.packets > 0 {
+            println!("netrecv: {}", self.summary(now));
+        }
+        *self = Self {
+            started: self.started,
+            next_sequence: self.next_sequence,
+            newest_sent_us: self.newest_sent_us,
+            min_offset_us: self.min_offset_us,
+            ..Self::new(now)
+        };
+    }
+
+    fn summary(&self, now: Instant) -> String {
+        let seconds = now.duration_since(self.last_print).as_secs_f64();
+        let on_time = self.packets - self.late;
+        format!(
+            "{:.0} reports/s, {} lost, {} late, jitter avg {} us max {} us",
+            self.packets as f64 / seconds,
+            self.lost,
+            self.late,
+            self.jitter_total_us / on_time.max(1),
+            self.jitter_max_us
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Stats;
+    use std::time::{Duration, Instant};
+
+    #[test]
+    fn stats_count_loss_late_packets_and_jitter() {
+        let start = Instant::now();
+        let at = |ms: u64| start + Duration::from_millis(ms);
+        let mut stats = Stats::new(start);
+
+        // Sent every 10 ms with 1 ms delay; packet 2 arrives after 3, packet
+        // 4 never arrives and packet 5 takes 5 ms.
+        stats.record(0, 0, at(1));
+        stats.record(1, 10_000, at(11));
+        stats.record(3, 30_000, at(31));
+        stats.record(2, 20_000, at(34));
+        stats.record(5, 50_000, at(55));
+
+        assert_eq!((stats.packets, stats.lost, stats.late), (5, 1, 1));
+        assert_eq!(stats.jitter_max_us, 4_000);
+        assert_eq!(
+            stats.summary(at(1000)),
+            "5 reports/s, 1 lost, 1 late, jitter avg 1000 us max 4000 us"
+        );
+    }
+
+    #[test]
+    fn stats_notice_a_restart_from_the_send_clock() {
+        let start = Instant::now();
+        let at = |ms: u64| start + Duration::from_millis(ms);
+        let mut stats = Stats::new(start);
+
+        // hidd ran for ten seconds, then restarted before sending as many
+        // reports as `RESTART_GAP`.
+        stats.record(998, 9_980_000, at(10_000));
+        stats.record(999, 9_990_000, at(10_010));
+        stats.record(0, 0, at(10_500));
+        stats.record(1, 10_000, at(10_510));
+
+        assert_eq!((stats.packets, stats.lost, stats.late), (4, 0, 0));
+        assert_eq!(stats.next_sequence, Some(2));
+        assert_eq!(stats.jitter_max_us, 0);
+    }
+}
@@ -0,0 +1,192 @@
+//! The local gamepad `netrecv` plays received reports into: a UHID device
+//! with the Xbox One S (1708) descriptor, whatever profile the Deck uses.
+
+use std::fs::{File, OpenOptions};
+use std::io::{Read, Write};
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::thread;
+
+use anyhow::{anyhow, Result};
+use common::config::ProfileConfig;
+use common::hid::{HidProfileMode, InputReport, OutputReport};
+
+const DEV_UHID: &str = "/dev/uhid";
+const UHID_DESTROY: u32 = 1;
+const UHID_OUTPUT: u32 = 6;
+const UHID_GET_REPORT: u32 = 9;
+const UHID_GET_REPORT_REPLY: u32 = 10;
+const UHID_CREATE2: u32 = 11;
+const UHID_INPUT2: u32 = 12;
+const UHID_SET_REPORT: u32 = 13;
+const UHID_SET_REPORT_REPLY: u32 = 14;
+
+const UHID_EVENT_SIZE: usize = 4376;
+const UHID_DATA_MAX: usize = 4096;
+const UHID_ERR_NOT_SUPPORTED: u16 = 95;
+const UHID_OUTPUT_REPORT: u8 = 1;
+
+/// Same bus as `hidd`'s UHID device, so the receiving machine binds the
+/// driver it would for the controller over Bluetooth.
+const BUS_BLUETOOTH: u16 = 0x05;
+
+pub struct Gamepad {
+    file: File,
+}
+
+impl Gamepad {
+    /// Create the gamepad. Rumble its host asks for arrives on the returned
+    /// channel.
+    pub fn create(name: &str) -> Result<(Self, Receiver<OutputReport>)> {
+        let mut file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .open(DEV_UHID)
+            .map_err(|e| anyhow!("failed to open {DEV_UHID}: {e}"))?;
+        file.write_all(&build_create2_event(name))
+            .map_err(|e| anyhow!("failed to write UHID_CREATE2 event: {e}"))?;
+
+        let mut io = file
+            .try_clone()
+            .map_err(|e| anyhow!("failed to clone UHID fd for events: {e}"))?;
+        let (rumble_tx, rumble_rx) = mpsc::channel();
+        thread::Builder::new()
+            .name("netrecv-uhid-events".to_string())
+            .spawn(move || drain_events(&mut io, &rumble_tx))
+            .map_err(|e| anyhow!("failed to spawn UHID event thread: {e}"))?;
+        Ok((Self { file }, rumble_rx))
+    }
+
+    pub fn send(&mut self, report: &InputReport) -> Result<()> {
+        let bytes = report.to_bytes();
+        let mut event = Vec::with_capacity(6 + bytes.len());
+        event.extend_from_slice(&UHID_INPUT2.to_ne_bytes());
+        event.extend_from_slice(&(bytes.len() as u16).to_ne_bytes());
+        event.extend_from_slice(&bytes);
+        self.file
+            .write_all(&event)
+            .map_err(|e| anyhow!("failed to write UHID_INPUT2 report: {e}"))
+    }
+}
+
+impl Drop for Gamepad {
+    fn drop(&mut self) {
+        let _ = self.file.write_all(&UHID_DESTROY.to_ne_bytes());
+    }
+}
+
+fn build_create2_event(name: &str) -> Vec<u8> {
+    // UHID_CREATE2 payload layout from linux/uapi/linux/uhid.h.
+    const CREATE2_PAYLOAD_LEN: usize = 4372;
+    const OFF_NAME: usize = 0;
+    const OFF_PHYS: usize = 128;
+    const OFF_UNIQ: usize = 192;
+    const OFF_RD_SIZE: usize = 256;
+    const OFF_BUS: usize = 258;
+    const OFF_VENDOR: usize = 260;
+    const OFF_PRODUCT: usize = 264;
+    const OFF_VERSION: usize = 268;
+    const OFF_COUNTRY: usize = 272;
+    const OFF_RD_DATA: usize = 276;
+
+    let profile = ProfileConfig::for_mode(HidProfileMode::XboxOneS1708);
+    let hid = profile.hid_profile();
+    let descriptor = hid.report_descriptor();
+
+    let mut payload = vec![0u8; CREATE2_PAYLOAD_LEN];
+    write_padded(&mut payload[OFF_NAME..OFF_PHYS], name);
+    write_padded(&mut payload[OFF_PHYS..OFF_UNIQ], "network");
+    write_padded(&mut payload[OFF_UNIQ..OFF_RD_SIZE], profile.mode.as_str());
+    payload[OFF_RD_SIZE..OFF_RD_SIZE + 2].copy_from_slice(&(descriptor.len() as u16).to_ne_bytes());
+    payload[OFF_BUS..OFF_BUS + 2].copy_from_slice(&BUS_BLUETOOTH.to_ne_bytes());
+    payload[OFF_VENDOR..OFF_VENDOR + 4]
+        .copy_from_slice(&u32::from(profile.vendor_id).to_ne_bytes());
+    payload[OFF_PRODUCT..OFF_PRODUCT + 4]
+        .copy_from_slice(&u32::from(profile.product_id).to_ne_bytes());
+    payload[OFF_VERSION..OFF_VERSION + 4]
+        .copy_from_slice(&u32::from(profile.version).to_ne_bytes());
+    payload[OFF_COUNTRY..OFF_COUNTRY + 4]
+        .copy_from_slice(&u32::from(profile.country).to_ne_bytes());
+    payload[OFF_RD_DATA..OFF_RD_DATA + descriptor.len()].copy_from_slice(descriptor);
+
+    let mut event = Vec::with_capacity(4 + payload.len());
+    event.extend_from_slice(&UHID_CREATE2.to_ne_bytes());
+    event.extend_from_slice(&payload);
+    event
+}
+
+fn write_padded(dst: &mut [u8], src: &str) {
+    let bytes = src.as_bytes();
+    let len = bytes.len().min(dst.len().saturating_sub(1));
+    dst[..len].copy_from_slice(&bytes[..len]);
+}
+
+/// Forward rumble from UHID_OUTPUT and output SET_REPORTs until the device
+/// goes away. GET_REPORT is not supported.
+fn drain_events(io: &mut File, rumble: &Sender<OutputReport>) {
+    let mut event = [0u8; UHID_EVENT_SIZE];
+    loop {
+        if let Err(e) = io.read_exact(&mut event) {
+            eprintln!("netrecv: UHID event read failed: {e}");
+            return;
+        }
+        let result = match read_u32(&event, 0) {
+            UHID_OUTPUT => {
+                let size = usize::from(read_u16(&event, 4 + UHID_DATA_MAX)).min(UHID_DATA_MAX);
+                forward(&event[4..4 + size], rumble);
+                Ok(())
+            }
+            UHID_SET_REPORT => {
+                let id = read_u32(&event, 4);
+                let size = usize::from(read_u16(&event, 10)).min(UHID_DATA_MAX);
+                if event[9] == UHID_OUTPUT_REPORT {
+                    forward(&event[12..12 + size], rumble);
+                }
+                write_reply(io, UHID_SET_REPORT_REPLY, id, 0)
+            }
+            UHID_GET_REPORT => write_reply(
+                io,
+                UHID_GET_REPORT_REPLY,
+                read_u32(&event, 4),
+                UHID_ERR_NOT_SUPPORTED,
+            ),
+            _ => Ok(()),
+        };
+        if let Err(e) = result {
+            eprintln!("netrecv: {e}");
+            return;
+        }
+    }
+}
+
+fn forward(data: &[u8], rumble: &Sender<OutputReport>) {
+    if let Some(report) = OutputReport::parse(data) {
+        // The main loop may have exited; nothing to forward to then.
+        let _ = rumble.send(report);
+    }
+}
+
+/// GET_REPORT and SET_REPORT replies; an empty GET_REPORT reply carries a
+/// zero data size, which the SET_REPORT layout simply lacks.
+fn write_reply(io: &mut File, kind: u32, id: u32, err: u16) -> std::io::Result<()> {
+    let mut event = Vec::with_capacity(12);
+    event.extend_from_slice(&kind.to_ne_bytes());
+    event.extend_from_slice(&id.to_ne_bytes());
+    event.extend_from_slice(&err.to_ne_bytes());
+    if kind == UHID_GET_REPORT_REPLY {
+        event.extend_from_slice(&0u16.to_ne_bytes());
+    }
+    io.write_all(&event)
+}
+
+fn read_u16(buf: &[u8], offset: usize) -> u16 {
+    u16::from_ne_bytes([buf[offset], buf[offset + 1]])
+}
+
+fn read_u32(buf: &[u8], offset: usize) -> u32 {
+    u32::from_ne_bytes([
+        buf[offset],
+        buf[offset + 1],
+        buf[offset + 2],
+        buf[offset + 3],
+    ])
+}